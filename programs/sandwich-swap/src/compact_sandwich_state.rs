@@ -0,0 +1,235 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::sandwich_state::check_frontrun_age;
+
+/// Compact alternative to `SandwichState` for high-frequency operators. Mints are
+/// stored as indices into the operator's `MintRegistry` and the sandwich id lives
+/// only in the PDA seeds, `[b"compact_sandwich", payer, sandwich_id (le)]`.
+#[account]
+pub struct CompactSandwichState {
+    pub frontrun_output_amount: u64, // Amount of tokens obtained from frontrun
+    pub frontrun_input_amount: u64,  // Amount of tokens spent in frontrun
+    pub frontrun_slot: u64,          // Slot the frontrun landed in
+    pub timestamp: i64,              // Unix time the frontrun landed at
+    pub token_in_index: u8,          // Input mint index in the MintRegistry
+    pub token_out_index: u8,         // Output mint index in the MintRegistry
+    pub is_complete: bool,           // Flag to prevent double execution
+    pub bump: u8,                    // PDA bump
+}
+
+impl CompactSandwichState {
+    pub const SIZE: usize = 8 + 8 + 8 + 8 + 1 + 1 + 1 + 1; // Size in bytes
+    pub const SEED: &'static [u8] = b"compact_sandwich";
+
+    /// Rejects a backrun of a frontrun that filled nothing, which has nothing to sell.
+    pub fn check_frontrun_filled(&self) -> Result<()> {
+        require!(self.frontrun_output_amount > 0, ErrorCode::EmptySupply);
+        Ok(())
+    }
+
+    pub fn check_backrun_slot(&self, min_slots_between: u8) -> Result<()> {
+        let current_slot = Clock::get()?.slot;
+        require!(
+            current_slot.saturating_sub(self.frontrun_slot) >= min_slots_between as u64,
+            ErrorCode::BackrunTooEarly
+        );
+        Ok(())
+    }
+
+    /// Rejects a backrun of a frontrun older than `max_state_age_secs`, 0 selects
+    /// `DEFAULT_MAX_STATE_AGE_SECS`, as `SandwichState::check_state_age` does.
+    pub fn check_state_age(&self, max_state_age_secs: u32) -> Result<()> {
+        check_frontrun_age(
+            self.timestamp,
+            Clock::get()?.unix_timestamp,
+            max_state_age_secs,
+        )
+    }
+}
+
+/// Per-operator list of mints referenced by `CompactSandwichState`, stored in the
+/// `[b"mint_registry", operator]` PDA. Mints are append-only so indices stay stable.
+#[account]
+pub struct MintRegistry {
+    pub operator: Pubkey,   // Owner of the registry
+    pub mints: Vec<Pubkey>, // Registered mints, addressed by index
+    pub bump: u8,           // PDA bump
+}
+
+impl MintRegistry {
+    pub const MAX_MINTS: usize = 32;
+    pub const SIZE: usize = 32 + 4 + 32 * Self::MAX_MINTS + 1; // Size in bytes
+    pub const SEED: &'static [u8] = b"mint_registry";
+
+    pub fn index_of(&self, mint: &Pubkey) -> Result<u8> {
+        self.mints
+            .iter()
+            .position(|registered| registered == mint)
+            .map(|index| index as u8)
+            .ok_or(error!(ErrorCode::MintNotRegistered))
+    }
+
+    pub fn mint_at(&self, index: u8) -> Result<Pubkey> {
+        self.mints
+            .get(index as usize)
+            .copied()
+            .ok_or(error!(ErrorCode::MintNotRegistered))
+    }
+
+    /// Appends `mint` and returns its index, registering a mint twice keeps the existing one
+    pub fn register(&mut self, mint: Pubkey) -> Result<u8> {
+        if let Ok(index) = self.index_of(&mint) {
+            return Ok(index);
+        }
+        require!(
+            self.mints.len() < Self::MAX_MINTS,
+            ErrorCode::MintRegistryFull
+        );
+        self.mints.push(mint);
+        Ok((self.mints.len() - 1) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{set_clock, syscall_stubs};
+
+    fn compact_state(frontrun_output_amount: u64) -> CompactSandwichState {
+        CompactSandwichState {
+            frontrun_output_amount,
+            frontrun_input_amount: 1_000,
+            frontrun_slot: 40,
+            timestamp: 100,
+            token_in_index: 0,
+            token_out_index: 1,
+            is_complete: false,
+            bump: 255,
+        }
+    }
+
+    fn registry(mints: &[Pubkey]) -> MintRegistry {
+        MintRegistry {
+            operator: Pubkey::new_unique(),
+            mints: mints.to_vec(),
+            bump: 254,
+        }
+    }
+
+    #[test]
+    fn a_frontrun_that_filled_nothing_has_no_backrun() {
+        let mut state = compact_state(0);
+        assert_eq!(
+            state.check_frontrun_filled().unwrap_err(),
+            error!(ErrorCode::EmptySupply)
+        );
+
+        state.frontrun_output_amount = 1;
+        assert!(state.check_frontrun_filled().is_ok());
+    }
+
+    #[test]
+    fn a_backrun_in_the_frontrun_slot_is_rejected() {
+        let _stubs = syscall_stubs();
+        let state = compact_state(1_000);
+
+        set_clock(40, 100);
+        assert_eq!(
+            state.check_backrun_slot(1).unwrap_err(),
+            error!(ErrorCode::BackrunTooEarly)
+        );
+        assert!(state.check_backrun_slot(0).is_ok());
+
+        set_clock(41, 100);
+        assert!(state.check_backrun_slot(1).is_ok());
+    }
+
+    #[test]
+    fn a_compact_state_past_its_max_age_is_rejected() {
+        let _stubs = syscall_stubs();
+        // Stamped at 100, a day and a second later is past the default age
+        let state = compact_state(1_000);
+
+        set_clock(41, 100 + 86_400);
+        assert!(state.check_state_age(0).is_ok());
+        set_clock(41, 100 + 86_401);
+        assert_eq!(
+            state.check_state_age(0).unwrap_err(),
+            error!(ErrorCode::SandwichExpired)
+        );
+        // A longer age keeps it open
+        assert!(state.check_state_age(86_401).is_ok());
+    }
+
+    #[test]
+    fn registered_mints_round_trip_through_their_indices() {
+        let (usdc, bonk) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut mint_registry = registry(&[]);
+
+        assert_eq!(mint_registry.register(usdc).unwrap(), 0);
+        assert_eq!(mint_registry.register(bonk).unwrap(), 1);
+        // Registering twice keeps the existing index
+        assert_eq!(mint_registry.register(usdc).unwrap(), 0);
+        assert_eq!(mint_registry.mints, vec![usdc, bonk]);
+
+        for mint in [usdc, bonk] {
+            let index = mint_registry.index_of(&mint).unwrap();
+            assert_eq!(mint_registry.mint_at(index).unwrap(), mint);
+        }
+        assert_eq!(
+            mint_registry.index_of(&Pubkey::new_unique()).unwrap_err(),
+            error!(ErrorCode::MintNotRegistered)
+        );
+        assert_eq!(
+            mint_registry.mint_at(2).unwrap_err(),
+            error!(ErrorCode::MintNotRegistered)
+        );
+    }
+
+    #[test]
+    fn a_full_registry_refuses_new_mints() {
+        let mints: Vec<Pubkey> = (0..MintRegistry::MAX_MINTS)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+        let mut mint_registry = registry(&mints);
+
+        assert_eq!(
+            mint_registry.register(Pubkey::new_unique()).unwrap_err(),
+            error!(ErrorCode::MintRegistryFull)
+        );
+        // A mint already in it still resolves to its index
+        assert_eq!(mint_registry.register(mints[31]).unwrap(), 31);
+    }
+
+    #[test]
+    fn accounts_round_trip_within_their_sizes() {
+        let mut state = compact_state(1_000);
+        state.frontrun_slot = u64::MAX;
+        let mut data = Vec::new();
+        state.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + CompactSandwichState::SIZE);
+
+        let stored = CompactSandwichState::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(stored.frontrun_output_amount, 1_000);
+        assert_eq!(stored.frontrun_input_amount, 1_000);
+        assert_eq!(stored.frontrun_slot, u64::MAX);
+        assert_eq!((stored.token_in_index, stored.token_out_index), (0, 1));
+        assert!(!stored.is_complete);
+        assert_eq!(stored.bump, 255);
+
+        // A full registry is exactly the space its account is created with
+        let mints: Vec<Pubkey> = (0..MintRegistry::MAX_MINTS)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+        let mint_registry = registry(&mints);
+        let mut data = Vec::new();
+        mint_registry.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + MintRegistry::SIZE);
+
+        let stored = MintRegistry::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(stored.operator, mint_registry.operator);
+        assert_eq!(stored.mints, mints);
+        assert_eq!(stored.bump, 254);
+    }
+}
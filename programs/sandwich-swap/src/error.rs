@@ -40,4 +40,70 @@ pub enum ErrorCode {
     UnprofitableSandwich,
     #[msg("Input and output token Mismatch")]
     TokenMintMismatch,
+    #[msg("Backrun executed too soon after the frontrun")]
+    BackrunTooEarly,
+    #[msg("Protocol fee recipient is not configured in the global config")]
+    InvalidFeeRecipient,
+    #[msg("Tick out of range")]
+    InvalidTick,
+    #[msg("Sqrt price out of range")]
+    InvalidSqrtPrice,
+    #[msg("Victim slippage tolerance is below the configured floor")]
+    VictimSlippageTooTight,
+    #[msg("Program is paused")]
+    ProgramPaused,
+    #[msg("Pool is not on the allowlist")]
+    PoolNotAllowed,
+    #[msg("Token cannot be sold back")]
+    TokenNotSellable,
+    #[msg("Mint is not in the mint registry")]
+    MintNotRegistered,
+    #[msg("Mint registry is full")]
+    MintRegistryFull,
+    #[msg("Account does not match the one recorded in the AMM pair state")]
+    AmmAccountMismatch,
+    #[msg("Event authority is not the PumpSwap event authority")]
+    InvalidEventAuthority,
+    #[msg("Too many remaining accounts")]
+    TooManyAccounts,
+    #[msg("Swaps are disabled on the pool")]
+    PoolSwapDisabled,
+    #[msg("Pool opened too recently")]
+    PoolTooNew,
+    #[msg("Observation state is not initialized")]
+    UninitializedObservation,
+    #[msg("Pool was already frontrun in this slot")]
+    DuplicateFrontrun,
+    #[msg("Victim swap would fail regardless of the frontrun")]
+    VictimWouldFail,
+    #[msg("Protocol fee recipient token account is not the recipient's quote ATA")]
+    InvalidFeeRecipientAta,
+    #[msg("Token account still holds tokens and can't be closed")]
+    TokenAccountNotEmpty,
+    #[msg("Frontrun exceeds the configured notional cap")]
+    ExceedsNotionalCap,
+    #[msg("CPMM config charges no trade fee")]
+    InvalidFeeConfig,
+    #[msg("Input token account holds less than the backrun sells")]
+    InsufficientUserBalance,
+    #[msg("Victim swap crosses more ticks than the configured bound")]
+    TooManyTickCrossings,
+    #[msg("Math self test failed")]
+    SelfTestFailed,
+    #[msg("Pool price drifted past the tolerance since the frontrun was sized")]
+    PriceDrifted,
+    #[msg("Mint is on the blocklist")]
+    MintBlocked,
+    #[msg("Victim program is not invoked after the frontrun in its transaction")]
+    VictimNotInTransaction,
+    #[msg("AMM fee cache is older than the allowed age")]
+    StaleFeeCache,
+    #[msg("Frontruns in this slot reached the configured limit")]
+    SlotLimitReached,
+    #[msg("Sandwich state is older than the allowed age")]
+    SandwichExpired,
+    #[msg("The slot counter is required while frontruns per slot are limited")]
+    SlotCounterRequired,
+    #[msg("Signer is not the program's upgrade authority")]
+    NotUpgradeAuthority,
 }
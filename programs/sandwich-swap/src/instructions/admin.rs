@@ -0,0 +1,362 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::program::SandwichSwap;
+use crate::program_config::{AllowedPool, BlockedMint, PoolFrontrunSlot, ProgramConfig};
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProgramConfig::SIZE,
+        seeds = [ProgramConfig::SEED],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// This program, pointing at its program data account
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, SandwichSwap>,
+
+    /// Only the upgrade authority may create the config and become its admin
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ ErrorCode::NotUpgradeAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool: Pubkey)]
+pub struct AllowPool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + AllowedPool::SIZE,
+        seeds = [AllowedPool::SEED, pool.as_ref()],
+        bump
+    )]
+    pub allowed_pool: Account<'info, AllowedPool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisallowPool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        close = admin,
+        seeds = [AllowedPool::SEED, allowed_pool.pool.as_ref()],
+        bump = allowed_pool.bump
+    )]
+    pub allowed_pool: Account<'info, AllowedPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct BlockMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + BlockedMint::SIZE,
+        seeds = [BlockedMint::SEED, mint.as_ref()],
+        bump
+    )]
+    pub blocked_mint: Account<'info, BlockedMint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnblockMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        close = admin,
+        seeds = [BlockedMint::SEED, blocked_mint.mint.as_ref()],
+        bump = blocked_mint.bump
+    )]
+    pub blocked_mint: Account<'info, BlockedMint>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool: Pubkey)]
+pub struct TrackFrontrunSlot<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + PoolFrontrunSlot::SIZE,
+        seeds = [PoolFrontrunSlot::SEED, pool.as_ref()],
+        bump
+    )]
+    pub frontrun_slot: Account<'info, PoolFrontrunSlot>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_config(ctx: Context<InitializeConfig>, max_consecutive_losses: u16) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.admin = ctx.accounts.admin.key();
+    config.paused = false;
+    config.max_consecutive_losses = max_consecutive_losses;
+    config.consecutive_losses = 0;
+    config.bump = ctx.bumps.config;
+    config.enforce_allowlist = false;
+    config.research_mode = false;
+    config.max_notional = 0;
+    config.clamp_notional = false;
+    config.allow_fee_free_pools = false;
+    config.max_absolute_slippage_consumed_bps = 0;
+    config.enforce_blocklist = false;
+    config.max_per_slot = 0;
+
+    Ok(())
+}
+
+pub fn reset_circuit_breaker(ctx: Context<UpdateConfig>) -> Result<()> {
+    // Clear the loss streak and resume trading
+    let config = &mut ctx.accounts.config;
+    config.consecutive_losses = 0;
+    config.paused = false;
+
+    Ok(())
+}
+
+pub fn set_enforce_allowlist(ctx: Context<UpdateConfig>, enforce_allowlist: bool) -> Result<()> {
+    ctx.accounts.config.enforce_allowlist = enforce_allowlist;
+
+    Ok(())
+}
+
+pub fn set_enforce_blocklist(ctx: Context<UpdateConfig>, enforce_blocklist: bool) -> Result<()> {
+    ctx.accounts.config.enforce_blocklist = enforce_blocklist;
+
+    Ok(())
+}
+
+pub fn set_research_mode(ctx: Context<UpdateConfig>, research_mode: bool) -> Result<()> {
+    ctx.accounts.config.research_mode = research_mode;
+
+    Ok(())
+}
+
+pub fn set_notional_cap(
+    ctx: Context<UpdateConfig>,
+    max_notional: u64,
+    clamp_notional: bool,
+) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.max_notional = max_notional;
+    config.clamp_notional = clamp_notional;
+
+    Ok(())
+}
+
+pub fn set_allow_fee_free_pools(
+    ctx: Context<UpdateConfig>,
+    allow_fee_free_pools: bool,
+) -> Result<()> {
+    ctx.accounts.config.allow_fee_free_pools = allow_fee_free_pools;
+
+    Ok(())
+}
+
+pub fn set_max_absolute_slippage_consumed(
+    ctx: Context<UpdateConfig>,
+    max_absolute_slippage_consumed_bps: u16,
+) -> Result<()> {
+    ctx.accounts.config.max_absolute_slippage_consumed_bps = max_absolute_slippage_consumed_bps;
+
+    Ok(())
+}
+
+pub fn set_max_per_slot(ctx: Context<UpdateConfig>, max_per_slot: u16) -> Result<()> {
+    ctx.accounts.config.max_per_slot = max_per_slot;
+
+    Ok(())
+}
+
+pub fn allow_pool(ctx: Context<AllowPool>, pool: Pubkey) -> Result<()> {
+    let allowed_pool = &mut ctx.accounts.allowed_pool;
+    allowed_pool.pool = pool;
+    allowed_pool.bump = ctx.bumps.allowed_pool;
+
+    Ok(())
+}
+
+pub fn disallow_pool(_ctx: Context<DisallowPool>) -> Result<()> {
+    // The marker account is closed by the context
+    Ok(())
+}
+
+pub fn block_mint(ctx: Context<BlockMint>, mint: Pubkey) -> Result<()> {
+    let blocked_mint = &mut ctx.accounts.blocked_mint;
+    blocked_mint.mint = mint;
+    blocked_mint.bump = ctx.bumps.blocked_mint;
+
+    Ok(())
+}
+
+pub fn unblock_mint(_ctx: Context<UnblockMint>) -> Result<()> {
+    // The marker account is closed by the context
+    Ok(())
+}
+
+pub fn track_frontrun_slot(ctx: Context<TrackFrontrunSlot>, pool: Pubkey) -> Result<()> {
+    let frontrun_slot = &mut ctx.accounts.frontrun_slot;
+    frontrun_slot.pool = pool;
+    frontrun_slot.last_slot = 0;
+    frontrun_slot.bump = ctx.bumps.frontrun_slot;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{account_info, program, syscall_stubs};
+    use anchor_lang::solana_program::bpf_loader_upgradeable;
+    use std::collections::BTreeSet;
+
+    // Every account of `initialize_config` signed by `admin`, for this program deployed
+    // with `upgrade_authority`
+    fn try_initialize_config(admin: Pubkey, upgrade_authority: Option<Pubkey>) -> Result<()> {
+        let (config_key, _) = Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        let (program_data_key, _) =
+            Pubkey::find_program_address(&[crate::ID.as_ref()], &bpf_loader_upgradeable::ID);
+
+        // The loader's bincode layouts: the program's points at its program data, which
+        // holds the deploy slot and the optional upgrade authority
+        let mut program_state = 2u32.to_le_bytes().to_vec();
+        program_state.extend_from_slice(program_data_key.as_ref());
+        let mut program_data = 3u32.to_le_bytes().to_vec();
+        program_data.extend_from_slice(&0u64.to_le_bytes());
+        match upgrade_authority {
+            Some(upgrade_authority) => {
+                program_data.push(1);
+                program_data.extend_from_slice(upgrade_authority.as_ref());
+            }
+            None => program_data.push(0),
+        }
+
+        // The config is left allocated, as the create account CPI that never runs would
+        let accounts = vec![
+            account_info(admin, Pubkey::default(), vec![], true, false),
+            account_info(
+                config_key,
+                crate::ID,
+                vec![0u8; 8 + ProgramConfig::SIZE],
+                false,
+                false,
+            ),
+            account_info(
+                crate::ID,
+                bpf_loader_upgradeable::ID,
+                program_state,
+                false,
+                true,
+            ),
+            account_info(
+                program_data_key,
+                bpf_loader_upgradeable::ID,
+                program_data,
+                false,
+                false,
+            ),
+            program(anchor_lang::system_program::ID),
+        ];
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        InitializeConfig::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &[],
+            &mut InitializeConfigBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+    }
+
+    #[test]
+    fn only_the_upgrade_authority_initializes_the_config() {
+        let _stubs = syscall_stubs();
+        let upgrade_authority = Pubkey::new_unique();
+
+        try_initialize_config(upgrade_authority, Some(upgrade_authority)).unwrap();
+
+        // Anyone else, or anyone at all once the program is immutable, is refused
+        for upgrade_authority in [Some(upgrade_authority), None] {
+            assert_eq!(
+                try_initialize_config(Pubkey::new_unique(), upgrade_authority).unwrap_err(),
+                error!(ErrorCode::NotUpgradeAuthority)
+            );
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+
+use crate::compact_sandwich_state::MintRegistry;
+
+#[derive(Accounts)]
+pub struct RegisterMint<'info> {
+    #[account(mut)]
+    pub operator: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = operator,
+        space = 8 + MintRegistry::SIZE,
+        seeds = [MintRegistry::SEED, operator.key().as_ref()],
+        bump
+    )]
+    pub mint_registry: Account<'info, MintRegistry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn register_mint(ctx: Context<RegisterMint>, mint: Pubkey) -> Result<()> {
+    let mint_registry = &mut ctx.accounts.mint_registry;
+    mint_registry.operator = ctx.accounts.operator.key();
+    mint_registry.bump = ctx.bumps.mint_registry;
+
+    // Registering twice keeps the existing index
+    mint_registry.register(mint)?;
+
+    Ok(())
+}
@@ -6,3 +6,21 @@ pub use pumpswap::*;
 
 pub mod pumpfun;
 pub use pumpfun::*;
+
+pub mod admin;
+pub use admin::*;
+
+pub mod slot_counter;
+pub use slot_counter::*;
+
+pub mod views;
+pub use views::*;
+
+pub mod mint_registry;
+pub use mint_registry::*;
+
+pub mod wsol;
+pub use wsol::*;
+
+pub mod self_test;
+pub use self_test::*;
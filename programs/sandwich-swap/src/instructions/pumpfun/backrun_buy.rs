@@ -1,22 +1,34 @@
 use crate::error::ErrorCode;
-use crate::instructions::pumpfun::bonding_curve::BondingCurveState;
-use crate::instructions::pumpfun::{PumpFun, PUMPFUN_PROGRAM_ID};
-use crate::sandwich_state::{SandwichCompleteEvent, SandwichState};
+use crate::instructions::pumpfun::bonding_curve::{BondingCurveState, PumpFunGlobal};
+use crate::instructions::pumpfun::{PumpFun, PUMPFUN_PROGRAM_ID, PUMPFUN_SELL_DISCRIMINATOR};
+use crate::sandwich_state::{
+    min_required_output, with_fixed_cost, with_min_profit_amount, SandwichCompleteEvent,
+    SandwichState,
+};
+use crate::program_config::ProgramConfig;
 use anchor_lang::prelude::*;
 use anchor_lang::prelude::{Account, Program, Signer, System};
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_spl::token::{spl_token, Mint, Token, TokenAccount};
 use solana_program::account_info::AccountInfo;
 use solana_program::instruction::Instruction;
-use solana_program::program::invoke_signed;
+use solana_program::program::{invoke_signed, set_return_data};
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(sandwich_id: String)]
+#[instruction(sandwich_id: u64)]
 pub struct PumpFunBackrunBuyContext<'info> {
-    /// CHECK: Global config
-    pub global: AccountInfo<'info>,
+    /// CHECK: PumpFun's global config, owned by PumpFun and read by hand for its fee
+    /// recipients
+    #[account(owner = PUMPFUN_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub global: UncheckedAccount<'info>,
 
-    /// CHECK: Protocol fee recipient
-    #[account(mut)]
+    /// CHECK: Protocol fee recipient, must be one of the global's fee recipients
+    #[account(
+        mut,
+        constraint = PumpFunGlobal::try_read(&global)?
+            .is_fee_recipient(&protocol_fee_recipient.key())
+            @ ErrorCode::InvalidFeeRecipient
+    )]
     pub protocol_fee_recipient: AccountInfo<'info>,
 
     /// Base token mint (the token being bought or sold)
@@ -30,7 +42,7 @@ pub struct PumpFunBackrunBuyContext<'info> {
     #[account(mut)]
     pub bonding_curve_ata: Box<Account<'info, TokenAccount>>,
 
-    /// User token account
+    /// User token account, closed once the sell has emptied it
     #[account(
         mut,
         close = user,
@@ -51,8 +63,9 @@ pub struct PumpFunBackrunBuyContext<'info> {
     /// token program
     pub token_program: Program<'info, Token>,
 
-    /// CHECK: Event authority account for PumpFun
-    pub event_authority: AccountInfo<'info>,
+    /// CHECK: Event authority account for PumpFun, apart from this program's own
+    /// `event_authority` the event CPI adds
+    pub pumpfun_event_authority: AccountInfo<'info>,
 
     /// The pump amm program
     #[account(address = PUMPFUN_PROGRAM_ID.parse::<Pubkey>().unwrap())]
@@ -61,26 +74,38 @@ pub struct PumpFunBackrunBuyContext<'info> {
     /// The account that stores sandwich state
     #[account(
        mut,
-       seeds = [b"sandwich", sandwich_id.as_bytes()],
+       seeds = [b"sandwich", user.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump = sandwich_state.bump,
        constraint = !sandwich_state.is_complete @ ErrorCode::SandwichAlreadyCompleted,
-       constraint = sandwich_state.token_in_mint == *mint.to_account_info().key
-           @ ErrorCode::TokenMintMismatch,
+       // The frontrun paid lamports, recorded as the native mint, for the token
+       constraint = sandwich_state.is_unwound_by(
+           mint.to_account_info().key,
+           &spl_token::native_mint::ID,
+       ) @ ErrorCode::TokenMintMismatch,
     )]
     pub sandwich_state: Account<'info, SandwichState>,
+
+    /// Program config, tracks the losing streak for the circuit breaker
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
 }
 
+/// Args of PumpFun's `sell`: the tokens sold and the fewest lamports accepted for them.
 #[derive(AnchorSerialize)]
 pub struct PumpFunSell {
     pub token_amount: u64,
-    pub max_sol_cost: u64,
+    pub min_sol_output: u64,
 }
 
 impl PumpFunSell {
     pub fn data(&self) -> Vec<u8> {
-        let mut data = vec![149, 39, 222, 155, 211, 124, 152, 26]; // buy instruction discriminator
+        let mut data = PUMPFUN_SELL_DISCRIMINATOR.to_vec(); // sell instruction discriminator
         data.extend_from_slice(&self.token_amount.to_le_bytes());
-        data.extend_from_slice(&self.max_sol_cost.to_le_bytes());
+        data.extend_from_slice(&self.min_sol_output.to_le_bytes());
         data
     }
 }
@@ -88,7 +113,20 @@ impl PumpFunSell {
 pub fn pumpfun_backrun_buy(
     ctx: Context<PumpFunBackrunBuyContext>,
     sandwich_id: u64,
+    min_slots_between: u8,
+    max_state_age_secs: u32,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
 ) -> Result<()> {
+    // Refuse to backrun a frontrun that filled nothing
+    ctx.accounts.sandwich_state.check_frontrun_filled()?;
+
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    ctx.accounts.sandwich_state.check_backrun_slot(min_slots_between)?;
+
+    // Refuse to unwind a position left open for too long, at a price long gone
+    ctx.accounts.sandwich_state.check_state_age(max_state_age_secs)?;
+
     let sandwich_state = &mut ctx.accounts.sandwich_state;
 
     let account_metas = vec![
@@ -102,7 +140,7 @@ pub fn pumpfun_backrun_buy(
         AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
         AccountMeta::new(ctx.accounts.creator_fee_vault.key(), false),
         AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
-        AccountMeta::new_readonly(ctx.accounts.event_authority.key(), false),
+        AccountMeta::new_readonly(ctx.accounts.pumpfun_event_authority.key(), false),
         AccountMeta::new_readonly(ctx.accounts.pump_program.key(), false)
     ];
 
@@ -117,13 +155,24 @@ pub fn pumpfun_backrun_buy(
         ctx.accounts.system_program.to_account_info(),
         ctx.accounts.creator_fee_vault.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
-        ctx.accounts.event_authority.to_account_info(),
+        ctx.accounts.pumpfun_event_authority.to_account_info(),
         ctx.accounts.pump_program.to_account_info(),
     ];
 
+    // The whole position is sold, for at least the lamports it cost plus the minimum
+    // profit, at least `min_profit_amount` lamports of it, net of the fees paid to land it
+    let cost_basis = sandwich_state.frontrun_input_amount;
     let ix_data = PumpFunSell {
         token_amount: sandwich_state.frontrun_output_amount,
-        max_sol_cost: 0,
+        min_sol_output: with_fixed_cost(
+            with_min_profit_amount(
+                min_required_output(cost_basis, 0, 0)?,
+                cost_basis,
+                min_profit_amount,
+            )?,
+            &spl_token::native_mint::ID,
+            fixed_cost_lamports,
+        )?,
     }.data();
 
     let sell_ix = Instruction {
@@ -135,21 +184,214 @@ pub fn pumpfun_backrun_buy(
     let output_token_balance_before = ctx.accounts.user.lamports();
     invoke_signed(&sell_ix, &accounts_vec, &[])?;
 
+    // The account is closed on exit, which only succeeds once the sell emptied it
+    ctx.accounts.user_ata.reload()?;
+    check_token_account_empty(ctx.accounts.user_ata.amount)?;
+
     sandwich_state.is_complete = true;
 
     // Calculate and store actual profit
     let output_token_balance_after = ctx.accounts.user.lamports();
     let actual_output = output_token_balance_after.saturating_sub(output_token_balance_before);
-    let profit = actual_output.saturating_sub(sandwich_state.frontrun_input_amount);
+    // The sell isn't simulated, so there is no expected output to compare against
+    let event = SandwichCompleteEvent::new(
+        sandwich_id,
+        sandwich_state.frontrun_input_amount,
+        actual_output,
+        None,
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
+
+    // Hand the profit straight to the client as well
+    set_profit_return_data(&event);
 
     // Emit an event with profit information
-    emit!(SandwichCompleteEvent {
-        sandwich_id,
-        profit,
-        input_amount: sandwich_state.frontrun_input_amount,
-        output_amount: actual_output,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+    emit_cpi!(event);
+
+    Ok(())
+}
+
+/// Sets the backrun's realized profit as return data, a little-endian u64 of lamports.
+/// A losing backrun returns 0, the event carries the loss.
+pub fn set_profit_return_data(event: &SandwichCompleteEvent) {
+    let profit = u64::try_from(event.realized_profit).unwrap_or(0);
+    set_return_data(&profit.to_le_bytes());
+}
 
+/// Rejects closing a token account still holding `amount` tokens with a clean error,
+/// rather than the token program's failure on exit.
+pub fn check_token_account_empty(amount: u64) -> Result<()> {
+    require!(amount == 0, ErrorCode::TokenAccountNotEmpty);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::pumpfun::bonding_curve::PUMPFUN_GLOBAL_DISCRIMINATOR;
+    use crate::instructions::raydium::cpmm::swap_base_output::tests::{mint, token_account};
+    use crate::program_config::tests::config;
+    use crate::sandwich_state::tests::state_with_mints;
+    use crate::test_utils::{account_info, program, program_account, syscall_stubs};
+    use solana_program::program::get_return_data;
+    use std::collections::BTreeSet;
+
+    // An empty account at `key`, the CPI accounts are only passed through by key
+    fn unchecked(key: Pubkey) -> AccountInfo<'static> {
+        account_info(key, Pubkey::default(), vec![], false, false)
+    }
+
+    // Every account of a PumpFun backrun paying its protocol fee to `protocol_fee_recipient`,
+    // with a global owned by `global_owner` listing `fee_recipient`. The bonding curve is
+    // owned by this program, as its loader checks
+    fn try_backrun_accounts(
+        global_owner: Pubkey,
+        fee_recipient: Pubkey,
+        protocol_fee_recipient: Pubkey,
+    ) -> Result<()> {
+        let mut global_data = PUMPFUN_GLOBAL_DISCRIMINATOR.to_vec();
+        PumpFunGlobal {
+            fee_recipient,
+            ..Default::default()
+        }
+        .serialize(&mut global_data)
+        .unwrap();
+
+        let bonding_curve = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            vec![0u8; 8 + std::mem::size_of::<BondingCurveState>()],
+            false,
+            false,
+        );
+        bonding_curve.try_borrow_mut_data().unwrap()[..8]
+            .copy_from_slice(&BondingCurveState::DISCRIMINATOR);
+
+        // An open sandwich the frontrun bought `mint` into with lamports
+        let mint = mint();
+        let user = Pubkey::new_unique();
+        let sandwich_id: u64 = 7;
+        let (sandwich_key, sandwich_bump) = SandwichState::pda(&user, sandwich_id);
+        let mut state = state_with_mints(spl_token::native_mint::ID, mint.key());
+        state.bump = sandwich_bump;
+
+        let (config_key, config_bump) =
+            Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        let mut program_config = config(0);
+        program_config.bump = config_bump;
+        let (event_authority, _) =
+            Pubkey::find_program_address(&[b"__event_authority"], &crate::ID);
+
+        let accounts = vec![
+            account_info(
+                Pubkey::new_unique(),
+                global_owner,
+                global_data,
+                false,
+                false,
+            ),
+            unchecked(protocol_fee_recipient),
+            mint.clone(),
+            bonding_curve,
+            token_account(mint.key(), 0),
+            token_account(mint.key(), 1_000),
+            account_info(user, Pubkey::default(), vec![], true, false),
+            program(anchor_lang::system_program::ID),
+            unchecked(Pubkey::new_unique()),
+            program(anchor_spl::token::ID),
+            unchecked(Pubkey::new_unique()),
+            program(PumpFun::id()),
+            program_account(sandwich_key, 8 + SandwichState::SIZE, &state),
+            program_account(config_key, 8 + ProgramConfig::SIZE, &program_config),
+            unchecked(event_authority),
+            unchecked(crate::ID),
+        ];
+
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        PumpFunBackrunBuyContext::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &sandwich_id.to_le_bytes(),
+            &mut PumpFunBackrunBuyContextBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+    }
+
+    #[test]
+    fn a_fee_recipient_off_pumpfun_s_global_is_rejected() {
+        let _stubs = syscall_stubs();
+        let fee_recipient = Pubkey::new_unique();
+
+        // PumpFun's own global account, not one of this program's, passes
+        try_backrun_accounts(PumpFun::id(), fee_recipient, fee_recipient).unwrap();
+
+        assert_eq!(
+            try_backrun_accounts(PumpFun::id(), fee_recipient, Pubkey::new_unique()).unwrap_err(),
+            error!(ErrorCode::InvalidFeeRecipient)
+        );
+        // A global some other program owns is never read for its recipients
+        assert_eq!(
+            try_backrun_accounts(crate::ID, fee_recipient, fee_recipient).unwrap_err(),
+            error!(anchor_lang::error::ErrorCode::ConstraintOwner)
+        );
+    }
+
+    #[test]
+    fn a_profitable_backrun_returns_its_profit() {
+        let _stubs = syscall_stubs();
+
+        // Sold back for 1_050_000 lamports after paying 1_000_000 in the frontrun
+        let event = SandwichCompleteEvent::new(1, 1_000_000, 1_050_000, None, 0);
+        set_profit_return_data(&event);
+
+        let (program_id, data) = get_return_data().unwrap();
+        assert_eq!(program_id, crate::ID);
+        let profit = u64::from_le_bytes(data.try_into().unwrap());
+        assert_eq!(profit as i64, event.realized_profit);
+        assert_eq!(profit, 50_000);
+    }
+
+    #[test]
+    fn a_pumpfun_backrun_sells_the_token_for_lamports() {
+        let mint = Pubkey::new_unique();
+        // The frontrun records the lamports it paid as the native mint
+        let state = state_with_mints(spl_token::native_mint::ID, mint);
+
+        assert!(state.is_unwound_by(&mint, &spl_token::native_mint::ID));
+        // A state recorded with the mints swapped belongs to no PumpFun frontrun
+        let swapped = state_with_mints(mint, spl_token::native_mint::ID);
+        assert!(!swapped.is_unwound_by(&mint, &spl_token::native_mint::ID));
+    }
+
+    #[test]
+    fn sell_data_is_the_sell_discriminator_then_le_args() {
+        let data = PumpFunSell {
+            token_amount: 0x0102_0304_0506_0708,
+            min_sol_output: 42,
+        }
+        .data();
+
+        // Anchor's `global:sell` sighash, not another program's sell variant
+        let sighash = solana_program::hash::hash(b"global:sell").to_bytes();
+        assert_eq!(data.len(), 24);
+        assert_eq!(data[..8], sighash[..8]);
+        assert_eq!(data[8..16], [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(data[16..24], [42, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn a_residual_balance_blocks_the_close() {
+        check_token_account_empty(0).unwrap();
+        assert_eq!(
+            check_token_account_empty(1).unwrap_err(),
+            error!(ErrorCode::TokenAccountNotEmpty)
+        );
+    }
+}
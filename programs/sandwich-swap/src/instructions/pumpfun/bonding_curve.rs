@@ -25,4 +25,109 @@ pub struct BondingCurveState {
 
     // wallet that created that token
     pub creator: Pubkey,
-}
\ No newline at end of file
+}
+
+/// Anchor discriminator of PumpFun's `Global` account, the first 8 bytes of
+/// sha256("account:Global")
+pub const PUMPFUN_GLOBAL_DISCRIMINATOR: [u8; 8] = [167, 232, 232, 177, 200, 108, 114, 127];
+
+// PumpFun's `global` account, holding the fee settings of every bonding curve. PumpFun owns
+// it, so it's read by hand rather than through this program's account loader
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, Debug)]
+pub struct PumpFunGlobal {
+    pub initialized: bool,
+    pub authority: Pubkey,
+
+    // original fee recipient, still accepted next to the rotated ones
+    pub fee_recipient: Pubkey,
+
+    pub initial_virtual_token_reserves: u64,
+    pub initial_virtual_sol_reserves: u64,
+    pub initial_real_token_reserves: u64,
+    pub token_total_supply: u64,
+    pub fee_basis_points: u64,
+    pub withdraw_authority: Pubkey,
+    pub enable_migrate: bool,
+    pub pool_migration_fee: u64,
+    pub creator_fee_basis_points: u64,
+
+    // additional fee recipients, PumpFun rotates between them
+    pub fee_recipients: [Pubkey; 7],
+}
+
+impl PumpFunGlobal {
+    /// Reads the global from PumpFun's `global` account, which must carry the `Global`
+    /// discriminator. The fields after the fee recipients are left unread.
+    pub fn try_read(global: &AccountInfo) -> Result<Self> {
+        let data = global.try_borrow_data()?;
+        require!(
+            data.starts_with(&PUMPFUN_GLOBAL_DISCRIMINATOR),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+        Self::deserialize(&mut &data[8..])
+            .map_err(|_| error!(anchor_lang::error::ErrorCode::AccountDidNotDeserialize))
+    }
+
+    /// Whether `recipient` is one of the configured fee recipients. Unused slots hold the
+    /// default pubkey, which never counts as a recipient.
+    pub fn is_fee_recipient(&self, recipient: &Pubkey) -> bool {
+        let fee_recipient = self.fee_recipient;
+        *recipient != Pubkey::default()
+            && (fee_recipient == *recipient || self.fee_recipients.contains(recipient))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::account_info;
+
+    #[test]
+    fn a_recipient_outside_the_global_is_rejected() {
+        let recipient = Pubkey::new_unique();
+        let rotated = Pubkey::new_unique();
+        let mut global = PumpFunGlobal {
+            fee_recipient: recipient,
+            ..Default::default()
+        };
+        global.fee_recipients[3] = rotated;
+
+        assert!(global.is_fee_recipient(&recipient));
+        assert!(global.is_fee_recipient(&rotated));
+        assert!(!global.is_fee_recipient(&Pubkey::new_unique()));
+        // The unused slots are default pubkeys, which must not pass as recipients
+        assert!(!global.is_fee_recipient(&Pubkey::default()));
+    }
+
+    #[test]
+    fn the_global_is_read_behind_pumpfun_s_discriminator() {
+        let recipient = Pubkey::new_unique();
+        let global = PumpFunGlobal {
+            fee_recipient: recipient,
+            ..Default::default()
+        };
+        let mut data = PUMPFUN_GLOBAL_DISCRIMINATOR.to_vec();
+        global.serialize(&mut data).unwrap();
+        // PumpFun's account carries more fields than the ones read
+        data.extend_from_slice(&[0u8; 64]);
+
+        let global_info = account_info(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            data,
+            false,
+            false,
+        );
+        assert!(PumpFunGlobal::try_read(&global_info)
+            .unwrap()
+            .is_fee_recipient(&recipient));
+
+        // The discriminator an account of this program's own type would carry is refused
+        global_info.try_borrow_mut_data().unwrap()[..8]
+            .copy_from_slice(&solana_program::hash::hash(b"account:PumpFunGlobal").to_bytes()[..8]);
+        assert_eq!(
+            PumpFunGlobal::try_read(&global_info).unwrap_err(),
+            error!(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch)
+        );
+    }
+}
@@ -1,24 +1,32 @@
 use crate::error::ErrorCode;
-use crate::instructions::pumpfun::bonding_curve::BondingCurveState;
-use crate::instructions::pumpfun::{PumpFun, PUMPFUN_PROGRAM_ID};
+use crate::instructions::pumpfun::bonding_curve::{BondingCurveState, PumpFunGlobal};
+use crate::instructions::pumpfun::{PumpFun, PUMPFUN_BUY_DISCRIMINATOR, PUMPFUN_PROGRAM_ID};
 use crate::sandwich_state::{SandwichState};
+use crate::program_config::*;
 use anchor_lang::prelude::*;
 use anchor_lang::prelude::{Account, Program, Signer};
 use anchor_lang::solana_program::pubkey::Pubkey;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_spl::token::{spl_token, Mint, Token, TokenAccount};
 use solana_program::account_info::AccountInfo;
 use solana_program::instruction::Instruction;
 use solana_program::program::invoke_signed;
 
 #[derive(Accounts)]
-#[instruction(sandwich_id: String)]
+#[instruction(_target_base_amount_out: u64, _target_max_quote_amount_in: u64, sandwich_id: u64)]
 pub struct PumpFunFrontrunBuyContext<'info> {
-    /// CHECK: Global config
-    pub global: AccountInfo<'info>,
+    /// CHECK: PumpFun's global config, owned by PumpFun and read by hand for its fee
+    /// recipients
+    #[account(owner = PUMPFUN_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub global: UncheckedAccount<'info>,
 
-    /// CHECK: Protocol fee recipient
-    #[account(mut)]
+    /// CHECK: Protocol fee recipient, must be one of the global's fee recipients
+    #[account(
+        mut,
+        constraint = PumpFunGlobal::try_read(&global)?
+            .is_fee_recipient(&protocol_fee_recipient.key())
+            @ ErrorCode::InvalidFeeRecipient
+    )]
     pub protocol_fee_recipient: AccountInfo<'info>,
 
     /// Base token mint (the token being bought or sold)
@@ -67,13 +75,16 @@ pub struct PumpFunFrontrunBuyContext<'info> {
        init_if_needed,
        payer = user,
        space = 8 + SandwichState::SIZE,
-       seeds = [b"sandwich", sandwich_id.as_bytes()],
+       seeds = [b"sandwich", user.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump
     )]
     pub sandwich_state: Account<'info, SandwichState>,
 
     // Associated token program for init_if_needed
     pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
 }
 
 #[derive(AnchorSerialize)]
@@ -84,7 +95,7 @@ pub struct PumpFunBuy {
 
 impl PumpFunBuy {
     pub fn data(&self) -> Vec<u8> {
-        let mut data = vec![250, 234, 13, 123, 213, 156, 19, 236]; // buy instruction discriminator
+        let mut data = PUMPFUN_BUY_DISCRIMINATOR.to_vec(); // buy instruction discriminator
         data.extend_from_slice(&self.token_amount.to_le_bytes());
         data.extend_from_slice(&self.max_sol_cost.to_le_bytes());
         data
@@ -103,7 +114,7 @@ impl PumpFunBuy {
 ///  Curve: constant‑product k = x·y                 (no time‑varying k)
 ///  Fee:   taken on swap‑input, i.e.  Δ_in_eff = Δ_in * (1‑fee)
 ///
-fn compute_front_run_with_fee(
+pub(crate) fn compute_front_run_with_fee(
     v_tokens: u64,
     v_sol: u64,
     target_token_amount_out: u64,
@@ -180,15 +191,20 @@ fn compute_front_run_with_fee(
     Some((my_token_amount_out, my_max_sol_in, profit_pct))
 }
 
-pub fn pumpfun_frontrun_buy(
-    ctx: Context<PumpFunFrontrunBuyContext>,
+/// PumpFun fee taken on every swap (1%).
+pub const PUMPFUN_FEE: f64 = 0.01;
+/// Smallest profit a PumpFun sandwich must make (0.5%).
+pub const PUMPFUN_MIN_PROFIT: f64 = 0.005;
+
+/// Sizes a frontrun ahead of a victim buying `target_token_amount_out` for at most
+/// `target_max_sol_amount_in` on the given virtual reserves, returning
+/// `(my_token_amount_out, my_max_sol_in, profit_pct)`.
+pub(crate) fn size_pumpfun_frontrun(
+    v_tokens: u64,
+    v_sol: u64,
     target_token_amount_out: u64,
     target_max_sol_amount_in: u64,
-    sandwich_id: u64,
-) -> Result<()> {
-    let curve_state = &mut ctx.accounts.bonding_curve.load_mut()?;
-    let v_tokens = curve_state.virtual_token_reserves;
-    let v_sol    = curve_state.virtual_sol_reserves;
+) -> Result<(u64, u64, f64)> {
     let price_now = v_sol as f64 / v_tokens as f64;
     let cost_now  = target_token_amount_out as f64 * price_now;
     require!(
@@ -196,18 +212,65 @@ pub fn pumpfun_frontrun_buy(
         ErrorCode::ExceededSlippage
     );
 
-    const FEE: f64 = 0.01; // 1%
-    const MIN_PROFIT: f64 = 0.005; // 0.5%
-
-    let (frontrun_token_out, frontrun_max_sol_in, _profit_pct) = compute_front_run_with_fee(
+    let sizing = compute_front_run_with_fee(
         v_tokens,
         v_sol,
         target_token_amount_out,
         target_max_sol_amount_in,
-        FEE,
-        MIN_PROFIT,
+        PUMPFUN_FEE,
+        PUMPFUN_MIN_PROFIT,
     ).ok_or(ErrorCode::UnprofitableSandwich)?;
 
+    Ok(sizing)
+}
+
+/// Lamports the buy cost, the drop in the user's balance across it.
+pub fn lamports_paid(lamports_before: u64, lamports_after: u64) -> u64 {
+    lamports_before.saturating_sub(lamports_after)
+}
+
+pub fn pumpfun_frontrun_buy(
+    ctx: Context<PumpFunFrontrunBuyContext>,
+    target_token_amount_out: u64,
+    target_max_sol_amount_in: u64,
+    sandwich_id: u64,
+    require_victim_ix: Option<Pubkey>,
+) -> Result<()> {
+    log_frontrun_compute_units("PumpFun");
+
+    // Refuse a paused program, a pool off the allowlist and a blocked mint
+    ctx.accounts.guards.check(&ctx.accounts.bonding_curve.key())?;
+    // The bonding curve is paid in lamports, checked as the native mint
+    ctx.accounts
+        .guards
+        .check_mints(&spl_token::native_mint::ID, &ctx.accounts.mint.key())?;
+
+    // Only frontrun a victim landing later in the same transaction, when one is named
+    ctx.accounts.guards.check_victim_ix(require_victim_ix)?;
+
+    // Read the reserves and let go of the curve, PumpFun writes it during the buy CPI
+    let (v_tokens, v_sol) = {
+        let curve_state = ctx.accounts.bonding_curve.load()?;
+        (
+            curve_state.virtual_token_reserves,
+            curve_state.virtual_sol_reserves,
+        )
+    };
+
+    let (frontrun_token_out, frontrun_max_sol_in, _profit_pct) = size_pumpfun_frontrun(
+        v_tokens,
+        v_sol,
+        target_token_amount_out,
+        target_max_sol_amount_in,
+    )?;
+
+    // Hold the frontrun to the configured notional cap, in lamports of SOL paid
+    let (frontrun_max_sol_in, frontrun_token_out) = ctx
+        .accounts
+        .guards
+        .config
+        .cap_notional(frontrun_max_sol_in, frontrun_token_out)?;
+
     let account_metas = vec![
         AccountMeta::new_readonly(ctx.accounts.global.key(), false),
         AccountMeta::new(ctx.accounts.protocol_fee_recipient.key(), false),
@@ -253,14 +316,45 @@ pub fn pumpfun_frontrun_buy(
     invoke_signed(&buy_ix, &accounts_vec, &[])?;
     let lamports_after = ctx.accounts.user.lamports();
 
-    let sandwich_state = &mut ctx.accounts.sandwich_state;
-    sandwich_state.frontrun_output_amount = frontrun_token_out;
-    sandwich_state.frontrun_input_amount = lamports_after.saturating_sub(lamports_before);
-    sandwich_state.sandwich_id = sandwich_id;
-    sandwich_state.token_out_mint = *ctx.accounts.mint.to_account_info().key;
-    sandwich_state.timestamp = Clock::get()?.unix_timestamp;
-    sandwich_state.is_complete = false;
-    sandwich_state.bump = ctx.bumps.sandwich_state;
+    ctx.accounts.sandwich_state.record_frontrun(
+        lamports_paid(lamports_before, lamports_after),
+        frontrun_token_out,
+        // The bonding curve is paid in lamports, recorded as the native mint
+        spl_token::native_mint::ID,
+        *ctx.accounts.mint.to_account_info().key,
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buy_data_is_the_buy_discriminator_then_le_args() {
+        let data = PumpFunBuy {
+            token_amount: 0x0102_0304_0506_0708,
+            max_sol_cost: 42,
+        }
+        .data();
+
+        // Anchor's `global:buy` sighash, not another program's buy variant
+        let sighash = solana_program::hash::hash(b"global:buy").to_bytes();
+        assert_eq!(data.len(), 24);
+        assert_eq!(data[..8], sighash[..8]);
+        assert_eq!(data[8..16], [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(data[16..24], [42, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn the_frontrun_records_the_lamports_it_spent() {
+        // The buy drew 1_010_000 lamports out of the user's balance
+        assert_eq!(lamports_paid(10_000_000, 8_990_000), 1_010_000);
+        // A balance that didn't drop paid nothing
+        assert_eq!(lamports_paid(10_000_000, 10_000_000), 0);
+        assert_eq!(lamports_paid(10_000_000, 10_500_000), 0);
+    }
+}
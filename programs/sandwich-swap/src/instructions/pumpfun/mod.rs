@@ -1,4 +1,3 @@
-use anchor_lang::AnchorSerialize;
 use solana_program::pubkey::Pubkey;
 use anchor_lang::prelude::*;
 
@@ -6,13 +5,22 @@ pub mod frontrun_buy;
 pub use frontrun_buy::*;
 
 pub mod backrun_buy;
-mod bonding_curve;
+pub mod bonding_curve;
 
 pub use backrun_buy::*;
 
+pub mod simulate_frontrun;
+pub use simulate_frontrun::*;
+
 // PumpFun program ID
 pub const PUMPFUN_PROGRAM_ID: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
 
+/// Anchor discriminator of PumpFun's `buy`, the first 8 bytes of sha256("global:buy")
+pub const PUMPFUN_BUY_DISCRIMINATOR: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+
+/// Anchor discriminator of PumpFun's `sell`, the first 8 bytes of sha256("global:sell")
+pub const PUMPFUN_SELL_DISCRIMINATOR: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+
 #[derive(Clone)]
 pub struct PumpFun;
 
@@ -21,20 +29,3 @@ impl anchor_lang::Id for PumpFun {
         PUMPFUN_PROGRAM_ID.parse::<Pubkey>().unwrap()
     }
 }
-
-#[derive(AnchorSerialize)]
-pub struct Swap {
-    pub discriminator: u8,
-    pub amount_in: u64,
-    pub min_amount_out: u64,
-}
-
-impl Swap {
-    pub fn data(&self) -> Vec<u8> {
-        let mut data = vec![250, 234, 13, 123, 213, 156, 19, 236]; // buy instruction discriminator
-        data.extend_from_slice(&self.discriminator.to_le_bytes());
-        data.extend_from_slice(&self.amount_in.to_le_bytes());
-        data.extend_from_slice(&self.min_amount_out.to_le_bytes());
-        data
-    }
-}
@@ -0,0 +1,102 @@
+use anchor_lang::prelude::*;
+
+use super::bonding_curve::BondingCurveState;
+use super::frontrun_buy::size_pumpfun_frontrun;
+
+use crate::sandwich_state::PumpFunFrontrunSimEvent;
+
+/// Read-only view of a bonding curve for sizing a frontrun against its live virtual
+/// reserves without buying.
+#[derive(Accounts)]
+pub struct PumpFunSimulateFrontrun<'info> {
+    /// Bonding curve account, only read
+    pub bonding_curve: AccountLoader<'info, BondingCurveState>,
+}
+
+/// Emits the frontrun `pumpfun_frontrun_buy` would make ahead of the given victim, failing
+/// the same way when the victim's slippage or the profit floor rules it out.
+pub fn pumpfun_simulate_frontrun(
+    ctx: Context<PumpFunSimulateFrontrun>,
+    target_token_amount_out: u64,
+    target_max_sol_amount_in: u64,
+) -> Result<()> {
+    let event = {
+        let curve_state = ctx.accounts.bonding_curve.load()?;
+        simulate_pumpfun_frontrun(
+            &curve_state,
+            target_token_amount_out,
+            target_max_sol_amount_in,
+        )?
+    };
+    emit!(event);
+
+    Ok(())
+}
+
+/// The frontrun sized against `curve_state`'s virtual reserves.
+pub fn simulate_pumpfun_frontrun(
+    curve_state: &BondingCurveState,
+    target_token_amount_out: u64,
+    target_max_sol_amount_in: u64,
+) -> Result<PumpFunFrontrunSimEvent> {
+    let (my_token_amount_out, my_max_sol_in, profit_pct) = size_pumpfun_frontrun(
+        curve_state.virtual_token_reserves,
+        curve_state.virtual_sol_reserves,
+        target_token_amount_out,
+        target_max_sol_amount_in,
+    )?;
+
+    Ok(PumpFunFrontrunSimEvent {
+        my_token_amount_out,
+        my_max_sol_in,
+        profit_pct,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::instructions::pumpfun::frontrun_buy::{
+        compute_front_run_with_fee, PUMPFUN_FEE, PUMPFUN_MIN_PROFIT,
+    };
+
+    // A fresh PumpFun curve, 1.073B tokens (6 decimals) against 30 SOL
+    fn fresh_curve() -> BondingCurveState {
+        BondingCurveState {
+            virtual_token_reserves: 1_073_000_000_000_000,
+            virtual_sol_reserves: 30_000_000_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn the_simulation_matches_the_frontrun_sizing() {
+        // A victim buying 30M tokens for at most 1.5 SOL
+        let event =
+            simulate_pumpfun_frontrun(&fresh_curve(), 30_000_000_000_000, 1_500_000_000).unwrap();
+
+        let (token_out, max_sol_in, profit_pct) = compute_front_run_with_fee(
+            1_073_000_000_000_000,
+            30_000_000_000,
+            30_000_000_000_000,
+            1_500_000_000,
+            PUMPFUN_FEE,
+            PUMPFUN_MIN_PROFIT,
+        )
+        .unwrap();
+        assert_eq!(event.my_token_amount_out, token_out);
+        assert_eq!(event.my_max_sol_in, max_sol_in);
+        assert_eq!(event.profit_pct, profit_pct);
+        assert!(event.profit_pct >= PUMPFUN_MIN_PROFIT);
+    }
+
+    #[test]
+    fn an_unprofitable_victim_fails_the_simulation() {
+        // A 10M token buy with little slippage leaves less than the profit floor
+        assert_eq!(
+            simulate_pumpfun_frontrun(&fresh_curve(), 10_000_000_000_000, 300_000_000).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+    }
+}
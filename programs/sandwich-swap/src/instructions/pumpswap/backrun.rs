@@ -2,126 +2,102 @@ use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{instruction::Instruction, program::invoke_signed};
 
 use crate::error::ErrorCode;
-use crate::sandwich_state::SandwichCompleteEvent;
-use super::{PumpSwapBuy, PumpSwapSell, PumpSwapContext};
+use crate::sandwich_state::{
+    min_required_output, with_fixed_cost, with_min_profit_amount, SandwichCompleteEvent,
+    SandwichState,
+};
+use super::{build_pumpswap_backrun_accounts, PumpSwapBuy, PumpSwapSell, PumpSwapBackrunContext};
+
+/// Rejects a backrun of `sandwich_state` unless it's an open position the swap of
+/// `input_mint` into `output_mint` unwinds, filled by its frontrun at least
+/// `min_slots_between` slots and at most `max_state_age_secs` seconds ago.
+pub fn check_pumpswap_backrun(
+    sandwich_state: &SandwichState,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    min_slots_between: u8,
+    max_state_age_secs: u32,
+) -> Result<()> {
+    require!(
+        !sandwich_state.is_complete,
+        ErrorCode::SandwichAlreadyCompleted
+    );
+    require!(
+        sandwich_state.is_unwound_by(input_mint, output_mint),
+        ErrorCode::TokenMintMismatch
+    );
+    sandwich_state.check_frontrun_filled()?;
+
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    sandwich_state.check_backrun_slot(min_slots_between)?;
+
+    // Refuse to unwind a position left open for too long, at a price long gone
+    sandwich_state.check_state_age(max_state_age_secs)
+}
+
+/// Least a backrun must take back for a frontrun that cost `cost_basis` of `profit_mint`:
+/// the cost plus the minimum profit, at least `min_profit_amount` of it, net of the
+/// `fixed_cost_lamports` paid to land it when the profit is in WSOL.
+pub fn pumpswap_backrun_min_output(
+    cost_basis: u64,
+    profit_mint: &Pubkey,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+) -> Result<u64> {
+    with_fixed_cost(
+        with_min_profit_amount(
+            min_required_output(cost_basis, 0, 0)?,
+            cost_basis,
+            min_profit_amount,
+        )?,
+        profit_mint,
+        fixed_cost_lamports,
+    )
+}
 
 /// Similar to swap_base_in, but used for completing the backrun part of a sandwich attack when the frontrun was a buy
 pub fn pumpswap_backrun_buy(
-    ctx: Context<PumpSwapContext>
+    ctx: Context<PumpSwapBackrunContext>,
+    _sandwich_id: u64,
+    min_slots_between: u8,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    max_state_age_secs: u32,
 ) -> Result<()> {
-    // Get accounts needed for the CPI
+    // Get accounts needed for the CPI, they're shared with every PumpSwap CPI
     let pump_program = ctx.accounts.pump_amm_program.to_account_info();
-    let pool = ctx.accounts.pool.to_account_info();
-    let user = ctx.accounts.user.to_account_info();
-    let global_config = ctx.accounts.global_config.to_account_info();
-    let base_mint = ctx.accounts.base_mint.to_account_info();
-    let quote_mint = ctx.accounts.quote_mint.to_account_info();
-    let user_base_token_account = ctx.accounts.user_base_token_account.to_account_info();
-    let user_quote_token_account = ctx.accounts.user_quote_token_account.to_account_info();
-    let pool_base_token_account = ctx.accounts.pool_base_token_account.to_account_info();
-    let pool_quote_token_account = ctx.accounts.pool_quote_token_account.to_account_info();
-    let protocol_fee_recipient = ctx.accounts.protocol_fee_recipient.to_account_info();
-    let protocol_fee_recipient_token_account = ctx
-        .accounts
-        .protocol_fee_recipient_token_account
-        .to_account_info();
-    let base_token_program = ctx.accounts.base_token_program.to_account_info();
-    let quote_token_program = ctx.accounts.quote_token_program.to_account_info();
-    let system_program = ctx.accounts.system_program.to_account_info();
-    let associated_token_program = ctx.accounts.associated_token_program.to_account_info();
-    let event_authority = ctx.accounts.event_authority.to_account_info();
-    let program = ctx.accounts.program.to_account_info();
-    
+    let (account_metas, accounts_vec) = build_pumpswap_backrun_accounts(ctx.accounts);
+
     // Get the sandwich state to access frontrun data
     let sandwich_state = &mut ctx.accounts.sandwich_state;
     
-    // Verify this is the proper backrun for the frontrun that occurred
-    if sandwich_state.is_complete {
-        return err!(ErrorCode::SandwichAlreadyCompleted);
-    }
-    
-    if sandwich_state.token_in_mint != ctx.accounts.base_mint.key() || 
-       sandwich_state.token_out_mint != ctx.accounts.quote_mint.key() {
-        return err!(ErrorCode::TokenMintMismatch);
-    }
-    
+    // The frontrun bought the base mint, sell it back for the quote mint
+    check_pumpswap_backrun(
+        sandwich_state,
+        &ctx.accounts.base_mint.key(),
+        &ctx.accounts.quote_mint.key(),
+        min_slots_between,
+        max_state_age_secs,
+    )?;
+
     // Prepare to sell the tokens we acquired in the frontrun
     let base_amount_in = sandwich_state.frontrun_output_amount;
     
-    if base_amount_in == 0 {
-        return err!(ErrorCode::EmptySupply);
-    }
-    
     // Record initial token balance to calculate profit later
     let quote_balance_before = ctx.accounts.user_quote_token_account.amount;
     
-    // Create the instruction data for the sell instruction (since we're selling in the backrun)
+    // Create the instruction data for the sell instruction (since we're selling in the backrun),
+    // which must return the quote the frontrun paid at a profit
     let ix_data = PumpSwapSell {
         base_amount_in,
-        min_quote_amount_out: sandwich_state.frontrun_input_amount
-            .checked_sub(sandwich_state.frontrun_input_amount
-                .saturating_mul(90)
-                .saturating_div(100)
-            ).unwrap()
+        min_quote_amount_out: pumpswap_backrun_min_output(
+            sandwich_state.frontrun_input_amount,
+            &ctx.accounts.quote_mint.key(),
+            fixed_cost_lamports,
+            min_profit_amount,
+        )?,
     }.data();
 
-    // Create the sell instruction for PumpSwap
-    // First create the account metas for the required accounts
-    let mut account_metas = vec![
-        AccountMeta::new(pool.key(), false),
-        AccountMeta::new(user.key(), true),
-        AccountMeta::new_readonly(global_config.key(), false),
-        AccountMeta::new_readonly(base_mint.key(), false),
-        AccountMeta::new_readonly(quote_mint.key(), false),
-        AccountMeta::new(user_base_token_account.key(), false),
-        AccountMeta::new(user_quote_token_account.key(), false),
-        AccountMeta::new(pool_base_token_account.key(), false),
-        AccountMeta::new(pool_quote_token_account.key(), false),
-        AccountMeta::new_readonly(protocol_fee_recipient.key(), false),
-        AccountMeta::new(protocol_fee_recipient_token_account.key(), false),
-        AccountMeta::new_readonly(base_token_program.key(), false),
-        AccountMeta::new_readonly(quote_token_program.key(), false),
-        AccountMeta::new_readonly(system_program.key(), false),
-        AccountMeta::new_readonly(associated_token_program.key(), false),
-        AccountMeta::new_readonly(event_authority.key(), false),
-        AccountMeta::new_readonly(program.key(), false),
-    ];
-
-    // accounts for the instruction
-    let mut accounts_vec = vec![
-        pool,
-        user,
-        global_config,
-        base_mint,
-        quote_mint,
-        user_base_token_account,
-        user_quote_token_account,
-        pool_base_token_account,
-        pool_quote_token_account,
-        protocol_fee_recipient,
-        protocol_fee_recipient_token_account,
-        base_token_program,
-        quote_token_program,
-        system_program,
-        associated_token_program,
-        event_authority,
-        program,
-    ];
-
-    // Add coin creator accounts if provided
-    if let Some(coin_creator_vault_ata) = &ctx.accounts.coin_creator_vault_ata {
-        account_metas.push(AccountMeta::new(coin_creator_vault_ata.key(), false));
-        accounts_vec.push(coin_creator_vault_ata.to_account_info());
-    }
-
-    if let Some(coin_creator_vault_authority) = &ctx.accounts.coin_creator_vault_authority {
-        account_metas.push(AccountMeta::new_readonly(
-            coin_creator_vault_authority.key(),
-            false,
-        ));
-        accounts_vec.push(coin_creator_vault_authority.to_account_info());
-    }
-
     // Create the instruction with all accounts
     let sell_ix = Instruction {
         program_id: pump_program.key(),
@@ -132,139 +108,75 @@ pub fn pumpswap_backrun_buy(
     // Invoke the PumpSwap sell instruction
     invoke_signed(&sell_ix, &accounts_vec, &[])?;
 
-    // Calculate profit
+    // Calculate profit from the balance the sell left
+    ctx.accounts.user_quote_token_account.reload()?;
     let quote_balance_after = ctx.accounts.user_quote_token_account.amount;
     let backrun_output_amount = quote_balance_after.saturating_sub(quote_balance_before);
-    let profit = backrun_output_amount.saturating_sub(sandwich_state.frontrun_input_amount);
 
     // Update the sandwich state to complete
     sandwich_state.is_complete = true;
 
+    // The sell isn't simulated, so there is no expected profit to report
+    let event = SandwichCompleteEvent::new(
+        sandwich_state.sandwich_id,
+        sandwich_state.frontrun_input_amount,
+        backrun_output_amount,
+        None,
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
+
     // Emit sandwich complete event
-    emit!(SandwichCompleteEvent {
-        sandwich_id: sandwich_state.sandwich_id,
-        profit,
-        input_amount: sandwich_state.frontrun_input_amount,
-        output_amount: backrun_output_amount,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+    emit_cpi!(event);
 
     Ok(())
 }
 
 /// Similar to swap_base_out, but used for completing the backrun part of a sandwich attack when the frontrun was a sell
 pub fn pumpswap_backrun_sell(
-    ctx: Context<PumpSwapContext>
+    ctx: Context<PumpSwapBackrunContext>,
+    _sandwich_id: u64,
+    min_slots_between: u8,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    max_state_age_secs: u32,
 ) -> Result<()> {
-    // Get accounts needed for the CPI
+    // Get accounts needed for the CPI, they're shared with every PumpSwap CPI
     let pump_program = ctx.accounts.pump_amm_program.to_account_info();
-    let pool = ctx.accounts.pool.to_account_info();
-    let user = ctx.accounts.user.to_account_info();
-    let global_config = ctx.accounts.global_config.to_account_info();
-    let base_mint = ctx.accounts.base_mint.to_account_info();
-    let quote_mint = ctx.accounts.quote_mint.to_account_info();
-    let user_base_token_account = ctx.accounts.user_base_token_account.to_account_info();
-    let user_quote_token_account = ctx.accounts.user_quote_token_account.to_account_info();
-    let pool_base_token_account = ctx.accounts.pool_base_token_account.to_account_info();
-    let pool_quote_token_account = ctx.accounts.pool_quote_token_account.to_account_info();
-    let protocol_fee_recipient = ctx.accounts.protocol_fee_recipient.to_account_info();
-    let protocol_fee_recipient_token_account = ctx
-        .accounts
-        .protocol_fee_recipient_token_account
-        .to_account_info();
-    let base_token_program = ctx.accounts.base_token_program.to_account_info();
-    let quote_token_program = ctx.accounts.quote_token_program.to_account_info();
-    let system_program = ctx.accounts.system_program.to_account_info();
-    let associated_token_program = ctx.accounts.associated_token_program.to_account_info();
-    let event_authority = ctx.accounts.event_authority.to_account_info();
-    let program = ctx.accounts.program.to_account_info();
-    
+    let (account_metas, accounts_vec) = build_pumpswap_backrun_accounts(ctx.accounts);
+
     // Get the sandwich state to access frontrun data
     let sandwich_state = &mut ctx.accounts.sandwich_state;
     
-    // Verify this is the proper backrun for the frontrun that occurred
-    if sandwich_state.is_complete {
-        return err!(ErrorCode::SandwichAlreadyCompleted);
-    }
-    
-    if sandwich_state.token_out_mint != ctx.accounts.base_mint.key() || 
-       sandwich_state.token_in_mint != ctx.accounts.quote_mint.key() {
-        return err!(ErrorCode::TokenMintMismatch);
-    }
-    
-    // In a backrun sell, we're buying back the base token by using the quote tokens we received
-    let base_amount_out = sandwich_state.frontrun_output_amount;
-    
-    if base_amount_out == 0 {
-        return err!(ErrorCode::EmptySupply);
-    }
+    // The frontrun sold the base mint, buy it back with the quote mint
+    check_pumpswap_backrun(
+        sandwich_state,
+        &ctx.accounts.quote_mint.key(),
+        &ctx.accounts.base_mint.key(),
+        min_slots_between,
+        max_state_age_secs,
+    )?;
 
     // Record initial token balance to calculate profit later
     let base_balance_before = ctx.accounts.user_base_token_account.amount;
     
-    // Create the instruction data for the buy instruction (since we're buying in the backrun)
+    // Create the instruction data for the buy instruction (since we're buying in the backrun),
+    // which spends no more than the frontrun's quote on buying back the base sold at a profit
     let ix_data = PumpSwapBuy {
-        base_amount_out: sandwich_state.frontrun_input_amount,
+        base_amount_out: pumpswap_backrun_min_output(
+            sandwich_state.frontrun_input_amount,
+            &ctx.accounts.base_mint.key(),
+            fixed_cost_lamports,
+            min_profit_amount,
+        )?,
         max_quote_amount_in: sandwich_state.frontrun_output_amount,
     }.data();
 
-    // Create the buy instruction for PumpSwap
-    // First create the account metas for the required accounts
-    let mut account_metas = vec![
-        AccountMeta::new(pool.key(), false),
-        AccountMeta::new(user.key(), true),
-        AccountMeta::new_readonly(global_config.key(), false),
-        AccountMeta::new_readonly(base_mint.key(), false),
-        AccountMeta::new_readonly(quote_mint.key(), false),
-        AccountMeta::new(user_base_token_account.key(), false),
-        AccountMeta::new(user_quote_token_account.key(), false),
-        AccountMeta::new(pool_base_token_account.key(), false),
-        AccountMeta::new(pool_quote_token_account.key(), false),
-        AccountMeta::new_readonly(protocol_fee_recipient.key(), false),
-        AccountMeta::new(protocol_fee_recipient_token_account.key(), false),
-        AccountMeta::new_readonly(base_token_program.key(), false),
-        AccountMeta::new_readonly(quote_token_program.key(), false),
-        AccountMeta::new_readonly(system_program.key(), false),
-        AccountMeta::new_readonly(associated_token_program.key(), false),
-        AccountMeta::new_readonly(event_authority.key(), false),
-        AccountMeta::new_readonly(program.key(), false),
-    ];
-
-    // accounts for the instruction
-    let mut accounts_vec = vec![
-        pool,
-        user,
-        global_config,
-        base_mint,
-        quote_mint,
-        user_base_token_account,
-        user_quote_token_account,
-        pool_base_token_account,
-        pool_quote_token_account,
-        protocol_fee_recipient,
-        protocol_fee_recipient_token_account,
-        base_token_program,
-        quote_token_program,
-        system_program,
-        associated_token_program,
-        event_authority,
-        program,
-    ];
-
-    // Add coin creator accounts if provided
-    if let Some(coin_creator_vault_ata) = &ctx.accounts.coin_creator_vault_ata {
-        account_metas.push(AccountMeta::new(coin_creator_vault_ata.key(), false));
-        accounts_vec.push(coin_creator_vault_ata.to_account_info());
-    }
-
-    if let Some(coin_creator_vault_authority) = &ctx.accounts.coin_creator_vault_authority {
-        account_metas.push(AccountMeta::new_readonly(
-            coin_creator_vault_authority.key(),
-            false,
-        ));
-        accounts_vec.push(coin_creator_vault_authority.to_account_info());
-    }
-
     // Create the instruction with all accounts
     let buy_ix = Instruction {
         program_id: pump_program.key(),
@@ -275,31 +187,215 @@ pub fn pumpswap_backrun_sell(
     // Invoke the PumpSwap buy instruction
     invoke_signed(&buy_ix, &accounts_vec, &[])?;
 
-    // Calculate profit
+    // Calculate profit from the balance the buy left
+    ctx.accounts.user_base_token_account.reload()?;
     let base_balance_after = ctx.accounts.user_base_token_account.amount;
     let backrun_output_amount = base_balance_after.saturating_sub(base_balance_before);
-    
-    // For sell backrun, the profit is calculated by comparing what we put in initially 
-    // vs what we got back after the complete sandwich
-    let profit = if backrun_output_amount <= sandwich_state.frontrun_input_amount {
-        // If we spent less than our initial input, then it's pure profit
-        sandwich_state.frontrun_input_amount.saturating_sub(backrun_output_amount)
-    } else {
-        // If we spent more, it's a loss
-        0
-    };
 
     // Update the sandwich state to complete
     sandwich_state.is_complete = true;
-    
+
+    // The buy takes back the base the frontrun sold, buying back less of it than was
+    // sold is a loss. The buy isn't simulated, so there is no expected profit to report
+    let event = SandwichCompleteEvent::new(
+        sandwich_state.sandwich_id,
+        sandwich_state.frontrun_input_amount,
+        backrun_output_amount,
+        None,
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
+
     // Emit sandwich complete event
-    emit!(SandwichCompleteEvent {
-        sandwich_id: sandwich_state.sandwich_id,
-        profit,
-        input_amount: sandwich_state.frontrun_input_amount,
-        output_amount: backrun_output_amount,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+    emit_cpi!(event);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::pumpswap::pump_amm::tests::{
+        pumpswap_backrun_accounts, pumpswap_swap_accounts,
+    };
+    use crate::instructions::pumpswap::{
+        protocol_fee_recipient_ata, PumpAmm, PumpSwapBackrunContextBumps,
+    };
+    use crate::instructions::raydium::cpmm::swap_base_output::tests::{mint, token_amount};
+    use crate::program_config::tests::config;
+    use crate::sandwich_state::tests::state_with_mints;
+    use crate::test_utils::{on_invoke, set_clock, syscall_stubs, take_invoked};
+    use anchor_lang::solana_program::entrypoint::ProgramResult;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_spl::token::spl_token;
+    use std::collections::BTreeSet;
+
+    // Stands in for a PumpSwap buy filling exactly: the user gets the base amount out
+    // and pays the whole of the quote cap for it
+    fn pumpswap_buy(instruction: &Instruction, accounts: &[AccountInfo]) -> ProgramResult {
+        if instruction.program_id != PumpAmm::id() {
+            return Ok(());
+        }
+        let argument =
+            |at: usize| u64::from_le_bytes(instruction.data[at..at + 8].try_into().unwrap());
+        // The user's base and quote token accounts
+        for (index, amount, credit) in [(5, argument(8), true), (6, argument(16), false)] {
+            let key = instruction.accounts[index].pubkey;
+            let account = accounts.iter().find(|account| *account.key == key).unwrap();
+            let mut data = account.try_borrow_mut_data()?;
+            let mut token = spl_token::state::Account::unpack(&data)?;
+            token.amount = if credit {
+                token.amount + amount
+            } else {
+                token.amount - amount
+            };
+            token.pack_into_slice(&mut data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn a_profitable_sell_backrun_records_a_positive_outcome() {
+        let _stubs = syscall_stubs();
+        // Two slots and ten seconds after the frontrun recorded in the state
+        set_clock(42, 110);
+        on_invoke(pumpswap_buy);
+
+        // The sell frontrun paid 500 base for 1_000 quote, all still held, after two losses
+        let (base_mint, quote_mint) = (mint(), mint());
+        let state = state_with_mints(base_mint.key(), quote_mint.key());
+        let mut program_config = config(3);
+        program_config.consecutive_losses = 2;
+
+        let user = Pubkey::new_unique();
+        let sandwich_id = state.sandwich_id;
+        let accounts = pumpswap_backrun_accounts(
+            user,
+            sandwich_id,
+            pumpswap_swap_accounts(
+                user,
+                (base_mint, quote_mint),
+                (0, 1_000),
+                protocol_fee_recipient_ata,
+            ),
+            state,
+            program_config,
+        );
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        let mut bumps = PumpSwapBackrunContextBumps::default();
+        let mut backrun = PumpSwapBackrunContext::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &sandwich_id.to_le_bytes(),
+            &mut bumps,
+            &mut BTreeSet::new(),
+        )
+        .unwrap();
+        let user_base_token_account = backrun.user_base_token_account.to_account_info();
+        let ctx = Context::new(&crate::ID, &mut backrun, &[], bumps);
+        pumpswap_backrun_sell(ctx, sandwich_id, 1, 0, 0, 0).unwrap();
+
+        // The buy takes back the 500 base sold plus the minimum profit, then the event self-CPI
+        let invoked = take_invoked();
+        assert_eq!(invoked.len(), 2);
+        assert_eq!(invoked[0].0.data[8..16], 502u64.to_le_bytes());
+        assert_eq!(token_amount(&user_base_token_account), 502);
+
+        // The 2 base gained is a profit, which ends the losing streak
+        assert!(backrun.sandwich_state.is_complete);
+        assert_eq!(backrun.sandwich_state.realized_profit, 2);
+        assert_eq!(backrun.config.consecutive_losses, 0);
+        assert!(!backrun.config.paused);
+    }
+
+    #[test]
+    fn a_backrun_in_the_frontrun_slot_is_rejected() {
+        let _stubs = syscall_stubs();
+        let (base_mint, quote_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // A buy frontrun paid quote for base in slot 40
+        let state = state_with_mints(quote_mint, base_mint);
+
+        set_clock(40, 100);
+        assert_eq!(
+            check_pumpswap_backrun(&state, &base_mint, &quote_mint, 1, 0).unwrap_err(),
+            error!(ErrorCode::BackrunTooEarly)
+        );
+        // Without a gap the same slot goes through, as does the next one with it
+        assert!(check_pumpswap_backrun(&state, &base_mint, &quote_mint, 0, 0).is_ok());
+        set_clock(41, 100);
+        assert!(check_pumpswap_backrun(&state, &base_mint, &quote_mint, 1, 0).is_ok());
+
+        // Selling the mint the frontrun paid with never unwinds it
+        assert_eq!(
+            check_pumpswap_backrun(&state, &quote_mint, &base_mint, 1, 0).unwrap_err(),
+            error!(ErrorCode::TokenMintMismatch)
+        );
+    }
+
+    #[test]
+    fn a_backrun_of_a_state_past_its_max_age_is_rejected() {
+        let _stubs = syscall_stubs();
+        let (base_mint, quote_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // A buy frontrun stamped at 100
+        let state = state_with_mints(quote_mint, base_mint);
+
+        // A day later it's still fresh, a second more is past the default age
+        set_clock(41, 100 + 86_400);
+        assert!(check_pumpswap_backrun(&state, &base_mint, &quote_mint, 1, 0).is_ok());
+        set_clock(41, 100 + 86_401);
+        assert_eq!(
+            check_pumpswap_backrun(&state, &base_mint, &quote_mint, 1, 0).unwrap_err(),
+            error!(ErrorCode::SandwichExpired)
+        );
+        // An explicit age overrides the default either way
+        assert!(check_pumpswap_backrun(&state, &base_mint, &quote_mint, 1, 86_401).is_ok());
+        set_clock(41, 160);
+        assert_eq!(
+            check_pumpswap_backrun(&state, &base_mint, &quote_mint, 1, 30).unwrap_err(),
+            error!(ErrorCode::SandwichExpired)
+        );
+    }
+
+    #[test]
+    fn the_backrun_asks_for_the_cost_plus_the_minimum_profit() {
+        let wsol = spl_token::native_mint::ID;
+        let token = Pubkey::new_unique();
+
+        // 0.5% over a 1_000_000 frontrun, rather than the 10% of it the sell once took
+        assert_eq!(
+            pumpswap_backrun_min_output(1_000_000, &token, 0, 0).unwrap(),
+            1_005_000
+        );
+        // A WSOL profit also covers the lamports paid to land the sandwich
+        assert_eq!(
+            pumpswap_backrun_min_output(1_000_000, &wsol, 20_000, 0).unwrap(),
+            1_025_000
+        );
+        // The fixed cost is in lamports, a profit in another mint is left alone
+        assert_eq!(
+            pumpswap_backrun_min_output(1_000_000, &token, 20_000, 0).unwrap(),
+            1_005_000
+        );
+    }
+
+    #[test]
+    fn a_tiny_profit_short_of_the_minimum_amount_is_rejected() {
+        let token = Pubkey::new_unique();
+
+        // 10% on a 100 token frontrun clears the 0.5% floor, but not a 1_000 token minimum
+        let floor = pumpswap_backrun_min_output(100, &token, 0, 1_000).unwrap();
+        assert_eq!(floor, 1_100);
+        assert!(110 < floor);
+
+        // On a large frontrun the relative floor is the higher one
+        assert_eq!(
+            pumpswap_backrun_min_output(1_000_000, &token, 0, 1_000).unwrap(),
+            1_005_000
+        );
+    }
+}
@@ -3,9 +3,16 @@ use anchor_lang::solana_program::{instruction::Instruction, program::invoke_sign
 use anchor_spl::associated_token::get_associated_token_address;
 
 use crate::error::ErrorCode;
+use crate::program_config::{log_frontrun_compute_units, log_victim_amounts};
+use crate::sandwich_state::{
+    safe_slippage_pips, self_slippage_bps_or_default, slippage_pips, SandwichState,
+    MIN_SANDWICH_PROFIT, PIPS_DENOMINATOR,
+};
 use crate::instructions::{get_transfer_fee, CurveCalculator, Fees};
 
-use super::PumpSwapContext;
+use super::{
+    build_pumpswap_accounts, coin_creator_fee_rate, pumpswap_frontrun_mints, PumpSwapContext,
+};
 
 // Buy instruction data structure
 #[derive(AnchorSerialize)]
@@ -27,30 +34,25 @@ pub fn pumpswap_frontrun_buy(
     ctx: Context<PumpSwapContext>,
     base_amount_out: u64,
     max_quote_amount_in: u64,
-    sandwich_id: u64
+    sandwich_id: u64,
+    self_slippage_bps: u16,
+    require_victim_ix: Option<Pubkey>,
 ) -> Result<()> {
+    log_frontrun_compute_units("PumpSwap");
+
+    // Shared context with the backruns, so the pause, allowlist and blocklist are enforced here
+    ctx.accounts.guards.check(&ctx.accounts.pool.key())?;
+    ctx.accounts.guards.check_mints(
+        &ctx.accounts.quote_mint.key(),
+        &ctx.accounts.base_mint.key(),
+    )?;
+
+    // Only frontrun a victim landing later in the same transaction, when one is named
+    ctx.accounts.guards.check_victim_ix(require_victim_ix)?;
+
     // Get accounts needed for the CPI
     let pump_program = ctx.accounts.pump_amm_program.to_account_info();
     let pool = ctx.accounts.pool.to_account_info();
-    let user = ctx.accounts.user.to_account_info();
-    let global_config = ctx.accounts.global_config.to_account_info();
-    let base_mint = ctx.accounts.base_mint.to_account_info();
-    let quote_mint = ctx.accounts.quote_mint.to_account_info();
-    let user_base_token_account = ctx.accounts.user_base_token_account.to_account_info();
-    let user_quote_token_account = ctx.accounts.user_quote_token_account.to_account_info();
-    let pool_base_token_account = ctx.accounts.pool_base_token_account.to_account_info();
-    let pool_quote_token_account = ctx.accounts.pool_quote_token_account.to_account_info();
-    let protocol_fee_recipient = ctx.accounts.protocol_fee_recipient.to_account_info();
-    let protocol_fee_recipient_token_account = ctx
-        .accounts
-        .protocol_fee_recipient_token_account
-        .to_account_info();
-    let base_token_program = ctx.accounts.base_token_program.to_account_info();
-    let quote_token_program = ctx.accounts.quote_token_program.to_account_info();
-    let system_program = ctx.accounts.system_program.to_account_info();
-    let associated_token_program = ctx.accounts.associated_token_program.to_account_info();
-    let event_authority = ctx.accounts.event_authority.to_account_info();
-    let program = ctx.accounts.program.to_account_info();
     
     let pool_state = &mut ctx.accounts.pool.load_mut()?;
     
@@ -82,36 +84,53 @@ pub fn pumpswap_frontrun_buy(
     )?;
     let target_actual_amount_in = max_quote_amount_in.saturating_sub(target_transfer_fee);
     let global_config_data = ctx.accounts.global_config.load()?;
+
+    // PumpSwap only takes the creator fee when the creator vault is passed
+    let coin_creator_fee_rate = coin_creator_fee_rate(
+        global_config_data.coin_creator_fee_basis_points,
+        ctx.accounts.coin_creator_vault_ata.is_some(),
+    );
     
     let expected_target_output = calculate_expected_output(
         target_actual_amount_in,
         total_input_amount,
         total_output_amount,
-        global_config_data.coin_creator_fee_basis_points * 100u64,
+        coin_creator_fee_rate,
         global_config_data.protocol_fee_basis_points * 100u64,
         global_config_data.lp_fee_basis_points * 100u64,
     )?;
     
-    let target_slippage_bps = if expected_target_output > 0 {
-        // Calculate as basis points (10000 = 100%)
-        ((expected_target_output.saturating_sub(base_amount_out)) as u128 * 10000)
-            / (expected_target_output as u128)
-    } else {
-        return err!(ErrorCode::CalculationFailure);
-    };
+    // In pips, a sub-bip tolerance isn't truncated away
+    let target_slippage_pips = slippage_pips(
+        expected_target_output.saturating_sub(base_amount_out),
+        expected_target_output,
+    )?;
+    log_victim_amounts(
+        "PumpSwap",
+        target_actual_amount_in,
+        expected_target_output,
+        target_slippage_pips,
+    );
     
-    let safe_slippage_bps = target_slippage_bps.saturating_mul(95).saturating_div(100);
+    let safe_slippage_pips = safe_slippage_pips(
+        target_slippage_pips,
+        ctx.accounts
+            .guards
+            .config
+            .max_absolute_slippage_consumed_bps,
+    );
 
     // Calculate optimal sandwich buy amount with improved profit calculation
     let optimal_buy_amount = calculate_optimal_sandwich_amount(
         total_input_amount,
         total_output_amount,
-        safe_slippage_bps,
+        safe_slippage_pips,
         max_quote_amount_in,
         target_actual_amount_in,
-        global_config_data.coin_creator_fee_basis_points * 100u64,
+        coin_creator_fee_rate,
         global_config_data.protocol_fee_basis_points * 100u64,
         global_config_data.lp_fee_basis_points * 100u64,
+        MIN_SANDWICH_PROFIT,
     )?;
     
     if optimal_buy_amount < 100 {
@@ -127,10 +146,18 @@ pub fn pumpswap_frontrun_buy(
         optimal_buy_amount,
         total_input_amount,
         total_output_amount,
-        global_config_data.coin_creator_fee_basis_points * 100u64,
+        coin_creator_fee_rate,
         global_config_data.protocol_fee_basis_points * 100u64,
         global_config_data.lp_fee_basis_points * 100u64,
+        self_slippage_bps,
     )?;
+
+    // Hold the frontrun to the configured notional cap
+    let (optimal_buy_amount, minimum_out_for_sandwich) = ctx
+        .accounts
+        .guards
+        .config
+        .cap_notional(optimal_buy_amount, minimum_out_for_sandwich)?;
     
     // Create the instruction data for the buy instruction
     let ix_data = PumpSwapBuy {
@@ -139,61 +166,8 @@ pub fn pumpswap_frontrun_buy(
     }
     .data();
 
-    // Create the buy instruction for PumpSwap
-    // First create the account metas for the required accounts
-    let mut account_metas = vec![
-        AccountMeta::new(pool.key(), false),
-        AccountMeta::new(user.key(), true),
-        AccountMeta::new_readonly(global_config.key(), false),
-        AccountMeta::new_readonly(base_mint.key(), false),
-        AccountMeta::new_readonly(quote_mint.key(), false),
-        AccountMeta::new(user_base_token_account.key(), false),
-        AccountMeta::new(user_quote_token_account.key(), false),
-        AccountMeta::new(pool_base_token_account.key(), false),
-        AccountMeta::new(pool_quote_token_account.key(), false),
-        AccountMeta::new_readonly(protocol_fee_recipient.key(), false),
-        AccountMeta::new(protocol_fee_recipient_token_account.key(), false),
-        AccountMeta::new_readonly(base_token_program.key(), false),
-        AccountMeta::new_readonly(quote_token_program.key(), false),
-        AccountMeta::new_readonly(system_program.key(), false),
-        AccountMeta::new_readonly(associated_token_program.key(), false),
-        AccountMeta::new_readonly(event_authority.key(), false),
-        AccountMeta::new_readonly(program.key(), false),
-    ];
-
-    // accounts for the instruction
-    let mut accounts_vec = vec![
-        pool,
-        user,
-        global_config,
-        base_mint,
-        quote_mint,
-        user_base_token_account,
-        user_quote_token_account,
-        pool_base_token_account,
-        pool_quote_token_account,
-        protocol_fee_recipient,
-        protocol_fee_recipient_token_account,
-        base_token_program,
-        quote_token_program,
-        system_program,
-        associated_token_program,
-        event_authority,
-        program,
-    ];
-    // Add coin creator accounts if provided
-    if let Some(coin_creator_vault_ata) = &ctx.accounts.coin_creator_vault_ata {
-        account_metas.push(AccountMeta::new(coin_creator_vault_ata.key(), false));
-        accounts_vec.push(coin_creator_vault_ata.to_account_info());
-    }
-
-    if let Some(coin_creator_vault_authority) = &ctx.accounts.coin_creator_vault_authority {
-        account_metas.push(AccountMeta::new_readonly(
-            coin_creator_vault_authority.key(),
-            false,
-        ));
-        accounts_vec.push(coin_creator_vault_authority.to_account_info());
-    }
+    // Create the buy instruction for PumpSwap, its accounts are shared with every PumpSwap CPI
+    let (account_metas, accounts_vec) = build_pumpswap_accounts(&ctx.accounts);
 
     // Create the instruction with conditional accounts
     let buy_ix = Instruction {
@@ -210,15 +184,19 @@ pub fn pumpswap_frontrun_buy(
         output_token_balance_after.saturating_sub(output_token_balance_before);
 
     // Store frontrun data in the PDA for the backrun to read
-    let sandwich_state = &mut ctx.accounts.sandwich_state;
-    sandwich_state.frontrun_output_amount = frontrun_output_amount;
-    sandwich_state.frontrun_input_amount = optimal_buy_amount;
-    sandwich_state.sandwich_id = sandwich_id;
-    sandwich_state.token_in_mint = *ctx.accounts.base_mint.to_account_info().key;
-    sandwich_state.token_out_mint = *ctx.accounts.quote_mint.to_account_info().key;
-    sandwich_state.timestamp = Clock::get()?.unix_timestamp;
-    sandwich_state.is_complete = false;
-    sandwich_state.bump = ctx.bumps.sandwich_state;
+    let (token_in_mint, token_out_mint) = pumpswap_frontrun_mints(
+        ctx.accounts.base_mint.key(),
+        ctx.accounts.quote_mint.key(),
+        true,
+    );
+    ctx.accounts.sandwich_state.record_frontrun(
+        optimal_buy_amount,
+        frontrun_output_amount,
+        token_in_mint,
+        token_out_mint,
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
     
     Ok(())
 }
@@ -227,12 +205,13 @@ pub fn pumpswap_frontrun_buy(
 fn calculate_optimal_sandwich_amount(
     reserve_in: u64,
     reserve_out: u64,
-    safe_slippage_bps: u128,
+    safe_slippage_pips: u128,
     _target_amount_in: u64,
     target_actual_amount_in: u64,
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    min_profit: u128,
 ) -> Result<u64> {
     // Convert to u128 for safer math
     let reserve_in = reserve_in as u128;
@@ -296,11 +275,11 @@ fn calculate_optimal_sandwich_amount(
         .destination_amount_swapped;
 
         // Check if target tx will still execute within slippage
-        let price_impact_bps = ((target_expected_output_before - target_expected_output_after)
-            * 10000)
+        let price_impact_pips = ((target_expected_output_before - target_expected_output_after)
+            * PIPS_DENOMINATOR)
             / target_expected_output_before;
 
-        let within_slippage = price_impact_bps <= safe_slippage_bps;
+        let within_slippage = price_impact_pips <= safe_slippage_pips;
 
         // If target would fail due to slippage, this attack size doesn't work
         if !within_slippage {
@@ -341,6 +320,9 @@ fn calculate_optimal_sandwich_amount(
         }
     }
 
+    // The initial estimate stands unchecked unless some trial beat it
+    require!(best_profit >= min_profit, ErrorCode::UnprofitableSandwich);
+
     // Convert best amount to u64 and return
     let result = best_amount.try_into().unwrap_or(u64::MAX);
 
@@ -354,6 +336,7 @@ fn calculate_minimum_out_for_sandwich(
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    self_slippage_bps: u16,
 ) -> Result<u64> {
     // Calculate expected output
     let expected_out = calculate_expected_output(
@@ -365,8 +348,9 @@ fn calculate_minimum_out_for_sandwich(
         fund_fee_rate,
     )?;
 
-    // Apply aggressive slippage tolerance (5%)
-    let min_out = expected_out.saturating_mul(95).saturating_div(100);
+    // Apply our own slippage tolerance (5% by default)
+    let slippage_bps = self_slippage_bps_or_default(self_slippage_bps)?;
+    let min_out = ((expected_out as u128) * (10_000 - slippage_bps) / 10_000) as u64;
 
     Ok(min_out)
 }
@@ -1,17 +1,20 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::pubkey::Pubkey;
 use anchor_spl::{
-    associated_token::AssociatedToken,
+    associated_token::{get_associated_token_address, AssociatedToken},
     token::{Mint, Token, TokenAccount},
 };
 
-use crate::{instructions::{PumpSwapGlobalConfig, PumpSwapPoolState}, sandwich_state::SandwichState};
+use crate::{error::ErrorCode, instructions::{PumpSwapGlobalConfig, PumpSwapPoolState}, program_config::*, sandwich_state::SandwichState};
 
 // PumpSwap program ID
 pub const PUMP_AMM_PROGRAM_ID: &str = "pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA";
 
+/// PumpSwap's `__event_authority` PDA, stored rather than derived on every instruction
+pub const PUMP_AMM_EVENT_AUTHORITY: &str = "GS4CU59F31iL7aR2Q8zVS8DRrcRnXX1yjQ66TqNVQnaR";
+
 #[derive(Accounts)]
-#[instruction(sandwich_id: u64)]
+#[instruction(_base_amount: u64, _quote_amount: u64, sandwich_id: u64)]
 pub struct PumpSwapContext<'info> {
     /// The pump amm program
     #[account(address = PUMP_AMM_PROGRAM_ID.parse::<Pubkey>().unwrap())]
@@ -50,11 +53,19 @@ pub struct PumpSwapContext<'info> {
     #[account(mut)]
     pub pool_quote_token_account: Box<Account<'info, TokenAccount>>,
 
-    /// CHECK: Protocol fee recipient, verified by PumpSwap during CPI
+    /// CHECK: Protocol fee recipient, must be one of the global config's fee recipients
+    #[account(
+        constraint = global_config.load()?.is_protocol_fee_recipient(&protocol_fee_recipient.key())
+            @ ErrorCode::InvalidFeeRecipient
+    )]
     pub protocol_fee_recipient: AccountInfo<'info>,
 
-    /// CHECK: Protocol fee recipient token account, verified by PumpSwap during CPI
-    #[account(mut)]
+    /// CHECK: Protocol fee recipient's quote token ATA, checked against the derived address
+    #[account(
+        mut,
+        address = protocol_fee_recipient_ata(&protocol_fee_recipient.key(), &quote_mint.key())
+            @ ErrorCode::InvalidFeeRecipientAta
+    )]
     pub protocol_fee_recipient_token_account: AccountInfo<'info>,
 
     /// Token program for the base token
@@ -69,11 +80,8 @@ pub struct PumpSwapContext<'info> {
     /// Associated token program
     pub associated_token_program: Program<'info, AssociatedToken>,
 
-    /// CHECK: Event authority account for PumpSwap, verified by CPI
-    pub event_authority: AccountInfo<'info>,
-
-    /// CHECK: PumpSwap program account for the CPI
-    pub program: AccountInfo<'info>,
+    /// PumpSwap's event authority and program, checked before the CPI
+    pub event_cpi: PumpSwapEventCpi<'info>,
 
     /// CHECK: Coin creator vault ATA, optional account for creator fees
     #[account(mut)]
@@ -87,10 +95,272 @@ pub struct PumpSwapContext<'info> {
        init_if_needed,
        payer = user,
        space = 8 + SandwichState::SIZE,
-       seeds = [b"sandwich", &sandwich_id.to_le_bytes()],
+       seeds = [b"sandwich", user.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump
    )]
     pub sandwich_state: Account<'info, SandwichState>,
+
+    /// Program config and the allowlist marker it enforces, checked by the frontruns
+    pub guards: FrontrunGuards<'info>,
+}
+
+/// The PumpSwap accounts of a frontrun, in the same order, with the sandwich state it
+/// opened and the writable config in place of the frontrun guards.
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(sandwich_id: u64)]
+pub struct PumpSwapBackrunContext<'info> {
+    /// The pump amm program
+    #[account(address = PUMP_AMM_PROGRAM_ID.parse::<Pubkey>().unwrap())]
+    pub pump_amm_program: Program<'info, PumpAmm>,
+
+    /// CHECK: This is the pool account from PumpSwap, verified by CPI
+    #[account(mut)]
+    pub pool: AccountLoader<'info, PumpSwapPoolState>,
+
+    /// The user making the swap
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: This is the global config account from PumpSwap, verified by CPI
+    pub global_config: AccountLoader<'info, PumpSwapGlobalConfig>,
+
+    /// Base token mint (the token being bought or sold)
+    pub base_mint: Box<Account<'info, Mint>>,
+
+    /// Quote token mint (typically a stablecoin or major token)
+    pub quote_mint: Box<Account<'info, Mint>>,
+
+    /// User's base token account
+    #[account(mut)]
+    pub user_base_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// User's quote token account
+    #[account(mut)]
+    pub user_quote_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// Pool's base token account
+    #[account(mut)]
+    pub pool_base_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// Pool's quote token account
+    #[account(mut)]
+    pub pool_quote_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Protocol fee recipient, must be one of the global config's fee recipients
+    #[account(
+        constraint = global_config.load()?.is_protocol_fee_recipient(&protocol_fee_recipient.key())
+            @ ErrorCode::InvalidFeeRecipient
+    )]
+    pub protocol_fee_recipient: AccountInfo<'info>,
+
+    /// CHECK: Protocol fee recipient's quote token ATA, checked against the derived address
+    #[account(
+        mut,
+        address = protocol_fee_recipient_ata(&protocol_fee_recipient.key(), &quote_mint.key())
+            @ ErrorCode::InvalidFeeRecipientAta
+    )]
+    pub protocol_fee_recipient_token_account: AccountInfo<'info>,
+
+    /// Token program for the base token
+    pub base_token_program: Program<'info, Token>,
+
+    /// Token program for the quote token
+    pub quote_token_program: Program<'info, Token>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+
+    /// Associated token program
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// PumpSwap's event authority and program, checked before the CPI
+    pub event_cpi: PumpSwapEventCpi<'info>,
+
+    /// CHECK: Coin creator vault ATA, optional account for creator fees
+    #[account(mut)]
+    pub coin_creator_vault_ata: Option<AccountInfo<'info>>,
+
+    /// CHECK: Coin creator vault authority, optional PDA for creator fees
+    pub coin_creator_vault_authority: Option<AccountInfo<'info>>,
+
+    /// The account that stores sandwich state
+    #[account(
+       mut,
+       seeds = [b"sandwich", user.key().as_ref(), &sandwich_id.to_le_bytes()],
+       bump = sandwich_state.bump
+    )]
+    pub sandwich_state: Account<'info, SandwichState>,
+
+    /// Program config, tracks the losing streak for the circuit breaker
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+/// The event-CPI accounts PumpSwap takes last: its event-authority PDA and the program
+/// itself. A wrong program would route the inner instruction elsewhere.
+#[derive(Accounts)]
+pub struct PumpSwapEventCpi<'info> {
+    /// CHECK: PumpSwap's `__event_authority` PDA
+    #[account(address = pump_amm_event_authority() @ ErrorCode::InvalidEventAuthority)]
+    pub event_authority: AccountInfo<'info>,
+
+    /// CHECK: PumpSwap program account for the CPI
+    #[account(address = PumpAmm::id())]
+    pub program: AccountInfo<'info>,
+}
+
+/// PumpSwap's event-authority PDA, which its instructions self-CPI through to log events.
+pub fn pump_amm_event_authority() -> Pubkey {
+    PUMP_AMM_EVENT_AUTHORITY.parse::<Pubkey>().unwrap()
+}
+
+/// The quote token ATA PumpSwap pays `protocol_fee_recipient`'s fee into.
+pub fn protocol_fee_recipient_ata(protocol_fee_recipient: &Pubkey, quote_mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(protocol_fee_recipient, quote_mint)
+}
+
+/// `(token_in_mint, token_out_mint)` a PumpSwap frontrun records: a buy pays the quote
+/// mint for the base mint, a sell pays the base mint for the quote mint.
+pub fn pumpswap_frontrun_mints(
+    base_mint: Pubkey,
+    quote_mint: Pubkey,
+    is_buy: bool,
+) -> (Pubkey, Pubkey) {
+    if is_buy {
+        (quote_mint, base_mint)
+    } else {
+        (base_mint, quote_mint)
+    }
+}
+
+/// Coin-creator fee rate a PumpSwap swap pays, in the curve's 1e6 denominator. PumpSwap
+/// only charges it when the coin creator vault is passed, so without one it is 0.
+pub fn coin_creator_fee_rate(
+    coin_creator_fee_basis_points: u64,
+    has_coin_creator_vault: bool,
+) -> u64 {
+    if !has_coin_creator_vault {
+        return 0;
+    }
+    coin_creator_fee_basis_points * 100
+}
+
+/// Writable flag of each account PumpSwap's buy and sell both take, in their order: the
+/// pool, the user, the global config, the base and quote mints, the user's and then the
+/// pool's base and quote token accounts, the fee recipient and its quote ATA, the base and
+/// quote token programs, the system and associated token programs, then the event accounts.
+const PUMPSWAP_ACCOUNTS_WRITABLE: [bool; 17] = [
+    true, true, false, false, false, true, true, true, true, false, true, false, false, false,
+    false, false, false,
+];
+
+/// Position of the user, the only signer, in `PUMPSWAP_ACCOUNTS_WRITABLE`.
+const PUMPSWAP_USER_INDEX: usize = 1;
+
+/// Account metas and infos of a PumpSwap buy or sell CPI, the optional creator accounts
+/// appended when given. Both instructions and both legs of the sandwich build them here,
+/// see `build_pumpswap_backrun_accounts`, so their orderings can't drift apart.
+pub fn build_pumpswap_accounts<'info>(
+    accounts: &PumpSwapContext<'info>,
+) -> (Vec<AccountMeta>, Vec<AccountInfo<'info>>) {
+    pumpswap_cpi_accounts(
+        [
+            accounts.pool.to_account_info(),
+            accounts.user.to_account_info(),
+            accounts.global_config.to_account_info(),
+            accounts.base_mint.to_account_info(),
+            accounts.quote_mint.to_account_info(),
+            accounts.user_base_token_account.to_account_info(),
+            accounts.user_quote_token_account.to_account_info(),
+            accounts.pool_base_token_account.to_account_info(),
+            accounts.pool_quote_token_account.to_account_info(),
+            accounts.protocol_fee_recipient.to_account_info(),
+            accounts
+                .protocol_fee_recipient_token_account
+                .to_account_info(),
+            accounts.base_token_program.to_account_info(),
+            accounts.quote_token_program.to_account_info(),
+            accounts.system_program.to_account_info(),
+            accounts.associated_token_program.to_account_info(),
+            accounts.event_cpi.event_authority.to_account_info(),
+            accounts.event_cpi.program.to_account_info(),
+        ],
+        accounts.coin_creator_vault_ata.clone(),
+        accounts.coin_creator_vault_authority.clone(),
+    )
+}
+
+/// `build_pumpswap_accounts` for a backrun, same accounts in the same order.
+pub fn build_pumpswap_backrun_accounts<'info>(
+    accounts: &PumpSwapBackrunContext<'info>,
+) -> (Vec<AccountMeta>, Vec<AccountInfo<'info>>) {
+    pumpswap_cpi_accounts(
+        [
+            accounts.pool.to_account_info(),
+            accounts.user.to_account_info(),
+            accounts.global_config.to_account_info(),
+            accounts.base_mint.to_account_info(),
+            accounts.quote_mint.to_account_info(),
+            accounts.user_base_token_account.to_account_info(),
+            accounts.user_quote_token_account.to_account_info(),
+            accounts.pool_base_token_account.to_account_info(),
+            accounts.pool_quote_token_account.to_account_info(),
+            accounts.protocol_fee_recipient.to_account_info(),
+            accounts
+                .protocol_fee_recipient_token_account
+                .to_account_info(),
+            accounts.base_token_program.to_account_info(),
+            accounts.quote_token_program.to_account_info(),
+            accounts.system_program.to_account_info(),
+            accounts.associated_token_program.to_account_info(),
+            accounts.event_cpi.event_authority.to_account_info(),
+            accounts.event_cpi.program.to_account_info(),
+        ],
+        accounts.coin_creator_vault_ata.clone(),
+        accounts.coin_creator_vault_authority.clone(),
+    )
+}
+
+fn pumpswap_cpi_accounts<'info>(
+    required: [AccountInfo<'info>; 17],
+    coin_creator_vault_ata: Option<AccountInfo<'info>>,
+    coin_creator_vault_authority: Option<AccountInfo<'info>>,
+) -> (Vec<AccountMeta>, Vec<AccountInfo<'info>>) {
+    let mut account_metas: Vec<AccountMeta> = required
+        .iter()
+        .zip(PUMPSWAP_ACCOUNTS_WRITABLE)
+        .enumerate()
+        .map(|(index, (account, is_writable))| {
+            let is_signer = index == PUMPSWAP_USER_INDEX;
+            if is_writable {
+                AccountMeta::new(account.key(), is_signer)
+            } else {
+                AccountMeta::new_readonly(account.key(), is_signer)
+            }
+        })
+        .collect();
+    let mut accounts_vec = required.to_vec();
+
+    // Add coin creator accounts if provided
+    if let Some(coin_creator_vault_ata) = coin_creator_vault_ata {
+        account_metas.push(AccountMeta::new(coin_creator_vault_ata.key(), false));
+        accounts_vec.push(coin_creator_vault_ata);
+    }
+
+    if let Some(coin_creator_vault_authority) = coin_creator_vault_authority {
+        account_metas.push(AccountMeta::new_readonly(
+            coin_creator_vault_authority.key(),
+            false,
+        ));
+        accounts_vec.push(coin_creator_vault_authority);
+    }
+
+    (account_metas, accounts_vec)
 }
 
 #[derive(Clone)]
@@ -101,3 +371,348 @@ impl anchor_lang::Id for PumpAmm {
         PUMP_AMM_PROGRAM_ID.parse::<Pubkey>().unwrap()
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::instructions::raydium::cpmm::swap_base_output::tests::{mint, token_account};
+    use crate::program_config::tests::config;
+    use crate::sandwich_state::tests::state_with_mints;
+    use crate::test_utils::{account_info, program, program_account, syscall_stubs};
+    use std::collections::BTreeSet;
+
+    // An empty account at `key`, the CPI accounts are only passed through by key
+    pub(crate) fn unchecked(key: Pubkey) -> AccountInfo<'static> {
+        account_info(key, Pubkey::default(), vec![], false, false)
+    }
+
+    fn try_event_cpi(event_authority: Pubkey, program: Pubkey) -> Result<()> {
+        let accounts: &'static [AccountInfo<'static>] =
+            vec![unchecked(event_authority), unchecked(program)].leak();
+        PumpSwapEventCpi::try_accounts(
+            &crate::ID,
+            &mut &accounts[..],
+            &[],
+            &mut PumpSwapEventCpiBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+    }
+
+    // The PumpSwap accounts both legs of a sandwich take, from the program through the
+    // coin creator accounts, for `user` holding `user_base` and `user_quote` of the two
+    // mints and paying the protocol fee into `protocol_fee_recipient_token_account`. The
+    // pool and global config are owned by this program, as their loaders check
+    pub(crate) fn pumpswap_swap_accounts(
+        user: Pubkey,
+        (base_mint, quote_mint): (AccountInfo<'static>, AccountInfo<'static>),
+        (user_base, user_quote): (u64, u64),
+        protocol_fee_recipient_token_account: impl FnOnce(&Pubkey, &Pubkey) -> Pubkey,
+    ) -> Vec<AccountInfo<'static>> {
+        let protocol_fee_recipient = Pubkey::new_unique();
+
+        let pool = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            vec![0u8; 8 + std::mem::size_of::<PumpSwapPoolState>()],
+            false,
+            false,
+        );
+        pool.try_borrow_mut_data().unwrap()[..8].copy_from_slice(&PumpSwapPoolState::DISCRIMINATOR);
+
+        let global_config = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            vec![0u8; 8 + std::mem::size_of::<PumpSwapGlobalConfig>()],
+            false,
+            false,
+        );
+        global_config.try_borrow_mut_data().unwrap()[..8]
+            .copy_from_slice(&PumpSwapGlobalConfig::DISCRIMINATOR);
+        let mut protocol_fee_recipients = [Pubkey::default(); 8];
+        protocol_fee_recipients[0] = protocol_fee_recipient;
+        *AccountLoader::<PumpSwapGlobalConfig>::try_from(Box::leak(Box::new(
+            global_config.clone(),
+        )))
+        .unwrap()
+        .load_mut()
+        .unwrap() = PumpSwapGlobalConfig {
+            protocol_fee_recipients,
+            ..Default::default()
+        };
+
+        // The program id stands in for each optional account left out
+        vec![
+            program(PumpAmm::id()),
+            pool,
+            account_info(user, Pubkey::default(), vec![], true, false),
+            global_config,
+            base_mint.clone(),
+            quote_mint.clone(),
+            token_account(base_mint.key(), user_base),
+            token_account(quote_mint.key(), user_quote),
+            token_account(base_mint.key(), 0),
+            token_account(quote_mint.key(), 0),
+            unchecked(protocol_fee_recipient),
+            unchecked(protocol_fee_recipient_token_account(
+                &protocol_fee_recipient,
+                &quote_mint.key(),
+            )),
+            program(anchor_spl::token::ID),
+            program(anchor_spl::token::ID),
+            program(anchor_lang::system_program::ID),
+            program(anchor_spl::associated_token::ID),
+            unchecked(pump_amm_event_authority()),
+            unchecked(PumpAmm::id()),
+            unchecked(crate::ID),
+            unchecked(crate::ID),
+        ]
+    }
+
+    // Every account of a PumpSwap frontrun paying the protocol fee into
+    // `protocol_fee_recipient_token_account`
+    fn try_pumpswap_accounts(
+        protocol_fee_recipient_token_account: impl FnOnce(&Pubkey, &Pubkey) -> Pubkey,
+    ) -> Result<()> {
+        // An open sandwich state, funded as the rent check of `init_if_needed` wants
+        let user = Pubkey::new_unique();
+        let sandwich_id: u64 = 7;
+        let (sandwich_key, _) = SandwichState::pda(&user, sandwich_id);
+        let sandwich_state = program_account(
+            sandwich_key,
+            8 + SandwichState::SIZE,
+            &state_with_mints(Pubkey::default(), Pubkey::default()),
+        );
+        **sandwich_state.try_borrow_mut_lamports().unwrap() =
+            Rent::default().minimum_balance(8 + SandwichState::SIZE);
+
+        let (config_key, config_bump) =
+            Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        let mut program_config = config(0);
+        program_config.bump = config_bump;
+
+        // The frontrun guards left out but for the config
+        let none = || unchecked(crate::ID);
+        let mut accounts = pumpswap_swap_accounts(
+            user,
+            (mint(), mint()),
+            (0, 0),
+            protocol_fee_recipient_token_account,
+        );
+        accounts.extend([
+            sandwich_state,
+            program_account(config_key, 8 + ProgramConfig::SIZE, &program_config),
+            none(),
+            none(),
+            none(),
+            none(),
+            none(),
+            none(),
+        ]);
+
+        // Base and quote amounts, then the sandwich id
+        let mut ix_data = vec![0u8; 16];
+        ix_data.extend_from_slice(&sandwich_id.to_le_bytes());
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        PumpSwapContext::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &ix_data,
+            &mut PumpSwapContextBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+    }
+
+    // Every account of a PumpSwap backrun by `user` of sandwich `sandwich_id`, left at
+    // `state` with `program_config` in force: the swap accounts, the sandwich state and the
+    // config, then the accounts of its own event CPI
+    pub(crate) fn pumpswap_backrun_accounts(
+        user: Pubkey,
+        sandwich_id: u64,
+        swap_accounts: Vec<AccountInfo<'static>>,
+        mut state: SandwichState,
+        mut program_config: ProgramConfig,
+    ) -> Vec<AccountInfo<'static>> {
+        let (sandwich_key, sandwich_bump) = SandwichState::pda(&user, sandwich_id);
+        state.bump = sandwich_bump;
+        let (config_key, config_bump) =
+            Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        program_config.bump = config_bump;
+        let (event_authority, _) =
+            Pubkey::find_program_address(&[b"__event_authority"], &crate::ID);
+
+        let mut accounts = swap_accounts;
+        accounts.extend([
+            program_account(sandwich_key, 8 + SandwichState::SIZE, &state),
+            program_account(config_key, 8 + ProgramConfig::SIZE, &program_config),
+            unchecked(event_authority),
+            program(crate::ID),
+        ]);
+        accounts
+    }
+
+    #[test]
+    fn the_pumpswap_backrun_takes_no_frontrun_guards() {
+        let _stubs = syscall_stubs();
+        let user = Pubkey::new_unique();
+        let sandwich_id: u64 = 7;
+        let accounts = pumpswap_backrun_accounts(
+            user,
+            sandwich_id,
+            pumpswap_swap_accounts(user, (mint(), mint()), (0, 0), protocol_fee_recipient_ata),
+            state_with_mints(Pubkey::default(), Pubkey::default()),
+            config(0),
+        );
+
+        // The sandwich state and config are followed straight by the event CPI accounts,
+        // with no allowlist or guard accounts between them
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        let backrun = PumpSwapBackrunContext::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &sandwich_id.to_le_bytes(),
+            &mut PumpSwapBackrunContextBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .unwrap();
+        assert!(accounts.is_empty());
+        assert!(backrun.config.to_account_info().is_writable);
+        assert!(backrun.sandwich_state.to_account_info().is_writable);
+    }
+
+    #[test]
+    fn a_fee_recipient_ata_for_the_wrong_mint_is_rejected() {
+        let _stubs = syscall_stubs();
+
+        try_pumpswap_accounts(protocol_fee_recipient_ata).unwrap();
+
+        // The recipient's ATA for some other mint, such as the base mint, is refused
+        // before the swap
+        assert_eq!(
+            try_pumpswap_accounts(|protocol_fee_recipient, _| {
+                get_associated_token_address(protocol_fee_recipient, &Pubkey::new_unique())
+            })
+            .unwrap_err(),
+            error!(ErrorCode::InvalidFeeRecipientAta)
+        );
+    }
+
+    #[test]
+    fn each_pumpswap_backrun_unwinds_its_own_frontrun() {
+        let (base_mint, quote_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let state = |is_buy| {
+            let (token_in_mint, token_out_mint) =
+                pumpswap_frontrun_mints(base_mint, quote_mint, is_buy);
+            state_with_mints(token_in_mint, token_out_mint)
+        };
+
+        // After a buy the backrun sells the base mint for the quote mint
+        assert!(state(true).is_unwound_by(&base_mint, &quote_mint));
+        assert!(!state(true).is_unwound_by(&quote_mint, &base_mint));
+        // After a sell it buys the base mint back with the quote mint
+        assert!(state(false).is_unwound_by(&quote_mint, &base_mint));
+        assert!(!state(false).is_unwound_by(&base_mint, &quote_mint));
+    }
+
+    #[test]
+    fn the_creator_fee_is_charged_only_with_a_creator_vault() {
+        assert_eq!(coin_creator_fee_rate(5, true), 500);
+        assert_eq!(coin_creator_fee_rate(5, false), 0);
+    }
+
+    #[test]
+    fn the_stored_event_authority_is_the_pump_swap_pda() {
+        assert_eq!(
+            pump_amm_event_authority(),
+            Pubkey::find_program_address(&[b"__event_authority"], &PumpAmm::id()).0
+        );
+    }
+
+    #[test]
+    fn the_pump_swap_event_accounts_are_accepted() {
+        try_event_cpi(pump_amm_event_authority(), PumpAmm::id()).unwrap();
+    }
+
+    #[test]
+    fn a_wrong_program_account_is_rejected() {
+        assert_eq!(
+            try_event_cpi(pump_amm_event_authority(), Pubkey::new_unique()).unwrap_err(),
+            error!(anchor_lang::error::ErrorCode::ConstraintAddress)
+        );
+    }
+
+    #[test]
+    fn a_wrong_event_authority_is_rejected() {
+        // The event authority of another program is still the wrong PDA
+        let (other_event_authority, _) =
+            Pubkey::find_program_address(&[b"__event_authority"], &crate::ID);
+        for event_authority in [other_event_authority, Pubkey::new_unique()] {
+            assert_eq!(
+                try_event_cpi(event_authority, PumpAmm::id()).unwrap_err(),
+                error!(ErrorCode::InvalidEventAuthority)
+            );
+        }
+    }
+
+    #[test]
+    fn every_pumpswap_cpi_shares_one_account_ordering() {
+        let required: [AccountInfo<'static>; 17] =
+            std::array::from_fn(|_| unchecked(Pubkey::new_unique()));
+        let keys: Vec<Pubkey> = required.iter().map(|account| *account.key).collect();
+
+        // Buy and sell, frontrun and backrun, all take the same required accounts
+        let (account_metas, accounts_vec) = pumpswap_cpi_accounts(required.clone(), None, None);
+        assert_eq!(
+            account_metas
+                .iter()
+                .map(|meta| meta.pubkey)
+                .collect::<Vec<_>>(),
+            keys
+        );
+        assert_eq!(
+            accounts_vec
+                .iter()
+                .map(|account| *account.key)
+                .collect::<Vec<_>>(),
+            keys
+        );
+
+        // Only the user signs
+        assert_eq!(
+            account_metas.iter().position(|meta| meta.is_signer),
+            Some(PUMPSWAP_USER_INDEX)
+        );
+        assert_eq!(
+            account_metas.iter().filter(|meta| meta.is_signer).count(),
+            1
+        );
+        // The pool, the user, the four token accounts and the fee recipient's ATA are written
+        let writable: Vec<usize> = account_metas
+            .iter()
+            .enumerate()
+            .filter(|(_, meta)| meta.is_writable)
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(writable, vec![0, 1, 5, 6, 7, 8, 10]);
+
+        // The creator accounts go last, the vault ATA before its authority
+        let coin_creator_vault_ata = unchecked(Pubkey::new_unique());
+        let coin_creator_vault_authority = unchecked(Pubkey::new_unique());
+        let (account_metas, accounts_vec) = pumpswap_cpi_accounts(
+            required,
+            Some(coin_creator_vault_ata.clone()),
+            Some(coin_creator_vault_authority.clone()),
+        );
+        assert_eq!(account_metas.len(), 19);
+        assert_eq!(accounts_vec.len(), 19);
+        assert_eq!(
+            account_metas[17],
+            AccountMeta::new(*coin_creator_vault_ata.key, false)
+        );
+        assert_eq!(
+            account_metas[18],
+            AccountMeta::new_readonly(*coin_creator_vault_authority.key, false)
+        );
+    }
+}
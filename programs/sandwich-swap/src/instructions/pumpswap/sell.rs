@@ -4,8 +4,15 @@ use anchor_spl::associated_token::get_associated_token_address;
 
 use crate::instructions::{get_transfer_fee, CurveCalculator, Fees};
 use crate::error::ErrorCode;
+use crate::program_config::{log_frontrun_compute_units, log_victim_amounts};
+use crate::sandwich_state::{
+    safe_slippage_pips, self_slippage_bps_or_default, slippage_pips, SandwichState,
+    MIN_SANDWICH_PROFIT, PIPS_DENOMINATOR,
+};
 
-use super::PumpSwapContext;
+use super::{
+    build_pumpswap_accounts, coin_creator_fee_rate, pumpswap_frontrun_mints, PumpSwapContext,
+};
 
 // Sell instruction data structure
 #[derive(AnchorSerialize)]
@@ -23,34 +30,31 @@ impl PumpSwapSell {
     }
 }
 
-pub fn pumpswap_frontrun_sell(
-    ctx: Context<PumpSwapContext>,
+#[allow(clippy::too_many_arguments)]
+pub fn pumpswap_frontrun_sell<'a, 'b, 'c: 'info, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, PumpSwapContext<'info>>,
     base_amount_in: u64,
     min_quote_amount_out: u64,
     sandwich_id: u64,
+    remaining_accounts_writable: Vec<bool>,
+    self_slippage_bps: u16,
+    require_victim_ix: Option<Pubkey>,
 ) -> Result<()> {
+    log_frontrun_compute_units("PumpSwap");
+
+    // Shared context with the backruns, so the pause, allowlist and blocklist are enforced here
+    ctx.accounts.guards.check(&ctx.accounts.pool.key())?;
+    ctx.accounts.guards.check_mints(
+        &ctx.accounts.base_mint.key(),
+        &ctx.accounts.quote_mint.key(),
+    )?;
+
+    // Only frontrun a victim landing later in the same transaction, when one is named
+    ctx.accounts.guards.check_victim_ix(require_victim_ix)?;
+
     // Get accounts needed for the CPI
     let pump_program = ctx.accounts.pump_amm_program.to_account_info();
     let pool = ctx.accounts.pool.to_account_info();
-    let user = ctx.accounts.user.to_account_info();
-    let global_config = ctx.accounts.global_config.to_account_info();
-    let base_mint = ctx.accounts.base_mint.to_account_info();
-    let quote_mint = ctx.accounts.quote_mint.to_account_info();
-    let user_base_token_account = ctx.accounts.user_base_token_account.to_account_info();
-    let user_quote_token_account = ctx.accounts.user_quote_token_account.to_account_info();
-    let pool_base_token_account = ctx.accounts.pool_base_token_account.to_account_info();
-    let pool_quote_token_account = ctx.accounts.pool_quote_token_account.to_account_info();
-    let protocol_fee_recipient = ctx.accounts.protocol_fee_recipient.to_account_info();
-    let protocol_fee_recipient_token_account = ctx
-        .accounts
-        .protocol_fee_recipient_token_account
-        .to_account_info();
-    let base_token_program = ctx.accounts.base_token_program.to_account_info();
-    let quote_token_program = ctx.accounts.quote_token_program.to_account_info();
-    let system_program = ctx.accounts.system_program.to_account_info();
-    let associated_token_program = ctx.accounts.associated_token_program.to_account_info();
-    let event_authority = ctx.accounts.event_authority.to_account_info();
-    let program = ctx.accounts.program.to_account_info();
     
     let pool_state = &mut ctx.accounts.pool.load_mut()?;
     
@@ -82,36 +86,53 @@ pub fn pumpswap_frontrun_sell(
     )?;
     let target_actual_amount_in = base_amount_in.saturating_sub(target_transfer_fee);
     let global_config_data = ctx.accounts.global_config.load()?;
+
+    // PumpSwap only takes the creator fee when the creator vault is passed
+    let coin_creator_fee_rate = coin_creator_fee_rate(
+        global_config_data.coin_creator_fee_basis_points,
+        ctx.accounts.coin_creator_vault_ata.is_some(),
+    );
     
     let expected_target_output = calculate_expected_output(
         target_actual_amount_in,
         total_input_amount,
         total_output_amount,
-        global_config_data.coin_creator_fee_basis_points * 100u64,
+        coin_creator_fee_rate,
         global_config_data.protocol_fee_basis_points * 100u64,
         global_config_data.lp_fee_basis_points * 100u64,
     )?;
     
-    let target_slippage_bps = if expected_target_output > 0 {
-        // Calculate as basis points (10000 = 100%)
-        ((expected_target_output.saturating_sub(min_quote_amount_out)) as u128 * 10000)
-            / (expected_target_output as u128)
-    } else {
-        return err!(ErrorCode::CalculationFailure);
-    };
+    // In pips, a sub-bip tolerance isn't truncated away
+    let target_slippage_pips = slippage_pips(
+        expected_target_output.saturating_sub(min_quote_amount_out),
+        expected_target_output,
+    )?;
+    log_victim_amounts(
+        "PumpSwap",
+        target_actual_amount_in,
+        expected_target_output,
+        target_slippage_pips,
+    );
     
-    let safe_slippage_bps = target_slippage_bps.saturating_mul(95).saturating_div(100);
+    let safe_slippage_pips = safe_slippage_pips(
+        target_slippage_pips,
+        ctx.accounts
+            .guards
+            .config
+            .max_absolute_slippage_consumed_bps,
+    );
 
     // Calculate optimal sandwich buy amount with improved profit calculation
     let optimal_buy_amount = calculate_optimal_sandwich_amount(
         total_input_amount,
         total_output_amount,
-        safe_slippage_bps,
+        safe_slippage_pips,
         base_amount_in,
         target_actual_amount_in,
-        global_config_data.coin_creator_fee_basis_points * 100u64,
+        coin_creator_fee_rate,
         global_config_data.protocol_fee_basis_points * 100u64,
         global_config_data.lp_fee_basis_points * 100u64,
+        MIN_SANDWICH_PROFIT,
     )?;
     
     if optimal_buy_amount < 100 {
@@ -127,10 +148,18 @@ pub fn pumpswap_frontrun_sell(
         optimal_buy_amount,
         total_input_amount,
         total_output_amount,
-        global_config_data.coin_creator_fee_basis_points * 100u64,
+        coin_creator_fee_rate,
         global_config_data.protocol_fee_basis_points * 100u64,
         global_config_data.lp_fee_basis_points * 100u64,
+        self_slippage_bps,
     )?;
+
+    // Hold the frontrun to the configured notional cap
+    let (optimal_buy_amount, minimum_out_for_sandwich) = ctx
+        .accounts
+        .guards
+        .config
+        .cap_notional(optimal_buy_amount, minimum_out_for_sandwich)?;
     
     // Create the instruction data for the sell instruction
     let ix_data = PumpSwapSell {
@@ -139,61 +168,17 @@ pub fn pumpswap_frontrun_sell(
     }
     .data();
 
-    // Create the sell instruction for PumpSwap
-    // First create the account metas for the required accounts
-    let mut account_metas = vec![
-        AccountMeta::new(pool.key(), false),
-        AccountMeta::new(user.key(), true),
-        AccountMeta::new_readonly(global_config.key(), false),
-        AccountMeta::new_readonly(base_mint.key(), false),
-        AccountMeta::new_readonly(quote_mint.key(), false),
-        AccountMeta::new(user_base_token_account.key(), false),
-        AccountMeta::new(user_quote_token_account.key(), false),
-        AccountMeta::new(pool_base_token_account.key(), false),
-        AccountMeta::new(pool_quote_token_account.key(), false),
-        AccountMeta::new_readonly(protocol_fee_recipient.key(), false),
-        AccountMeta::new(protocol_fee_recipient_token_account.key(), false),
-        AccountMeta::new_readonly(base_token_program.key(), false),
-        AccountMeta::new_readonly(quote_token_program.key(), false),
-        AccountMeta::new_readonly(system_program.key(), false),
-        AccountMeta::new_readonly(associated_token_program.key(), false),
-        AccountMeta::new_readonly(event_authority.key(), false),
-        AccountMeta::new_readonly(program.key(), false),
-    ];
-
-    // accounts for the instruction
-    let mut accounts_vec = vec![
-        pool,
-        user,
-        global_config,
-        base_mint,
-        quote_mint,
-        user_base_token_account,
-        user_quote_token_account,
-        pool_base_token_account,
-        pool_quote_token_account,
-        protocol_fee_recipient,
-        protocol_fee_recipient_token_account,
-        base_token_program,
-        quote_token_program,
-        system_program,
-        associated_token_program,
-        event_authority,
-        program,
-    ];
-    // Add coin creator accounts if provided
-    if let Some(coin_creator_vault_ata) = &ctx.accounts.coin_creator_vault_ata {
-        account_metas.push(AccountMeta::new(coin_creator_vault_ata.key(), false));
-        accounts_vec.push(coin_creator_vault_ata.to_account_info());
-    }
+    // Create the sell instruction for PumpSwap, its accounts are shared with every PumpSwap CPI
+    let (mut account_metas, mut accounts_vec) = build_pumpswap_accounts(&ctx.accounts);
+
+    // Forward any extra accounts after the known ones, flagged writable or readonly by the caller
+    forward_remaining_accounts(
+        &mut account_metas,
+        &mut accounts_vec,
+        ctx.remaining_accounts,
+        remaining_accounts_writable,
+    )?;
 
-    if let Some(coin_creator_vault_authority) = &ctx.accounts.coin_creator_vault_authority {
-        account_metas.push(AccountMeta::new_readonly(
-            coin_creator_vault_authority.key(),
-            false,
-        ));
-        accounts_vec.push(coin_creator_vault_authority.to_account_info());
-    }
     let sell_ix = Instruction {
         program_id: pump_program.key(),
         accounts: account_metas,
@@ -209,29 +194,58 @@ pub fn pumpswap_frontrun_sell(
         output_token_balance_after.saturating_sub(output_token_balance_before);
 
     // Store frontrun data in the PDA for the backrun to read
-    let sandwich_state = &mut ctx.accounts.sandwich_state;
-    sandwich_state.frontrun_output_amount = frontrun_output_amount;
-    sandwich_state.frontrun_input_amount = optimal_buy_amount;
-    sandwich_state.sandwich_id = sandwich_id;
-    sandwich_state.token_in_mint = *ctx.accounts.base_mint.to_account_info().key;
-    sandwich_state.token_out_mint = *ctx.accounts.quote_mint.to_account_info().key;
-    sandwich_state.timestamp = Clock::get()?.unix_timestamp;
-    sandwich_state.is_complete = false;
-    sandwich_state.bump = ctx.bumps.sandwich_state;
+    let (token_in_mint, token_out_mint) = pumpswap_frontrun_mints(
+        ctx.accounts.base_mint.key(),
+        ctx.accounts.quote_mint.key(),
+        false,
+    );
+    ctx.accounts.sandwich_state.record_frontrun(
+        optimal_buy_amount,
+        frontrun_output_amount,
+        token_in_mint,
+        token_out_mint,
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
         
     Ok(())
 }
 
+// Appends the remaining accounts to the CPI, one writable flag per account
+fn forward_remaining_accounts<'info>(
+    account_metas: &mut Vec<AccountMeta>,
+    accounts_vec: &mut Vec<AccountInfo<'info>>,
+    remaining_accounts: &[AccountInfo<'info>],
+    remaining_accounts_writable: Vec<bool>,
+) -> Result<()> {
+    require!(
+        remaining_accounts_writable.len() == remaining_accounts.len(),
+        ErrorCode::InvalidInput
+    );
+    for (account, is_writable) in remaining_accounts.iter().zip(remaining_accounts_writable) {
+        // A readonly account can't be passed on as writable
+        require!(!is_writable || account.is_writable, ErrorCode::InvalidInput);
+        account_metas.push(if is_writable {
+            AccountMeta::new(account.key(), false)
+        } else {
+            AccountMeta::new_readonly(account.key(), false)
+        });
+        accounts_vec.push(account.clone());
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 fn calculate_optimal_sandwich_amount(
     reserve_in: u64,
     reserve_out: u64,
-    safe_slippage_bps: u128,
+    safe_slippage_pips: u128,
     _target_amount_in: u64,
     target_actual_amount_in: u64,
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    min_profit: u128,
 ) -> Result<u64> {
     // Convert to u128 for safer math
     let reserve_in = reserve_in as u128;
@@ -295,11 +309,11 @@ fn calculate_optimal_sandwich_amount(
         .destination_amount_swapped;
 
         // Check if target tx will still execute within slippage
-        let price_impact_bps = ((target_expected_output_before - target_expected_output_after)
-            * 10000)
+        let price_impact_pips = ((target_expected_output_before - target_expected_output_after)
+            * PIPS_DENOMINATOR)
             / target_expected_output_before;
 
-        let within_slippage = price_impact_bps <= safe_slippage_bps;
+        let within_slippage = price_impact_pips <= safe_slippage_pips;
 
         // If target would fail due to slippage, this attack size doesn't work
         if !within_slippage {
@@ -340,6 +354,9 @@ fn calculate_optimal_sandwich_amount(
         }
     }
 
+    // The initial estimate stands unchecked unless some trial beat it
+    require!(best_profit >= min_profit, ErrorCode::UnprofitableSandwich);
+
     // Convert best amount to u64 and return
     let result = best_amount.try_into().unwrap_or(u64::MAX);
 
@@ -353,6 +370,7 @@ fn calculate_minimum_out_for_sandwich(
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    self_slippage_bps: u16,
 ) -> Result<u64> {
     // Calculate expected output
     let expected_out = calculate_expected_output(
@@ -364,8 +382,9 @@ fn calculate_minimum_out_for_sandwich(
         fund_fee_rate,
     )?;
 
-    // Apply aggressive slippage tolerance (5%)
-    let min_out = expected_out.saturating_mul(95).saturating_div(100);
+    // Apply our own slippage tolerance (5% by default)
+    let slippage_bps = self_slippage_bps_or_default(self_slippage_bps)?;
+    let min_out = ((expected_out as u128) * (10_000 - slippage_bps) / 10_000) as u64;
 
     Ok(min_out)
 }
@@ -411,3 +430,95 @@ fn calculate_expected_output(
         .try_into()
         .map_err(|_| ErrorCode::CalculationFailure)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_accounts_are_forwarded_with_their_writable_flag() {
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let owner = Pubkey::new_unique();
+        let (mut lamports_0, mut lamports_1) = (0, 0);
+        let (mut data_0, mut data_1) = (vec![], vec![]);
+        let remaining_accounts = [
+            AccountInfo::new(&keys[0], false, true, &mut lamports_0, &mut data_0, &owner, false, 0),
+            AccountInfo::new(&keys[1], false, false, &mut lamports_1, &mut data_1, &owner, false, 0),
+        ];
+
+        let mut account_metas = vec![AccountMeta::new_readonly(owner, false)];
+        let mut accounts_vec = vec![];
+        forward_remaining_accounts(
+            &mut account_metas,
+            &mut accounts_vec,
+            &remaining_accounts,
+            vec![true, false],
+        )
+        .unwrap();
+
+        assert_eq!(
+            account_metas[1..],
+            [AccountMeta::new(keys[0], false), AccountMeta::new_readonly(keys[1], false)]
+        );
+        assert_eq!(
+            accounts_vec.iter().map(|account| *account.key).collect::<Vec<_>>(),
+            keys
+        );
+    }
+
+    #[test]
+    fn a_pool_without_a_creator_vault_is_sized_without_the_creator_fee() {
+        let size = |has_coin_creator_vault: bool| {
+            calculate_optimal_sandwich_amount(
+                1_000_000_000,
+                1_000_000_000,
+                47_500,
+                10_000_000,
+                10_000_000,
+                coin_creator_fee_rate(5, has_coin_creator_vault),
+                500,
+                2_000,
+                MIN_SANDWICH_PROFIT,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(size(true), 24_760_245);
+        // Same as a pool charging no creator fee at all
+        assert_eq!(size(false), 24_754_046);
+    }
+
+    #[test]
+    fn a_writable_flag_is_required_per_remaining_account() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let remaining_accounts =
+            [AccountInfo::new(&key, false, true, &mut lamports, &mut data, &key, false, 0)];
+
+        assert_eq!(
+            forward_remaining_accounts(&mut vec![], &mut vec![], &remaining_accounts, vec![])
+                .unwrap_err(),
+            error!(ErrorCode::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn a_readonly_remaining_account_is_not_forwarded_as_writable() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let remaining_accounts =
+            [AccountInfo::new(&key, false, false, &mut lamports, &mut data, &key, false, 0)];
+
+        assert_eq!(
+            forward_remaining_accounts(&mut vec![], &mut vec![], &remaining_accounts, vec![true])
+                .unwrap_err(),
+            error!(ErrorCode::InvalidInput)
+        );
+        assert!(
+            forward_remaining_accounts(&mut vec![], &mut vec![], &remaining_accounts, vec![false])
+                .is_ok()
+        );
+    }
+}
@@ -1,48 +1,53 @@
 use crate::error::ErrorCode;
-use crate::instructions::{AmmAuthority, AMM_AUTHORITY_ID, Serum, SERUM_PROGRAM_ID, Amm, AMM_PROGRAM_ID, Swap};
-use crate::sandwich_state::{SandwichCompleteEvent, SandwichState};
+use crate::instructions::{AMM_AUTHORITY_ID, Serum, SERUM_PROGRAM_ID, Amm, AMM_PROGRAM_ID, Swap, SWAP_BASE_IN_INSTRUCTION};
+use crate::sandwich_state::{
+    min_required_output, with_fixed_cost, with_min_profit_amount, SandwichCompleteEvent,
+    SandwichState,
+};
+use crate::program_config::ProgramConfig;
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use solana_program::instruction::Instruction;
 use solana_program::program::invoke_signed;
-use crate::instructions::amm::pair::ProgramAccount;
+use crate::instructions::amm::pair::{serum_market_vaults, ProgramAccount};
 
+#[event_cpi]
 #[derive(Accounts, Clone)]
-#[instruction(sandwich_id: String)]
+#[instruction(sandwich_id: u64)]
 pub struct AmmBackrunSwapBaseIn<'info> {
     /// token program
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 
     /// CHECK Pair account
     #[account(mut)]
     pub amm: AccountLoader<'info, ProgramAccount>,
 
-    /// Raydium Authority
+    /// CHECK Raydium Authority, a PDA of the AMM program checked against its address
     #[account(address = AMM_AUTHORITY_ID.parse::<Pubkey>().unwrap())]
-    pub amm_authority: Program<'info, AmmAuthority>,
+    pub amm_authority: UncheckedAccount<'info>,
 
     /// CHECK Open Orders account
-    #[account(mut)]
+    #[account(mut, address = amm.load()?.open_orders @ ErrorCode::AmmAccountMismatch)]
     pub amm_open_orders: AccountInfo<'info>,
 
     /// CHECK Target Orders account
-    #[account(mut)]
+    #[account(mut, address = amm.load()?.target_orders @ ErrorCode::AmmAccountMismatch)]
     pub amm_target_orders: AccountInfo<'info>,
 
     /// Pool base token account
-    #[account(mut)]
-    pub pool_coin_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = amm.load()?.base_vault @ ErrorCode::AmmAccountMismatch)]
+    pub pool_coin_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// Pool quote token account
-    #[account(mut)]
-    pub pool_pc_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = amm.load()?.quote_vault @ ErrorCode::AmmAccountMismatch)]
+    pub pool_pc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// OpenBook program id
     #[account(address = SERUM_PROGRAM_ID.parse::<Pubkey>().unwrap())]
     pub serum_program: Program<'info, Serum>,
 
     /// CHECK Serum market account
-    #[account(mut)]
+    #[account(mut, address = amm.load()?.market_id @ ErrorCode::AmmAccountMismatch)]
     pub serum_market: AccountInfo<'info>,
 
     /// CHECK Serum bids account
@@ -57,27 +62,33 @@ pub struct AmmBackrunSwapBaseIn<'info> {
     #[account(mut)]
     pub serum_event_queue: AccountInfo<'info>,
 
-    /// Pool base token account
-    #[account(mut)]
-    pub serum_coin_vault_account: Box<Account<'info, TokenAccount>>,
+    /// Serum base token vault
+    #[account(
+        mut,
+        address = serum_market_vaults(&serum_market)?.0 @ ErrorCode::AmmAccountMismatch
+    )]
+    pub serum_coin_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// Pool quote token account
-    #[account(mut)]
-    pub serum_pc_vault_account: Box<Account<'info, TokenAccount>>,
+    /// Serum quote token vault
+    #[account(
+        mut,
+        address = serum_market_vaults(&serum_market)?.1 @ ErrorCode::AmmAccountMismatch
+    )]
+    pub serum_pc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK Serum vault signer account
     pub serum_vault_signer: AccountInfo<'info>,
 
     /// User source token account
     #[account(mut)]
-    pub user_source_token_account: Box<Account<'info, TokenAccount>>,
+    pub user_source_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// User destination token account
     #[account(
         mut,
         close = user_source_owner,
     )]
-    pub user_target_token_account: Box<Account<'info, TokenAccount>>,
+    pub user_target_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The user making the swap
     #[account(mut)]
@@ -86,9 +97,13 @@ pub struct AmmBackrunSwapBaseIn<'info> {
     /// The account that stores sandwich state
     #[account(
        mut,
-       seeds = [b"sandwich", sandwich_id.as_bytes()],
+       seeds = [b"sandwich", user_source_owner.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump = sandwich_state.bump,
        constraint = !sandwich_state.is_complete @ ErrorCode::SandwichAlreadyCompleted,
+       constraint = sandwich_state.is_unwound_by(
+           &user_source_token_account.mint,
+           &user_target_token_account.mint,
+       ) @ ErrorCode::TokenMintMismatch,
     )]
     pub sandwich_state: Account<'info, SandwichState>,
 
@@ -100,36 +115,80 @@ pub struct AmmBackrunSwapBaseIn<'info> {
     #[account(
         constraint = base_mint.key() == amm.load()?.base_mint
     )]
-    pub base_mint: Account<'info, Mint>,
+    pub base_mint: InterfaceAccount<'info, Mint>,
+
+    /// Program config, tracks the losing streak for the circuit breaker
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+impl AmmBackrunSwapBaseIn<'_> {
+    /// Accounts of the Raydium `swap_base_in` selling the frontrun position, in the order
+    /// the AMM program reads them.
+    pub(crate) fn swap_account_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new_readonly(self.token_program.key(), false),
+            AccountMeta::new(self.amm.key(), false),
+            AccountMeta::new_readonly(self.amm_authority.key(), false),
+            AccountMeta::new(self.amm_open_orders.key(), false),
+            AccountMeta::new(self.amm_target_orders.key(), false),
+            AccountMeta::new(self.pool_coin_token_account.key(), false),
+            AccountMeta::new(self.pool_pc_token_account.key(), false),
+            AccountMeta::new_readonly(self.serum_program.key(), false),
+            AccountMeta::new(self.serum_market.key(), false),
+            AccountMeta::new(self.serum_bids.key(), false),
+            AccountMeta::new(self.serum_asks.key(), false),
+            AccountMeta::new(self.serum_event_queue.key(), false),
+            AccountMeta::new(self.serum_coin_vault_account.key(), false),
+            AccountMeta::new(self.serum_pc_vault_account.key(), false),
+            AccountMeta::new_readonly(self.serum_vault_signer.key(), false),
+            AccountMeta::new(self.user_source_token_account.key(), false),
+            AccountMeta::new(self.user_target_token_account.key(), false),
+            AccountMeta::new(self.user_source_owner.key(), true),
+        ]
+    }
 }
 
 /// swap_base_in instruction
 pub fn amm_backrun_swap_base_in(
     ctx: Context<AmmBackrunSwapBaseIn>,
     sandwich_id: u64,
+    min_slots_between: u8,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    max_state_age_secs: u32,
 ) -> Result<()> {
+    // Refuse to backrun a frontrun that filled nothing
+    ctx.accounts.sandwich_state.check_frontrun_filled()?;
+
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    ctx.accounts.sandwich_state.check_backrun_slot(min_slots_between)?;
+
+    // Refuse to unwind a position left open for too long, at a price long gone
+    ctx.accounts.sandwich_state.check_state_age(max_state_age_secs)?;
+
+    // Size the minimum output from the current reserves and the frontrun cost
+    let (min_amount_out, expected_backrun_output) = {
+        let amm_state = ctx.accounts.amm.load()?;
+        calculate_amm_backrun_minimum_output(
+            &amm_state,
+            ctx.accounts.pool_coin_token_account.amount,
+            ctx.accounts.pool_pc_token_account.amount,
+            ctx.accounts.sandwich_state.frontrun_output_amount,
+            ctx.accounts.sandwich_state.frontrun_input_amount,
+            &ctx.accounts.user_target_token_account.mint,
+            fixed_cost_lamports,
+            min_profit_amount,
+        )?
+    };
+
     let sandwich_state = &mut ctx.accounts.sandwich_state;
 
-    let account_metas = vec![
-        AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
-        AccountMeta::new(ctx.accounts.amm.key(), false),
-        AccountMeta::new_readonly(ctx.accounts.amm_authority.key(), false),
-        AccountMeta::new(ctx.accounts.amm_open_orders.key(), false),
-        AccountMeta::new(ctx.accounts.amm_target_orders.key(), false),
-        AccountMeta::new(ctx.accounts.pool_coin_token_account.key(), false),
-        AccountMeta::new(ctx.accounts.pool_pc_token_account.key(), false),
-        AccountMeta::new_readonly(ctx.accounts.serum_program.key(), false),
-        AccountMeta::new(ctx.accounts.serum_market.key(), false),
-        AccountMeta::new(ctx.accounts.serum_bids.key(), false),
-        AccountMeta::new(ctx.accounts.serum_asks.key(), false),
-        AccountMeta::new(ctx.accounts.serum_event_queue.key(), false),
-        AccountMeta::new(ctx.accounts.serum_coin_vault_account.key(), false),
-        AccountMeta::new(ctx.accounts.serum_pc_vault_account.key(), false),
-        AccountMeta::new_readonly(ctx.accounts.serum_vault_signer.key(), false),
-        AccountMeta::new(ctx.accounts.user_source_token_account.key(), false),
-        AccountMeta::new(ctx.accounts.user_target_token_account.key(), false),
-        AccountMeta::new(ctx.accounts.user_source_owner.key(), true),
-    ];
+    let account_metas = ctx.accounts.swap_account_metas();
 
     let accounts_vec = vec![
         ctx.accounts.token_program.to_account_info(),
@@ -153,9 +212,9 @@ pub fn amm_backrun_swap_base_in(
     ];
 
     let ix_data = Swap {
-        discriminator: 9,
+        discriminator: SWAP_BASE_IN_INSTRUCTION,
         amount_in: sandwich_state.frontrun_output_amount,
-        min_amount_out: 0,
+        min_amount_out,
     }.data();
 
     let buy_ix = Instruction {
@@ -169,19 +228,571 @@ pub fn amm_backrun_swap_base_in(
 
     sandwich_state.is_complete = true;
 
-    ctx.accounts.user_source_token_account.reload()?;
-    let output_token_balance_after = ctx.accounts.user_source_token_account.amount;
-    let actual_output = output_token_balance_after.saturating_sub(output_token_balance_before);
-    let profit = actual_output.saturating_sub(sandwich_state.frontrun_input_amount);
+    // The swap pays out into the target account, the one the balance was taken from
+    ctx.accounts.user_target_token_account.reload()?;
+    let event = amm_backrun_complete_event(
+        sandwich_id,
+        sandwich_state.frontrun_input_amount,
+        output_token_balance_before,
+        ctx.accounts.user_target_token_account.amount,
+        expected_backrun_output,
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
 
     // Emit an event with profit information
-    emit!(SandwichCompleteEvent {
-        sandwich_id,
-        profit,
-        input_amount: sandwich_state.frontrun_input_amount,
-        output_amount: actual_output,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+    emit_cpi!(event);
 
     Ok(())
 }
+
+// Completion of a backrun whose quote account went from `balance_before` to
+// `balance_after`, both read from the same account, with the signed profit
+// against what the frontrun paid
+pub(crate) fn amm_backrun_complete_event(
+    sandwich_id: u64,
+    frontrun_input_amount: u64,
+    balance_before: u64,
+    balance_after: u64,
+    expected_output: u64,
+    timestamp: i64,
+) -> SandwichCompleteEvent {
+    SandwichCompleteEvent::new(
+        sandwich_id,
+        frontrun_input_amount,
+        balance_after.saturating_sub(balance_before),
+        Some(expected_output),
+        timestamp,
+    )
+}
+
+// Minimum quote out for selling the frontrun position back into the pool: the
+// expected output with a 2% safety margin, floored at the cost basis plus the
+// minimum profit (relative, and absolute at `min_profit_amount`) and, for a WSOL
+// quote, the fixed cost. Errors if the pool can't pay
+// the floor at current reserves.
+// Returns `(minimum_output, expected_output)`.
+pub(crate) fn calculate_amm_backrun_minimum_output(
+    amm_state: &ProgramAccount,
+    pool_coin_amount: u64,
+    pool_pc_amount: u64,
+    sell_amount: u64,
+    cost_basis: u64,
+    profit_mint: &Pubkey,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+) -> Result<(u64, u64)> {
+    // Calculate reserves excluding the pnl Raydium hasn't taken yet
+    let coin_reserve = pool_coin_amount.saturating_sub(amm_state.base_need_take_pnl);
+    let pc_reserve = pool_pc_amount.saturating_sub(amm_state.quote_need_take_pnl);
+
+    // Calculate the input left after Raydium's swap fee (rounded up like Raydium does)
+    let swap_fee_numerator = amm_state.swap_fee_numerator as u128;
+    let swap_fee_denominator = amm_state.swap_fee_denominator as u128;
+    require!(swap_fee_denominator > 0, ErrorCode::CalculationFailure);
+    let swap_fee = (sell_amount as u128 * swap_fee_numerator + swap_fee_denominator - 1)
+        / swap_fee_denominator;
+    let amount_in_after_fee = (sell_amount as u128).saturating_sub(swap_fee);
+
+    // Calculate expected output from the constant product curve
+    let expected_backrun_output = u64::try_from(
+        (pc_reserve as u128)
+            .checked_mul(amount_in_after_fee)
+            .ok_or(ErrorCode::CalculationFailure)?
+            .checked_div((coin_reserve as u128) + amount_in_after_fee)
+            .ok_or(ErrorCode::CalculationFailure)?,
+    )
+    .map_err(|_| error!(ErrorCode::CalculationFailure))?;
+
+    // Calculate the output needed to cover the frontrun cost plus the profit floor
+    let min_required_output = with_min_profit_amount(
+        min_required_output(cost_basis, 0, 0)?,
+        cost_basis,
+        min_profit_amount,
+    )?;
+    let min_required_output =
+        with_fixed_cost(min_required_output, profit_mint, fixed_cost_lamports)?;
+
+    // Bail instead of dumping the position below the floor
+    if expected_backrun_output < min_required_output {
+        return err!(ErrorCode::UnprofitableSandwich);
+    }
+
+    // Use the higher of expected output with safety margin or minimum required output
+    let minimum_output = std::cmp::max(
+        expected_backrun_output.saturating_mul(98).saturating_div(100), // 2% safety margin
+        min_required_output,
+    );
+    Ok((minimum_output, expected_backrun_output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::raydium::cpmm::swap_base_output::tests::transfer_fee_mint;
+    use crate::test_utils::{account_info, program, program_account, syscall_stubs, take_invoked};
+    use anchor_spl::token::spl_token;
+    use anchor_spl::token_2022::spl_token_2022;
+    use solana_program::program_pack::Pack;
+    use std::collections::BTreeSet;
+
+    // 1_000_000 coin against 2_000_000 pc once the pending pnl is taken out
+    fn amm_state() -> ProgramAccount {
+        ProgramAccount {
+            swap_fee_numerator: 25,
+            swap_fee_denominator: 10_000,
+            base_need_take_pnl: 1_000,
+            quote_need_take_pnl: 2_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backrun_minimum_output_keeps_a_margin_under_the_expected_output() {
+        // 19_752 expected, the cost basis asks for no more than 15_075
+        assert_eq!(
+            calculate_amm_backrun_minimum_output(
+                &amm_state(),
+                1_001_000,
+                2_002_000,
+                10_000,
+                15_000,
+                &Pubkey::default(),
+                0,
+                0,
+            )
+            .unwrap(),
+            (19_356, 19_752)
+        );
+    }
+
+    #[test]
+    fn backrun_minimum_output_is_floored_at_the_required_output() {
+        // 19_602 plus the 0.5% profit floor needs 19_700
+        assert_eq!(
+            calculate_amm_backrun_minimum_output(
+                &amm_state(),
+                1_001_000,
+                2_002_000,
+                10_000,
+                19_602,
+                &Pubkey::default(),
+                0,
+                0,
+            )
+            .unwrap(),
+            (19_700, 19_752)
+        );
+    }
+
+    #[test]
+    fn backrun_below_the_required_output_is_refused() {
+        // 19_655 plus the 0.5% profit floor needs 19_753, one more than expected
+        assert_eq!(
+            calculate_amm_backrun_minimum_output(
+                &amm_state(),
+                1_001_000,
+                2_002_000,
+                10_000,
+                19_655,
+                &Pubkey::default(),
+                0,
+                0,
+            )
+            .unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+    }
+
+    #[test]
+    fn a_gross_profit_under_the_fixed_cost_is_refused() {
+        let wsol = spl_token::native_mint::ID;
+        let minimum_output = |profit_mint, fixed_cost_lamports| {
+            calculate_amm_backrun_minimum_output(
+                &amm_state(),
+                1_001_000,
+                2_002_000,
+                10_000,
+                15_000,
+                &profit_mint,
+                fixed_cost_lamports,
+                0,
+            )
+        };
+
+        // 4_752 gross profit on 15_000, the 0.5% floor needs 15_075 back
+        assert_eq!(minimum_output(wsol, 0).unwrap(), (19_356, 19_752));
+        assert_eq!(minimum_output(wsol, 4_677).unwrap(), (19_752, 19_752));
+        // A tip eating the profit leaves the sandwich net negative
+        assert_eq!(
+            minimum_output(wsol, 4_678).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+        // Lamports aren't charged against a profit in another mint
+        assert_eq!(
+            minimum_output(Pubkey::new_unique(), 4_678).unwrap(),
+            (19_356, 19_752)
+        );
+    }
+
+    const SANDWICH_ID: u64 = 7;
+
+    fn unchecked() -> AccountInfo<'static> {
+        account_info(
+            Pubkey::new_unique(),
+            Pubkey::default(),
+            vec![],
+            false,
+            false,
+        )
+    }
+
+    fn token_account(token_program: Pubkey) -> AccountInfo<'static> {
+        token_account_of(token_program, Pubkey::new_unique())
+    }
+
+    fn token_account_of(token_program: Pubkey, mint: Pubkey) -> AccountInfo<'static> {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(Pubkey::new_unique(), token_program, data, false, false)
+    }
+
+    // Every account of the backrun context in order, consistent with the pair state
+    fn backrun_accounts() -> Vec<AccountInfo<'static>> {
+        let mut mint_data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut mint_data);
+        let base_mint = account_info(Pubkey::new_unique(), spl_token::ID, mint_data, false, false);
+
+        backrun_accounts_for(spl_token::ID, base_mint)
+    }
+
+    // The backrun context on a pool whose token accounts belong to `token_program`
+    fn backrun_accounts_for(
+        token_program: Pubkey,
+        base_mint: AccountInfo<'static>,
+    ) -> Vec<AccountInfo<'static>> {
+        let pool_coin = token_account(token_program);
+        let pool_pc = token_account(token_program);
+        let serum_coin_vault = token_account(token_program);
+        let serum_pc_vault = token_account(token_program);
+        let open_orders = unchecked();
+        let target_orders = unchecked();
+
+        // The frontrun paid `paid_mint` for `bought_mint`, which the backrun sells back
+        let (paid_mint, bought_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let mut market_data = vec![0u8; 388];
+        market_data[117..149].copy_from_slice(serum_coin_vault.key.as_ref());
+        market_data[165..197].copy_from_slice(serum_pc_vault.key.as_ref());
+        let serum_market = account_info(
+            Pubkey::new_unique(),
+            Pubkey::default(),
+            market_data,
+            false,
+            false,
+        );
+
+        let amm = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            vec![0u8; 8 + std::mem::size_of::<ProgramAccount>()],
+            false,
+            false,
+        );
+        amm.try_borrow_mut_data().unwrap()[..8].copy_from_slice(&ProgramAccount::DISCRIMINATOR);
+        let amm_loader =
+            AccountLoader::<ProgramAccount>::try_from(Box::leak(Box::new(amm.clone()))).unwrap();
+        *amm_loader.load_mut().unwrap() = ProgramAccount {
+            base_vault: *pool_coin.key,
+            quote_vault: *pool_pc.key,
+            base_mint: *base_mint.key,
+            open_orders: *open_orders.key,
+            market_id: *serum_market.key,
+            target_orders: *target_orders.key,
+            ..Default::default()
+        };
+
+        let owner = Pubkey::new_unique();
+        let (sandwich_key, sandwich_bump) = Pubkey::find_program_address(
+            &[b"sandwich", owner.as_ref(), &SANDWICH_ID.to_le_bytes()],
+            &crate::ID,
+        );
+        let sandwich_state = program_account(
+            sandwich_key,
+            8 + SandwichState::SIZE,
+            &SandwichState {
+                frontrun_output_amount: 0,
+                frontrun_input_amount: 0,
+                target_tx_signature: [0; 64],
+                sandwich_id: SANDWICH_ID,
+                is_complete: false,
+                token_in_mint: paid_mint,
+                token_out_mint: bought_mint,
+                timestamp: 0,
+                bump: sandwich_bump,
+                version: SandwichState::VERSION,
+                remaining_output_amount: 0,
+                remaining_input_amount: 0,
+                frontrun_slot: 0,
+                realized_profit: 0,
+                completed_at: 0,
+                completed_slot: 0,
+                retained_output_amount: 0,
+            },
+        );
+
+        let (config_key, config_bump) =
+            Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        let config = program_account(
+            config_key,
+            8 + ProgramConfig::SIZE,
+            &ProgramConfig {
+                admin: Pubkey::new_unique(),
+                paused: false,
+                max_consecutive_losses: 0,
+                consecutive_losses: 0,
+                bump: config_bump,
+                enforce_allowlist: false,
+                research_mode: false,
+                max_notional: 0,
+                clamp_notional: false,
+                allow_fee_free_pools: false,
+                max_absolute_slippage_consumed_bps: 0,
+                enforce_blocklist: false,
+                max_per_slot: 0,
+            },
+        );
+
+        vec![
+            program(token_program),
+            amm,
+            account_info(
+                AMM_AUTHORITY_ID.parse().unwrap(),
+                Pubkey::default(),
+                vec![],
+                false,
+                false,
+            ),
+            open_orders,
+            target_orders,
+            pool_coin,
+            pool_pc,
+            program(Serum::id()),
+            serum_market,
+            unchecked(),
+            unchecked(),
+            unchecked(),
+            serum_coin_vault,
+            serum_pc_vault,
+            unchecked(),
+            token_account_of(token_program, bought_mint),
+            token_account_of(token_program, paid_mint),
+            account_info(owner, Pubkey::default(), vec![], true, false),
+            sandwich_state,
+            program(Amm::id()),
+            base_mint,
+            config,
+            event_authority(),
+            program(crate::ID),
+        ]
+    }
+
+    // The program's own event-authority PDA, the backrun self-CPIs through it
+    fn event_authority() -> AccountInfo<'static> {
+        let (key, _) = Pubkey::find_program_address(&[b"__event_authority"], &crate::ID);
+        account_info(key, Pubkey::default(), vec![], false, false)
+    }
+
+    fn try_backrun_accounts(accounts: Vec<AccountInfo<'static>>) -> Result<()> {
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        AmmBackrunSwapBaseIn::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &SANDWICH_ID.to_le_bytes(),
+            &mut AmmBackrunSwapBaseInBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+    }
+
+    #[test]
+    fn a_high_percentage_profit_under_the_min_profit_amount_is_refused() {
+        let minimum_output = |min_profit_amount| {
+            calculate_amm_backrun_minimum_output(
+                &amm_state(),
+                1_001_000,
+                2_002_000,
+                10_000,
+                15_000,
+                &Pubkey::default(),
+                0,
+                min_profit_amount,
+            )
+        };
+
+        // 4_752 back on 15_000 is a 31% profit, well above the 0.5% floor
+        assert_eq!(minimum_output(4_752).unwrap(), (19_752, 19_752));
+        // But not worth landing when the operator wants 5_000 of the quote mint
+        assert_eq!(
+            minimum_output(5_000).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+    }
+
+    #[test]
+    fn the_profit_is_the_quote_account_delta() {
+        // The quote account held 5_000 before the sell and 24_800 after it
+        let event = amm_backrun_complete_event(SANDWICH_ID, 19_500, 5_000, 24_800, 19_752, 0);
+        assert_eq!(event.output_amount, 19_800);
+        assert_eq!(event.realized_profit, 300);
+        assert_eq!(event.expected_profit, Some(252));
+
+        // A sell returning less than the frontrun paid reports the loss
+        let event = amm_backrun_complete_event(SANDWICH_ID, 19_500, 5_000, 24_000, 19_752, 0);
+        assert_eq!(event.realized_profit, -500);
+    }
+
+    #[test]
+    fn the_complete_event_is_emitted_through_the_self_cpi() {
+        let mut accounts: &[AccountInfo<'static>] = backrun_accounts().leak();
+        let mut bumps = AmmBackrunSwapBaseInBumps::default();
+        let mut backrun = AmmBackrunSwapBaseIn::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &SANDWICH_ID.to_le_bytes(),
+            &mut bumps,
+            &mut BTreeSet::new(),
+        )
+        .unwrap();
+        let ctx = Context::new(&crate::ID, &mut backrun, &[], bumps);
+        let event = amm_backrun_complete_event(SANDWICH_ID, 19_500, 5_000, 24_800, 19_752, 0);
+
+        let _stubs = syscall_stubs();
+        let emit = || -> Result<()> {
+            emit_cpi!(event);
+            Ok(())
+        };
+        emit().unwrap();
+
+        // One inner instruction back into this program, signed by its event authority
+        let (instruction, seeds) = take_invoked().pop().unwrap();
+        assert_eq!(instruction.program_id, crate::ID);
+        assert_eq!(
+            instruction.accounts,
+            vec![AccountMeta::new_readonly(
+                ctx.accounts.event_authority.key(),
+                true
+            )]
+        );
+        assert_eq!(
+            seeds,
+            vec![
+                b"__event_authority".to_vec(),
+                vec![ctx.bumps.event_authority]
+            ]
+        );
+
+        // Tagged as an event, the payload decodes back to the emitted event
+        let (tag, data) = instruction.data.split_at(8);
+        assert_eq!(tag, anchor_lang::event::EVENT_IX_TAG_LE);
+        assert_eq!(data, anchor_lang::Event::data(&event));
+        let logged = SandwichCompleteEvent::try_from_slice(&data[8..]).unwrap();
+        assert_eq!(logged.realized_profit, 300);
+    }
+
+    #[test]
+    fn the_accounts_recorded_in_the_pair_state_are_accepted() {
+        try_backrun_accounts(backrun_accounts()).unwrap();
+    }
+
+    #[test]
+    fn a_backrun_selling_the_mint_the_frontrun_paid_with_is_rejected() {
+        // Source and destination passed the frontrun's way round
+        let mut accounts = backrun_accounts();
+        accounts.swap(15, 16);
+
+        assert_eq!(
+            try_backrun_accounts(accounts).unwrap_err(),
+            error!(ErrorCode::TokenMintMismatch)
+        );
+    }
+
+    #[test]
+    fn a_token_2022_pool_is_accepted() {
+        // Token-2022 vaults and a base mint charging a 0.1% transfer fee
+        try_backrun_accounts(backrun_accounts_for(
+            spl_token_2022::ID,
+            transfer_fee_mint(10),
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn the_swap_is_signed_for_by_the_amm_authority() {
+        let mut accounts: &[AccountInfo<'static>] = backrun_accounts().leak();
+        let backrun = AmmBackrunSwapBaseIn::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &SANDWICH_ID.to_le_bytes(),
+            &mut AmmBackrunSwapBaseInBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .unwrap();
+
+        // Third in the AMM's account list, the authority PDA rather than the program id
+        let authority = AMM_AUTHORITY_ID.parse::<Pubkey>().unwrap();
+        let metas = backrun.swap_account_metas();
+        assert_eq!(metas[2], AccountMeta::new_readonly(authority, false));
+        assert_ne!(authority, Amm::id());
+
+        // The program id in its place is rejected before the CPI
+        let mut accounts = backrun_accounts();
+        accounts[2] = program(Amm::id());
+        assert_eq!(
+            try_backrun_accounts(accounts).unwrap_err(),
+            error!(anchor_lang::error::ErrorCode::ConstraintAddress)
+        );
+    }
+
+    #[test]
+    fn each_spoofed_pair_account_is_rejected() {
+        for (index, name) in [
+            (3, "amm_open_orders"),
+            (4, "amm_target_orders"),
+            (5, "pool_coin_token_account"),
+            (6, "pool_pc_token_account"),
+            (8, "serum_market"),
+            (12, "serum_coin_vault_account"),
+            (13, "serum_pc_vault_account"),
+        ] {
+            // Same data under another address, only the key gives the spoof away
+            let mut accounts = backrun_accounts();
+            accounts[index] = AccountInfo {
+                key: Box::leak(Box::new(Pubkey::new_unique())),
+                ..accounts[index].clone()
+            };
+
+            assert_eq!(
+                try_backrun_accounts(accounts).unwrap_err(),
+                error!(ErrorCode::AmmAccountMismatch),
+                "{}",
+                name
+            );
+        }
+    }
+}
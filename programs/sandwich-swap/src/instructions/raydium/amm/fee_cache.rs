@@ -0,0 +1,126 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::instructions::amm::frontrun_swap_base_in::amm_fee_fraction;
+use crate::instructions::amm::pair::ProgramAccount;
+
+/// Input fee of an AMM pair, stored in the `[b"amm_fee", amm]` PDA so frontrunning the
+/// same pair repeatedly skips recomputing it from the pair's four fee fields.
+#[account]
+pub struct AmmFeeCache {
+    pub amm: Pubkey,           // The cached pair
+    pub fee_numerator: u128,   // Swap fee plus 16 % of the trade fee, see `amm_fee_fraction`
+    pub fee_denominator: u128, // Common denominator of both fees
+    pub updated_slot: u64,     // Slot of the last refresh
+    pub bump: u8,
+}
+
+impl AmmFeeCache {
+    pub const SIZE: usize = 32 + 16 + 16 + 8 + 1; // Size in bytes
+    pub const SEED: &'static [u8] = b"amm_fee";
+
+    /// Recomputes the fee of `amm_state` as of `slot`.
+    pub fn refresh(&mut self, amm_state: &ProgramAccount, slot: u64) -> Result<()> {
+        (self.fee_numerator, self.fee_denominator) = amm_fee_fraction(amm_state)?;
+        self.updated_slot = slot;
+        Ok(())
+    }
+
+    /// The cached `(numerator, denominator)`, unless more than `max_age_slots` slots
+    /// passed since the last refresh by `slot`. The pair's admin can change its fees at
+    /// any time, so a stale cache could size the frontrun on the wrong fee.
+    pub fn fee_fraction(&self, slot: u64, max_age_slots: u64) -> Result<(u128, u128)> {
+        require!(
+            slot.saturating_sub(self.updated_slot) <= max_age_slots,
+            ErrorCode::StaleFeeCache
+        );
+        Ok((self.fee_numerator, self.fee_denominator))
+    }
+}
+
+#[derive(Accounts)]
+pub struct RefreshAmmFees<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK Pair account whose fees are cached
+    pub amm: AccountLoader<'info, ProgramAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + AmmFeeCache::SIZE,
+        seeds = [AmmFeeCache::SEED, amm.key().as_ref()],
+        bump
+    )]
+    pub fee_cache: Account<'info, AmmFeeCache>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Caches the pair's current fee. Anyone can refresh it, the fee is read from the pair.
+pub fn refresh_amm_fees(ctx: Context<RefreshAmmFees>) -> Result<()> {
+    let fee_cache = &mut ctx.accounts.fee_cache;
+    fee_cache.amm = ctx.accounts.amm.key();
+    fee_cache.bump = ctx.bumps.fee_cache;
+    fee_cache.refresh(&ctx.accounts.amm.load()?, Clock::get()?.slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(swap_fee_numerator: u64, trade_fee_numerator: u64) -> ProgramAccount {
+        ProgramAccount {
+            swap_fee_numerator,
+            swap_fee_denominator: 10_000,
+            trade_fee_numerator,
+            trade_fee_denominator: 10_000,
+            ..Default::default()
+        }
+    }
+
+    fn empty_cache() -> AmmFeeCache {
+        AmmFeeCache {
+            amm: Pubkey::new_unique(),
+            fee_numerator: 0,
+            fee_denominator: 0,
+            updated_slot: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn the_cached_fee_matches_a_fresh_computation() {
+        let mut cache = empty_cache();
+
+        for pair in [pair(25, 25), pair(30, 0), pair(0, 100)] {
+            cache.refresh(&pair, 7).unwrap();
+            assert_eq!(
+                cache.fee_fraction(7, 0).unwrap(),
+                amm_fee_fraction(&pair).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn a_stale_cache_is_rejected() {
+        let mut cache = empty_cache();
+        cache.refresh(&pair(25, 25), 100).unwrap();
+
+        // Fresh up to the allowed age, including the refresh slot itself
+        assert_eq!(
+            cache.fee_fraction(100, 10).unwrap(),
+            (29_000_000, 10_000_000_000)
+        );
+        cache.fee_fraction(110, 10).unwrap();
+        assert_eq!(
+            cache.fee_fraction(111, 10).unwrap_err(),
+            error!(ErrorCode::StaleFeeCache)
+        );
+
+        // A refresh makes it fresh again
+        cache.refresh(&pair(25, 25), 111).unwrap();
+        cache.fee_fraction(111, 10).unwrap();
+    }
+}
@@ -1,49 +1,60 @@
 use crate::error::ErrorCode;
-use crate::instructions::{Amm, AmmAuthority, Serum, AMM_AUTHORITY_ID, SERUM_PROGRAM_ID, AMM_PROGRAM_ID, Swap};
-use crate::sandwich_state::SandwichState;
+use crate::instructions::{Amm, Serum, AMM_AUTHORITY_ID, SERUM_PROGRAM_ID, AMM_PROGRAM_ID, Swap, SWAP_BASE_IN_INSTRUCTION};
+use crate::sandwich_state::{SandwichState, MIN_PROFIT_BPS};
+use crate::program_config::*;
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{spl_token, Mint, Token, TokenAccount};
+use anchor_spl::token::Token;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeConfig},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use solana_program::instruction::Instruction;
 use solana_program::program::invoke_signed;
-use crate::instructions::amm::pair::ProgramAccount;
+use crate::instructions::amm::fee_cache::AmmFeeCache;
+use crate::instructions::amm::pair::{serum_market_vaults, ProgramAccount};
+use crate::instructions::amm::quadratic::{floor_positive_root, U256};
 
 #[derive(Accounts, Clone)]
-#[instruction(sandwich_id: String)]
+#[instruction(_target_amount_in: u64, _target_minimum_amount_out: u64, sandwich_id: u64)]
 pub struct AmmFrontrunSwapBaseIn<'info> {
     /// token program
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 
     /// CHECK Pair account
     #[account(mut)]
     pub amm: AccountLoader<'info, ProgramAccount>,
 
-    /// Raydium Authority
+    /// CHECK Raydium Authority, a PDA of the AMM program checked against its address
     #[account(address = AMM_AUTHORITY_ID.parse::<Pubkey>().unwrap())]
-    pub amm_authority: Program<'info, AmmAuthority>,
+    pub amm_authority: UncheckedAccount<'info>,
 
     /// CHECK Open Orders account
-    #[account(mut)]
+    #[account(mut, address = amm.load()?.open_orders @ ErrorCode::AmmAccountMismatch)]
     pub amm_open_orders: AccountInfo<'info>,
 
     /// CHECK Target Orders account
-    #[account(mut)]
+    #[account(mut, address = amm.load()?.target_orders @ ErrorCode::AmmAccountMismatch)]
     pub amm_target_orders: AccountInfo<'info>,
 
     /// Pool base token account
-    #[account(mut)]
-    pub pool_coin_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = amm.load()?.base_vault @ ErrorCode::AmmAccountMismatch)]
+    pub pool_coin_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// Pool quote token account
-    #[account(mut)]
-    pub pool_pc_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = amm.load()?.quote_vault @ ErrorCode::AmmAccountMismatch)]
+    pub pool_pc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// OpenBook program id
     #[account(address = SERUM_PROGRAM_ID.parse::<Pubkey>().unwrap())]
     pub serum_program: Program<'info, Serum>,
 
     /// CHECK Serum market account
-    #[account(mut)]
+    #[account(mut, address = amm.load()?.market_id @ ErrorCode::AmmAccountMismatch)]
     pub serum_market: AccountInfo<'info>,
 
     /// CHECK Serum bids account
@@ -58,29 +69,36 @@ pub struct AmmFrontrunSwapBaseIn<'info> {
     #[account(mut)]
     pub serum_event_queue: AccountInfo<'info>,
 
-    /// Pool base token account
-    #[account(mut)]
-    pub serum_coin_vault_account: Box<Account<'info, TokenAccount>>,
+    /// Serum base token vault
+    #[account(
+        mut,
+        address = serum_market_vaults(&serum_market)?.0 @ ErrorCode::AmmAccountMismatch
+    )]
+    pub serum_coin_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// Pool quote token account
-    #[account(mut)]
-    pub serum_pc_vault_account: Box<Account<'info, TokenAccount>>,
+    /// Serum quote token vault
+    #[account(
+        mut,
+        address = serum_market_vaults(&serum_market)?.1 @ ErrorCode::AmmAccountMismatch
+    )]
+    pub serum_pc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK Serum vault signer account
     pub serum_vault_signer: AccountInfo<'info>,
 
     /// User source token account
     #[account(mut)]
-    pub user_source_token_account: Box<Account<'info, TokenAccount>>,
+    pub user_source_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// User destination token account
     #[account(
         init_if_needed,
         payer = user_source_owner,
         associated_token::mint = base_mint,
-        associated_token::authority = user_source_owner
+        associated_token::authority = user_source_owner,
+        associated_token::token_program = token_program,
     )]
-    pub user_target_token_account: Box<Account<'info, TokenAccount>>,
+    pub user_target_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The user making the swap
     #[account(mut)]
@@ -91,7 +109,7 @@ pub struct AmmFrontrunSwapBaseIn<'info> {
        init_if_needed,
        payer = user_source_owner,
        space = 8 + SandwichState::SIZE,
-       seeds = [b"sandwich", sandwich_id.as_bytes()],
+       seeds = [b"sandwich", user_source_owner.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump
     )]
     pub sandwich_state: Account<'info, SandwichState>,
@@ -110,76 +128,144 @@ pub struct AmmFrontrunSwapBaseIn<'info> {
     #[account(
         constraint = base_mint.key() == amm.load()?.base_mint
     )]
-    pub base_mint: Account<'info, Mint>,
+    pub base_mint: InterfaceAccount<'info, Mint>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
+
+    /// Cached fee of the pair, read instead of the pair's fee fields when given
+    #[account(has_one = amm @ ErrorCode::AmmAccountMismatch)]
+    pub fee_cache: Option<Account<'info, AmmFeeCache>>,
 }
 
 /// Computes the maximum base‑in amount you can swap **before** the victim
 /// so that their `minimum_amount_out` is still satisfied, **including**
-/// Raydium’s input fee (default tier: 0.25 % of which 16 % is kept).
+/// Raydium’s input fee (`fee_numerator / fee_denominator` of every input).
 ///
 /// Returns:
 ///   • my_amount_in        – base/coin lamports you should swap
-///   • my_min_amount_out   – quote/pc lamports you expect (with 0.2 % slack)
-///   • profit_pct          – sandwich profit in base, relative to amount‑in
+///   • my_min_amount_out   – quote/pc lamports you expect (with 0.2 % slack)
+///   • profit_bps          – sandwich profit in base, relative to amount‑in
 ///
 /// Returns `None` if the sandwich would break slippage **or** profit < floor.
+///
+/// A Token-2022 base mint withholds `base_transfer_fee` on every base transfer: the
+/// victim's and our input reach the pool net of it, so our input is grossed up, and
+/// the base our back-run returns arrives net of it.
+///
+/// All integer: with g = 1 − fee and Δ = g·Δₜ the victim's effective input,
+/// the coin reserve after our buy x₁ = x₀ + D must satisfy
+/// M·x₁·(x₁ + Δ) ≤ g·Δ·x₀·y₀, whose largest integer solution is solved
+/// exactly in 256 bits (see `floor_positive_root`).
+#[allow(clippy::too_many_arguments)]
 fn compute_front_run_base_in_with_fee(
     x_base_reserve: u64,          // pool coin reserve      (x₀)
     y_quote_reserve: u64,         // pool pc   reserve      (y₀)
     target_amount_in: u64,        // victim amount_in       (Δₜ raw)
     target_min_amount_out: u64,   // victim minimum_out     (M)
-    fee_fraction: f64,            // 0.0004  (Raydium v4 default)
-    min_profit_pct: f64,          // 0.005   (0.5 %)
+    fee_numerator: u128,          // fee = numerator / denominator
+    fee_denominator: u128,
+    min_profit_bps: u128,         // 50 (0.5 %)
+    base_transfer_fee: Option<&TransferFee>, // Token-2022 base mint fee this epoch
 ) -> Option<(u64 /*my_amount_in*/,
              u64 /*my_min_amount_out*/,
-             f64 /*profit_pct*/)> {
+             u64 /*profit_bps*/)> {
 
     // ---------- constants ----------
-    let g = 1.0 - fee_fraction;                 // fraction that reaches pool
-    let x0 = x_base_reserve  as f64;
-    let y0 = y_quote_reserve as f64;
-    let k  = x0 * y0;                           // invariant
-
-    let dt_eff = target_amount_in as f64 * g;   // Δₜ·g   (effective add to x)
-    let m      = target_min_amount_out as f64;  // M
-
-    // ---------- quadratic coeffs  (see derivation in the answer) ----------
-    let a = m;                                  // a = M
-    let b = m * (dt_eff + 2.0 * x0);            // b = M (Δₜ·g + 2x₀)
-    let c = m * dt_eff * x0 + m * x0 * x0       // c = M (Δₜ·g·x₀ + x₀²)
-        - g * dt_eff * x0 * y0;             //     − gΔₜ x₀ y₀
-
-    let disc = b * b - 4.0 * a * c;
-    if disc <= 0.0 { return None; }             // victim already fails
+    let g_num = fee_denominator.checked_sub(fee_numerator)?; // g = g_num / fee_denominator
+    if g_num == 0 || target_min_amount_out == 0 { return None; }
+    let x0 = x_base_reserve  as u128;
+    let y0 = y_quote_reserve as u128;
+    let m  = target_min_amount_out as u128;             // M
+    let k  = x0 * y0;                                   // invariant, < 2^128
+
+    let transfer_fee = |amount: u64| match base_transfer_fee {
+        Some(fee) => fee.calculate_fee(amount),
+        None => Some(0),
+    };
+    let target_amount_in = target_amount_in.checked_sub(transfer_fee(target_amount_in)?)?;
+    let dt_eff = (target_amount_in as u128).checked_mul(g_num)? / fee_denominator; // Δₜ·g
 
-    let d_max = (-b + disc.sqrt()) / (2.0 * a); // D = g · my_amount_in
-    if d_max <= 0.0 { return None; }
+    // ---------- largest x₁ with x₁(x₁ + Δ) ≤ g·Δ·k / M ----------
+    let bound = U256::full_mul(k, dt_eff.checked_mul(g_num)?)
+        .checked_div(fee_denominator.checked_mul(m)?)?;
+    let x1 = floor_positive_root(dt_eff, bound)?;
+    if x1 <= x0 { return None; }                        // victim already fails
 
-    let my_amount_in = (d_max / g).floor() as u64;
+    let d_max = x1 - x0;                                // D = g · my_amount_in
+    let my_amount_in = u64::try_from(d_max.checked_mul(fee_denominator)? / g_num).ok()?;
     if my_amount_in == 0 { return None; }
+    let my_amount_in = match base_transfer_fee {                // gross up for the mint
+        Some(fee) => my_amount_in.checked_add(fee.calculate_inverse_fee(my_amount_in)?)?,
+        None => my_amount_in,
+    };
 
     // ---------- our front‑run quote out ----------
-    let y1     = k / (x0 + d_max);
-    let q_out  = y0 - y1;                       // quote we receive
-    if q_out <= 0.0 { return None; }
+    let q_out = y0.checked_mul(d_max)? / x1;            // quote we receive
+    if q_out == 0 { return None; }
 
     // ---------- simulate victim then our back‑run (quote‑in) ----------
-    let x1         = x0 + d_max;
-    let x2         = x1 + dt_eff;
+    let x2         = x1.checked_add(dt_eff)?;
     let y2         = k / x2;
-    let q_eff_back = q_out * g;                 // quote reaches pool (fee again)
-    let y3         = y2 + q_eff_back;
-    let x3         = k / y3;
-    let base_back  = x2 - x3;                   // we receive in back‑run
-    let profit     = base_back - (d_max / g);   // net in base/coin
-    let profit_pct = profit / (d_max / g);
-
-    if profit_pct < min_profit_pct { return None; }
+    let q_eff_back = q_out.checked_mul(g_num)? / fee_denominator; // quote reaches pool (fee again)
+    let base_back  = U256::full_mul(x2, q_eff_back)     // we receive in back‑run
+        .checked_div(y2.checked_add(q_eff_back)?)?
+        .to_u128()?;
+    let base_back  = base_back - transfer_fee(u64::try_from(base_back).ok()?)? as u128;
+    let my_amount_in = my_amount_in as u128;
+    if base_back.checked_mul(10_000)? < my_amount_in.checked_mul(10_000 + min_profit_bps)? {
+        return None;
+    }
+    let profit_bps = (base_back - my_amount_in) * 10_000 / my_amount_in;
+
+    // Provide a 0.2 % personal slippage cushion on our min_out
+    let my_min_amount_out = u64::try_from(q_out.checked_mul(998)? / 1000).ok()?;
+
+    let profit_bps = u64::try_from(profit_bps).unwrap_or(u64::MAX);
+
+    Some((my_amount_in as u64, my_min_amount_out, profit_bps))
+}
 
-    // Provide a 0.2 % personal slippage cushion on our min_out
-    let my_min_amount_out = (q_out * 0.998).floor() as u64;
+// Raydium's input fee as `(numerator, denominator)`: the swap fee plus the 16 % of the
+// trade fee that is kept
+pub(crate) fn amm_fee_fraction(amm_state: &ProgramAccount) -> Result<(u128, u128)> {
+    let swap_fee_numerator = amm_state.swap_fee_numerator as u128;
+    let swap_fee_denominator = amm_state.swap_fee_denominator as u128;
+    let trade_fee_numerator = amm_state.trade_fee_numerator as u128;
+    let trade_fee_denominator = amm_state.trade_fee_denominator as u128;
+    require!(
+        swap_fee_denominator > 0 && trade_fee_denominator > 0,
+        ErrorCode::CalculationFailure
+    );
+
+    let cross = |a: u128, b: u128, scale: u128| {
+        a.checked_mul(b)
+            .and_then(|product| product.checked_mul(scale))
+            .ok_or(ErrorCode::CalculationFailure)
+    };
+    let numerator = cross(swap_fee_numerator, trade_fee_denominator, 100)?
+        .checked_add(cross(trade_fee_numerator, swap_fee_denominator, 16)?)
+        .ok_or(ErrorCode::CalculationFailure)?;
+    let denominator = cross(swap_fee_denominator, trade_fee_denominator, 100)?;
+    require!(numerator < denominator, ErrorCode::CalculationFailure);
+    Ok((numerator, denominator))
+}
 
-    Some((my_amount_in, my_min_amount_out, profit_pct))
+/// Transfer fee `mint_info` charges in `epoch`, `None` for a classic SPL mint or a
+/// Token-2022 mint without the extension
+pub(crate) fn epoch_transfer_fee(
+    mint_info: &AccountInfo,
+    epoch: u64,
+) -> Result<Option<TransferFee>> {
+    if *mint_info.owner == Token::id() {
+        return Ok(None);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    Ok(mint
+        .get_extension::<TransferFeeConfig>()
+        .ok()
+        .map(|transfer_fee_config| *transfer_fee_config.get_epoch_fee(epoch)))
 }
 
 /// swap_base_in instruction
@@ -188,30 +274,55 @@ pub fn amm_frontrun_swap_base_in(
     target_amount_in: u64,
     target_minimum_amount_out: u64,
     sandwich_id: u64,
+    require_victim_ix: Option<Pubkey>,
+    max_fee_cache_age_slots: u64,
 ) -> Result<()> {
+    log_frontrun_compute_units("AMM");
+
+    // Refuse a paused program, a pool off the allowlist and a blocked mint
+    ctx.accounts.guards.check(&ctx.accounts.amm.key())?;
+    ctx.accounts.guards.check_mints(
+        &ctx.accounts.user_source_token_account.mint,
+        &ctx.accounts.user_target_token_account.mint,
+    )?;
+
+    // Only frontrun a victim landing later in the same transaction, when one is named
+    ctx.accounts.guards.check_victim_ix(require_victim_ix)?;
+
     let pool_coin  = ctx.accounts.pool_coin_token_account.amount;
     let pool_quote = ctx.accounts.pool_pc_token_account.amount;
 
-    let amm_state = ctx.accounts.amm.load()?;
-
-    let trade_fee = amm_state.trade_fee_numerator as f64
-        / amm_state.trade_fee_denominator as f64;
-    let swap_fee  = amm_state.swap_fee_numerator  as f64
-        / amm_state.swap_fee_denominator  as f64;
-    let fee_fraction = swap_fee + trade_fee * 0.16;
+    // fee = swap_fee + 16 % of trade_fee, over a common denominator, from the fee cache
+    // when one is passed. The pair borrow ends with the statement, before the swap CPI
+    // writes the account
+    let (fee_numerator, fee_denominator) = match &ctx.accounts.fee_cache {
+        Some(fee_cache) => fee_cache.fee_fraction(Clock::get()?.slot, max_fee_cache_age_slots)?,
+        None => amm_fee_fraction(&ctx.accounts.amm.load()?)?,
+    };
 
-    const MIN_PROFIT: f64 = 0.005; // 0.5%
+    // A Token-2022 base mint takes its transfer fee off every base leg
+    let base_transfer_fee =
+        epoch_transfer_fee(&ctx.accounts.base_mint.to_account_info(), Clock::get()?.epoch)?;
 
-    let (frontrun_amount_in, frontrun_min_out, _profit_pct) =
+    let (frontrun_amount_in, frontrun_min_out, _profit_bps) =
         compute_front_run_base_in_with_fee(
             pool_coin,
             pool_quote,
             target_amount_in,
             target_minimum_amount_out,
-            fee_fraction,
-            MIN_PROFIT,
+            fee_numerator,
+            fee_denominator,
+            MIN_PROFIT_BPS as u128,
+            base_transfer_fee.as_ref(),
         ).ok_or(ErrorCode::UnprofitableSandwich)?;
 
+    // Hold the frontrun to the configured notional cap
+    let (frontrun_amount_in, frontrun_min_out) = ctx
+        .accounts
+        .guards
+        .config
+        .cap_notional(frontrun_amount_in, frontrun_min_out)?;
+
     let account_metas = vec![
         AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
         AccountMeta::new(ctx.accounts.amm.key(), false),
@@ -255,7 +366,7 @@ pub fn amm_frontrun_swap_base_in(
     ];
 
     let ix_data = Swap {
-        discriminator: 9,
+        discriminator: SWAP_BASE_IN_INSTRUCTION,
         amount_in: frontrun_amount_in,
         min_amount_out: frontrun_min_out,
     }.data();
@@ -266,22 +377,244 @@ pub fn amm_frontrun_swap_base_in(
         data: ix_data,
     };
 
-    let lamports_before = ctx.accounts.user_source_token_account.amount;
+    let source_balance_before = ctx.accounts.user_source_token_account.amount;
     invoke_signed(&buy_ix, &accounts_vec, &[])?;
 
     ctx.accounts.user_source_token_account.reload()?;
     ctx.accounts.user_target_token_account.reload()?;
-    let lamports_after = ctx.accounts.user_source_token_account.amount;
-
-    let sandwich_state = &mut ctx.accounts.sandwich_state;
-    sandwich_state.frontrun_output_amount = ctx.accounts.user_target_token_account.amount;
-    sandwich_state.frontrun_input_amount = lamports_after.saturating_sub(lamports_before);
-    sandwich_state.sandwich_id = sandwich_id;
-    sandwich_state.token_in_mint = spl_token::native_mint::id();
-    sandwich_state.token_out_mint = *ctx.accounts.base_mint.to_account_info().key;
-    sandwich_state.timestamp = Clock::get()?.unix_timestamp;
-    sandwich_state.is_complete = false;
-    sandwich_state.bump = ctx.bumps.sandwich_state;
+    let source_balance_after = ctx.accounts.user_source_token_account.amount;
+
+    let (token_in_mint, token_out_mint) = amm_frontrun_mints(
+        &ctx.accounts.user_source_token_account,
+        &ctx.accounts.user_target_token_account,
+    )?;
+    ctx.accounts.sandwich_state.record_frontrun(
+        amm_frontrun_input_amount(source_balance_before, source_balance_after),
+        ctx.accounts.user_target_token_account.amount,
+        token_in_mint,
+        token_out_mint,
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
 
     Ok(())
 }
+
+/// Amount a frontrun spent from its source account, which went from `balance_before` down
+/// to `balance_after` during the swap.
+pub(crate) fn amm_frontrun_input_amount(balance_before: u64, balance_after: u64) -> u64 {
+    balance_before.saturating_sub(balance_after)
+}
+
+/// `(token_in_mint, token_out_mint)` of a frontrun paying from `user_source_token_account`
+/// into `user_target_token_account`. The context holds the target to the base mint's ATA,
+/// so the frontrun always buys the base mint, but it pays with whatever mint the source
+/// account holds, which isn't necessarily SOL.
+pub(crate) fn amm_frontrun_mints(
+    user_source_token_account: &TokenAccount,
+    user_target_token_account: &TokenAccount,
+) -> Result<(Pubkey, Pubkey)> {
+    require!(
+        user_source_token_account.mint != user_target_token_account.mint,
+        ErrorCode::TokenMintMismatch
+    );
+    Ok((user_source_token_account.mint, user_target_token_account.mint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::raydium::cpmm::swap_base_output::tests::transfer_fee_mint;
+    use anchor_spl::token::spl_token;
+    use solana_program::program_pack::Pack;
+
+    fn token_account(mint: Pubkey) -> TokenAccount {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        TokenAccount::try_deserialize(&mut data.as_slice()).unwrap()
+    }
+
+    // Raydium's default 0.25 % swap fee plus 16 % of the 0.25 % trade fee
+    fn default_fee() -> (u128, u128) {
+        amm_fee_fraction(&ProgramAccount {
+            swap_fee_numerator: 25,
+            swap_fee_denominator: 10_000,
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    // Fee-less output of `target_amount_in` into `reserve` on both sides, less `slippage_pct`
+    fn victim_minimum_out(reserve: u128, target_amount_in: u128, slippage_pct: u128) -> u128 {
+        reserve * target_amount_in / (reserve + target_amount_in) * (100 - slippage_pct) / 100
+    }
+
+    // Victim buying `target_amount_in` into `reserve` on both sides, accepting 5 % slippage
+    fn frontrun(reserve: u64, target_amount_in: u64) -> Option<(u64, u64, u64)> {
+        let (fee_numerator, fee_denominator) = default_fee();
+        compute_front_run_base_in_with_fee(
+            reserve,
+            reserve,
+            target_amount_in,
+            victim_minimum_out(reserve as u128, target_amount_in as u128, 5) as u64,
+            fee_numerator,
+            fee_denominator,
+            50,
+            None,
+        )
+    }
+
+    #[test]
+    fn fee_fraction_keeps_16_percent_of_the_trade_fee() {
+        assert_eq!(default_fee(), (29_000_000, 10_000_000_000));
+    }
+
+    #[test]
+    fn small_pool_sizing() {
+        assert_eq!(
+            frontrun(1_000_000_000, 10_000_000),
+            Some((23_199_203, 22_563_719, 135))
+        );
+    }
+
+    #[test]
+    fn large_pool_sizing_matches_an_exact_reference() {
+        // Solved with arbitrary precision integers, f64 lands 43 lamports higher and
+        // pushes the victim under its minimum
+        assert_eq!(
+            frontrun(1 << 60, 1 << 54),
+            Some((26_830_005_403_245_273, 26_093_231_008_521_739, 244))
+        );
+    }
+
+    #[test]
+    fn large_pool_frontrun_is_the_largest_the_victim_tolerates() {
+        let (reserve, target_amount_in) = (1u128 << 60, 1u128 << 54);
+        let (fee_numerator, fee_denominator) = default_fee();
+        let g_num = fee_denominator - fee_numerator;
+        let target_min_amount_out = victim_minimum_out(reserve, target_amount_in, 5);
+        let dt_eff = target_amount_in * g_num / fee_denominator;
+
+        // M·x₁·(x₁ + Δ)·fee_denominator ≤ g_num·Δ·x₀·y₀, compared exactly
+        let fits = |my_amount_in: u128| {
+            let x1 = reserve + my_amount_in * g_num / fee_denominator;
+            U256::full_mul(x1 * (x1 + dt_eff), fee_denominator * target_min_amount_out)
+                <= U256::full_mul(reserve * reserve, dt_eff * g_num)
+        };
+
+        // Rounding our input down through the fee leaves at most a lamport on the table
+        let (my_amount_in, _, _) = frontrun(1 << 60, 1 << 54).unwrap();
+        assert!(fits(my_amount_in as u128));
+        assert!(!fits(my_amount_in as u128 + 2));
+    }
+
+    #[test]
+    fn no_frontrun_without_victim_slippage() {
+        let (fee_numerator, fee_denominator) = default_fee();
+        let (reserve, target_amount_in) = (1u64 << 60, 1u64 << 54);
+        assert_eq!(
+            compute_front_run_base_in_with_fee(
+                reserve,
+                reserve,
+                target_amount_in,
+                victim_minimum_out(reserve as u128, target_amount_in as u128, 0) as u64,
+                fee_numerator,
+                fee_denominator,
+                50,
+                None,
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn a_token_2022_base_mint_is_sized_net_of_its_transfer_fee() {
+        let (fee_numerator, fee_denominator) = default_fee();
+        let (reserve, target_amount_in) = (1_000_000_000u64, 10_000_000u64);
+        let target_min_amount_out =
+            victim_minimum_out(reserve as u128, target_amount_in as u128, 5) as u64;
+        let size = |target_amount_in, base_transfer_fee| {
+            compute_front_run_base_in_with_fee(
+                reserve,
+                reserve,
+                target_amount_in,
+                target_min_amount_out,
+                fee_numerator,
+                fee_denominator,
+                50,
+                base_transfer_fee,
+            )
+        };
+
+        // 0.1% of the victim's 10_000_000 is withheld, sized fee-free on what's left
+        let mint = transfer_fee_mint(10);
+        let base_transfer_fee = epoch_transfer_fee(&mint, 0).unwrap().unwrap();
+        assert_eq!(size(9_990_000, None), Some((22_688_508, 22_078_001, 135)));
+
+        // Our input is grossed up to land the same amount in the pool, and paying the fee
+        // on both base legs eats into the profit
+        assert_eq!(
+            size(target_amount_in, Some(&base_transfer_fee)),
+            Some((22_711_220, 22_078_001, 114))
+        );
+        assert_eq!(
+            22_711_220 - base_transfer_fee.calculate_fee(22_711_220).unwrap(),
+            22_688_508
+        );
+
+        // A classic SPL mint has no fee to account for
+        let classic_mint = AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            false,
+            Box::leak(Box::new(0)),
+            &mut [],
+            &spl_token::ID,
+            false,
+            0,
+        );
+        assert!(epoch_transfer_fee(&classic_mint, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn the_recorded_input_is_what_the_source_account_spent() {
+        // The source account held 50_000 before the buy and 30_500 after it
+        assert_eq!(amm_frontrun_input_amount(50_000, 30_500), 19_500);
+        assert_eq!(amm_frontrun_input_amount(50_000, 50_000), 0);
+    }
+
+    #[test]
+    fn the_input_mint_is_the_source_account_mint() {
+        // Paying with USDC-like quote tokens rather than SOL
+        let quote_mint = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        assert_eq!(
+            amm_frontrun_mints(&token_account(quote_mint), &token_account(base_mint)).unwrap(),
+            (quote_mint, base_mint)
+        );
+
+        // A SOL input is still recorded as the native mint
+        assert_eq!(
+            amm_frontrun_mints(
+                &token_account(spl_token::native_mint::id()),
+                &token_account(base_mint)
+            )
+            .unwrap(),
+            (spl_token::native_mint::id(), base_mint)
+        );
+
+        // Swapping a mint for itself has no direction
+        assert_eq!(
+            amm_frontrun_mints(&token_account(base_mint), &token_account(base_mint)).unwrap_err(),
+            error!(ErrorCode::TokenMintMismatch)
+        );
+    }
+}
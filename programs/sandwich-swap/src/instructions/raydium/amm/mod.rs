@@ -4,9 +4,33 @@ use solana_program::pubkey::Pubkey;
 pub use frontrun_swap_base_in::*;
 
 pub mod backrun_swap_base_in;
+pub mod fee_cache;
 mod pair;
+pub(crate) mod quadratic;
 
 pub use backrun_swap_base_in::*;
+pub use fee_cache::*;
+
+/// Raydium AMM v4 `swap_base_in` instruction tag
+pub const SWAP_BASE_IN_INSTRUCTION: u8 = 9;
+
+/// Raydium AMM v4 is a native program: its instruction data is a 1-byte tag followed
+/// by the little-endian args, with no 8-byte Anchor discriminator in front.
+pub struct Swap {
+    pub discriminator: u8, // Instruction tag, see SWAP_BASE_IN_INSTRUCTION
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+}
+
+impl Swap {
+    pub fn data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(1 + 8 + 8);
+        data.push(self.discriminator);
+        data.extend_from_slice(&self.amount_in.to_le_bytes());
+        data.extend_from_slice(&self.min_amount_out.to_le_bytes());
+        data
+    }
+}
 
 // AMM program ID
 pub const AMM_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
@@ -32,14 +56,25 @@ impl anchor_lang::Id for Serum {
     }
 }
 
-// AMM program ID
-pub const AMM_AUTHORITY_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+// AMM authority, the PDA of the AMM program owning every pair's vaults (not the program itself)
+pub const AMM_AUTHORITY_ID: &str = "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1";
 
-#[derive(Clone)]
-pub struct AmmAuthority;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl anchor_lang::Id for AmmAuthority {
-    fn id() -> Pubkey {
-        AMM_AUTHORITY_ID.parse::<Pubkey>().unwrap()
+    #[test]
+    fn swap_data_is_tag_then_le_args() {
+        let swap = Swap {
+            discriminator: SWAP_BASE_IN_INSTRUCTION,
+            amount_in: 0x0102_0304_0506_0708,
+            min_amount_out: 42,
+        };
+
+        let data = swap.data();
+        assert_eq!(data.len(), 17);
+        assert_eq!(data[0], 9);
+        assert_eq!(data[1..9], [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(data[9..17], [42, 0, 0, 0, 0, 0, 0, 0]);
     }
 }
@@ -1,6 +1,8 @@
 use anchor_lang::prelude::*;
 use solana_program::pubkey::Pubkey;
 
+use crate::error::ErrorCode;
+
 #[account(zero_copy(unsafe))]
 #[repr(C, packed)]
 #[derive(Default, Debug)]
@@ -56,4 +58,24 @@ pub struct ProgramAccount {
     pub owner: Pubkey,
     pub lp_reserve: u64,
     pub padding: [u64; 3],
-}
\ No newline at end of file
+}
+// Serum market layout: 5 bytes of "serum" padding, the account flags, own address, vault
+// signer nonce and the coin and pc mints, then the coin vault and, after the coin
+// deposit and fee totals, the pc vault
+const SERUM_MARKET_COIN_VAULT_OFFSET: usize = 5 + 8 + 32 + 8 + 32 + 32;
+const SERUM_MARKET_PC_VAULT_OFFSET: usize = SERUM_MARKET_COIN_VAULT_OFFSET + 32 + 8 + 8;
+
+/// Coin and pc vaults recorded in the pair's serum market, which the pair state itself
+/// doesn't record.
+pub fn serum_market_vaults(serum_market: &AccountInfo) -> Result<(Pubkey, Pubkey)> {
+    let data = serum_market.try_borrow_data()?;
+    let vault_at = |offset: usize| {
+        data.get(offset..offset + 32)
+            .and_then(|bytes| Pubkey::try_from(bytes).ok())
+            .ok_or_else(|| error!(ErrorCode::AmmAccountMismatch))
+    };
+    Ok((
+        vault_at(SERUM_MARKET_COIN_VAULT_OFFSET)?,
+        vault_at(SERUM_MARKET_PC_VAULT_OFFSET)?,
+    ))
+}
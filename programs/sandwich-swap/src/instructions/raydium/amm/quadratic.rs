@@ -0,0 +1,252 @@
+//! Integer solver for the frontrun quadratic. Reserves above 2^52 don't fit f64's
+//! mantissa and `b*b - 4ac` cancels what precision is left, so the solve is exact here.
+
+/// Unsigned 256-bit integer as high and low u128 halves, just wide enough for the
+/// quadratic's discriminant. Field order makes the derived ordering numeric.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct U256 {
+    pub hi: u128,
+    pub lo: u128,
+}
+
+impl U256 {
+    pub const fn from_u128(value: u128) -> Self {
+        U256 { hi: 0, lo: value }
+    }
+
+    /// Full product of two u128s, which always fits.
+    pub fn full_mul(a: u128, b: u128) -> Self {
+        const MASK: u128 = u64::MAX as u128;
+        let (a_hi, a_lo) = (a >> 64, a & MASK);
+        let (b_hi, b_lo) = (b >> 64, b & MASK);
+
+        let lo_lo = a_lo * b_lo;
+        let hi_lo = a_hi * b_lo;
+        let lo_hi = a_lo * b_hi;
+        let hi_hi = a_hi * b_hi;
+
+        // Middle 64-bit column, three terms below 2^64 each so the sum can't overflow
+        let mid = (lo_lo >> 64) + (hi_lo & MASK) + (lo_hi & MASK);
+        U256 {
+            hi: hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (mid >> 64),
+            lo: (mid << 64) | (lo_lo & MASK),
+        }
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let (lo, carry) = self.lo.overflowing_add(other.lo);
+        let hi = self.hi.checked_add(other.hi)?.checked_add(carry as u128)?;
+        Some(U256 { hi, lo })
+    }
+
+    /// `self * 2^64`, `None` if it passes 256 bits.
+    pub fn checked_shl_64(self) -> Option<Self> {
+        if self.hi >> 64 != 0 {
+            return None;
+        }
+        Some(U256 {
+            hi: (self.hi << 64) | (self.lo >> 64),
+            lo: self.lo << 64,
+        })
+    }
+
+    /// Floor division by a u128, `None` for a zero divisor.
+    pub fn checked_div(self, divisor: u128) -> Option<Self> {
+        self.checked_div_rem(divisor).map(|(quotient, _)| quotient)
+    }
+
+    /// Floor division by a u128 along with its remainder, `None` for a zero divisor.
+    pub fn checked_div_rem(self, divisor: u128) -> Option<(Self, u128)> {
+        if divisor == 0 {
+            return None;
+        }
+        if self.hi == 0 {
+            return Some((U256::from_u128(self.lo / divisor), self.lo % divisor));
+        }
+
+        // Binary long division, one dividend bit at a time
+        let mut quotient = U256::default();
+        let mut remainder = 0u128;
+        for bit in (0..256).rev() {
+            // The bit shifted out of the remainder makes it larger than any divisor
+            let carry = remainder >> 127;
+            remainder = (remainder << 1) | self.bit(bit);
+            if carry == 1 || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.set_bit(bit);
+            }
+        }
+        Some((quotient, remainder))
+    }
+
+    pub fn to_u128(self) -> Option<u128> {
+        if self.hi == 0 {
+            Some(self.lo)
+        } else {
+            None
+        }
+    }
+
+    fn bit(&self, bit: u32) -> u128 {
+        if bit >= 128 {
+            (self.hi >> (bit - 128)) & 1
+        } else {
+            (self.lo >> bit) & 1
+        }
+    }
+
+    fn set_bit(&mut self, bit: u32) {
+        if bit >= 128 {
+            self.hi |= 1 << (bit - 128);
+        } else {
+            self.lo |= 1 << bit;
+        }
+    }
+}
+
+/// Integer square root rounded down.
+pub fn isqrt(n: U256) -> u128 {
+    // The root of anything below 2^256 fits in 128 bits, settle them from the top
+    let mut root = 0u128;
+    for bit in (0..128).rev() {
+        let candidate = root | (1 << bit);
+        if U256::full_mul(candidate, candidate) <= n {
+            root = candidate;
+        }
+    }
+    root
+}
+
+/// Largest integer `x` with `x * (x + b) <= c`, i.e. the positive root of
+/// `x^2 + b*x - c` rounded down. `None` if the discriminant overflows.
+pub fn floor_positive_root(b: u128, c: U256) -> Option<u128> {
+    let two_c = c.checked_add(c)?;
+    let discriminant = U256::full_mul(b, b).checked_add(two_c.checked_add(two_c)?)?;
+
+    // An integer x fits iff 2x + b <= sqrt(b^2 + 4c), which only depends on the floor
+    // of the root. The root is at least b since c is unsigned
+    Some((isqrt(discriminant) - b) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_mul_matches_u128_products() {
+        assert_eq!(U256::full_mul(3, 5), U256::from_u128(15));
+        assert_eq!(
+            U256::full_mul(u64::MAX as u128, u64::MAX as u128),
+            U256::from_u128((u64::MAX as u128) * (u64::MAX as u128))
+        );
+        // (2^128 - 1)^2 = 2^256 - 2^129 + 1
+        assert_eq!(
+            U256::full_mul(u128::MAX, u128::MAX),
+            U256 {
+                hi: u128::MAX - 1,
+                lo: 1
+            }
+        );
+    }
+
+    #[test]
+    fn checked_div_inverts_full_mul() {
+        let divisor = (1u128 << 100) + 12_345;
+        let quotient = (1u128 << 120) + 678;
+        let dividend = U256::full_mul(quotient, divisor)
+            .checked_add(U256::from_u128(divisor - 1))
+            .unwrap();
+        assert_eq!(
+            dividend.checked_div(divisor),
+            Some(U256::from_u128(quotient))
+        );
+
+        // A divisor with its top bit set exercises the carried-out remainder bit
+        assert_eq!(
+            U256::full_mul(u128::MAX, u128::MAX).checked_div(u128::MAX),
+            Some(U256::from_u128(u128::MAX))
+        );
+        assert_eq!(U256::from_u128(1).checked_div(0), None);
+    }
+
+    #[test]
+    fn checked_div_rem_keeps_what_the_division_drops() {
+        let divisor = (1u128 << 100) + 12_345;
+        let quotient = (1u128 << 120) + 678;
+        let dividend = U256::full_mul(quotient, divisor)
+            .checked_add(U256::from_u128(divisor - 1))
+            .unwrap();
+        assert_eq!(
+            dividend.checked_div_rem(divisor),
+            Some((U256::from_u128(quotient), divisor - 1))
+        );
+        assert_eq!(
+            U256::from_u128(17).checked_div_rem(5),
+            Some((U256::from_u128(3), 2))
+        );
+    }
+
+    #[test]
+    fn checked_shl_64_fails_past_256_bits() {
+        assert_eq!(
+            U256::from_u128(u128::MAX).checked_shl_64(),
+            Some(U256 {
+                hi: u64::MAX as u128,
+                lo: u128::MAX << 64
+            })
+        );
+        assert_eq!(U256 { hi: 1 << 64, lo: 0 }.checked_shl_64(), None);
+    }
+
+    #[test]
+    fn isqrt_rounds_down() {
+        assert_eq!(isqrt(U256::default()), 0);
+        assert_eq!(isqrt(U256::from_u128(15)), 3);
+        assert_eq!(isqrt(U256::from_u128(16)), 4);
+
+        let root = (1u128 << 100) + 7;
+        let square = U256::full_mul(root, root);
+        assert_eq!(isqrt(square), root);
+        assert_eq!(
+            isqrt(square.checked_add(U256::from_u128(2 * root)).unwrap()),
+            root
+        );
+        assert_eq!(
+            isqrt(U256 {
+                hi: u128::MAX,
+                lo: u128::MAX
+            }),
+            u128::MAX
+        );
+    }
+
+    // Reference root by bisection on exact 256-bit products
+    fn bisect_root(b: u128, c: U256) -> u128 {
+        let fits = |x: u128| U256::full_mul(x, x + b) <= c;
+        let (mut low, mut high) = (0u128, 1u128 << 100);
+        while low < high {
+            let mid = low + (high - low + 1) / 2;
+            if fits(mid) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        low
+    }
+
+    #[test]
+    fn floor_positive_root_matches_bisection_above_2_52() {
+        for (b, c) in [
+            (1u128 << 54, U256::full_mul(1 << 60, 1 << 60)),
+            ((1 << 53) + 1, U256::full_mul((1 << 61) - 1, (1 << 62) + 3)),
+            (0, U256::full_mul(u64::MAX as u128, u64::MAX as u128)),
+            (1 << 63, U256::full_mul((1 << 90) + 5, 1 << 70)),
+        ] {
+            let root = floor_positive_root(b, c).unwrap();
+            assert_eq!(root, bisect_root(b, c));
+            assert!(U256::full_mul(root, root + b) <= c);
+            assert!(U256::full_mul(root + 1, root + 1 + b) > c);
+        }
+    }
+}
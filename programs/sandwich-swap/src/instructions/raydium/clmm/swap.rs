@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
+    associated_token::AssociatedToken,
     memo::Memo,
     token::Token,
     token_2022::spl_token_2022::{
@@ -9,13 +10,21 @@ use anchor_spl::{
             BaseStateWithExtensions, StateWithExtensions,
         },
     },
-    token_interface::{Mint, Token2022, TokenAccount},
+    token_interface::{Mint, Token2022, TokenAccount, TokenInterface},
 };
 use raydium_clmm_cpi::{cpi, program::RaydiumClmm};
 
 use crate::{
     error::ErrorCode,
-    sandwich_state::{SandwichCompleteEvent, SandwichState},
+    instructions::{amm::quadratic::U256, ensure_token_sellable},
+    program_config::*,
+    sandwich_state::{
+        backrun_output_floor, check_backrun_balance, check_pool_age, check_victim_slippage,
+        min_required_output, self_price_impact_cap_bps, slippage_pips, with_fixed_cost,
+        with_min_profit_amount, with_retained_output, ClmmFrontrunDetailEvent,
+        SandwichCompleteEvent, SandwichState, SlippageModel, MIN_SANDWICH_PROFIT, PIPS_DENOMINATOR,
+        PIPS_PER_BPS,
+    },
 };
 
 // Number of ObservationState element
@@ -28,6 +37,15 @@ pub const Q64: u128 = (u64::MAX as u128) + 1; // 2^64
 pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
 /// The maximum value that can be returned from #get_sqrt_price_at_tick. Equivalent to get_sqrt_price_at_tick(MAX_TICK)
 pub const MAX_SQRT_PRICE_X64: u128 = 79226673521066979257578248091;
+/// The minimum tick that can be used on any pool
+pub const MIN_TICK: i32 = -443636;
+/// The maximum tick that can be used on any pool
+pub const MAX_TICK: i32 = -MIN_TICK;
+// Fractional bits computed by the log2 approximation in get_tick_at_sqrt_price
+const BIT_PRECISION: u32 = 16;
+/// Most remaining accounts forwarded to a CLMM swap: the tick array bitmap extension
+/// plus up to eight tick arrays
+pub const MAX_TICK_ARRAYS: usize = 1 + 8;
 
 // We define this here instead of importing AmmConfig to avoid duplicate
 // accounts error during idl building
@@ -84,6 +102,19 @@ pub struct ClmmObservationState {
     pub padding: [u128; 5],
 }
 
+impl ClmmObservationState {
+    /// Rejects an uninitialized observation unless `allow_uninitialized` lets the caller
+    /// fall back to the pool's spot `sqrt_price_x64`, which is logged
+    pub fn check_initialized(&self, allow_uninitialized: bool) -> Result<()> {
+        if self.initialized {
+            return Ok(());
+        }
+        require!(allow_uninitialized, ErrorCode::UninitializedObservation);
+        msg!("Observation state uninitialized, sizing from the pool's spot price");
+        Ok(())
+    }
+}
+
 // We define this here instead of importing PoolState to avoid duplicate
 // accounts error during idl building
 // details here https://github.com/solana-foundation/anchor/issues/3500
@@ -178,6 +209,13 @@ pub struct ClmmPoolState {
     pub padding2: [u64; 32],
 }
 
+impl ClmmPoolState {
+    /// Whether `vault` is one of the pool's two token vaults
+    pub fn is_token_vault(&self, vault: &Pubkey) -> bool {
+        *vault == self.token_vault_0 || *vault == self.token_vault_1
+    }
+}
+
 #[zero_copy(unsafe)]
 #[repr(C, packed)]
 #[derive(Default, Debug, PartialEq, Eq)]
@@ -232,16 +270,23 @@ pub struct ClmmSwap<'info> {
     pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The vault token account for input token
-    #[account(mut)]
+    #[account(
+      mut,
+      constraint = pool_state.load()?.is_token_vault(&input_vault.key())
+    )]
     pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The vault token account for output token
-    #[account(mut)]
+    #[account(
+      mut,
+      constraint = pool_state.load()?.is_token_vault(&output_vault.key())
+    )]
     pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// The program account for the most recent oracle observation
+    /// CHECK: The program account for the most recent oracle observation, only passed on
+    /// to the swap CPI which updates it. Checked by key, its data is never loaded
     #[account(mut, address = pool_state.load()?.observation_key)]
-    pub observation_state: AccountLoader<'info, ClmmObservationState>,
+    pub observation_state: UncheckedAccount<'info>,
 
     /// SPL program for token transfers
     pub token_program: Program<'info, Token>,
@@ -277,6 +322,8 @@ pub fn clmm_swap<'a, 'b, 'c: 'info, 'info>(
     sqrt_price_limit_x64: u128,
     is_base_input: bool,
 ) -> Result<()> {
+    check_tick_array_count(ctx.remaining_accounts)?;
+
     let cpi_accounts = cpi::accounts::SwapSingleV2 {
         payer: ctx.accounts.payer.to_account_info(),
         amm_config: ctx.accounts.amm_config.to_account_info(),
@@ -304,7 +351,13 @@ pub fn clmm_swap<'a, 'b, 'c: 'info, 'info>(
 }
 
 #[derive(Accounts)]
-#[instruction(sandwich_id: String)]
+#[instruction(
+    _target_amount: u64,
+    _target_other_amount_threshold: u64,
+    _target_sqrt_price_limit_x64: u128,
+    _target_is_base_input: bool,
+    sandwich_id: u64
+)]
 pub struct ClmmSandwichFrontrun<'info> {
     pub clmm_program: Program<'info, RaydiumClmm>,
 
@@ -324,25 +377,32 @@ pub struct ClmmSandwichFrontrun<'info> {
     #[account(mut)]
     pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// The user token account for output token
-    #[account(mut)]
+    /// The user's associated token account for the output token, created if missing
+    #[account(
+       init_if_needed,
+       payer = payer,
+       associated_token::mint = output_vault_mint,
+       associated_token::authority = payer,
+       associated_token::token_program = output_token_program,
+    )]
     pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The vault token account for input token
     #[account(
       mut,
-      constraint = input_vault.key() == pool_state.load()?.token_vault_0 || input_vault.key() == pool_state.load()?.token_vault_1
+      constraint = pool_state.load()?.is_token_vault(&input_vault.key())
     )]
     pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The vault token account for output token
     #[account(
       mut,
-      constraint = output_vault.key() == pool_state.load()?.token_vault_0 || output_vault.key() == pool_state.load()?.token_vault_1
+      constraint = pool_state.load()?.is_token_vault(&output_vault.key())
     )]
     pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// The program account for the most recent oracle observation
+    /// The program account for the most recent oracle observation, read to tell whether
+    /// it's initialized before sizing
     #[account(mut, address = pool_state.load()?.observation_key)]
     pub observation_state: AccountLoader<'info, ClmmObservationState>,
 
@@ -363,21 +423,122 @@ pub struct ClmmSandwichFrontrun<'info> {
     #[account(address = output_vault.mint)]
     pub output_vault_mint: Box<InterfaceAccount<'info, Mint>>,
 
+    /// SPL program owning the output mint, for creating the output token account
+    #[account(address = *output_vault_mint.to_account_info().owner)]
+    pub output_token_program: Interface<'info, TokenInterface>,
+
     /// The account that will store sandwich state
     #[account(
        init,
        payer = payer,
        space = 8 + SandwichState::SIZE,
-       seeds = [b"sandwich", sandwich_id.as_bytes()],
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump
     )]
     pub sandwich_state: Account<'info, SandwichState>,
 
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
     pub system_program: Program<'info, System>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
+}
+
+impl<'info> ClmmSandwichFrontrun<'info> {
+    /// The victim's amount as it reaches the pool: an exact input less the input
+    /// transfer fee, an exact output plus the output transfer fee.
+    pub fn target_actual_amount(
+        &self,
+        target_amount: u64,
+        target_is_base_input: bool,
+    ) -> Result<u64> {
+        let epoch = get_recent_epoch()?;
+        if target_is_base_input {
+            let transfer_fee = clmm_get_transfer_fee(&self.input_vault_mint, target_amount, epoch)?;
+            Ok(target_amount.saturating_sub(transfer_fee))
+        } else {
+            let transfer_fee =
+                clmm_get_transfer_inverse_fee(&self.output_vault_mint, target_amount, epoch)?;
+            Ok(target_amount.saturating_add(transfer_fee))
+        }
+    }
+
+    /// Runs the guards every CLMM frontrun passes before it is sized: the program, pool
+    /// and mint guards, the tick arrays, the victim's instruction, the price drift since
+    /// the frontrun was sized off-chain, the pool's age and the observation.
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_frontrun(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        require_victim_ix: Option<Pubkey>,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        min_pool_age_secs: u32,
+        allow_uninitialized_observation: bool,
+    ) -> Result<()> {
+        // Refuse a paused program, a pool off the allowlist and a blocked mint
+        self.guards.check(&self.pool_state.key())?;
+        self.guards
+            .check_mints(&self.input_vault_mint.key(), &self.output_vault_mint.key())?;
+        check_tick_array_count(remaining_accounts)?;
+
+        // Only frontrun a victim landing later in the same transaction, when one is named
+        self.guards.check_victim_ix(require_victim_ix)?;
+
+        // Refuse a pool whose price moved since the frontrun was sized off-chain, the
+        // expected price is passed as a sqrt price like the pool's
+        let pool_state = self.pool_state.load()?;
+        check_sqrt_price_drift(
+            pool_state.sqrt_price_x64,
+            expected_current_price,
+            max_price_drift_bps,
+        )?;
+
+        // Check if the pool is open for trading, and has been for long enough
+        let now = Clock::get()?.unix_timestamp;
+        require_gt!(now as u64, pool_state.open_time);
+        check_pool_age(pool_state.open_time, now, min_pool_age_secs)?;
+
+        // Without an initialized observation the sizing only has the spot price to go on
+        self.observation_state
+            .load()?
+            .check_initialized(allow_uninitialized_observation)
+    }
+
+    /// Makes sure the output token of a frontrun swapping `frontrun_amount` at the current
+    /// price can be sold back before buying it.
+    pub fn check_sellable(
+        &self,
+        frontrun_amount: u64,
+        current_sqrt_price_x64: u128,
+        current_tick: i32,
+        liquidity: u128,
+        zero_for_one: bool,
+    ) -> Result<()> {
+        let expected_frontrun_output = simulate_clmm_swap_output(
+            current_sqrt_price_x64,
+            current_tick,
+            liquidity,
+            frontrun_amount,
+            zero_for_one,
+            self.amm_config.trade_fee_rate,
+            self.amm_config.protocol_fee_rate,
+            self.amm_config.fund_fee_rate,
+        )?;
+        if expected_frontrun_output == 0 {
+            return err!(ErrorCode::TokenNotSellable);
+        }
+        ensure_token_sellable(
+            &self.output_vault_mint.to_account_info(),
+            expected_frontrun_output,
+        )
+    }
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(sandwich_id: String)]
+#[instruction(sandwich_id: u64)]
 pub struct ClmmSandwichBackrun<'info> {
     pub clmm_program: Program<'info, RaydiumClmm>,
 
@@ -404,20 +565,21 @@ pub struct ClmmSandwichBackrun<'info> {
     /// The vault token account for input token (was output in frontrun)
     #[account(
       mut,
-      constraint = input_vault.key() == pool_state.load()?.token_vault_0 || input_vault.key() == pool_state.load()?.token_vault_1
+      constraint = pool_state.load()?.is_token_vault(&input_vault.key())
     )]
     pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The vault token account for output token (was input in frontrun)
     #[account(
       mut,
-      constraint = output_vault.key() == pool_state.load()?.token_vault_0 || output_vault.key() == pool_state.load()?.token_vault_1
+      constraint = pool_state.load()?.is_token_vault(&output_vault.key())
     )]
     pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// The program account for the most recent oracle observation
+    /// CHECK: The program account for the most recent oracle observation, only passed on
+    /// to the swap CPI which updates it. Checked by key, its data is never loaded
     #[account(mut, address = pool_state.load()?.observation_key)]
-    pub observation_state: AccountLoader<'info, ClmmObservationState>,
+    pub observation_state: UncheckedAccount<'info>,
 
     /// SPL program for token transfers
     pub token_program: Program<'info, Token>,
@@ -439,17 +601,26 @@ pub struct ClmmSandwichBackrun<'info> {
     /// The account that stores sandwich state
     #[account(
        mut,
-       seeds = [b"sandwich", sandwich_id.as_bytes()],
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump = sandwich_state.bump,
        constraint = !sandwich_state.is_complete @ ErrorCode::SandwichAlreadyCompleted,
-       constraint = sandwich_state.token_in_mint == *output_vault_mint.to_account_info().key
-           @ ErrorCode::TokenMintMismatch,
-       constraint = sandwich_state.token_out_mint == *input_vault_mint.to_account_info().key
-           @ ErrorCode::TokenMintMismatch
+       constraint = sandwich_state.is_unwound_by(
+           input_vault_mint.to_account_info().key,
+           output_vault_mint.to_account_info().key,
+       ) @ ErrorCode::TokenMintMismatch
     )]
     pub sandwich_state: Account<'info, SandwichState>,
+
+    /// Program config, tracks the losing streak for the circuit breaker
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn clmm_frontrun_swap<'a, 'b, 'c: 'info, 'info>(
     ctx: Context<'a, 'b, 'c, 'info, ClmmSandwichFrontrun<'info>>,
     target_amount: u64,
@@ -457,32 +628,64 @@ pub fn clmm_frontrun_swap<'a, 'b, 'c: 'info, 'info>(
     target_sqrt_price_limit_x64: u128,
     target_is_base_input: bool,
     sandwich_id: u64,
+    min_victim_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    check_sellable: bool,
+    min_pool_age_secs: u32,
+    allow_uninitialized_observation: bool,
+    max_ticks_to_cross: u16,
+    expected_current_price: u128,
+    max_price_drift_bps: u16,
+    require_victim_ix: Option<Pubkey>,
+    slippage_model: SlippageModel,
 ) -> Result<()> {
+    log_frontrun_compute_units("CLMM");
+
+    // Run the guards shared by every CLMM frontrun
+    ctx.accounts.check_frontrun(
+        ctx.remaining_accounts,
+        require_victim_ix,
+        expected_current_price,
+        max_price_drift_bps,
+        min_pool_age_secs,
+        allow_uninitialized_observation,
+    )?;
+
     // Load pool state to get current price and liquidity
     let pool_state = ctx.accounts.pool_state.load()?;
     let current_sqrt_price_x64 = pool_state.sqrt_price_x64;
     let current_tick = pool_state.tick_current;
     let liquidity = pool_state.liquidity;
-
-    // Check if the pool is open for trading
-    require_gt!(Clock::get()?.unix_timestamp as u64, pool_state.open_time);
+    let tick_spacing = pool_state.tick_spacing;
 
     // Determine the swap direction
     let zero_for_one = ctx.accounts.input_vault.mint == pool_state.token_mint_0;
 
+    // Release the pool borrow, the swap CPI needs the account free
+    drop(pool_state);
+
     // Calculate adjustments for transfer fees if needed
-    let target_actual_amount = if target_is_base_input {
-        let transfer_fee =
-            clmm_get_transfer_fee(*ctx.accounts.input_vault_mint.clone(), target_amount)?;
-        target_amount.saturating_sub(transfer_fee)
-    } else {
-        let transfer_fee =
-            clmm_get_transfer_inverse_fee(*ctx.accounts.output_vault_mint.clone(), target_amount)?;
-        target_amount.saturating_add(transfer_fee)
-    };
+    let target_actual_amount = ctx
+        .accounts
+        .target_actual_amount(target_amount, target_is_base_input)?;
+
+    // Keep the sizing's compute bounded, a victim sweeping too many ticks is refused
+    let victim_tick_crossings = clmm_victim_tick_crossings(
+        current_sqrt_price_x64,
+        current_tick,
+        tick_spacing,
+        liquidity,
+        target_actual_amount,
+        target_is_base_input,
+        zero_for_one,
+        ctx.accounts.amm_config.trade_fee_rate,
+        ctx.accounts.amm_config.protocol_fee_rate,
+        ctx.accounts.amm_config.fund_fee_rate,
+    )?;
+    check_tick_crossings(victim_tick_crossings, max_ticks_to_cross)?;
 
-    // Calculate target's slippage tolerance in basis points
-    let target_slippage_bps = calculate_clmm_slippage(
+    // Calculate target's slippage tolerance in pips, reduced to basis points only to compare
+    let (target_expected_amount, target_slippage_pips) = calculate_clmm_slippage(
         target_actual_amount,
         target_other_amount_threshold,
         target_is_base_input,
@@ -495,9 +698,33 @@ pub fn clmm_frontrun_swap<'a, 'b, 'c: 'info, 'info>(
         ctx.accounts.amm_config.protocol_fee_rate,
         ctx.accounts.amm_config.fund_fee_rate,
     )?;
+    let (target_amount_in, target_amount_out) = if target_is_base_input {
+        (target_actual_amount, target_expected_amount)
+    } else {
+        (target_expected_amount, target_actual_amount)
+    };
+    log_victim_amounts(
+        "CLMM",
+        target_amount_in,
+        target_amount_out,
+        target_slippage_pips,
+    );
+
+    // Skip victims whose tolerance is too tight to sandwich without breaking their tx
+    check_victim_slippage(target_slippage_pips, min_victim_slippage_bps)?;
+
+    // Use the slippage model's share of target's slippage tolerance to ensure their tx
+    // succeeds, up to the configured absolute cap
+    let safe_slippage_pips = slippage_model.safe_slippage_pips(
+        target_slippage_pips,
+        ctx.accounts
+            .guards
+            .config
+            .max_absolute_slippage_consumed_bps,
+    );
 
-    // Use 95% of target's slippage tolerance to ensure their tx succeeds
-    let safe_slippage_bps = target_slippage_bps.saturating_mul(95).saturating_div(100);
+    // Cap on how far our own buy may move the mid price, whichever cap binds first wins
+    let self_impact_cap_bps = self_price_impact_cap_bps(max_self_price_impact_bps)?;
 
     // Calculate optimal sandwich amount through binary search
     let optimal_amount = calculate_optimal_clmm_sandwich_amount(
@@ -505,12 +732,14 @@ pub fn clmm_frontrun_swap<'a, 'b, 'c: 'info, 'info>(
         current_tick,
         liquidity,
         target_actual_amount,
-        safe_slippage_bps,
+        safe_slippage_pips,
+        self_impact_cap_bps,
         target_is_base_input,
         zero_for_one,
         ctx.accounts.amm_config.trade_fee_rate,
         ctx.accounts.amm_config.protocol_fee_rate,
         ctx.accounts.amm_config.fund_fee_rate,
+        MIN_SANDWICH_PROFIT,
     )?;
 
     // Ensure calculated amount is reasonable
@@ -518,54 +747,214 @@ pub fn clmm_frontrun_swap<'a, 'b, 'c: 'info, 'info>(
         return err!(ErrorCode::InsufficientSandwichAmount);
     }
 
-    // Calculate appropriate sqrt_price_limit_x64 for our frontrun transaction
-    let frontrun_sqrt_price_limit_x64 = if zero_for_one {
-        // Limit how far down the price can go to ensure target transaction success
-        let price_impact = calculate_price_impact(
+    // Hold the frontrun to the configured notional cap, it sets no minimum output
+    let (optimal_amount, _) = ctx.accounts.guards.config.cap_notional(optimal_amount, 0)?;
+
+    // Optionally make sure the output token can be sold back before buying it
+    if check_sellable {
+        ctx.accounts.check_sellable(
+            optimal_amount,
             current_sqrt_price_x64,
+            current_tick,
             liquidity,
-            optimal_amount,
             zero_for_one,
-            true, // Always exact input for frontrun
-            ctx.accounts.amm_config.trade_fee_rate,
         )?;
+    }
 
-        let min_allowed_price = if target_sqrt_price_limit_x64 > 0 {
-            // If target specified a price limit, respect it
-            std::cmp::max(
-                target_sqrt_price_limit_x64,
-                current_sqrt_price_x64.saturating_sub(price_impact),
-            )
-        } else {
-            current_sqrt_price_x64.saturating_sub(price_impact)
-        };
+    execute_clmm_frontrun(
+        ctx,
+        optimal_amount,
+        current_sqrt_price_x64,
+        liquidity,
+        zero_for_one,
+        target_sqrt_price_limit_x64,
+        safe_slippage_pips,
+        sandwich_id,
+    )
+}
 
-        // Ensure we don't go below the absolute minimum allowed
-        std::cmp::max(MIN_SQRT_PRICE_X64 + 1, min_allowed_price)
+/// Same as `clmm_frontrun_swap`, but swaps `frontrun_amount` sized off-chain instead of
+/// searching for it, refusing an amount that breaks the victim's slippage.
+#[allow(clippy::too_many_arguments)]
+pub fn clmm_frontrun_swap_fixed<'a, 'b, 'c: 'info, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, ClmmSandwichFrontrun<'info>>,
+    target_amount: u64,
+    target_other_amount_threshold: u64,
+    target_sqrt_price_limit_x64: u128,
+    target_is_base_input: bool,
+    sandwich_id: u64,
+    frontrun_amount: u64,
+    min_victim_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    check_sellable: bool,
+    min_pool_age_secs: u32,
+    allow_uninitialized_observation: bool,
+    max_ticks_to_cross: u16,
+    expected_current_price: u128,
+    max_price_drift_bps: u16,
+    require_victim_ix: Option<Pubkey>,
+    slippage_model: SlippageModel,
+) -> Result<()> {
+    log_frontrun_compute_units("CLMM");
+
+    // Run the guards shared by every CLMM frontrun
+    ctx.accounts.check_frontrun(
+        ctx.remaining_accounts,
+        require_victim_ix,
+        expected_current_price,
+        max_price_drift_bps,
+        min_pool_age_secs,
+        allow_uninitialized_observation,
+    )?;
+
+    // Load pool state to get current price and liquidity
+    let pool_state = ctx.accounts.pool_state.load()?;
+    let current_sqrt_price_x64 = pool_state.sqrt_price_x64;
+    let current_tick = pool_state.tick_current;
+    let liquidity = pool_state.liquidity;
+    let tick_spacing = pool_state.tick_spacing;
+
+    // Determine the swap direction
+    let zero_for_one = ctx.accounts.input_vault.mint == pool_state.token_mint_0;
+
+    // Release the pool borrow, the swap CPI needs the account free
+    drop(pool_state);
+
+    // Calculate adjustments for transfer fees if needed
+    let target_actual_amount = ctx
+        .accounts
+        .target_actual_amount(target_amount, target_is_base_input)?;
+
+    // Keep the check's compute bounded, a victim sweeping too many ticks is refused
+    let victim_tick_crossings = clmm_victim_tick_crossings(
+        current_sqrt_price_x64,
+        current_tick,
+        tick_spacing,
+        liquidity,
+        target_actual_amount,
+        target_is_base_input,
+        zero_for_one,
+        ctx.accounts.amm_config.trade_fee_rate,
+        ctx.accounts.amm_config.protocol_fee_rate,
+        ctx.accounts.amm_config.fund_fee_rate,
+    )?;
+    check_tick_crossings(victim_tick_crossings, max_ticks_to_cross)?;
+
+    // Calculate target's slippage tolerance in pips, reduced to basis points only to compare
+    let (target_expected_amount, target_slippage_pips) = calculate_clmm_slippage(
+        target_actual_amount,
+        target_other_amount_threshold,
+        target_is_base_input,
+        target_sqrt_price_limit_x64,
+        current_sqrt_price_x64,
+        current_tick,
+        liquidity,
+        zero_for_one,
+        ctx.accounts.amm_config.trade_fee_rate,
+        ctx.accounts.amm_config.protocol_fee_rate,
+        ctx.accounts.amm_config.fund_fee_rate,
+    )?;
+    let (target_amount_in, target_amount_out) = if target_is_base_input {
+        (target_actual_amount, target_expected_amount)
     } else {
-        // Limit how high the price can go to ensure target transaction success
-        let price_impact = calculate_price_impact(
+        (target_expected_amount, target_actual_amount)
+    };
+    log_victim_amounts(
+        "CLMM",
+        target_amount_in,
+        target_amount_out,
+        target_slippage_pips,
+    );
+
+    // Skip victims whose tolerance is too tight to sandwich without breaking their tx
+    check_victim_slippage(target_slippage_pips, min_victim_slippage_bps)?;
+
+    // Use the slippage model's share of target's slippage tolerance to ensure their tx
+    // succeeds, up to the configured absolute cap
+    let safe_slippage_pips = slippage_model.safe_slippage_pips(
+        target_slippage_pips,
+        ctx.accounts
+            .guards
+            .config
+            .max_absolute_slippage_consumed_bps,
+    );
+
+    // Hold the frontrun to the configured notional cap, it sets no minimum output
+    let (frontrun_amount, _) = ctx
+        .accounts
+        .guards
+        .config
+        .cap_notional(frontrun_amount, 0)?;
+    require_gt!(frontrun_amount, 0, ErrorCode::InsufficientSandwichAmount);
+
+    // Cap on how far our own buy may move the mid price, same as the searching frontrun
+    let self_impact_cap_bps = self_price_impact_cap_bps(max_self_price_impact_bps)?;
+
+    // The fixed frontrun skips the search, it only has to leave the victim within its
+    // tolerance and stay under the self cap
+    check_clmm_fixed_frontrun(
+        current_sqrt_price_x64,
+        current_tick,
+        liquidity,
+        frontrun_amount,
+        target_actual_amount,
+        safe_slippage_pips,
+        self_impact_cap_bps,
+        target_is_base_input,
+        zero_for_one,
+        ctx.accounts.amm_config.trade_fee_rate,
+        ctx.accounts.amm_config.protocol_fee_rate,
+        ctx.accounts.amm_config.fund_fee_rate,
+    )?;
+
+    // Optionally make sure the output token can be sold back before buying it
+    if check_sellable {
+        ctx.accounts.check_sellable(
+            frontrun_amount,
             current_sqrt_price_x64,
+            current_tick,
             liquidity,
-            optimal_amount,
             zero_for_one,
-            true, // Always exact input for frontrun
-            ctx.accounts.amm_config.trade_fee_rate,
         )?;
+    }
 
-        let max_allowed_price = if target_sqrt_price_limit_x64 > 0 {
-            // If target specified a price limit, respect it
-            std::cmp::min(
-                target_sqrt_price_limit_x64,
-                current_sqrt_price_x64.saturating_add(price_impact),
-            )
-        } else {
-            current_sqrt_price_x64.saturating_add(price_impact)
-        };
+    execute_clmm_frontrun(
+        ctx,
+        frontrun_amount,
+        current_sqrt_price_x64,
+        liquidity,
+        zero_for_one,
+        target_sqrt_price_limit_x64,
+        safe_slippage_pips,
+        sandwich_id,
+    )
+}
 
-        // Ensure we don't go above the absolute maximum allowed
-        std::cmp::min(MAX_SQRT_PRICE_X64 - 1, max_allowed_price)
-    };
+// Swaps `optimal_amount` into the pool as the frontrun, its price limit bounded by the
+// target's, and records it for the backrun
+#[allow(clippy::too_many_arguments)]
+fn execute_clmm_frontrun<'a, 'b, 'c: 'info, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, ClmmSandwichFrontrun<'info>>,
+    optimal_amount: u64,
+    current_sqrt_price_x64: u128,
+    liquidity: u128,
+    zero_for_one: bool,
+    target_sqrt_price_limit_x64: u128,
+    safe_slippage_pips: u128,
+    sandwich_id: u64,
+) -> Result<()> {
+    // Emit the chosen frontrun parameters for debugging
+    let detail = clmm_frontrun_detail(
+        optimal_amount,
+        current_sqrt_price_x64,
+        liquidity,
+        zero_for_one,
+        target_sqrt_price_limit_x64,
+        safe_slippage_pips,
+        ctx.accounts.amm_config.trade_fee_rate,
+    )?;
+    let frontrun_sqrt_price_limit_x64 = detail.frontrun_sqrt_price_limit_x64;
+    emit!(detail);
 
     // Record initial balances
     let output_token_balance_before = ctx.accounts.output_token_account.amount;
@@ -617,95 +1006,192 @@ pub fn clmm_frontrun_swap<'a, 'b, 'c: 'info, 'info>(
         .unwrap();
 
     // Store frontrun data in PDA for backrun
-    let sandwich_state = &mut ctx.accounts.sandwich_state;
-    sandwich_state.frontrun_output_amount = frontrun_output_amount;
-    sandwich_state.frontrun_input_amount = frontrun_input_amount;
-    sandwich_state.sandwich_id = sandwich_id;
-    sandwich_state.token_in_mint = *ctx.accounts.input_vault_mint.to_account_info().key;
-    sandwich_state.token_out_mint = *ctx.accounts.output_vault_mint.to_account_info().key;
-    sandwich_state.timestamp = Clock::get()?.unix_timestamp;
-    sandwich_state.is_complete = false;
-    sandwich_state.bump = ctx.bumps.sandwich_state;
+    ctx.accounts.sandwich_state.record_frontrun(
+        frontrun_input_amount,
+        frontrun_output_amount,
+        *ctx.accounts.input_vault_mint.to_account_info().key,
+        *ctx.accounts.output_vault_mint.to_account_info().key,
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
 
     Ok(())
 }
 
-pub fn clmm_backrun_swap<'a, 'b, 'c: 'info, 'info>(
-    ctx: Context<'a, 'b, 'c, 'info, ClmmSandwichBackrun<'info>>,
-    sandwich_id: u64,
-) -> Result<()> {
-    // Get the exact amounts from the frontrun transaction
-    let frontrun_output = ctx.accounts.sandwich_state.frontrun_output_amount;
-    let frontrun_input = ctx.accounts.sandwich_state.frontrun_input_amount;
-
-    // Load pool state to get current price (after target tx)
-    let pool_state = ctx.accounts.pool_state.load()?;
-    let current_sqrt_price_x64 = pool_state.sqrt_price_x64;
-    let current_tick = pool_state.tick_current;
-    let liquidity = pool_state.liquidity;
-
-    // Check if the pool is open for trading
-    require_gt!(Clock::get()?.unix_timestamp as u64, pool_state.open_time);
+// The frontrun's parameters, emitted before its swap: the sqrt price limit stops it at
+// the price `optimal_amount` is expected to move the pool to, bounded by the target's
+// own limit and the pool's absolute bounds
+fn clmm_frontrun_detail(
+    optimal_amount: u64,
+    current_sqrt_price_x64: u128,
+    liquidity: u128,
+    zero_for_one: bool,
+    target_sqrt_price_limit_x64: u128,
+    safe_slippage_pips: u128,
+    trade_fee_rate: u32,
+) -> Result<ClmmFrontrunDetailEvent> {
+    // Calculate appropriate sqrt_price_limit_x64 for our frontrun transaction
+    let frontrun_sqrt_price_limit_x64 = if zero_for_one {
+        // Limit how far down the price can go to ensure target transaction success
+        let price_impact = calculate_price_impact(
+            current_sqrt_price_x64,
+            liquidity,
+            optimal_amount,
+            zero_for_one,
+            true, // Always exact input for frontrun
+            trade_fee_rate,
+        )?;
 
-    // Determine trade direction for backrun (opposite of frontrun direction)
-    let zero_for_one = ctx.accounts.input_vault.mint == pool_state.token_mint_0;
+        let min_allowed_price = if target_sqrt_price_limit_x64 > 0 {
+            // If target specified a price limit, respect it
+            std::cmp::max(
+                target_sqrt_price_limit_x64,
+                current_sqrt_price_x64.saturating_sub(price_impact),
+            )
+        } else {
+            current_sqrt_price_x64.saturating_sub(price_impact)
+        };
 
-    // Calculate transfer fee adjustment if needed
-    let amount_with_fee = if *ctx.accounts.input_vault_mint.to_account_info().owner == Token::id() {
-        // No transfer fees for regular SPL tokens
-        frontrun_output
+        // Ensure we don't go below the absolute minimum allowed
+        std::cmp::max(MIN_SQRT_PRICE_X64 + 1, min_allowed_price)
     } else {
-        // For token-2022 tokens with transfer fees
-        let transfer_fee =
-            clmm_get_transfer_fee(*ctx.accounts.input_vault_mint.clone(), frontrun_output)?;
-        frontrun_output.saturating_sub(transfer_fee)
-    };
-
-    // Calculate expected output from backrun based on current market conditions
-    let raw_expected_output = simulate_clmm_swap_output(
-        current_sqrt_price_x64,
-        current_tick,
-        liquidity,
-        amount_with_fee,
+        // Limit how high the price can go to ensure target transaction success
+        let price_impact = calculate_price_impact(
+            current_sqrt_price_x64,
+            liquidity,
+            optimal_amount,
+            zero_for_one,
+            true, // Always exact input for frontrun
+            trade_fee_rate,
+        )?;
+
+        let max_allowed_price = if target_sqrt_price_limit_x64 > 0 {
+            // If target specified a price limit, respect it
+            std::cmp::min(
+                target_sqrt_price_limit_x64,
+                current_sqrt_price_x64.saturating_add(price_impact),
+            )
+        } else {
+            current_sqrt_price_x64.saturating_add(price_impact)
+        };
+
+        // Ensure we don't go above the absolute maximum allowed
+        std::cmp::min(MAX_SQRT_PRICE_X64 - 1, max_allowed_price)
+    };
+
+    Ok(ClmmFrontrunDetailEvent {
+        optimal_amount,
+        frontrun_sqrt_price_limit_x64,
+        current_sqrt_price_x64,
         zero_for_one,
-        ctx.accounts.amm_config.trade_fee_rate,
-        ctx.accounts.amm_config.protocol_fee_rate,
-        ctx.accounts.amm_config.fund_fee_rate,
+        safe_slippage_bps: safe_slippage_pips / PIPS_PER_BPS,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn clmm_backrun_swap<'a, 'b, 'c: 'info, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, ClmmSandwichBackrun<'info>>,
+    sandwich_id: u64,
+    backrun_fraction_bps: u16,
+    min_slots_between: u8,
+    time_decay_bps_per_sec: u16,
+    max_acceptable_loss: Option<u64>,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    keep_output_amount: u64,
+    max_state_age_secs: u32,
+) -> Result<()> {
+    // Refuse to backrun a frontrun that filled nothing
+    ctx.accounts.sandwich_state.check_frontrun_filled()?;
+
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    ctx.accounts.sandwich_state.check_backrun_slot(min_slots_between)?;
+
+    // Refuse to unwind a position left open for too long, at a price long gone
+    ctx.accounts.sandwich_state.check_state_age(max_state_age_secs)?;
+    check_tick_array_count(ctx.remaining_accounts)?;
+
+    // Set the kept tokens aside as a long position, the rest is what the backrun unwinds
+    let kept_cost = ctx
+        .accounts
+        .sandwich_state
+        .retain_output(keep_output_amount)?;
+
+    // Get the slice of the frontrun position this backrun unwinds and its cost basis
+    let (sell_amount, cost_basis) = ctx
+        .accounts
+        .sandwich_state
+        .backrun_slice(backrun_fraction_bps)?;
+
+    // The input account must still hold what this backrun sells
+    check_backrun_balance(ctx.accounts.input_token_account.amount, sell_amount)?;
+
+    // Load pool state to get current price (after target tx)
+    let pool_state = ctx.accounts.pool_state.load()?;
+    let current_sqrt_price_x64 = pool_state.sqrt_price_x64;
+    // Derive the tick from the post-swap price so sizing starts from the real tick
+    let current_tick = get_tick_at_sqrt_price(current_sqrt_price_x64)?;
+    let liquidity = pool_state.liquidity;
+
+    // Check if the pool is open for trading
+    require_gt!(Clock::get()?.unix_timestamp as u64, pool_state.open_time);
+
+    // Determine trade direction for backrun (opposite of frontrun direction)
+    let zero_for_one = ctx.accounts.input_vault.mint == pool_state.token_mint_0;
+
+    // Release the pool borrow, the swap CPI needs the account free
+    drop(pool_state);
+
+    // Re-read the transfer fee configs at execution time, the epoch (and with it the
+    // fee) may have rolled over since the frontrun was sized
+    let epoch = get_recent_epoch()?;
+
+    // Calculate expected output from backrun based on current market conditions, net of
+    // the transfer fees of both mints
+    let expected_output = clmm_backrun_expected_output(
+        &ctx.accounts.input_vault_mint,
+        &ctx.accounts.output_vault_mint,
+        epoch,
+        sell_amount,
+        current_sqrt_price_x64,
+        current_tick,
+        liquidity,
+        zero_for_one,
+        &ctx.accounts.amm_config,
     )?;
 
-    // Apply any transfer fees on output token if applicable
-    let expected_output = if *ctx.accounts.output_vault_mint.to_account_info().owner == Token::id()
-    {
-        // No transfer fees for regular SPL tokens
-        raw_expected_output
-    } else {
-        // For token-2022 tokens with transfer fees
-        let inverse_fee = clmm_get_transfer_inverse_fee(
-            *ctx.accounts.output_vault_mint.clone(),
-            raw_expected_output,
-        )?;
-        raw_expected_output.saturating_sub(inverse_fee)
-    };
+    // Calculate minimum acceptable output for backrun for profitability, decayed by
+    // the time the position has been held, at least `min_profit_amount` above the cost
+    // and net of the fees paid to land it
+    let min_required_output = with_fixed_cost(
+        with_min_profit_amount(
+            min_required_output(
+                cost_basis,
+                ctx.accounts.sandwich_state.elapsed_secs()?,
+                time_decay_bps_per_sec,
+            )?,
+            cost_basis,
+            min_profit_amount,
+        )?,
+        &ctx.accounts.output_vault_mint.key(),
+        fixed_cost_lamports,
+    )?;
 
-    // Calculate minimum acceptable output for backrun for profitability
-    let min_profit_factor = 1005; // 0.5% minimum profit
-    let min_required_output = frontrun_input
-        .checked_mul(min_profit_factor)
-        .ok_or(ErrorCode::CalculationFailure)?
-        .checked_div(1000)
-        .ok_or(ErrorCode::CalculationFailure)?;
+    // Unwind at a bounded loss instead when the operator accepts one
+    let output_floor = backrun_output_floor(cost_basis, min_required_output, max_acceptable_loss);
 
-    // Use max of expected output with safety margin or minimum required output
+    // The expected output is net of the execution-time fees, bail out before the CPI
+    // when they leave nothing above the floor
+    if expected_output < output_floor {
+        return err!(ErrorCode::UnprofitableSandwich);
+    }
+
+    // Use max of expected output with safety margin or the floor
     let minimum_output = std::cmp::max(
         expected_output.saturating_mul(98).saturating_div(100), // 2% safety margin
-        min_required_output,
+        output_floor,
     );
 
-    // Verify potential profitability
-    if minimum_output <= frontrun_input {
-        return err!(ErrorCode::UnprofitableSandwich);
-    }
-
     // Record initial balances
     let output_token_balance_before = ctx.accounts.output_token_account.amount;
 
@@ -732,7 +1218,7 @@ pub fn clmm_backrun_swap<'a, 'b, 'c: 'info, 'info>(
     // Use exact input with minimum output requirement
     cpi::swap_v2(
         cpi_context,
-        frontrun_output, // Sell all tokens acquired in frontrun
+        sell_amount,      // Sell this backrun's slice of the frontrun position
         minimum_output,  // Ensure we get at least our minimum profitable amount
         if zero_for_one {
             // Set price limit to ensure the swap completes
@@ -743,8 +1229,10 @@ pub fn clmm_backrun_swap<'a, 'b, 'c: 'info, 'info>(
         true, // Always base input for backrun - selling what we got
     )?;
 
-    // Mark this sandwich as complete to prevent replay
-    ctx.accounts.sandwich_state.is_complete = true;
+    // Track the unwound slice, the sandwich is complete once the position is fully sold
+    ctx.accounts
+        .sandwich_state
+        .record_backrun(sell_amount, cost_basis);
 
     // Calculate and record profit
     ctx.accounts.output_token_account.reload()?;
@@ -752,23 +1240,34 @@ pub fn clmm_backrun_swap<'a, 'b, 'c: 'info, 'info>(
         .accounts
         .output_token_account
         .amount
-        .checked_sub(output_token_balance_before)
-        .unwrap();
-    let profit = actual_output.saturating_sub(frontrun_input);
+        .saturating_sub(output_token_balance_before);
+    // The tokens kept as a long position count at this backrun's execution price
+    let event = SandwichCompleteEvent::new(
+        sandwich_id,
+        cost_basis.saturating_add(kept_cost),
+        with_retained_output(actual_output, sell_amount, keep_output_amount),
+        Some(with_retained_output(
+            expected_output,
+            sell_amount,
+            keep_output_amount,
+        )),
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    ctx.accounts.sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
 
     // Emit profit event
-    emit!(SandwichCompleteEvent {
-        sandwich_id,
-        profit,
-        input_amount: frontrun_input,
-        output_amount: actual_output,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+    emit_cpi!(event);
 
     Ok(())
 }
 
-// Calculate slippage tolerance based on target parameters
+// Calculate slippage tolerance in pips based on target parameters, along with the other
+// side of the target's swap at the current price
 #[allow(clippy::too_many_arguments)]
 fn calculate_clmm_slippage(
     amount: u64,
@@ -782,7 +1281,7 @@ fn calculate_clmm_slippage(
     trade_fee_rate: u32,
     protocol_fee_rate: u32,
     fund_fee_rate: u32,
-) -> Result<u128> {
+) -> Result<(u64, u128)> {
     if is_base_input {
         // For exact input, threshold is minimum output
         // Simulate expected output at current price
@@ -797,17 +1296,12 @@ fn calculate_clmm_slippage(
             fund_fee_rate,
         )?;
 
-        // Calculate slippage as (expected - threshold) / expected * 10000
-        if expected_output > threshold {
-            let slippage =
-                ((expected_output - threshold) as u128 * 10000) / expected_output as u128;
-            Ok(slippage)
-        } else {
-            // If threshold > expected, user expects more than current market offers
-            // This could be due to using outdated price data or specific routing
-            // Default to a small slippage to be safe
-            Ok(10) // 0.1% slippage
-        }
+        // The victim asks for more than the market offers, their swap fails without us
+        require!(expected_output >= threshold, ErrorCode::VictimWouldFail);
+
+        // Calculate slippage as (expected - threshold) / expected
+        let pips = slippage_pips(expected_output - threshold, expected_output)?;
+        Ok((expected_output, pips))
     } else {
         // For exact output, threshold is maximum input
         // Simulate expected input at current price
@@ -822,15 +1316,12 @@ fn calculate_clmm_slippage(
             fund_fee_rate,
         )?;
 
-        // Calculate slippage as (threshold - expected) / expected * 10000
-        if threshold > expected_input {
-            let slippage = ((threshold - expected_input) as u128 * 10000) / expected_input as u128;
-            Ok(slippage)
-        } else {
-            // If expected > threshold, user is willing to pay less than current market requires
-            // Default to a small slippage to be safe
-            Ok(10) // 0.1% slippage
-        }
+        // The victim pays less than the market requires, their swap fails without us
+        require!(threshold >= expected_input, ErrorCode::VictimWouldFail);
+
+        // Calculate slippage as (threshold - expected) / expected
+        let pips = slippage_pips(threshold - expected_input, expected_input)?;
+        Ok((expected_input, pips))
     }
 }
 
@@ -873,6 +1364,151 @@ fn calculate_price_impact(
     }
 }
 
+// How far, in pips, a frontrun of `frontrun_amount` moves the victim's quote, with the
+// sqrt price the frontrun leaves the pool at
+#[allow(clippy::too_many_arguments)]
+fn clmm_victim_impact_pips(
+    current_sqrt_price_x64: u128,
+    current_tick: i32,
+    liquidity: u128,
+    frontrun_amount: u64,
+    target_amount: u64,
+    target_is_base_input: bool,
+    zero_for_one: bool,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+    fund_fee_rate: u32,
+) -> Result<(u128, u128)> {
+    // 1. FRONTRUN: Calculate where the frontrun leaves the price
+    let frontrun_price_impact = calculate_price_impact(
+        current_sqrt_price_x64,
+        liquidity,
+        frontrun_amount,
+        zero_for_one,
+        true, // Frontrun always uses exact input
+        trade_fee_rate,
+    )?;
+
+    let after_frontrun_price = if zero_for_one {
+        current_sqrt_price_x64.saturating_sub(frontrun_price_impact)
+    } else {
+        current_sqrt_price_x64.saturating_add(frontrun_price_impact)
+    };
+
+    let after_frontrun_tick = get_tick_at_sqrt_price(after_frontrun_price).unwrap_or(current_tick);
+
+    // 2. TARGET TX: Check if target would still succeed with new price
+    // First, calculate target output or input before frontrun
+    let (target_expected_output_before, target_expected_input_before) = if target_is_base_input {
+        let output = simulate_clmm_swap_output(
+            current_sqrt_price_x64,
+            current_tick,
+            liquidity,
+            target_amount,
+            zero_for_one,
+            trade_fee_rate,
+            protocol_fee_rate,
+            fund_fee_rate,
+        )?;
+        (output, target_amount)
+    } else {
+        let input = simulate_clmm_swap_input(
+            current_sqrt_price_x64,
+            current_tick,
+            liquidity,
+            target_amount,
+            zero_for_one,
+            trade_fee_rate,
+            protocol_fee_rate,
+            fund_fee_rate,
+        )?;
+        (target_amount, input)
+    };
+
+    // Then, calculate target output after frontrun
+    let (target_expected_output_after, target_expected_input_after) = if target_is_base_input {
+        let output = simulate_clmm_swap_output(
+            after_frontrun_price,
+            after_frontrun_tick,
+            liquidity,
+            target_amount,
+            zero_for_one,
+            trade_fee_rate,
+            protocol_fee_rate,
+            fund_fee_rate,
+        )?;
+        (output, target_amount)
+    } else {
+        let input = simulate_clmm_swap_input(
+            after_frontrun_price,
+            after_frontrun_tick,
+            liquidity,
+            target_amount,
+            zero_for_one,
+            trade_fee_rate,
+            protocol_fee_rate,
+            fund_fee_rate,
+        )?;
+        (target_amount, input)
+    };
+
+    // Calculate price impact percentage for target
+    let price_impact_pips = if target_is_base_input {
+        if target_expected_output_before > 0 {
+            ((target_expected_output_before - target_expected_output_after) as u128
+                * PIPS_DENOMINATOR)
+                / target_expected_output_before as u128
+        } else {
+            0
+        }
+    } else if target_expected_input_before > 0 {
+        ((target_expected_input_after - target_expected_input_before) as u128 * PIPS_DENOMINATOR)
+            / target_expected_input_before as u128
+    } else {
+        0
+    };
+
+    Ok((price_impact_pips, after_frontrun_price))
+}
+
+// Refuses a frontrun of `frontrun_amount` sized off-chain that moves the victim's quote
+// past `safe_slippage_pips` or the mid price past `self_impact_cap_bps`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn check_clmm_fixed_frontrun(
+    current_sqrt_price_x64: u128,
+    current_tick: i32,
+    liquidity: u128,
+    frontrun_amount: u64,
+    target_amount: u64,
+    safe_slippage_pips: u128,
+    self_impact_cap_bps: u128,
+    target_is_base_input: bool,
+    zero_for_one: bool,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+    fund_fee_rate: u32,
+) -> Result<()> {
+    let (price_impact_pips, after_frontrun_price) = clmm_victim_impact_pips(
+        current_sqrt_price_x64,
+        current_tick,
+        liquidity,
+        frontrun_amount,
+        target_amount,
+        target_is_base_input,
+        zero_for_one,
+        trade_fee_rate,
+        protocol_fee_rate,
+        fund_fee_rate,
+    )?;
+    require!(
+        price_impact_pips <= safe_slippage_pips
+            && clmm_mid_price_impact_bps(current_sqrt_price_x64, after_frontrun_price)
+                <= self_impact_cap_bps,
+        ErrorCode::ExceededSlippage
+    );
+    Ok(())
+}
+
 // Calculate optimal sandwich amount using binary search
 #[allow(clippy::too_many_arguments)]
 fn calculate_optimal_clmm_sandwich_amount(
@@ -880,12 +1516,14 @@ fn calculate_optimal_clmm_sandwich_amount(
     current_tick: i32,
     liquidity: u128,
     target_amount: u64,
-    safe_slippage_bps: u128,
+    safe_slippage_pips: u128,
+    self_impact_cap_bps: u128,
     target_is_base_input: bool,
     zero_for_one: bool,
     trade_fee_rate: u32,
     protocol_fee_rate: u32,
     fund_fee_rate: u32,
+    min_profit: u128,
 ) -> Result<u64> {
     // Use binary search to find optimal attack size
     let max_search_amount = target_amount.saturating_mul(3);
@@ -902,21 +1540,22 @@ fn calculate_optimal_clmm_sandwich_amount(
 
         let mid = (low + high) / 2;
 
-        // 1. FRONTRUN: Calculate frontrun swap result
-        let frontrun_price_impact = calculate_price_impact(
+        // 1. FRONTRUN and 2. TARGET TX: Check if target would still succeed with the
+        // price the frontrun leaves
+        let (price_impact_pips, after_frontrun_price) = clmm_victim_impact_pips(
             current_sqrt_price_x64,
+            current_tick,
             liquidity,
             mid,
+            target_amount,
+            target_is_base_input,
             zero_for_one,
-            true, // Frontrun always uses exact input
             trade_fee_rate,
+            protocol_fee_rate,
+            fund_fee_rate,
         )?;
-
-        let after_frontrun_price = if zero_for_one {
-            current_sqrt_price_x64.saturating_sub(frontrun_price_impact)
-        } else {
-            current_sqrt_price_x64.saturating_add(frontrun_price_impact)
-        };
+        let after_frontrun_tick =
+            get_tick_at_sqrt_price(after_frontrun_price).unwrap_or(current_tick);
 
         // Simulate frontrun output
         let frontrun_output = simulate_clmm_swap_output(
@@ -930,82 +1569,17 @@ fn calculate_optimal_clmm_sandwich_amount(
             fund_fee_rate,
         )?;
 
-        // 2. TARGET TX: Check if target would still succeed with new price
-        // First, calculate target output or input before frontrun
-        let (target_expected_output_before, target_expected_input_before) = if target_is_base_input
-        {
-            let output = simulate_clmm_swap_output(
-                current_sqrt_price_x64,
-                current_tick,
-                liquidity,
-                target_amount,
-                zero_for_one,
-                trade_fee_rate,
-                protocol_fee_rate,
-                fund_fee_rate,
-            )?;
-            (output, target_amount)
-        } else {
-            let input = simulate_clmm_swap_input(
-                current_sqrt_price_x64,
-                current_tick,
-                liquidity,
-                target_amount,
-                zero_for_one,
-                trade_fee_rate,
-                protocol_fee_rate,
-                fund_fee_rate,
-            )?;
-            (target_amount, input)
-        };
-
-        // Then, calculate target output after frontrun
-        let (target_expected_output_after, target_expected_input_after) = if target_is_base_input {
-            let output = simulate_clmm_swap_output(
-                after_frontrun_price,
-                current_tick, // Approximate, would need full tick crossing simulation
-                liquidity,
-                target_amount,
-                zero_for_one,
-                trade_fee_rate,
-                protocol_fee_rate,
-                fund_fee_rate,
-            )?;
-            (output, target_amount)
-        } else {
-            let input = simulate_clmm_swap_input(
-                after_frontrun_price,
-                current_tick,
-                liquidity,
-                target_amount,
-                zero_for_one,
-                trade_fee_rate,
-                protocol_fee_rate,
-                fund_fee_rate,
-            )?;
-            (target_amount, input)
-        };
-
-        // Calculate price impact percentage for target
-        let price_impact_bps = if target_is_base_input {
-            if target_expected_output_before > 0 {
-                ((target_expected_output_before - target_expected_output_after) as u128 * 10000)
-                    / target_expected_output_before as u128
-            } else {
-                0
-            }
-        } else if target_expected_input_before > 0 {
-            ((target_expected_input_after - target_expected_input_before) as u128 * 10000)
-                / target_expected_input_before as u128
-        } else {
-            0
-        };
-
         // Check if target tx will still execute within slippage
-        let within_slippage = price_impact_bps <= safe_slippage_bps;
+        let within_slippage = price_impact_pips <= safe_slippage_pips;
+
+        // Our own buy must not move the mid price past the self cap either
+        let within_self_cap =
+            clmm_mid_price_impact_bps(current_sqrt_price_x64, after_frontrun_price)
+                <= self_impact_cap_bps;
 
-        if !within_slippage {
-            // If target tx would fail due to excessive slippage, reduce search space
+        if !within_slippage || !within_self_cap {
+            // If target tx would fail due to excessive slippage, or we would move price
+            // too far, reduce search space
             high = mid.saturating_sub(1);
             continue;
         }
@@ -1025,7 +1599,7 @@ fn calculate_optimal_clmm_sandwich_amount(
             // For exact output, we need to calculate the input amount first
             let target_input = simulate_clmm_swap_input(
                 after_frontrun_price,
-                current_tick,
+                after_frontrun_tick,
                 liquidity,
                 target_amount,
                 zero_for_one,
@@ -1049,11 +1623,12 @@ fn calculate_optimal_clmm_sandwich_amount(
         } else {
             after_frontrun_price.saturating_add(target_price_impact)
         };
+        let after_target_tick = get_tick_at_sqrt_price(after_target_price).unwrap_or(current_tick);
 
         // Now calculate how much we'll get back in the backrun
         let backrun_output = simulate_clmm_swap_output(
             after_target_price,
-            current_tick, // Approximate
+            after_target_tick,
             liquidity,
             frontrun_output,
             !zero_for_one, // Opposite direction from frontrun
@@ -1080,14 +1655,138 @@ fn calculate_optimal_clmm_sandwich_amount(
         }
     }
 
-    // Ensure we have a minimum viable amount
-    if best_profit < 100 {
-        return err!(ErrorCode::InsufficientSandwichAmount);
-    }
+    // The initial guess stands unchecked unless some trial beat it
+    require!(
+        best_profit as u128 >= min_profit,
+        ErrorCode::UnprofitableSandwich
+    );
 
     Ok(best_amount)
 }
 
+// How far, in bps, moving the sqrt price from `sqrt_price_x64` to `new_sqrt_price_x64`
+// pushes the mid price, in either direction
+fn clmm_mid_price_impact_bps(sqrt_price_x64: u128, new_sqrt_price_x64: u128) -> u128 {
+    if sqrt_price_x64 == 0 {
+        return 0;
+    }
+    // Ratio of the sqrt prices scaled by 1e6, squared into the price ratio scaled by 1e12
+    let sqrt_price_ratio = new_sqrt_price_x64.saturating_mul(1_000_000) / sqrt_price_x64;
+    let price_ratio = sqrt_price_ratio.saturating_mul(sqrt_price_ratio);
+    price_ratio.abs_diff(1_000_000_000_000) / 100_000_000
+}
+
+// Net output a backrun selling `sell_amount` expects. A pool of two classic SPL mints,
+// the common case, charges no transfer fee and skips the fee machinery altogether
+#[allow(clippy::too_many_arguments)]
+fn clmm_backrun_expected_output(
+    input_vault_mint: &InterfaceAccount<Mint>,
+    output_vault_mint: &InterfaceAccount<Mint>,
+    epoch: u64,
+    sell_amount: u64,
+    sqrt_price_x64: u128,
+    tick: i32,
+    liquidity: u128,
+    zero_for_one: bool,
+    amm_config: &ClmmAmmConfig,
+) -> Result<u64> {
+    if is_spl_mint(input_vault_mint) && is_spl_mint(output_vault_mint) {
+        return simulate_clmm_swap_output(
+            sqrt_price_x64,
+            tick,
+            liquidity,
+            sell_amount,
+            zero_for_one,
+            amm_config.trade_fee_rate,
+            amm_config.protocol_fee_rate,
+            amm_config.fund_fee_rate,
+        );
+    }
+
+    clmm_backrun_output_net_of_fees(
+        input_vault_mint,
+        output_vault_mint,
+        epoch,
+        sell_amount,
+        sqrt_price_x64,
+        tick,
+        liquidity,
+        zero_for_one,
+        amm_config,
+    )
+}
+
+// Whether `mint` belongs to the classic token program, which has no transfer fee
+fn is_spl_mint(mint: &InterfaceAccount<Mint>) -> bool {
+    *mint.to_account_info().owner == Token::id()
+}
+
+// Net output of the backrun with each leg paying its own mint's transfer fee, like the
+// swap does: the input fee comes off what reaches the vault and the output fee off the
+// gross swap output. With two fee-bearing mints both apply, neither on the amount the
+// other one already took its cut from
+#[allow(clippy::too_many_arguments)]
+fn clmm_backrun_output_net_of_fees(
+    input_vault_mint: &InterfaceAccount<Mint>,
+    output_vault_mint: &InterfaceAccount<Mint>,
+    epoch: u64,
+    sell_amount: u64,
+    sqrt_price_x64: u128,
+    tick: i32,
+    liquidity: u128,
+    zero_for_one: bool,
+    amm_config: &ClmmAmmConfig,
+) -> Result<u64> {
+    // SPL mints charge no transfer fee, clmm_get_transfer_fee returns 0 for them
+    let input_transfer_fee = clmm_get_transfer_fee(input_vault_mint, sell_amount, epoch)?;
+    let gross_output = simulate_clmm_swap_output(
+        sqrt_price_x64,
+        tick,
+        liquidity,
+        sell_amount.saturating_sub(input_transfer_fee),
+        zero_for_one,
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
+    )?;
+    let output_transfer_fee = clmm_get_transfer_fee(output_vault_mint, gross_output, epoch)?;
+    Ok(gross_output.saturating_sub(output_transfer_fee))
+}
+
+/// Raydium's fee split of an exact-input CLMM swap step. The protocol and fund fees are
+/// carved out of the trade fee, they change who the fee goes to but not the net amount
+/// that moves the price.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ClmmSwapFees {
+    pub net_amount_in: u128, // Reaches the liquidity and moves the price
+    pub trade_fee: u128,     // Charged on the gross amount, rounded up
+    pub protocol_fee: u128,  // Share of the trade fee, rounded down
+    pub fund_fee: u128,      // Share of the trade fee, rounded down
+}
+
+/// Splits `amount_in` the way Raydium's `compute_swap_step` does for an exact input.
+pub fn clmm_swap_fees(
+    amount_in: u64,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+    fund_fee_rate: u32,
+) -> Result<ClmmSwapFees> {
+    require!(trade_fee_rate < 1_000_000, ErrorCode::InvalidInput);
+
+    let net_amount_in = mul_div(
+        amount_in as u128,
+        1_000_000 - trade_fee_rate as u128,
+        1_000_000,
+    )?;
+    let trade_fee = amount_in as u128 - net_amount_in;
+    Ok(ClmmSwapFees {
+        net_amount_in,
+        trade_fee,
+        protocol_fee: mul_div(trade_fee, protocol_fee_rate as u128, 1_000_000)?,
+        fund_fee: mul_div(trade_fee, fund_fee_rate as u128, 1_000_000)?,
+    })
+}
+
 // Simulate output amount for a CLMM swap
 #[allow(clippy::too_many_arguments)]
 fn simulate_clmm_swap_output(
@@ -1097,12 +1796,12 @@ fn simulate_clmm_swap_output(
     amount_in: u64,
     zero_for_one: bool,
     trade_fee_rate: u32,
-    _protocol_fee_rate: u32,
-    _fund_fee_rate: u32,
+    protocol_fee_rate: u32,
+    fund_fee_rate: u32,
 ) -> Result<u64> {
-    // Apply fee rate
-    let fee_adjustment = 1_000_000 - trade_fee_rate as u128;
-    let adjusted_amount = (amount_in as u128 * fee_adjustment) / 1_000_000;
+    // Apply the fee stack, only the trade fee leaves the swapped amount
+    let adjusted_amount =
+        clmm_swap_fees(amount_in, trade_fee_rate, protocol_fee_rate, fund_fee_rate)?.net_amount_in;
 
     // Calculate output based on concentrated liquidity formulas
     let amount_out = if zero_for_one {
@@ -1194,7 +1893,7 @@ fn simulate_clmm_swap_input(
 }
 
 // Helper function to calculate sqrt price after an amount in
-fn sqrt_price_after_amount_in(
+pub(crate) fn sqrt_price_after_amount_in(
     sqrt_price_x64: u128,
     liquidity: u128,
     amount_in: u128,
@@ -1222,8 +1921,8 @@ fn sqrt_price_after_amount_in(
     }
 }
 
-// Helper function to calculate amount0 delta
-fn calculate_amount0_delta(
+// Helper function to calculate amount0 delta, the magnitude for either price ordering
+pub(crate) fn calculate_amount0_delta(
     sqrt_price_a_x64: u128,
     sqrt_price_b_x64: u128,
     liquidity: u128,
@@ -1235,30 +1934,30 @@ fn calculate_amount0_delta(
         (sqrt_price_b_x64, sqrt_price_a_x64)
     };
 
-    let numerator1 = liquidity << 64;
-    let numerator2 = sqrt_price_high - sqrt_price_low;
-
     if sqrt_price_low == 0 {
         return err!(ErrorCode::CalculationFailure);
     }
 
-    let amount = if round_up {
-        // Round up division for calculating input amounts
-        mul_div_ceil(numerator1, numerator2, sqrt_price_high * sqrt_price_low)?
-    } else {
-        // Round down division for calculating output amounts
-        mul_div(numerator1, numerator2, sqrt_price_high * sqrt_price_low)?
-    };
+    // ((L << 64) * (high - low) / high) / low, in Raydium's order. The first product can
+    // pass 256 bits, so divide L * (high - low) by high and carry its remainder into the
+    // low 64 bits the shift opens up
+    let (quotient, remainder) = U256::full_mul(liquidity, sqrt_price_high - sqrt_price_low)
+        .checked_div_rem(sqrt_price_high)
+        .ok_or(ErrorCode::CalculationFailure)?;
+    let (carried, remainder) = U256::full_mul(remainder, Q64)
+        .checked_div_rem(sqrt_price_high)
+        .ok_or(ErrorCode::CalculationFailure)?;
+    let round = (round_up && remainder != 0) as u128;
+    let numerator = quotient
+        .checked_shl_64()
+        .and_then(|shifted| shifted.checked_add(U256::from_u128(carried + round)))
+        .ok_or(ErrorCode::CalculationFailure)?;
 
-    if sqrt_price_a_x64 <= sqrt_price_b_x64 {
-        Ok(amount)
-    } else {
-        Ok(amount.neg())
-    }
+    div_u256(numerator, sqrt_price_low, round_up)
 }
 
-// Helper function to calculate amount1 delta
-fn calculate_amount1_delta(
+// Helper function to calculate amount1 delta, the magnitude for either price ordering
+pub(crate) fn calculate_amount1_delta(
     sqrt_price_a_x64: u128,
     sqrt_price_b_x64: u128,
     liquidity: u128,
@@ -1270,23 +1969,35 @@ fn calculate_amount1_delta(
         (sqrt_price_b_x64, sqrt_price_a_x64)
     };
 
-    let amount = if round_up {
+    if round_up {
         // Round up division for calculating input amounts
-        mul_div_ceil(liquidity, sqrt_price_high - sqrt_price_low, Q64)?
+        mul_div_ceil(liquidity, sqrt_price_high - sqrt_price_low, Q64)
     } else {
         // Round down division for calculating output amounts
-        mul_div(liquidity, sqrt_price_high - sqrt_price_low, Q64)?
-    };
+        mul_div(liquidity, sqrt_price_high - sqrt_price_low, Q64)
+    }
+}
 
-    if sqrt_price_a_x64 <= sqrt_price_b_x64 {
-        Ok(amount)
+// Helper for a 256-bit numerator over a u128, rounded up for input amounts and down
+// for output amounts, failing if the quotient doesn't fit u128
+fn div_u256(numerator: U256, denominator: u128, round_up: bool) -> Result<u128> {
+    let numerator = if round_up {
+        numerator
+            .checked_add(U256::from_u128(denominator.saturating_sub(1)))
+            .ok_or(ErrorCode::CalculationFailure)?
     } else {
-        Ok(amount.neg())
-    }
+        numerator
+    };
+
+    let quotient = numerator
+        .checked_div(denominator)
+        .and_then(U256::to_u128)
+        .ok_or(ErrorCode::CalculationFailure)?;
+    Ok(quotient)
 }
 
 // Helper for ceiling division
-fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Result<u128> {
+pub(crate) fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Result<u128> {
     let product = a.checked_mul(b).ok_or(ErrorCode::CalculationFailure)?;
 
     if product == 0 {
@@ -1299,7 +2010,7 @@ fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Result<u128> {
 }
 
 // Helper for floor division
-fn mul_div(a: u128, b: u128, denominator: u128) -> Result<u128> {
+pub(crate) fn mul_div(a: u128, b: u128, denominator: u128) -> Result<u128> {
     if denominator == 0 {
         return err!(ErrorCode::CalculationFailure);
     }
@@ -1310,24 +2021,229 @@ fn mul_div(a: u128, b: u128, denominator: u128) -> Result<u128> {
     Ok(result)
 }
 
-// Extension trait for negative value handling
-trait Neg {
-    fn neg(self) -> Self;
+// this is from the raydium clmm code
+// https://github.com/raydium-io/raydium-clmm/blob/master/programs/amm/src/libraries/tick_math.rs
+/// Calculates 1.0001^(tick/2) as a U64.64 number representing
+/// the square root of the ratio of the two assets (token_1/token_0)
+pub fn get_sqrt_price_at_tick(tick: i32) -> Result<u128> {
+    let abs_tick = tick.unsigned_abs();
+    require!(abs_tick <= MAX_TICK as u32, ErrorCode::InvalidTick);
+
+    // i = 0
+    let mut ratio: u128 = if abs_tick & 0x1 != 0 {
+        0xfffcb933bd6fb800
+    } else {
+        // 2^64
+        Q64
+    };
+    // i = 1..18, each constant is 1.0001^(-2^(i - 1)) * 2^64
+    const RATIOS: [u128; 18] = [
+        0xfff97272373d4000,
+        0xfff2e50f5f657000,
+        0xffe5caca7e10f000,
+        0xffcb9843d60f7000,
+        0xff973b41fa98e800,
+        0xff2ea16466c9b000,
+        0xfe5dee046a9a3800,
+        0xfcbe86c7900bb000,
+        0xf987a7253ac65800,
+        0xf3392b0822bb6000,
+        0xe7159475a2caf000,
+        0xd097f3bdfd2f2000,
+        0xa9f746462d9f8000,
+        0x70d869a156f31c00,
+        0x31be135f97ed3200,
+        0x9aa508b5b85a500,
+        0x5d6af8dedc582c,
+        0x2216e584f5fa,
+    ];
+    for (i, factor) in RATIOS.iter().enumerate() {
+        if abs_tick & (0x2 << i) != 0 {
+            ratio = (ratio * factor) >> 64;
+        }
+    }
+
+    // Divide to obtain 1.0001^(2^(i - 1)) * 2^32 in numerator
+    if tick > 0 {
+        ratio = u128::MAX / ratio;
+    }
+
+    Ok(ratio)
 }
 
-impl Neg for u128 {
-    fn neg(self) -> Self {
-        0u128.saturating_sub(self)
+// this is from the raydium clmm code
+// https://github.com/raydium-io/raydium-clmm/blob/master/programs/amm/src/libraries/tick_math.rs
+/// Calculates the greatest tick value such that get_sqrt_price_at_tick(tick) <= sqrt_price_x64
+pub fn get_tick_at_sqrt_price(sqrt_price_x64: u128) -> Result<i32> {
+    // second inequality must be < because the price can never reach the price at the max tick
+    require!(
+        (MIN_SQRT_PRICE_X64..MAX_SQRT_PRICE_X64).contains(&sqrt_price_x64),
+        ErrorCode::InvalidSqrtPrice
+    );
+
+    // Determine log_b(sqrt_ratio). First by calculating integer portion (msb)
+    let msb: u32 = 128 - sqrt_price_x64.leading_zeros() - 1;
+    let log2p_integer_x32 = (msb as i128 - 64) << 32;
+
+    // get fractional value (r/2^msb), msb always > 128
+    // We begin the iteration from bit 63 (0.5 in Q64.64)
+    let mut bit: i128 = 0x8000_0000_0000_0000i128;
+    let mut precision = 0;
+    let mut log2p_fraction_x64 = 0;
+
+    // Log2 iterative approximation for the fractional part
+    // Go through each 2^(j) bit where j < 64 in a Q64.64 number
+    // Append current bit value to fraction result if r^2 Q2.126 is more than 2
+    let mut r = if msb >= 64 {
+        sqrt_price_x64 >> (msb - 63)
+    } else {
+        sqrt_price_x64 << (63 - msb)
+    };
+
+    while bit > 0 && precision < BIT_PRECISION {
+        r *= r;
+        let is_r_more_than_two = (r >> 127) as u32;
+        r >>= 63 + is_r_more_than_two;
+        log2p_fraction_x64 += bit * is_r_more_than_two as i128;
+        bit >>= 1;
+        precision += 1;
     }
+    let log2p_fraction_x32 = log2p_fraction_x64 >> 32;
+    let log2p_x32 = log2p_integer_x32 + log2p_fraction_x32;
+
+    // Change of base rule: multiply with 2^16 / log2 (√1.0001)
+    let log_sqrt_10001_x64 = log2p_x32 * 59543866431248i128;
+
+    // tick - 0.01
+    let tick_low = ((log_sqrt_10001_x64 - 184467440737095516i128) >> 64) as i32;
+
+    // tick + (2^-14 / log2(√1.001)) + 0.01
+    let tick_high = ((log_sqrt_10001_x64 + 15793534762490258745i128) >> 64) as i32;
+
+    Ok(if tick_low == tick_high {
+        tick_low
+    } else if get_sqrt_price_at_tick(tick_high)? <= sqrt_price_x64 {
+        tick_high
+    } else {
+        tick_low
+    })
 }
+
+/// Bounds the accounts forwarded to the swap CPI so a caller can't blow the compute budget.
+pub fn check_tick_array_count(remaining_accounts: &[AccountInfo]) -> Result<()> {
+    require!(
+        remaining_accounts.len() <= MAX_TICK_ARRAYS,
+        ErrorCode::TooManyAccounts
+    );
+    Ok(())
+}
+
+/// Tick-spacing boundaries the price passes moving from `sqrt_price_x64` to
+/// `new_sqrt_price_x64`, each one a step a tick-crossing simulation has to walk.
+pub fn clmm_tick_crossings(
+    sqrt_price_x64: u128,
+    new_sqrt_price_x64: u128,
+    tick_spacing: u16,
+) -> Result<u32> {
+    require!(tick_spacing > 0, ErrorCode::InvalidInput);
+    let tick_spacing = tick_spacing as i32;
+    // A price pushed past the bounds stops at them, as the swap would
+    let new_sqrt_price_x64 = new_sqrt_price_x64.clamp(MIN_SQRT_PRICE_X64, MAX_SQRT_PRICE_X64 - 1);
+    let from = get_tick_at_sqrt_price(sqrt_price_x64)?.div_euclid(tick_spacing);
+    let to = get_tick_at_sqrt_price(new_sqrt_price_x64)?.div_euclid(tick_spacing);
+    Ok(from.abs_diff(to))
+}
+
+/// Tick-spacing boundaries the victim's swap crosses from the current price, sized on the
+/// in-range liquidity like the rest of the simulation.
+#[allow(clippy::too_many_arguments)]
+fn clmm_victim_tick_crossings(
+    sqrt_price_x64: u128,
+    tick: i32,
+    tick_spacing: u16,
+    liquidity: u128,
+    target_amount: u64,
+    target_is_base_input: bool,
+    zero_for_one: bool,
+    trade_fee_rate: u32,
+    protocol_fee_rate: u32,
+    fund_fee_rate: u32,
+) -> Result<u32> {
+    let target_amount_in = if target_is_base_input {
+        target_amount
+    } else {
+        simulate_clmm_swap_input(
+            sqrt_price_x64,
+            tick,
+            liquidity,
+            target_amount,
+            zero_for_one,
+            trade_fee_rate,
+            protocol_fee_rate,
+            fund_fee_rate,
+        )?
+    };
+    let net_amount_in = clmm_swap_fees(
+        target_amount_in,
+        trade_fee_rate,
+        protocol_fee_rate,
+        fund_fee_rate,
+    )?
+    .net_amount_in;
+    let new_sqrt_price_x64 =
+        sqrt_price_after_amount_in(sqrt_price_x64, liquidity, net_amount_in, zero_for_one)?;
+    clmm_tick_crossings(sqrt_price_x64, new_sqrt_price_x64, tick_spacing)
+}
+
+/// Rejects a victim swap crossing more than `max_ticks_to_cross` tick-spacing boundaries.
+/// 0 disables the check.
+pub fn check_tick_crossings(crossings: u32, max_ticks_to_cross: u16) -> Result<()> {
+    require!(
+        max_ticks_to_cross == 0 || crossings <= max_ticks_to_cross as u32,
+        ErrorCode::TooManyTickCrossings
+    );
+    Ok(())
+}
+
+/// `check_price_drift` for sqrt prices, with the drift measured on the price they are the
+/// square root of: `live² - expected² = (live - expected)(live + expected)`.
+pub fn check_sqrt_price_drift(
+    live_sqrt_price_x64: u128,
+    expected_sqrt_price_x64: u128,
+    max_price_drift_bps: u16,
+) -> Result<()> {
+    if max_price_drift_bps == 0 {
+        return Ok(());
+    }
+    require!(expected_sqrt_price_x64 > 0, ErrorCode::InvalidInput);
+    let drift = U256::full_mul(
+        live_sqrt_price_x64
+            .abs_diff(expected_sqrt_price_x64)
+            .checked_mul(10_000)
+            .ok_or(ErrorCode::CalculationFailure)?,
+        live_sqrt_price_x64
+            .checked_add(expected_sqrt_price_x64)
+            .ok_or(ErrorCode::CalculationFailure)?,
+    );
+    let tolerance = U256::full_mul(
+        expected_sqrt_price_x64
+            .checked_mul(max_price_drift_bps as u128)
+            .ok_or(ErrorCode::CalculationFailure)?,
+        expected_sqrt_price_x64,
+    );
+    require!(drift <= tolerance, ErrorCode::PriceDrifted);
+    Ok(())
+}
+
 pub fn get_recent_epoch() -> Result<u64> {
     Ok(Clock::get()?.epoch)
 }
 
 /// Calculate the fee for input amount
 pub fn clmm_get_transfer_fee(
-    mint_account: InterfaceAccount<Mint>,
+    mint_account: &InterfaceAccount<Mint>,
     pre_fee_amount: u64,
+    epoch: u64,
 ) -> Result<u64> {
     let mint_info = mint_account.to_account_info();
     if *mint_info.owner == Token::id() {
@@ -1338,8 +2254,8 @@ pub fn clmm_get_transfer_fee(
 
     let fee = if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
         transfer_fee_config
-            .calculate_epoch_fee(get_recent_epoch()?, pre_fee_amount)
-            .unwrap()
+            .calculate_epoch_fee(epoch, pre_fee_amount)
+            .ok_or(ErrorCode::CalculationFailure)?
     } else {
         0
     };
@@ -1348,8 +2264,9 @@ pub fn clmm_get_transfer_fee(
 
 /// Calculate the fee for output amount
 pub fn clmm_get_transfer_inverse_fee(
-    mint_account: InterfaceAccount<Mint>,
+    mint_account: &InterfaceAccount<Mint>,
     post_fee_amount: u64,
+    epoch: u64,
 ) -> Result<u64> {
     let mint_info = mint_account.to_account_info();
     if *mint_info.owner == Token::id() {
@@ -1359,18 +2276,885 @@ pub fn clmm_get_transfer_inverse_fee(
     let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
 
     let fee = if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
-        let epoch = get_recent_epoch()?;
-
         let transfer_fee = transfer_fee_config.get_epoch_fee(epoch);
         if u16::from(transfer_fee.transfer_fee_basis_points) == MAX_FEE_BASIS_POINTS {
             u64::from(transfer_fee.maximum_fee)
         } else {
             transfer_fee_config
                 .calculate_inverse_epoch_fee(epoch, post_fee_amount)
-                .unwrap()
+                .ok_or(ErrorCode::CalculationFailure)?
         }
     } else {
         0
     };
     Ok(fee)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::raydium::cpmm::swap_base_output::tests as cpmm_tests;
+    use crate::program_config::tests::config;
+    use crate::sandwich_state::tests::state_with_mints;
+    use crate::test_utils::{account_info, on_invoke, program, syscall_stubs, take_invoked};
+    use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+    use anchor_spl::token::spl_token;
+    use anchor_spl::token_2022::spl_token_2022::extension::{
+        transfer_fee::TransferFee, BaseStateWithExtensionsMut, StateWithExtensionsMut,
+    };
+    use solana_program::entrypoint::ProgramResult;
+    use solana_program::instruction::Instruction;
+    use solana_program::program_pack::Pack;
+
+    // The shared Token-2022 fee mint, as the InterfaceAccount the CLMM fee helpers take
+    fn transfer_fee_mint(transfer_fee_basis_points: u16) -> InterfaceAccount<'static, Mint> {
+        let info = cpmm_tests::transfer_fee_mint(transfer_fee_basis_points);
+        InterfaceAccount::try_from(Box::leak(Box::new(info))).unwrap()
+    }
+
+    // Classic SPL mint, leaked like `transfer_fee_mint`
+    fn spl_mint() -> InterfaceAccount<'static, Mint> {
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+
+        let info = AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            false,
+            Box::leak(Box::new(0)),
+            data.leak(),
+            &spl_token::ID,
+            false,
+            0,
+        );
+        InterfaceAccount::try_from(Box::leak(Box::new(info))).unwrap()
+    }
+
+    #[test]
+    fn transfer_fees_are_read_from_the_mint() {
+        let mint = transfer_fee_mint(100);
+        assert_eq!(clmm_get_transfer_fee(&mint, 10_000, 0).unwrap(), 100);
+        assert_eq!(clmm_get_transfer_inverse_fee(&mint, 9_900, 0).unwrap(), 100);
+    }
+
+    #[test]
+    fn the_backrun_output_pays_both_transfer_fees() {
+        let amm_config = ClmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        };
+        // Deep liquidity at price 1 keeps the price impact out of the picture
+        let expected_output = |input_fee_bps, output_fee_bps| {
+            clmm_backrun_expected_output(
+                &transfer_fee_mint(input_fee_bps),
+                &transfer_fee_mint(output_fee_bps),
+                0,
+                1_000_000,
+                Q64,
+                0,
+                1_000_000_000_000_000,
+                true,
+                &amm_config,
+            )
+            .unwrap()
+        };
+
+        let without_fees = expected_output(0, 0);
+        assert_eq!(without_fees, 997_499);
+
+        // 1% off the input and 2% off the output, each charged once on its own leg
+        let with_both_fees = expected_output(100, 200);
+        assert_eq!(with_both_fees, 967_773);
+        assert!(with_both_fees < expected_output(100, 0));
+        assert!(with_both_fees < expected_output(0, 200));
+        assert!((without_fees * 99 / 100 * 98 / 100).abs_diff(with_both_fees) <= 2);
+    }
+
+    #[test]
+    fn the_backrun_output_pays_the_fee_of_its_own_epoch() {
+        // The output mint charges 1% until epoch 10 and 2% from then on
+        let output_mint = transfer_fee_mint(100);
+        {
+            let info = output_mint.to_account_info();
+            let mut data = info.try_borrow_mut_data().unwrap();
+            let mut mint =
+                StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack(&mut data).unwrap();
+            mint.get_extension_mut::<TransferFeeConfig>().unwrap().newer_transfer_fee =
+                TransferFee {
+                    epoch: 10u64.into(),
+                    maximum_fee: u64::MAX.into(),
+                    transfer_fee_basis_points: 200u16.into(),
+                };
+        }
+        let amm_config = ClmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        };
+        let expected_output = |epoch| {
+            clmm_backrun_expected_output(
+                &transfer_fee_mint(0),
+                &output_mint,
+                epoch,
+                1_000_000,
+                Q64,
+                0,
+                1_000_000_000_000_000,
+                true,
+                &amm_config,
+            )
+            .unwrap()
+        };
+
+        // A frontrun sized in epoch 9 expected 1% off the 997_499 gross output, a backrun
+        // landing in epoch 10 pays 2%
+        assert_eq!(expected_output(9), 997_499 - 9_975);
+        assert_eq!(expected_output(10), 997_499 - 19_950);
+    }
+
+    #[test]
+    fn a_pure_spl_pool_skips_the_transfer_fees_for_the_same_output() {
+        let amm_config = ClmmAmmConfig {
+            trade_fee_rate: 2_500,
+            protocol_fee_rate: 120_000,
+            fund_fee_rate: 40_000,
+            ..Default::default()
+        };
+        let (input_mint, output_mint) = (spl_mint(), spl_mint());
+
+        // Shallow and deep liquidity, in both directions
+        for (liquidity, zero_for_one) in [
+            (1_000_000_000_000_000, true),
+            (1_000_000_000_000_000, false),
+            (50_000_000, true),
+            (50_000_000, false),
+        ] {
+            let fast_path = clmm_backrun_expected_output(
+                &input_mint,
+                &output_mint,
+                0,
+                1_000_000,
+                Q64,
+                0,
+                liquidity,
+                zero_for_one,
+                &amm_config,
+            )
+            .unwrap();
+            let with_fees = clmm_backrun_output_net_of_fees(
+                &input_mint,
+                &output_mint,
+                0,
+                1_000_000,
+                Q64,
+                0,
+                liquidity,
+                zero_for_one,
+                &amm_config,
+            )
+            .unwrap();
+            assert_eq!(fast_path, with_fees);
+        }
+    }
+
+    #[test]
+    fn the_fee_stack_splits_the_trade_fee() {
+        // 0.25% trade fee with a 12% protocol and 4% fund share, Raydium's common tier
+        assert_eq!(
+            clmm_swap_fees(1_000_001, 2_500, 120_000, 40_000).unwrap(),
+            ClmmSwapFees {
+                net_amount_in: 997_500,
+                trade_fee: 2_501,
+                protocol_fee: 300,
+                fund_fee: 100,
+            }
+        );
+        // 1% trade fee with a 25% protocol and no fund share
+        assert_eq!(
+            clmm_swap_fees(50_000, 10_000, 250_000, 0).unwrap(),
+            ClmmSwapFees {
+                net_amount_in: 49_500,
+                trade_fee: 500,
+                protocol_fee: 125,
+                fund_fee: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn protocol_and_fund_fees_leave_the_output_unchanged() {
+        let output = |protocol_fee_rate, fund_fee_rate| {
+            simulate_clmm_swap_output(
+                Q64,
+                0,
+                1_000_000_000_000_000,
+                1_000_000,
+                true,
+                2_500,
+                protocol_fee_rate,
+                fund_fee_rate,
+            )
+            .unwrap()
+        };
+
+        // The sub-split is paid from the trade fee, the net reaching the liquidity is the same
+        assert_eq!(output(0, 0), 997_499);
+        assert_eq!(output(120_000, 40_000), 997_499);
+        assert_eq!(output(500_000, 500_000), 997_499);
+    }
+
+    // Victim slippage on a deep pool at price 1 with the 0.25% trade fee
+    fn victim_slippage(amount: u64, threshold: u64, is_base_input: bool) -> Result<u128> {
+        calculate_clmm_slippage(
+            amount,
+            threshold,
+            is_base_input,
+            0,
+            Q64,
+            0,
+            1_000_000_000_000_000,
+            true,
+            2_500,
+            0,
+            0,
+        )
+        .map(|(_expected_amount, slippage_pips)| slippage_pips)
+    }
+
+    #[test]
+    fn a_base_input_victim_asking_above_the_market_would_fail() {
+        // 1_000_000 in buys 997_499 out
+        assert_eq!(victim_slippage(1_000_000, 987_524, true).unwrap(), 10_000);
+        assert_eq!(victim_slippage(1_000_000, 997_499, true).unwrap(), 0);
+        assert_eq!(
+            victim_slippage(1_000_000, 997_500, true).unwrap_err(),
+            error!(ErrorCode::VictimWouldFail)
+        );
+    }
+
+    #[test]
+    fn an_exact_output_victim_paying_below_the_market_would_fail() {
+        let expected_input =
+            simulate_clmm_swap_input(Q64, 0, 1_000_000_000_000_000, 997_499, true, 2_500, 0, 0)
+                .unwrap();
+
+        assert_eq!(victim_slippage(997_499, expected_input, false).unwrap(), 0);
+        assert_eq!(
+            victim_slippage(997_499, expected_input - 1, false).unwrap_err(),
+            error!(ErrorCode::VictimWouldFail)
+        );
+    }
+
+    #[test]
+    fn a_malformed_transfer_fee_config_is_a_clean_error() {
+        // Basis points above 100% can't be inverted and overflow the forward fee
+        let mint = transfer_fee_mint(20_000);
+        assert_eq!(
+            clmm_get_transfer_fee(&mint, u64::MAX, 0).unwrap_err(),
+            error!(ErrorCode::CalculationFailure)
+        );
+        assert_eq!(
+            clmm_get_transfer_inverse_fee(&mint, 1_000, 0).unwrap_err(),
+            error!(ErrorCode::CalculationFailure)
+        );
+    }
+
+    #[test]
+    fn only_the_pool_vaults_are_accepted() {
+        let pool = ClmmPoolState {
+            token_vault_0: Pubkey::new_unique(),
+            token_vault_1: Pubkey::new_unique(),
+            ..Default::default()
+        };
+
+        assert!(pool.is_token_vault(&{ pool.token_vault_0 }));
+        assert!(pool.is_token_vault(&{ pool.token_vault_1 }));
+        assert!(!pool.is_token_vault(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn an_uninitialized_observation_needs_the_fallback_flag() {
+        let mut observation_state = ClmmObservationState {
+            initialized: false,
+            pool_id: Pubkey::new_unique(),
+            observations: [ClmmObservation::default(); OBSERVATION_NUM],
+            padding: [0; 5],
+        };
+
+        assert_eq!(
+            observation_state.check_initialized(false).unwrap_err(),
+            error!(ErrorCode::UninitializedObservation)
+        );
+        assert!(observation_state.check_initialized(true).is_ok());
+
+        observation_state.initialized = true;
+        assert!(observation_state.check_initialized(false).is_ok());
+    }
+
+    #[test]
+    fn too_many_tick_arrays_are_rejected() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = [];
+        let info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &key,
+            false,
+            0,
+        );
+
+        // The bitmap extension and every tick array fit, one more account doesn't
+        let accounts = vec![info; MAX_TICK_ARRAYS + 1];
+        assert!(check_tick_array_count(&accounts[..MAX_TICK_ARRAYS]).is_ok());
+        assert_eq!(
+            check_tick_array_count(&accounts).unwrap_err(),
+            error!(ErrorCode::TooManyAccounts)
+        );
+    }
+
+    #[test]
+    fn a_victim_sweeping_many_ticks_hits_the_bound() {
+        // At tick 0, 10_000_000_000 of token 1 into 1e12 liquidity lifts the price ~2%
+        let crossings = |tick_spacing| {
+            clmm_victim_tick_crossings(
+                Q64,
+                0,
+                tick_spacing,
+                1_000_000_000_000,
+                10_000_000_000,
+                true,
+                false,
+                2_500,
+                0,
+                0,
+            )
+            .unwrap()
+        };
+        assert_eq!(crossings(1), 198);
+        assert_eq!(crossings(10), 19);
+
+        assert_eq!(
+            check_tick_crossings(crossings(1), 100).unwrap_err(),
+            error!(ErrorCode::TooManyTickCrossings)
+        );
+        // A coarser spacing crosses fewer boundaries, 0 disables the bound
+        assert!(check_tick_crossings(crossings(10), 100).is_ok());
+        assert!(check_tick_crossings(crossings(1), 0).is_ok());
+    }
+
+    #[test]
+    fn the_sqrt_price_drift_is_measured_on_the_price() {
+        let expected = 1u128 << 64;
+        let sqrt_price = |per_mille: u128| expected * per_mille / 1_000;
+
+        // A 0.4% sqrt move is a 0.8% price move, within 100 bps either way
+        assert!(check_sqrt_price_drift(sqrt_price(1_004), expected, 100).is_ok());
+        assert!(check_sqrt_price_drift(sqrt_price(996), expected, 100).is_ok());
+        // 0.6% is a 1.2% price move
+        assert_eq!(
+            check_sqrt_price_drift(sqrt_price(1_006), expected, 100).unwrap_err(),
+            error!(ErrorCode::PriceDrifted)
+        );
+        assert!(check_sqrt_price_drift(sqrt_price(1_006), expected, 0).is_ok());
+    }
+
+    #[test]
+    fn both_price_orderings_give_the_same_delta_magnitude() {
+        let (low, high) = (Q64, Q64 + 12_345_678_901_234_567);
+        let liquidity = 1_000_000_000_000;
+
+        // Exactly 668_812_984.10 of token0 and 669_260_594.28 of token1 between the two
+        for (round_up, amount0, amount1) in
+            [(false, 668_812_984, 669_260_594), (true, 668_812_985, 669_260_595)]
+        {
+            assert_eq!(
+                calculate_amount0_delta(low, high, liquidity, round_up).unwrap(),
+                amount0
+            );
+            assert_eq!(
+                calculate_amount0_delta(high, low, liquidity, round_up).unwrap(),
+                amount0
+            );
+            assert_eq!(
+                calculate_amount1_delta(low, high, liquidity, round_up).unwrap(),
+                amount1
+            );
+            assert_eq!(
+                calculate_amount1_delta(high, low, liquidity, round_up).unwrap(),
+                amount1
+            );
+        }
+    }
+
+    #[test]
+    fn get_tick_at_sqrt_price_bounds() {
+        assert_eq!(
+            get_tick_at_sqrt_price(MIN_SQRT_PRICE_X64).unwrap(),
+            MIN_TICK
+        );
+        assert_eq!(
+            get_tick_at_sqrt_price(MAX_SQRT_PRICE_X64 - 1).unwrap(),
+            MAX_TICK - 1
+        );
+        assert_eq!(get_tick_at_sqrt_price(Q64).unwrap(), 0);
+
+        for sqrt_price_x64 in [0, MIN_SQRT_PRICE_X64 - 1, MAX_SQRT_PRICE_X64] {
+            assert_eq!(
+                get_tick_at_sqrt_price(sqrt_price_x64).unwrap_err(),
+                error!(ErrorCode::InvalidSqrtPrice)
+            );
+        }
+    }
+
+    #[test]
+    fn get_tick_at_sqrt_price_known_pairs() {
+        // sqrt(price) * 2^64 of pools at these raw prices (token_1 per token_0, in base units)
+        let pairs = [
+            ("SOL/USDC at 150", 7_144_393_258_922_745_604u128, -18_973),
+            ("USDC/USDT at 0.9998", 18_444_899_307_059_235_767, -3),
+            ("RAY/SOL at 0.0112", 1_952_219_892_715_600_516, -44_921),
+            ("BONK/SOL at 1.87e-7", 7_977_017_150_176_843, -154_930),
+        ];
+
+        for (pair, sqrt_price_x64, tick) in pairs {
+            assert_eq!(
+                get_tick_at_sqrt_price(sqrt_price_x64).unwrap(),
+                tick,
+                "{}",
+                pair
+            );
+            assert!(
+                get_sqrt_price_at_tick(tick).unwrap() <= sqrt_price_x64,
+                "{}",
+                pair
+            );
+            assert!(
+                get_sqrt_price_at_tick(tick + 1).unwrap() > sqrt_price_x64,
+                "{}",
+                pair
+            );
+        }
+    }
+
+    #[test]
+    fn get_tick_at_sqrt_price_inverts_get_sqrt_price_at_tick() {
+        for tick in [
+            MIN_TICK + 1,
+            -200_000,
+            -18_973,
+            -1,
+            1,
+            60,
+            18_973,
+            200_000,
+            MAX_TICK - 1,
+        ] {
+            let sqrt_price_x64 = get_sqrt_price_at_tick(tick).unwrap();
+            assert_eq!(get_tick_at_sqrt_price(sqrt_price_x64).unwrap(), tick);
+            assert_eq!(
+                get_tick_at_sqrt_price(sqrt_price_x64 - 1).unwrap(),
+                tick - 1
+            );
+        }
+    }
+
+    #[test]
+    fn self_price_impact_cap_shrinks_the_frontrun() {
+        let liquidity = 1_000_000_000_000u128;
+        let size = |self_impact_cap_bps| {
+            calculate_optimal_clmm_sandwich_amount(
+                Q64,
+                0,
+                liquidity,
+                10_000_000_000,
+                50_000,
+                self_impact_cap_bps,
+                true,
+                true,
+                2_500,
+                0,
+                0,
+                MIN_SANDWICH_PROFIT,
+            )
+            .unwrap()
+        };
+
+        // The victim's 5% leaves room for a buy moving the mid price well past 10 bps
+        let uncapped = size(self_price_impact_cap_bps(0).unwrap());
+        let capped = size(self_price_impact_cap_bps(10).unwrap());
+        assert!(capped < uncapped, "{} >= {}", capped, uncapped);
+
+        let price_impact =
+            calculate_price_impact(Q64, liquidity, capped, true, true, 2_500).unwrap();
+        let impact_bps = clmm_mid_price_impact_bps(Q64, Q64 - price_impact);
+        assert!(impact_bps <= 10, "{}", impact_bps);
+    }
+
+    #[test]
+    fn a_clmm_backrun_swaps_the_vault_mints_the_other_way() {
+        let (input_vault_mint, output_vault_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // The frontrun records its input and output vault mints
+        let state = state_with_mints(input_vault_mint, output_vault_mint);
+
+        // The backrun passes the vaults the other way round, its input vault is the
+        // frontrun's output vault
+        assert!(state.is_unwound_by(&output_vault_mint, &input_vault_mint));
+        assert!(!state.is_unwound_by(&input_vault_mint, &output_vault_mint));
+    }
+
+    #[test]
+    fn a_fixed_frontrun_breaking_the_victim_is_refused() {
+        let check = |frontrun_amount| {
+            check_clmm_fixed_frontrun(
+                Q64,
+                0,
+                1_000_000_000_000,
+                frontrun_amount,
+                10_000_000_000,
+                50_000,
+                u128::MAX,
+                true,
+                true,
+                2_500,
+                0,
+                0,
+            )
+        };
+
+        // Moves the victim's quote 39_312 pips, inside the 50_000 it leaves us
+        assert!(check(20_000_000_000).is_ok());
+        // 58_676 pips would push the victim past its tolerance
+        assert_eq!(
+            check(30_000_000_000).unwrap_err(),
+            error!(ErrorCode::ExceededSlippage)
+        );
+    }
+
+    #[test]
+    fn a_fixed_frontrun_moving_the_price_past_the_self_cap_is_refused() {
+        let check = |self_impact_cap_bps| {
+            check_clmm_fixed_frontrun(
+                Q64,
+                0,
+                1_000_000_000_000,
+                20_000_000_000,
+                10_000_000_000,
+                50_000,
+                self_impact_cap_bps,
+                true,
+                true,
+                2_500,
+                0,
+                0,
+            )
+        };
+
+        // The victim has room for it, but the buy moves the mid price about 395 bps
+        assert!(check(400).is_ok());
+        assert_eq!(check(390).unwrap_err(), error!(ErrorCode::ExceededSlippage));
+    }
+
+    #[test]
+    fn the_frontrun_detail_carries_the_limit_it_swaps_with() {
+        let liquidity = 1_000_000_000_000;
+        let detail = |zero_for_one: bool, target_sqrt_price_limit_x64: u128| {
+            clmm_frontrun_detail(
+                1_000_000,
+                Q64,
+                liquidity,
+                zero_for_one,
+                target_sqrt_price_limit_x64,
+                47_500,
+                2_500,
+            )
+            .unwrap()
+        };
+        // 1_000_000 in less the 0.25% fee moves a sqrt price of 1 this far either way
+        let impact = 997_500 * Q64 / liquidity;
+
+        // Without a target limit the frontrun stops where its own swap moves the price
+        let down = detail(true, 0);
+        assert_eq!(down.frontrun_sqrt_price_limit_x64, Q64 - impact);
+        assert_eq!(down.optimal_amount, 1_000_000);
+        assert_eq!(down.current_sqrt_price_x64, Q64);
+        assert!(down.zero_for_one);
+        assert_eq!(down.safe_slippage_bps, 475);
+        assert_eq!(detail(false, 0).frontrun_sqrt_price_limit_x64, Q64 + impact);
+
+        // A target limit short of that binds first
+        assert_eq!(detail(true, Q64 - 1).frontrun_sqrt_price_limit_x64, Q64 - 1);
+        assert_eq!(
+            detail(false, Q64 + 1).frontrun_sqrt_price_limit_x64,
+            Q64 + 1
+        );
+    }
+
+    fn token_account(mint: Pubkey) -> AccountInfo<'static> {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(Pubkey::new_unique(), spl_token::ID, data, false, false)
+    }
+
+    #[test]
+    fn the_swap_checks_the_observation_by_key_without_loading_it() {
+        let (mint_0, mint_1) = (spl_mint(), spl_mint());
+        let (vault_0, vault_1) = (token_account(mint_0.key()), token_account(mint_1.key()));
+
+        let mut amm_config_data = Vec::new();
+        ClmmAmmConfig::default()
+            .try_serialize(&mut amm_config_data)
+            .unwrap();
+        let amm_config = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            amm_config_data,
+            false,
+            false,
+        );
+
+        // No data at all, loading it the way the frontrun does fails
+        let observation = account_info(Pubkey::new_unique(), crate::ID, vec![], false, false);
+        let leaked_observation = Box::leak(Box::new(observation.clone()));
+        assert!(AccountLoader::<ClmmObservationState>::try_from(leaked_observation).is_err());
+
+        let pool_state = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            vec![0u8; 8 + std::mem::size_of::<ClmmPoolState>()],
+            false,
+            false,
+        );
+        pool_state.try_borrow_mut_data().unwrap()[..8]
+            .copy_from_slice(&ClmmPoolState::DISCRIMINATOR);
+        *AccountLoader::<ClmmPoolState>::try_from(Box::leak(Box::new(pool_state.clone())))
+            .unwrap()
+            .load_mut()
+            .unwrap() = ClmmPoolState {
+            amm_config: *amm_config.key,
+            token_vault_0: *vault_0.key,
+            token_vault_1: *vault_1.key,
+            observation_key: *observation.key,
+            ..Default::default()
+        };
+
+        let accounts = vec![
+            program(RaydiumClmm::id()),
+            account_info(Pubkey::new_unique(), Pubkey::default(), vec![], true, false),
+            amm_config,
+            pool_state,
+            token_account(mint_0.key()),
+            token_account(mint_1.key()),
+            vault_0,
+            vault_1,
+            observation.clone(),
+            program(spl_token::ID),
+            program(spl_token_2022::ID),
+            program(Memo::id()),
+            mint_0.to_account_info(),
+            mint_1.to_account_info(),
+        ];
+        let try_swap_accounts = |accounts: Vec<AccountInfo<'static>>| {
+            let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+            ClmmSwap::try_accounts(
+                &crate::ID,
+                &mut accounts,
+                &[],
+                &mut ClmmSwapBumps::default(),
+                &mut std::collections::BTreeSet::new(),
+            )
+            .map(|swap| swap.observation_state.key())
+        };
+
+        // The swap and the backrun only hand it on to Raydium, the key check is all
+        // the compute they spend on it
+        assert_eq!(
+            try_swap_accounts(accounts.clone()).unwrap(),
+            *observation.key
+        );
+
+        // Still, another account in its place is refused
+        let mut wrong_observation = accounts;
+        wrong_observation[8] = account_info(Pubkey::new_unique(), crate::ID, vec![], false, false);
+        assert_eq!(
+            try_swap_accounts(wrong_observation).unwrap_err(),
+            error!(anchor_lang::error::ErrorCode::ConstraintAddress)
+        );
+    }
+
+    // Stands in for the system and associated token programs creating the frontrun's
+    // accounts, whose buffers are sized up front as a test can't grow them
+    fn create_accounts(instruction: &Instruction, accounts: &[AccountInfo]) -> ProgramResult {
+        let account = |index: usize| {
+            let key = instruction.accounts[index].pubkey;
+            accounts.iter().find(|account| *account.key == key).unwrap()
+        };
+        if instruction.program_id == anchor_spl::associated_token::ID {
+            // Payer, associated token account, wallet, mint, system and token programs
+            let token_account = account(1);
+            token_account.assign(&instruction.accounts[5].pubkey);
+            **token_account.try_borrow_mut_lamports()? =
+                Rent::default().minimum_balance(spl_token::state::Account::LEN);
+            spl_token::state::Account {
+                mint: instruction.accounts[3].pubkey,
+                owner: instruction.accounts[2].pubkey,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut token_account.try_borrow_mut_data()?);
+        } else if instruction.program_id == anchor_lang::system_program::ID {
+            // create_account: lamports at 4, space at 12 and the owner at 20
+            let new_account = account(1);
+            let lamports = u64::from_le_bytes(instruction.data[4..12].try_into().unwrap());
+            **new_account.try_borrow_mut_lamports()? = lamports;
+            new_account.assign(&Pubkey::try_from(&instruction.data[20..52]).unwrap());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn a_missing_output_ata_is_created_for_the_frontrun() {
+        let _stubs = syscall_stubs();
+        on_invoke(create_accounts);
+
+        let (mint_0, mint_1) = (spl_mint(), spl_mint());
+        let (vault_0, vault_1) = (token_account(mint_0.key()), token_account(mint_1.key()));
+
+        let mut amm_config_data = Vec::new();
+        ClmmAmmConfig::default()
+            .try_serialize(&mut amm_config_data)
+            .unwrap();
+        let amm_config = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            amm_config_data,
+            false,
+            false,
+        );
+
+        let mut observation_data = vec![0u8; 8 + std::mem::size_of::<ClmmObservationState>()];
+        observation_data[..8].copy_from_slice(&ClmmObservationState::DISCRIMINATOR);
+        let observation = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            observation_data,
+            false,
+            false,
+        );
+
+        let pool_state = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            vec![0u8; 8 + std::mem::size_of::<ClmmPoolState>()],
+            false,
+            false,
+        );
+        pool_state.try_borrow_mut_data().unwrap()[..8]
+            .copy_from_slice(&ClmmPoolState::DISCRIMINATOR);
+        *AccountLoader::<ClmmPoolState>::try_from(Box::leak(Box::new(pool_state.clone())))
+            .unwrap()
+            .load_mut()
+            .unwrap() = ClmmPoolState {
+            amm_config: *amm_config.key,
+            token_vault_0: *vault_0.key,
+            token_vault_1: *vault_1.key,
+            observation_key: *observation.key,
+            ..Default::default()
+        };
+
+        // The payer has never held the output mint, its associated account and the
+        // sandwich state are both still empty system accounts
+        let payer = Pubkey::new_unique();
+        let sandwich_id: u64 = 7;
+        let output_ata =
+            get_associated_token_address_with_program_id(&payer, &mint_1.key(), &spl_token::ID);
+        let (sandwich_key, _) = SandwichState::pda(&payer, sandwich_id);
+
+        let (config_key, config_bump) =
+            Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        let mut program_config = config(0);
+        program_config.bump = config_bump;
+        let mut config_data = vec![0u8; 8 + ProgramConfig::SIZE];
+        program_config
+            .try_serialize(&mut config_data.as_mut_slice())
+            .unwrap();
+
+        // The program id stands in for each optional guard account left out
+        let none = || account_info(crate::ID, Pubkey::default(), vec![], false, false);
+        let accounts = vec![
+            program(RaydiumClmm::id()),
+            account_info(payer, Pubkey::default(), vec![], true, false),
+            amm_config,
+            pool_state,
+            token_account(mint_0.key()),
+            account_info(
+                output_ata,
+                anchor_lang::system_program::ID,
+                vec![0u8; spl_token::state::Account::LEN],
+                false,
+                false,
+            ),
+            vault_0,
+            vault_1,
+            observation,
+            program(spl_token::ID),
+            program(spl_token_2022::ID),
+            program(Memo::id()),
+            mint_0.to_account_info(),
+            mint_1.to_account_info(),
+            program(spl_token::ID),
+            account_info(
+                sandwich_key,
+                anchor_lang::system_program::ID,
+                vec![0u8; 8 + SandwichState::SIZE],
+                false,
+                false,
+            ),
+            program(anchor_spl::associated_token::ID),
+            program(anchor_lang::system_program::ID),
+            account_info(config_key, crate::ID, config_data, false, false),
+            none(),
+            none(),
+            none(),
+            none(),
+            none(),
+            none(),
+        ];
+
+        // Victim amount, threshold, price limit and direction, then the sandwich id
+        let mut ix_data = vec![0u8; 8 + 8 + 16 + 1];
+        ix_data.extend_from_slice(&sandwich_id.to_le_bytes());
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        let frontrun = ClmmSandwichFrontrun::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &ix_data,
+            &mut ClmmSandwichFrontrunBumps::default(),
+            &mut std::collections::BTreeSet::new(),
+        )
+        .unwrap();
+
+        // The output account, right after the input one, is created as the payer's
+        // associated account before the state
+        let invoked = take_invoked();
+        let (create_ata, _) = &invoked[0];
+        assert_eq!(create_ata.program_id, anchor_spl::associated_token::ID);
+        assert_eq!(create_ata.accounts[1].pubkey, output_ata);
+        assert_eq!(frontrun.output_token_account.key(), output_ata);
+        assert_eq!(frontrun.output_token_account.owner, payer);
+        assert_eq!(frontrun.output_token_account.mint, mint_1.key());
+    }
+}
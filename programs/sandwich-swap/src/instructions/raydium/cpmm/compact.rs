@@ -0,0 +1,403 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use raydium_cpmm_cpi::program::RaydiumCpmm;
+
+use super::{
+    calculate_cpmm_backrun_minimum_output, CpmmAmmConfig, CpmmObservationState, CpmmPoolState,
+    CpmmSwapAccounts,
+};
+
+use crate::compact_sandwich_state::{CompactSandwichState, MintRegistry};
+use crate::error::ErrorCode;
+use crate::program_config::*;
+use crate::sandwich_state::{
+    check_backrun_balance, min_required_output, with_fixed_cost, with_min_profit_amount,
+    SandwichCompleteEvent, SlippageModel,
+};
+
+#[derive(Accounts)]
+#[instruction(_target_amount_in: u64, _target_minimum_amount_out: u64, sandwich_id: u64)]
+pub struct CpmmCompactFrontrun<'info> {
+    pub cp_swap_program: Program<'info, RaydiumCpmm>,
+    /// The user performing the swap
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: pool vault and lp mint authority
+    #[account(
+     seeds = [
+       raydium_cpmm_cpi::AUTH_SEED.as_bytes(),
+     ],
+     seeds::program = cp_swap_program.key(),
+     bump,
+   )]
+    pub authority: UncheckedAccount<'info>,
+
+    /// The factory state to read protocol fees
+    #[account(address = pool_state.load()?.amm_config)]
+    pub amm_config: Box<Account<'info, CpmmAmmConfig>>,
+
+    /// The program account of the pool in which the swap will be performed
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, CpmmPoolState>,
+
+    /// The user token account for input token
+    #[account(mut)]
+    pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user token account for output token
+    #[account(mut)]
+    pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault token account for input token
+    #[account(
+     mut,
+     constraint = input_vault.key() == pool_state.load()?.token_0_vault || input_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault token account for output token
+    #[account(
+     mut,
+     constraint = output_vault.key() == pool_state.load()?.token_0_vault || output_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// SPL program for input token transfers
+    pub input_token_program: Interface<'info, TokenInterface>,
+
+    /// SPL program for output token transfers
+    pub output_token_program: Interface<'info, TokenInterface>,
+
+    /// The mint of input token
+    #[account(address = input_vault.mint)]
+    pub input_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The mint of output token
+    #[account(address = output_vault.mint)]
+    pub output_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The program account for the most recent oracle observation
+    #[account(mut, address = pool_state.load()?.observation_key)]
+    pub observation_state: AccountLoader<'info, CpmmObservationState>,
+
+    /// The operator's mint registry, both mints must be registered
+    #[account(
+        seeds = [MintRegistry::SEED, payer.key().as_ref()],
+        bump = mint_registry.bump
+    )]
+    pub mint_registry: Box<Account<'info, MintRegistry>>,
+
+    /// The account that will store the compact sandwich state
+    #[account(
+       init,
+       payer = payer,
+       space = 8 + CompactSandwichState::SIZE,
+       seeds = [CompactSandwichState::SEED, payer.key().as_ref(), &sandwich_id.to_le_bytes()],
+       bump
+   )]
+    pub sandwich_state: Account<'info, CompactSandwichState>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
+}
+
+impl<'info> CpmmCompactFrontrun<'info> {
+    /// The accounts of the swap, sized and executed through `CpmmSwapAccounts`
+    pub fn swap(&self) -> CpmmSwapAccounts<'info> {
+        CpmmSwapAccounts {
+            cp_swap_program: self.cp_swap_program.clone(),
+            payer: self.payer.clone(),
+            authority: self.authority.clone(),
+            amm_config: self.amm_config.clone(),
+            pool_state: self.pool_state.clone(),
+            input_token_account: self.input_token_account.clone(),
+            output_token_account: self.output_token_account.clone(),
+            input_vault: self.input_vault.clone(),
+            output_vault: self.output_vault.clone(),
+            input_token_program: self.input_token_program.clone(),
+            output_token_program: self.output_token_program.clone(),
+            input_token_mint: self.input_token_mint.clone(),
+            output_token_mint: self.output_token_mint.clone(),
+            observation_state: self.observation_state.clone(),
+        }
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(sandwich_id: u64)]
+pub struct CpmmCompactBackrun<'info> {
+    pub cp_swap_program: Program<'info, RaydiumCpmm>,
+    /// The user performing the swap
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: pool vault and lp mint authority
+    #[account(
+     seeds = [
+       raydium_cpmm_cpi::AUTH_SEED.as_bytes(),
+     ],
+     seeds::program = cp_swap_program.key(),
+     bump,
+   )]
+    pub authority: UncheckedAccount<'info>,
+
+    /// The factory state to read protocol fees
+    #[account(address = pool_state.load()?.amm_config)]
+    pub amm_config: Box<Account<'info, CpmmAmmConfig>>,
+
+    /// The program account of the pool in which the swap will be performed
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, CpmmPoolState>,
+
+    /// The user token account for input token (was output in frontrun)
+    #[account(mut)]
+    pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user token account for output token (was input in frontrun)
+    #[account(mut)]
+    pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault token account for input token (was output in frontrun)
+    #[account(
+     mut,
+     constraint = input_vault.key() == pool_state.load()?.token_0_vault || input_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault token account for output token (was input in frontrun)
+    #[account(
+     mut,
+     constraint = output_vault.key() == pool_state.load()?.token_0_vault || output_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// SPL program for input token transfers
+    pub input_token_program: Interface<'info, TokenInterface>,
+
+    /// SPL program for output token transfers
+    pub output_token_program: Interface<'info, TokenInterface>,
+
+    /// The mint of input token (was output in frontrun)
+    #[account(address = input_vault.mint)]
+    pub input_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The mint of output token (was input in frontrun)
+    #[account(address = output_vault.mint)]
+    pub output_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The program account for the most recent oracle observation
+    #[account(mut, address = pool_state.load()?.observation_key)]
+    pub observation_state: AccountLoader<'info, CpmmObservationState>,
+
+    /// The operator's mint registry, resolves the mint indices of the state
+    #[account(
+        seeds = [MintRegistry::SEED, payer.key().as_ref()],
+        bump = mint_registry.bump
+    )]
+    pub mint_registry: Box<Account<'info, MintRegistry>>,
+
+    /// The account that stores the compact sandwich state
+    #[account(
+       mut,
+       seeds = [CompactSandwichState::SEED, payer.key().as_ref(), &sandwich_id.to_le_bytes()],
+       bump = sandwich_state.bump,
+       constraint = !sandwich_state.is_complete @ ErrorCode::SandwichAlreadyCompleted,
+       constraint = mint_registry.mint_at(sandwich_state.token_in_index)? == output_token_mint.key()
+           @ ErrorCode::TokenMintMismatch,
+       constraint = mint_registry.mint_at(sandwich_state.token_out_index)? == input_token_mint.key()
+           @ ErrorCode::TokenMintMismatch
+   )]
+    pub sandwich_state: Account<'info, CompactSandwichState>,
+
+    /// Program config, tracks the losing streak for the circuit breaker
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+impl<'info> CpmmCompactBackrun<'info> {
+    /// The accounts of the swap, sized and executed through `CpmmSwapAccounts`
+    pub fn swap(&self) -> CpmmSwapAccounts<'info> {
+        CpmmSwapAccounts {
+            cp_swap_program: self.cp_swap_program.clone(),
+            payer: self.payer.clone(),
+            authority: self.authority.clone(),
+            amm_config: self.amm_config.clone(),
+            pool_state: self.pool_state.clone(),
+            input_token_account: self.input_token_account.clone(),
+            output_token_account: self.output_token_account.clone(),
+            input_vault: self.input_vault.clone(),
+            output_vault: self.output_vault.clone(),
+            input_token_program: self.input_token_program.clone(),
+            output_token_program: self.output_token_program.clone(),
+            input_token_mint: self.input_token_mint.clone(),
+            output_token_mint: self.output_token_mint.clone(),
+            observation_state: self.observation_state.clone(),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn cpmm_compact_frontrun_swap_base_input(
+    ctx: Context<CpmmCompactFrontrun>,
+    target_amount_in: u64,
+    target_minimum_amount_out: u64,
+    _sandwich_id: u64,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    check_sellable: bool,
+    min_pool_age_secs: u32,
+    default_victim_slippage_bps: u16,
+    expected_current_price: u128,
+    max_price_drift_bps: u16,
+    require_victim_ix: Option<Pubkey>,
+    slippage_model: SlippageModel,
+    allow_uninitialized_observation: bool,
+) -> Result<()> {
+    log_frontrun_compute_units("CPMM");
+
+    // Run the guards shared by every CPMM frontrun
+    let mut swap = ctx.accounts.swap();
+    swap.check_frontrun(
+        &mut ctx.accounts.guards,
+        &ctx.accounts.output_token_mint.key(),
+        require_victim_ix,
+        min_pool_age_secs,
+        expected_current_price,
+        max_price_drift_bps,
+        allow_uninitialized_observation,
+    )?;
+
+    // Resolve the mint indices up front, unregistered mints can't be stored compactly
+    let token_in_index = ctx
+        .accounts
+        .mint_registry
+        .index_of(&ctx.accounts.input_token_mint.key())?;
+    let token_out_index = ctx
+        .accounts
+        .mint_registry
+        .index_of(&ctx.accounts.output_token_mint.key())?;
+
+    // Size the frontrun against the current reserves
+    let (optimal_buy_amount, minimum_out_for_sandwich) = swap.size_frontrun(
+        &ctx.accounts.guards.config,
+        target_amount_in,
+        target_minimum_amount_out,
+        true,
+        &[],
+        default_victim_slippage_bps,
+        min_victim_slippage_bps,
+        self_slippage_bps,
+        max_self_price_impact_bps,
+        slippage_model,
+        check_sellable,
+    )?;
+
+    // Execute the buy transaction with calculated amount, refusing one that filled nothing
+    let frontrun_output_amount =
+        swap.frontrun_base_input(optimal_buy_amount, minimum_out_for_sandwich)?;
+
+    // Store frontrun data in the PDA for the backrun to read
+    let sandwich_state = &mut ctx.accounts.sandwich_state;
+    sandwich_state.frontrun_output_amount = frontrun_output_amount;
+    sandwich_state.frontrun_input_amount = optimal_buy_amount;
+    let clock = Clock::get()?;
+    sandwich_state.frontrun_slot = clock.slot;
+    sandwich_state.timestamp = clock.unix_timestamp;
+    sandwich_state.token_in_index = token_in_index;
+    sandwich_state.token_out_index = token_out_index;
+    sandwich_state.is_complete = false;
+    sandwich_state.bump = ctx.bumps.sandwich_state;
+
+    Ok(())
+}
+
+pub fn cpmm_compact_backrun_swap_base_input(
+    ctx: Context<CpmmCompactBackrun>,
+    sandwich_id: u64,
+    min_slots_between: u8,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    max_state_age_secs: u32,
+) -> Result<()> {
+    // Refuse to backrun a frontrun that filled nothing
+    ctx.accounts.sandwich_state.check_frontrun_filled()?;
+
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    ctx.accounts.sandwich_state.check_backrun_slot(min_slots_between)?;
+
+    // Refuse to unwind a position left open for too long, at a price long gone
+    ctx.accounts
+        .sandwich_state
+        .check_state_age(max_state_age_secs)?;
+
+    // The compact state always unwinds the whole position
+    let sell_amount = ctx.accounts.sandwich_state.frontrun_output_amount;
+    let cost_basis = ctx.accounts.sandwich_state.frontrun_input_amount;
+
+    // The input account must still hold what this backrun sells
+    check_backrun_balance(ctx.accounts.input_token_account.amount, sell_amount)?;
+
+    // The compact state keeps no timestamp, so its profit floor never decays
+    let min_required_output = with_fixed_cost(
+        with_min_profit_amount(
+            min_required_output(cost_basis, 0, 0)?,
+            cost_basis,
+            min_profit_amount,
+        )?,
+        &ctx.accounts.output_token_mint.key(),
+        fixed_cost_lamports,
+    )?;
+
+    // Size the backrun exactly like the full-state backrun does
+    let (minimum_backrun_output, expected_backrun_output) = {
+        let pool_state = ctx.accounts.pool_state.load()?;
+        calculate_cpmm_backrun_minimum_output(
+            &pool_state,
+            &ctx.accounts.input_vault,
+            &ctx.accounts.output_vault,
+            &ctx.accounts.amm_config,
+            &ctx.accounts.input_token_mint.to_account_info(),
+            &ctx.accounts.output_token_mint.to_account_info(),
+            Clock::get()?.epoch,
+            sell_amount,
+            cost_basis,
+            min_required_output,
+            None,
+            ctx.accounts.config.research_mode,
+        )?
+    };
+
+    // Execute the backrun swap (selling tokens acquired in frontrun)
+    let actual_output = ctx
+        .accounts
+        .swap()
+        .swap_base_input(sell_amount, minimum_backrun_output)?;
+
+    ctx.accounts.sandwich_state.is_complete = true;
+
+    // Calculate the actual profit next to the one the sizing expected
+    let event = SandwichCompleteEvent::new(
+        sandwich_id,
+        cost_basis,
+        actual_output,
+        Some(expected_backrun_output),
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
+
+    // Emit an event with profit information
+    emit_cpi!(event);
+
+    Ok(())
+}
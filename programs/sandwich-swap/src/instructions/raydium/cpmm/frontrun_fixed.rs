@@ -0,0 +1,73 @@
+use anchor_lang::prelude::*;
+
+use super::CpmmSandwichFrontrun;
+
+use crate::program_config::*;
+use crate::sandwich_state::SlippageModel;
+
+/// Same as `cpmm_frontrun_swap_base_input`, but buys `frontrun_amount` sized off-chain
+/// instead of searching for it, refusing an amount that breaks the victim's slippage.
+#[allow(clippy::too_many_arguments)]
+pub fn cpmm_frontrun_swap_fixed(
+    ctx: Context<CpmmSandwichFrontrun>,
+    target_amount_in: u64,
+    target_minimum_amount_out: u64,
+    sandwich_id: u64,
+    frontrun_amount: u64,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    check_sellable: bool,
+    min_pool_age_secs: u32,
+    default_victim_slippage_bps: u16,
+    expected_current_price: u128,
+    max_price_drift_bps: u16,
+    require_victim_ix: Option<Pubkey>,
+    slippage_model: SlippageModel,
+    allow_uninitialized_observation: bool,
+) -> Result<()> {
+    log_frontrun_compute_units("CPMM");
+
+    // Run the guards shared by every CPMM frontrun
+    let mut swap = ctx.accounts.swap();
+    swap.check_frontrun(
+        &mut ctx.accounts.guards,
+        &ctx.accounts.output_token_mint.key(),
+        require_victim_ix,
+        min_pool_age_secs,
+        expected_current_price,
+        max_price_drift_bps,
+        allow_uninitialized_observation,
+    )?;
+
+    // Check the fixed frontrun against the pre-swap reserves, the victim has to clear
+    // its tolerance behind it
+    let (frontrun_amount, minimum_out_for_sandwich) = swap.size_fixed_frontrun(
+        &ctx.accounts.guards.config,
+        frontrun_amount,
+        target_amount_in,
+        target_minimum_amount_out,
+        default_victim_slippage_bps,
+        min_victim_slippage_bps,
+        self_slippage_bps,
+        max_self_price_impact_bps,
+        slippage_model,
+        check_sellable,
+    )?;
+
+    // Execute the buy transaction with the fixed amount, refusing one that filled nothing
+    let frontrun_output_amount =
+        swap.frontrun_base_input(frontrun_amount, minimum_out_for_sandwich)?;
+
+    // Store frontrun data in the PDA for the backrun to read
+    ctx.accounts.sandwich_state.record_frontrun(
+        frontrun_amount,
+        frontrun_output_amount,
+        ctx.accounts.input_token_mint.key(),
+        ctx.accounts.output_token_mint.key(),
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{self, spl_token, SyncNative},
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+use raydium_cpmm_cpi::program::RaydiumCpmm;
+
+use super::{CpmmAmmConfig, CpmmObservationState, CpmmPoolState, CpmmSwapAccounts};
+
+use crate::sandwich_state::{SandwichState, SlippageModel};
+use crate::program_config::*;
+
+#[derive(Accounts)]
+#[instruction(_target_amount_in: u64, _target_minimum_amount_out: u64, sandwich_id: u64)]
+pub struct CpmmSandwichFrontrunPrepared<'info> {
+    pub cp_swap_program: Program<'info, RaydiumCpmm>,
+    /// The user performing the swap
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: pool vault and lp mint authority
+    #[account(
+     seeds = [
+       raydium_cpmm_cpi::AUTH_SEED.as_bytes(),
+     ],
+     seeds::program = cp_swap_program.key(),
+     bump,
+   )]
+    pub authority: UncheckedAccount<'info>,
+
+    /// The factory state to read protocol fees
+    #[account(address = pool_state.load()?.amm_config)]
+    pub amm_config: Box<Account<'info, CpmmAmmConfig>>,
+
+    /// The program account of the pool in which the swap will be performed
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, CpmmPoolState>,
+
+    /// The vault token account for input token
+    #[account(
+     mut,
+     constraint = input_vault.key() == pool_state.load()?.token_0_vault || input_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault token account for output token
+    #[account(
+     mut,
+     constraint = output_vault.key() == pool_state.load()?.token_0_vault || output_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// SPL program for input token transfers
+    pub input_token_program: Interface<'info, TokenInterface>,
+
+    /// SPL program for output token transfers
+    pub output_token_program: Interface<'info, TokenInterface>,
+
+    /// The mint of input token
+    #[account(address = input_vault.mint)]
+    pub input_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The mint of output token
+    #[account(address = output_vault.mint)]
+    pub output_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The user's associated token account for the input token, created if missing
+    #[account(
+       init_if_needed,
+       payer = payer,
+       associated_token::mint = input_token_mint,
+       associated_token::authority = payer,
+       associated_token::token_program = input_token_program,
+   )]
+    pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user's associated token account for the output token, created if missing
+    #[account(
+       init_if_needed,
+       payer = payer,
+       associated_token::mint = output_token_mint,
+       associated_token::authority = payer,
+       associated_token::token_program = output_token_program,
+   )]
+    pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The program account for the most recent oracle observation
+    #[account(mut, address = pool_state.load()?.observation_key)]
+    pub observation_state: AccountLoader<'info, CpmmObservationState>,
+
+    /// The account that will store sandwich state
+    #[account(
+       init,
+       payer = payer,
+       space = 8 + SandwichState::SIZE,
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
+       bump
+   )]
+    pub sandwich_state: Account<'info, SandwichState>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
+}
+
+impl<'info> CpmmSandwichFrontrunPrepared<'info> {
+    /// The accounts of the swap, sized and executed through `CpmmSwapAccounts`
+    pub fn swap(&self) -> CpmmSwapAccounts<'info> {
+        CpmmSwapAccounts {
+            cp_swap_program: self.cp_swap_program.clone(),
+            payer: self.payer.clone(),
+            authority: self.authority.clone(),
+            amm_config: self.amm_config.clone(),
+            pool_state: self.pool_state.clone(),
+            input_token_account: self.input_token_account.clone(),
+            output_token_account: self.output_token_account.clone(),
+            input_vault: self.input_vault.clone(),
+            output_vault: self.output_vault.clone(),
+            input_token_program: self.input_token_program.clone(),
+            output_token_program: self.output_token_program.clone(),
+            input_token_mint: self.input_token_mint.clone(),
+            output_token_mint: self.output_token_mint.clone(),
+            observation_state: self.observation_state.clone(),
+        }
+    }
+}
+
+/// Same as `cpmm_frontrun_swap_base_input`, but creates the payer's missing ATAs
+/// and wraps SOL for a WSOL input before executing the frontrun.
+#[allow(clippy::too_many_arguments)]
+pub fn cpmm_frontrun_prepared(
+    ctx: Context<CpmmSandwichFrontrunPrepared>,
+    target_amount_in: u64,
+    target_minimum_amount_out: u64,
+    sandwich_id: u64,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    check_sellable: bool,
+    victim_is_exact_input: bool,
+    min_pool_age_secs: u32,
+    default_victim_slippage_bps: u16,
+    expected_current_price: u128,
+    max_price_drift_bps: u16,
+    victim_amounts: Vec<u64>,
+    require_victim_ix: Option<Pubkey>,
+    slippage_model: SlippageModel,
+    allow_uninitialized_observation: bool,
+) -> Result<()> {
+    log_frontrun_compute_units("CPMM");
+
+    // Run the guards shared by every CPMM frontrun
+    let mut swap = ctx.accounts.swap();
+    swap.check_frontrun(
+        &mut ctx.accounts.guards,
+        &ctx.accounts.output_token_mint.key(),
+        require_victim_ix,
+        min_pool_age_secs,
+        expected_current_price,
+        max_price_drift_bps,
+        allow_uninitialized_observation,
+    )?;
+
+    // Size the frontrun against the pre-swap reserves
+    let (optimal_buy_amount, minimum_out_for_sandwich) = swap.size_frontrun(
+        &ctx.accounts.guards.config,
+        target_amount_in,
+        target_minimum_amount_out,
+        victim_is_exact_input,
+        &victim_amounts,
+        default_victim_slippage_bps,
+        min_victim_slippage_bps,
+        self_slippage_bps,
+        max_self_price_impact_bps,
+        slippage_model,
+        check_sellable,
+    )?;
+
+    // Wrap the missing SOL when the input side is WSOL
+    if ctx.accounts.input_token_mint.key() == spl_token::native_mint::ID
+        && ctx.accounts.input_token_account.amount < optimal_buy_amount
+    {
+        let shortfall = optimal_buy_amount - ctx.accounts.input_token_account.amount;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: ctx.accounts.input_token_account.to_account_info(),
+                },
+            ),
+            shortfall,
+        )?;
+        token::sync_native(CpiContext::new(
+            ctx.accounts.input_token_program.to_account_info(),
+            SyncNative {
+                account: ctx.accounts.input_token_account.to_account_info(),
+            },
+        ))?;
+    }
+
+    // Execute the buy transaction with calculated amount, refusing one that filled nothing
+    let frontrun_output_amount =
+        swap.frontrun_base_input(optimal_buy_amount, minimum_out_for_sandwich)?;
+
+    // Store frontrun data in the PDA for the backrun to read
+    ctx.accounts.sandwich_state.record_frontrun(
+        optimal_buy_amount,
+        frontrun_output_amount,
+        *ctx.accounts.input_token_mint.to_account_info().key,
+        *ctx.accounts.output_token_mint.to_account_info().key,
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
+
+    Ok(())
+}
@@ -4,6 +4,21 @@ pub use swap_base_input::*;
 pub mod swap_base_output;
 pub use swap_base_output::*;
 
+pub mod frontrun_prepared;
+pub use frontrun_prepared::*;
+
+pub mod frontrun_fixed;
+pub use frontrun_fixed::*;
+
+pub mod route_frontrun;
+pub use route_frontrun::*;
+
+pub mod compact;
+pub use compact::*;
+
+pub mod simulate_grid;
+pub use simulate_grid::*;
+
 pub mod curve;
 pub use curve::*;
 
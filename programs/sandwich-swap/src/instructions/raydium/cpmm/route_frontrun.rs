@@ -0,0 +1,289 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use raydium_cpmm_cpi::program::RaydiumCpmm;
+
+use super::{
+    resolve_direction, CpmmAmmConfig, CpmmObservationState, CpmmPoolState, CpmmSwapAccounts,
+    CurveCalculator,
+};
+
+use crate::error::ErrorCode;
+use crate::program_config::*;
+use crate::sandwich_state::{SandwichState, SlippageModel};
+
+/// Frontrun of a two-hop (A -> B -> C) route swap. We only trade the first hop's
+/// CPMM pool, the second hop's pool is read to translate the victim's final
+/// minimum out into a first-hop allowance.
+#[derive(Accounts)]
+#[instruction(_target_amount_in: u64, _target_minimum_amount_out: u64, sandwich_id: u64)]
+pub struct RaydiumRouteFrontrun<'info> {
+    pub cp_swap_program: Program<'info, RaydiumCpmm>,
+    /// The user performing the swap
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: pool vault and lp mint authority
+    #[account(
+     seeds = [
+       raydium_cpmm_cpi::AUTH_SEED.as_bytes(),
+     ],
+     seeds::program = cp_swap_program.key(),
+     bump,
+   )]
+    pub authority: UncheckedAccount<'info>,
+
+    /// The factory state to read protocol fees
+    #[account(address = pool_state.load()?.amm_config)]
+    pub amm_config: Box<Account<'info, CpmmAmmConfig>>,
+
+    /// The first hop pool, the one we frontrun
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, CpmmPoolState>,
+
+    /// The user token account for input token
+    #[account(mut)]
+    pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user token account for output token
+    #[account(mut)]
+    pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault token account for input token
+    #[account(
+     mut,
+     constraint = input_vault.key() == pool_state.load()?.token_0_vault || input_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault token account for output token
+    #[account(
+     mut,
+     constraint = output_vault.key() == pool_state.load()?.token_0_vault || output_vault.key() == pool_state.load()?.token_1_vault
+   )]
+    pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// SPL program for input token transfers
+    pub input_token_program: Interface<'info, TokenInterface>,
+
+    /// SPL program for output token transfers
+    pub output_token_program: Interface<'info, TokenInterface>,
+
+    /// The mint of input token
+    #[account(address = input_vault.mint)]
+    pub input_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The mint of output token
+    #[account(address = output_vault.mint)]
+    pub output_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The program account for the most recent oracle observation
+    #[account(mut, address = pool_state.load()?.observation_key)]
+    pub observation_state: AccountLoader<'info, CpmmObservationState>,
+
+    /// The factory state of the second hop pool
+    #[account(address = second_hop_pool_state.load()?.amm_config)]
+    pub second_hop_amm_config: Box<Account<'info, CpmmAmmConfig>>,
+
+    /// The second hop pool, only read to size the frontrun
+    pub second_hop_pool_state: AccountLoader<'info, CpmmPoolState>,
+
+    /// The second hop vault receiving the intermediate token
+    #[account(
+     constraint = second_hop_input_vault.key() == second_hop_pool_state.load()?.token_0_vault || second_hop_input_vault.key() == second_hop_pool_state.load()?.token_1_vault,
+     constraint = second_hop_input_vault.mint == output_vault.mint @ ErrorCode::TokenMintMismatch
+   )]
+    pub second_hop_input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The second hop vault paying out the victim's final token
+    #[account(
+     constraint = second_hop_output_vault.key() == second_hop_pool_state.load()?.token_0_vault || second_hop_output_vault.key() == second_hop_pool_state.load()?.token_1_vault
+   )]
+    pub second_hop_output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The account that will store sandwich state
+    #[account(
+       init,
+       payer = payer,
+       space = 8 + SandwichState::SIZE,
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
+       bump
+   )]
+    pub sandwich_state: Account<'info, SandwichState>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
+}
+
+impl<'info> RaydiumRouteFrontrun<'info> {
+    /// The accounts of the swap, sized and executed through `CpmmSwapAccounts`
+    pub fn swap(&self) -> CpmmSwapAccounts<'info> {
+        CpmmSwapAccounts {
+            cp_swap_program: self.cp_swap_program.clone(),
+            payer: self.payer.clone(),
+            authority: self.authority.clone(),
+            amm_config: self.amm_config.clone(),
+            pool_state: self.pool_state.clone(),
+            input_token_account: self.input_token_account.clone(),
+            output_token_account: self.output_token_account.clone(),
+            input_vault: self.input_vault.clone(),
+            output_vault: self.output_vault.clone(),
+            input_token_program: self.input_token_program.clone(),
+            output_token_program: self.output_token_program.clone(),
+            input_token_mint: self.input_token_mint.clone(),
+            output_token_mint: self.output_token_mint.clone(),
+            observation_state: self.observation_state.clone(),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn cpmm_route_frontrun(
+    ctx: Context<RaydiumRouteFrontrun>,
+    target_amount_in: u64,
+    target_minimum_amount_out: u64,
+    sandwich_id: u64,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    check_sellable: bool,
+    min_pool_age_secs: u32,
+    default_victim_slippage_bps: u16,
+    expected_current_price: u128,
+    max_price_drift_bps: u16,
+    require_victim_ix: Option<Pubkey>,
+    slippage_model: SlippageModel,
+    allow_uninitialized_observation: bool,
+) -> Result<()> {
+    log_frontrun_compute_units("CPMM");
+
+    // Run the guards shared by every CPMM frontrun, the victim's final token being the
+    // second hop's output
+    let mut swap = ctx.accounts.swap();
+    swap.check_frontrun(
+        &mut ctx.accounts.guards,
+        &ctx.accounts.second_hop_output_vault.mint,
+        require_victim_ix,
+        min_pool_age_secs,
+        expected_current_price,
+        max_price_drift_bps,
+        allow_uninitialized_observation,
+    )?;
+
+    // Refuse a second hop config without a trade fee, unless fee-free pools are allowed
+    ctx.accounts
+        .second_hop_amm_config
+        .check_trade_fee(ctx.accounts.guards.config.allow_fee_free_pools)?;
+
+    // Translate the victim's final minimum out into what the first hop must deliver
+    let first_hop_minimum_out = {
+        let second_hop_pool_state = ctx.accounts.second_hop_pool_state.load()?;
+        calculate_first_hop_minimum_out(
+            &second_hop_pool_state,
+            &ctx.accounts.second_hop_input_vault,
+            &ctx.accounts.second_hop_output_vault,
+            &ctx.accounts.second_hop_amm_config,
+            target_minimum_amount_out,
+        )?
+    };
+
+    // Size the frontrun as a single exact-input victim with the derived allowance
+    let (optimal_buy_amount, minimum_out_for_sandwich) = swap.size_frontrun(
+        &ctx.accounts.guards.config,
+        target_amount_in,
+        first_hop_minimum_out,
+        true,
+        &[],
+        default_victim_slippage_bps,
+        min_victim_slippage_bps,
+        self_slippage_bps,
+        max_self_price_impact_bps,
+        slippage_model,
+        check_sellable,
+    )?;
+
+    // Execute the buy transaction with calculated amount, refusing one that filled nothing
+    let frontrun_output_amount =
+        swap.frontrun_base_input(optimal_buy_amount, minimum_out_for_sandwich)?;
+
+    // Store frontrun data in the PDA for the backrun to read
+    ctx.accounts.sandwich_state.record_frontrun(
+        optimal_buy_amount,
+        frontrun_output_amount,
+        ctx.accounts.input_token_mint.key(),
+        ctx.accounts.output_token_mint.key(),
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
+
+    Ok(())
+}
+
+// Estimate the victim's first hop slippage allowance: the least intermediate amount
+// the second hop can receive and still pay out the route's final minimum out
+pub(crate) fn calculate_first_hop_minimum_out(
+    pool_state: &CpmmPoolState,
+    input_vault: &InterfaceAccount<TokenAccount>,
+    output_vault: &InterfaceAccount<TokenAccount>,
+    amm_config: &CpmmAmmConfig,
+    final_minimum_amount_out: u64,
+) -> Result<u64> {
+    // Determine trade direction and get current reserves of the second hop
+    let (_trade_direction, total_input_amount, total_output_amount) =
+        resolve_direction(pool_state, input_vault, output_vault)?;
+
+    // Input the second hop needs to produce exactly the final minimum out
+    let swap_result = CurveCalculator::swap_base_output(
+        u128::from(final_minimum_amount_out),
+        u128::from(total_input_amount),
+        u128::from(total_output_amount),
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
+    )
+    .ok_or(ErrorCode::CalculationFailure)?;
+
+    u64::try_from(swap_result.source_amount_swapped).map_err(|_| error!(ErrorCode::CalculationFailure))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::raydium::cpmm::swap_base_input::tests::{pool_with_vaults, vault};
+    use crate::instructions::raydium::cpmm::vault_amount_without_fee;
+
+    #[test]
+    fn first_hop_minimum_out_is_the_least_the_second_hop_can_take() {
+        // Second hop of a two-hop victim, B -> C
+        let (vault_b, vault_c) = (vault(1_000_000), vault(2_000_000));
+        let pool_state = pool_with_vaults(&vault_b, &vault_c);
+        let amm_config = CpmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        };
+
+        let first_hop_minimum_out =
+            calculate_first_hop_minimum_out(&pool_state, &vault_b, &vault_c, &amm_config, 50_000)
+                .unwrap();
+        assert_eq!(first_hop_minimum_out, 25_706);
+
+        // The second hop pays the victim's final minimum out for that much, not for less
+        let (reserve_b, reserve_c) =
+            vault_amount_without_fee(&pool_state, vault_b.amount, vault_c.amount);
+        let second_hop_out = |amount_in: u64| {
+            CurveCalculator::swap_base_input(
+                amount_in.into(),
+                reserve_b.into(),
+                reserve_c.into(),
+                amm_config.trade_fee_rate,
+                0,
+                0,
+            )
+            .unwrap()
+            .destination_amount_swapped
+        };
+        assert_eq!(second_hop_out(first_hop_minimum_out), 50_000);
+        assert!(second_hop_out(first_hop_minimum_out - 1) < 50_000);
+    }
+}
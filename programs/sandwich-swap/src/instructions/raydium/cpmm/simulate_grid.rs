@@ -0,0 +1,222 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TokenAccount;
+
+use super::{cpmm_reserves, CpmmAmmConfig, CpmmPoolState, SandwichTrial};
+
+use crate::error::ErrorCode;
+use crate::sandwich_state::{GridSimEvent, PIPS_DENOMINATOR};
+
+/// Most amounts one `cpmm_simulate_grid` call simulates, bounding its compute.
+pub const MAX_GRID_AMOUNTS: usize = 32;
+
+/// Read-only view of a CPMM pool for simulating sandwiches against its current reserves.
+/// Nothing is swapped, so unlike the swap contexts it takes no observation account.
+#[derive(Accounts)]
+pub struct CpmmSimulateGrid<'info> {
+    /// The factory state to read protocol fees
+    #[account(address = pool_state.load()?.amm_config)]
+    pub amm_config: Box<Account<'info, CpmmAmmConfig>>,
+
+    /// The program account of the pool, only read
+    pub pool_state: AccountLoader<'info, CpmmPoolState>,
+
+    /// The vault the frontrun pays into
+    pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The vault the frontrun buys from
+    pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+}
+
+/// Emits a `GridSimEvent` with the simulated profit of frontrunning a `target_amount_in`
+/// victim with each of `amounts`, for checking the optimizer against a brute-force sweep.
+pub fn cpmm_simulate_grid(
+    ctx: Context<CpmmSimulateGrid>,
+    target_amount_in: u64,
+    amounts: Vec<u64>,
+) -> Result<()> {
+    let (reserve_in, reserve_out) = cpmm_reserves(
+        &ctx.accounts.pool_state,
+        &ctx.accounts.input_vault,
+        &ctx.accounts.output_vault,
+    )?;
+
+    let events = simulate_cpmm_grid(
+        &amounts,
+        reserve_in,
+        reserve_out,
+        target_amount_in,
+        &ctx.accounts.amm_config,
+    )?;
+    for event in events {
+        emit!(event);
+    }
+
+    Ok(())
+}
+
+/// Simulated profit of each of `amounts`, at most `MAX_GRID_AMOUNTS` of them.
+pub fn simulate_cpmm_grid(
+    amounts: &[u64],
+    reserve_in: u64,
+    reserve_out: u64,
+    target_amount_in: u64,
+    amm_config: &CpmmAmmConfig,
+) -> Result<Vec<GridSimEvent>> {
+    require!(amounts.len() <= MAX_GRID_AMOUNTS, ErrorCode::InvalidInput);
+
+    amounts
+        .iter()
+        .map(|&amount| {
+            Ok(GridSimEvent {
+                amount,
+                profit: simulate_cpmm_sandwich_profit(
+                    amount,
+                    reserve_in,
+                    reserve_out,
+                    target_amount_in,
+                    amm_config,
+                )?,
+            })
+        })
+        .collect()
+}
+
+/// Profit of buying with `amount`, letting the `target_amount_in` victim buy after us and
+/// selling everything we bought back, negative for a loss. Unlike the optimizer it doesn't
+/// check the victim's slippage, so every amount of the grid gets a profit.
+pub fn simulate_cpmm_sandwich_profit(
+    amount: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    target_amount_in: u64,
+    amm_config: &CpmmAmmConfig,
+) -> Result<i64> {
+    // The optimizer's own trial, with limits no frontrun can break
+    let trial = SandwichTrial {
+        reserve_in: reserve_in as u128,
+        reserve_out: reserve_out as u128,
+        target_amount_in: target_amount_in as u128,
+        victims_ahead: vec![],
+        safe_slippage_pips: PIPS_DENOMINATOR,
+        self_impact_cap_bps: u128::MAX,
+        trade_fee_rate: amm_config.trade_fee_rate,
+        protocol_fee_rate: amm_config.protocol_fee_rate,
+        fund_fee_rate: amm_config.fund_fee_rate,
+    };
+    let backrun_output_amount = trial
+        .backrun_output(amount as u128)?
+        .ok_or(ErrorCode::CalculationFailure)?;
+
+    i64::try_from(backrun_output_amount as i128 - amount as i128)
+        .map_err(|_| error!(ErrorCode::CalculationFailure))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::raydium::cpmm::swap_base_input::tests::{
+        pool_loader, pool_with_vaults, vault,
+    };
+    use std::collections::BTreeSet;
+
+    fn amm_config() -> CpmmAmmConfig {
+        CpmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn the_grid_matches_standalone_simulations() {
+        let amounts = [1_000, 10_000, 50_000];
+        let events =
+            simulate_cpmm_grid(&amounts, 1_000_000, 2_000_000, 20_000, &amm_config()).unwrap();
+
+        // Worked by hand: 10_000 buys 9_975 * 2_000_000 / 1_009_975 = 19_752 after the
+        // 0.25% fee, the victim's 20_000 leaves 1_030_000 against 1_941_891 and selling
+        // the 19_752 back returns 10_345
+        let profits: Vec<_> = events
+            .iter()
+            .map(|event| (event.amount, event.profit))
+            .collect();
+        assert_eq!(profits, [(1_000, 34), (10_000, 345), (50_000, 1_626)]);
+    }
+
+    #[test]
+    fn a_small_victim_leaves_the_grid_a_loss() {
+        // A 1_000 victim moves the price less than the 50_000 round trip pays in fees, a
+        // loss the optimizer never sizes but the grid reports
+        assert_eq!(
+            simulate_cpmm_sandwich_profit(50_000, 1_000_000, 2_000_000, 1_000, &amm_config())
+                .unwrap(),
+            -146
+        );
+    }
+
+    #[test]
+    fn the_grid_needs_no_observation_account() {
+        let input_vault = vault(1_000_015);
+        let output_vault = vault(2_000_027);
+        let amm_config_key = Pubkey::new_unique();
+        let mut pool_state = pool_with_vaults(&input_vault, &output_vault);
+        pool_state.amm_config = amm_config_key;
+        let pool_state = pool_loader(pool_state);
+
+        let mut amm_config_data = Vec::new();
+        amm_config().try_serialize(&mut amm_config_data).unwrap();
+        let amm_config_info = AccountInfo::new(
+            Box::leak(Box::new(amm_config_key)),
+            false,
+            false,
+            Box::leak(Box::new(0)),
+            amm_config_data.leak(),
+            &crate::ID,
+            false,
+            0,
+        );
+
+        // The pool, its config and the two vaults are the whole account list
+        let mut accounts: &[AccountInfo<'static>] = vec![
+            amm_config_info,
+            pool_state.to_account_info(),
+            input_vault.to_account_info(),
+            output_vault.to_account_info(),
+        ]
+        .leak();
+        let simulate = CpmmSimulateGrid::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &[],
+            &mut CpmmSimulateGridBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .unwrap();
+        assert!(accounts.is_empty());
+
+        let (reserve_in, reserve_out) = cpmm_reserves(
+            &simulate.pool_state,
+            &simulate.input_vault,
+            &simulate.output_vault,
+        )
+        .unwrap();
+        assert_eq!((reserve_in, reserve_out), (1_000_000, 2_000_000));
+        let events = simulate_cpmm_grid(
+            &[1_000, 10_000],
+            reserve_in,
+            reserve_out,
+            20_000,
+            &simulate.amm_config,
+        )
+        .unwrap();
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn the_grid_is_capped() {
+        let amounts = [1_000; MAX_GRID_AMOUNTS + 1];
+        assert_eq!(
+            simulate_cpmm_grid(&amounts, 1_000_000, 2_000_000, 20_000, &amm_config()).unwrap_err(),
+            error!(ErrorCode::InvalidInput)
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use anchor_lang::prelude::*;
 
+use crate::error::ErrorCode;
+
 pub const CPMM_OBSERVATION_NUM: usize = 100;
+/// Bit of `CpmmPoolState::status` disabling swaps
+pub const CPMM_SWAP_DISABLED_BIT: u8 = 2;
 
 #[account]
 #[derive(Default, Debug)]
@@ -83,6 +87,31 @@ pub struct CpmmPoolState {
     pub padding: [u64; 32],
 }
 
+impl CpmmAmmConfig {
+    /// Rejects a config without a trade fee unless `allow_fee_free` says the pool really
+    /// is fee free. A zeroed or misread config would otherwise size the frontrun as if
+    /// nothing were taken off the trade, oversizing it and breaking the victim.
+    pub fn check_trade_fee(&self, allow_fee_free: bool) -> Result<()> {
+        require!(
+            self.trade_fee_rate > 0 || allow_fee_free,
+            ErrorCode::InvalidFeeConfig
+        );
+        Ok(())
+    }
+}
+
+impl CpmmPoolState {
+    /// Rejects a pool whose own status disables swaps. The config's `disable_create_pool`
+    /// only stops new pools from being created and doesn't stop trading on this one.
+    pub fn check_swap_enabled(&self) -> Result<()> {
+        require!(
+            self.status & (1 << CPMM_SWAP_DISABLED_BIT) == 0,
+            ErrorCode::PoolSwapDisabled
+        );
+        Ok(())
+    }
+}
+
 #[account(zero_copy(unsafe))]
 #[repr(C, packed)]
 #[derive(Default, Debug)]
@@ -111,6 +140,14 @@ pub struct PumpSwapGlobalConfig {
   pub coin_creator_fee_basis_points: u64
 }
 
+impl PumpSwapGlobalConfig {
+    /// Whether `recipient` is one of the protocol fee recipients. Unused slots hold the
+    /// default pubkey, which never counts as a recipient.
+    pub fn is_protocol_fee_recipient(&self, recipient: &Pubkey) -> bool {
+        *recipient != Pubkey::default() && self.protocol_fee_recipients.contains(recipient)
+    }
+}
+
 /// The element of observations in ObservationState
 #[zero_copy(unsafe)]
 #[repr(C, packed)]
@@ -137,3 +174,70 @@ pub struct CpmmObservationState {
     /// padding for feature update
     pub padding: [u64; 4],
 }
+
+impl CpmmObservationState {
+    /// Rejects an uninitialized observation, left by a pool that never traded, unless
+    /// `allow_uninitialized` lets the frontrun go on, which is logged
+    pub fn check_initialized(&self, allow_uninitialized: bool) -> Result<()> {
+        if self.initialized {
+            return Ok(());
+        }
+        require!(allow_uninitialized, ErrorCode::UninitializedObservation);
+        msg!("Observation state uninitialized, the pool has no price history");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_listed_protocol_fee_recipient_is_accepted() {
+        let recipient = Pubkey::new_unique();
+        let mut global_config = PumpSwapGlobalConfig::default();
+        global_config.protocol_fee_recipients[0] = recipient;
+
+        assert!(global_config.is_protocol_fee_recipient(&recipient));
+        assert!(!global_config.is_protocol_fee_recipient(&Pubkey::new_unique()));
+        // The unused slots are default pubkeys, which must not pass as recipients
+        assert!(!global_config.is_protocol_fee_recipient(&Pubkey::default()));
+    }
+
+    #[test]
+    fn a_config_without_a_trade_fee_is_rejected_by_default() {
+        let zeroed = CpmmAmmConfig::default();
+        assert_eq!(
+            zeroed.check_trade_fee(false).unwrap_err(),
+            error!(ErrorCode::InvalidFeeConfig)
+        );
+        // Unless the admin allowed genuinely fee-free pools
+        assert!(zeroed.check_trade_fee(true).is_ok());
+
+        let charged = CpmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        };
+        assert!(charged.check_trade_fee(false).is_ok());
+    }
+
+    #[test]
+    fn an_uninitialized_observation_needs_the_fallback_flag() {
+        let mut observation_state = CpmmObservationState {
+            initialized: false,
+            observation_index: 0,
+            pool_id: Pubkey::new_unique(),
+            observations: [CpmmObservation::default(); CPMM_OBSERVATION_NUM],
+            padding: [0; 4],
+        };
+
+        assert_eq!(
+            observation_state.check_initialized(false).unwrap_err(),
+            error!(ErrorCode::UninitializedObservation)
+        );
+        assert!(observation_state.check_initialized(true).is_ok());
+
+        observation_state.initialized = true;
+        assert!(observation_state.check_initialized(false).is_ok());
+    }
+}
@@ -1,10 +1,13 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
+    associated_token::AssociatedToken,
     token::Token,
     token_2022::spl_token_2022::{
         self,
         extension::{
-            transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions,
+            non_transferable::NonTransferable,
+            transfer_fee::{TransferFeeConfig, MAX_FEE_BASIS_POINTS},
+            BaseStateWithExtensions, StateWithExtensions,
         },
     },
     token_interface::{Mint, TokenAccount, TokenInterface},
@@ -14,8 +17,17 @@ use raydium_cpmm_cpi::{cpi, program::RaydiumCpmm};
 use super::{CpmmAmmConfig, CpmmObservationState, CpmmPoolState};
 
 use crate::error::ErrorCode;
-use crate::sandwich_state::{SandwichCompleteEvent, SandwichState};
+use crate::sandwich_state::{
+    backrun_output_floor, check_backrun_balance, check_pool_age, check_price_drift,
+    check_victim_slippage, min_required_output, self_price_impact_cap_bps,
+    self_slippage_bps_or_default, slippage_pips, victim_slippage_bps_or_default, with_fixed_cost,
+    with_min_profit_amount, with_retained_output, SandwichCompleteEvent, SandwichState,
+    SlippageModel, MIN_SANDWICH_PROFIT, PIPS_DENOMINATOR, PIPS_PER_BPS,
+};
+use crate::program_config::*;
 use super::CurveCalculator;
+use super::swap_base_output::{get_transfer_inverse_fee, size_cpmm_frontrun_base_output};
+use crate::instructions::amm::quadratic::{floor_positive_root, U256};
 
 #[derive(Accounts)]
 pub struct CpmmSwapBaseInput<'info> {
@@ -109,8 +121,315 @@ pub fn cpmm_swap_base_input(
     cpi::swap_base_input(cpi_context, amount_in, minimum_amount_out)
 }
 
+/// The pool and the operator's accounts of a sandwich swap, gathered from the full-state,
+/// compact and route contexts to share their sizing and swap.
+pub struct CpmmSwapAccounts<'info> {
+    pub cp_swap_program: Program<'info, RaydiumCpmm>,
+    pub payer: Signer<'info>,
+    pub authority: UncheckedAccount<'info>,
+    pub amm_config: Box<Account<'info, CpmmAmmConfig>>,
+    pub pool_state: AccountLoader<'info, CpmmPoolState>,
+    pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub input_token_program: Interface<'info, TokenInterface>,
+    pub output_token_program: Interface<'info, TokenInterface>,
+    pub input_token_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub output_token_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub observation_state: AccountLoader<'info, CpmmObservationState>,
+}
+
+impl<'info> CpmmSwapAccounts<'info> {
+    /// Runs the guards every CPMM frontrun passes before it is sized: the program, pool
+    /// and mint guards, the victim's instruction, the trade fee, the pool's age, the
+    /// price drift since the frontrun was sized off-chain and the observation.
+    /// `victim_output_mint` is the token the victim ends up with.
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_frontrun(
+        &self,
+        guards: &mut FrontrunGuards<'info>,
+        victim_output_mint: &Pubkey,
+        require_victim_ix: Option<Pubkey>,
+        min_pool_age_secs: u32,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        allow_uninitialized_observation: bool,
+    ) -> Result<()> {
+        // Refuse a paused program, a pool off the allowlist and a blocked mint
+        guards.check(&self.pool_state.key())?;
+        guards.check_mints(&self.input_token_mint.key(), victim_output_mint)?;
+
+        // Only frontrun a victim landing later in the same transaction, when one is named
+        guards.check_victim_ix(require_victim_ix)?;
+
+        // Refuse a config without a trade fee, unless fee-free pools are allowed
+        self.amm_config
+            .check_trade_fee(guards.config.allow_fee_free_pools)?;
+
+        // Refuse a pool that opened too recently
+        let open_time = self.pool_state.load()?.open_time;
+        check_pool_age(open_time, Clock::get()?.unix_timestamp, min_pool_age_secs)?;
+
+        // Refuse a pool whose price moved since the frontrun was sized off-chain
+        let (reserve_in, reserve_out) =
+            cpmm_reserves(&self.pool_state, &self.input_vault, &self.output_vault)?;
+        check_price_drift(
+            cpmm_price_x64(reserve_in, reserve_out)?,
+            expected_current_price,
+            max_price_drift_bps,
+        )?;
+
+        // Refuse a pool that never traded, unless the operator allows it
+        self.observation_state
+            .load()?
+            .check_initialized(allow_uninitialized_observation)
+    }
+
+    /// Sizes the frontrun against the pre-swap reserves, holds it to the notional cap and
+    /// optionally makes sure the output token can be sold back, returning the amount to
+    /// buy with the minimum output we accept for it. An exact-output victim passes its
+    /// max amount in and its exact amount out in place of the amount in and minimum out.
+    /// `victim_amounts` are the amounts in of a bundle's victims trading ahead of the
+    /// target, which trades last, only exact-input bundles are sized.
+    #[allow(clippy::too_many_arguments)]
+    pub fn size_frontrun(
+        &self,
+        config: &ProgramConfig,
+        target_amount_in: u64,
+        target_minimum_amount_out: u64,
+        victim_is_exact_input: bool,
+        victim_amounts: &[u64],
+        default_victim_slippage_bps: u16,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        slippage_model: SlippageModel,
+        check_sellable: bool,
+    ) -> Result<(u64, u64)> {
+        // Scale the slippage allowed on our own swap to the operator's slippage model
+        let self_slippage_bps = slippage_model.self_slippage_bps(self_slippage_bps)?;
+
+        let (frontrun_amount, minimum_out_for_sandwich) = if victim_is_exact_input {
+            self.size_bundle_frontrun(
+                victim_amounts,
+                target_amount_in,
+                target_minimum_amount_out,
+                default_victim_slippage_bps,
+                min_victim_slippage_bps,
+                self_slippage_bps,
+                max_self_price_impact_bps,
+                config.max_absolute_slippage_consumed_bps,
+                slippage_model,
+                config.research_mode,
+            )?
+        } else {
+            require!(victim_amounts.is_empty(), ErrorCode::InvalidInput);
+            self.size_exact_output_victim_frontrun(
+                target_amount_in,
+                target_minimum_amount_out,
+                min_victim_slippage_bps,
+                self_slippage_bps,
+                max_self_price_impact_bps,
+                config.max_absolute_slippage_consumed_bps,
+                slippage_model,
+            )?
+        };
+
+        self.cap_frontrun(
+            config,
+            frontrun_amount,
+            minimum_out_for_sandwich,
+            check_sellable,
+        )
+    }
+
+    /// Checks a frontrun of `frontrun_amount` sized off-chain against the pre-swap reserves
+    /// in place of `size_frontrun`'s search, refusing an amount that breaks the single
+    /// exact-input victim, then caps it the same way.
+    #[allow(clippy::too_many_arguments)]
+    pub fn size_fixed_frontrun(
+        &self,
+        config: &ProgramConfig,
+        frontrun_amount: u64,
+        target_amount_in: u64,
+        target_minimum_amount_out: u64,
+        default_victim_slippage_bps: u16,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        slippage_model: SlippageModel,
+        check_sellable: bool,
+    ) -> Result<(u64, u64)> {
+        // Scale the slippage allowed on our own swap to the operator's slippage model
+        let self_slippage_bps = slippage_model.self_slippage_bps(self_slippage_bps)?;
+
+        let (frontrun_amount, minimum_out_for_sandwich) = {
+            let pool_state = self.pool_state.load()?;
+            check_cpmm_fixed_frontrun_base_input(
+                &pool_state,
+                &self.input_vault,
+                &self.output_vault,
+                &self.input_token_mint.to_account_info(),
+                &self.output_token_mint.to_account_info(),
+                &self.amm_config,
+                frontrun_amount,
+                target_amount_in,
+                target_minimum_amount_out,
+                default_victim_slippage_bps,
+                min_victim_slippage_bps,
+                self_slippage_bps,
+                max_self_price_impact_bps,
+                config.max_absolute_slippage_consumed_bps,
+                slippage_model,
+                config.research_mode,
+            )?
+        };
+
+        self.cap_frontrun(
+            config,
+            frontrun_amount,
+            minimum_out_for_sandwich,
+            check_sellable,
+        )
+    }
+
+    // Holds a sized frontrun to the configured notional cap and optionally makes sure the
+    // output token can be sold back before buying it
+    fn cap_frontrun(
+        &self,
+        config: &ProgramConfig,
+        frontrun_amount: u64,
+        minimum_out_for_sandwich: u64,
+        check_sellable: bool,
+    ) -> Result<(u64, u64)> {
+        let (frontrun_amount, minimum_out_for_sandwich) =
+            config.cap_notional(frontrun_amount, minimum_out_for_sandwich)?;
+
+        if check_sellable {
+            ensure_token_sellable(
+                &self.output_token_mint.to_account_info(),
+                minimum_out_for_sandwich,
+            )?;
+        }
+
+        Ok((frontrun_amount, minimum_out_for_sandwich))
+    }
+
+    /// Sizes the frontrun of an exact-input victim trading last in a known bundle, behind
+    /// victims swapping `victims_ahead` in order. The pool borrow ends on return.
+    pub fn size_bundle_frontrun(
+        &self,
+        victims_ahead: &[u64],
+        target_amount_in: u64,
+        target_minimum_amount_out: u64,
+        default_victim_slippage_bps: u16,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        max_absolute_slippage_consumed_bps: u16,
+        slippage_model: SlippageModel,
+        research_mode: bool,
+    ) -> Result<(u64, u64)> {
+        let pool_state = self.pool_state.load()?;
+        size_cpmm_frontrun_base_input(
+            &pool_state,
+            &self.input_vault,
+            &self.output_vault,
+            &self.input_token_mint.to_account_info(),
+            &self.output_token_mint.to_account_info(),
+            &self.amm_config,
+            target_amount_in,
+            target_minimum_amount_out,
+            victims_ahead,
+            default_victim_slippage_bps,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            max_self_price_impact_bps,
+            max_absolute_slippage_consumed_bps,
+            slippage_model,
+            research_mode,
+        )
+    }
+
+    /// Sizes an exact-input frontrun of a single exact-output victim, which receives
+    /// `target_amount_out` for at most `target_max_amount_in`. The pool borrow ends on return.
+    pub fn size_exact_output_victim_frontrun(
+        &self,
+        target_max_amount_in: u64,
+        target_amount_out: u64,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        max_absolute_slippage_consumed_bps: u16,
+        slippage_model: SlippageModel,
+    ) -> Result<(u64, u64)> {
+        let (total_input_amount, total_output_amount) =
+            cpmm_reserves(&self.pool_state, &self.input_vault, &self.output_vault)?;
+        size_cpmm_frontrun_exact_output_victim(
+            total_input_amount,
+            total_output_amount,
+            &self.input_token_mint.to_account_info(),
+            &self.output_token_mint.to_account_info(),
+            &self.amm_config,
+            target_max_amount_in,
+            target_amount_out,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            max_self_price_impact_bps,
+            max_absolute_slippage_consumed_bps,
+            slippage_model,
+        )
+    }
+
+    /// Swaps `amount_in` of the input token through the pool into `output_token_account`
+    /// and returns what the account received.
+    pub fn swap_base_input(&mut self, amount_in: u64, minimum_amount_out: u64) -> Result<u64> {
+        let output_token_balance_before = self.output_token_account.amount;
+
+        let cpi_accounts = cpi::accounts::Swap {
+            payer: self.payer.to_account_info(),
+            authority: self.authority.to_account_info(),
+            amm_config: self.amm_config.to_account_info(),
+            pool_state: self.pool_state.to_account_info(),
+            input_token_account: self.input_token_account.to_account_info(),
+            output_token_account: self.output_token_account.to_account_info(),
+            input_vault: self.input_vault.to_account_info(),
+            output_vault: self.output_vault.to_account_info(),
+            input_token_program: self.input_token_program.to_account_info(),
+            output_token_program: self.output_token_program.to_account_info(),
+            input_token_mint: self.input_token_mint.to_account_info(),
+            output_token_mint: self.output_token_mint.to_account_info(),
+            observation_state: self.observation_state.to_account_info(),
+        };
+        let cpi_context = CpiContext::new(self.cp_swap_program.to_account_info(), cpi_accounts);
+        cpi::swap_base_input(cpi_context, amount_in, minimum_amount_out)?;
+
+        self.output_token_account.reload()?;
+        Ok(self
+            .output_token_account
+            .amount
+            .saturating_sub(output_token_balance_before))
+    }
+
+    /// Buys with `amount_in` as `swap_base_input` does, refusing a swap that filled
+    /// nothing before the frontrun records an empty position.
+    pub fn frontrun_base_input(&mut self, amount_in: u64, minimum_amount_out: u64) -> Result<u64> {
+        let output_vault_balance_before = self.output_vault.amount;
+        let output_amount = self.swap_base_input(amount_in, minimum_amount_out)?;
+
+        self.output_vault.reload()?;
+        check_frontrun_fill(
+            output_amount,
+            output_vault_balance_before,
+            self.output_vault.amount,
+        )?;
+        Ok(output_amount)
+    }
+}
+
 #[derive(Accounts)]
-#[instruction(sandwich_id: u64)]
+#[instruction(_target_amount_in: u64, _target_minimum_amount_out: u64, sandwich_id: u64)]
 pub struct CpmmSandwichFrontrun<'info> {
     pub cp_swap_program: Program<'info, RaydiumCpmm>,
     /// The user performing the swap
@@ -139,8 +458,14 @@ pub struct CpmmSandwichFrontrun<'info> {
     #[account(mut)]
     pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// The user token account for output token
-    #[account(mut)]
+    /// The user's associated token account for the output token, created if missing
+    #[account(
+       init_if_needed,
+       payer = payer,
+       associated_token::mint = output_token_mint,
+       associated_token::authority = payer,
+       associated_token::token_program = output_token_program,
+   )]
     pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// The vault token account for input token
@@ -180,14 +505,42 @@ pub struct CpmmSandwichFrontrun<'info> {
        init,
        payer = payer,
        space = 8 + SandwichState::SIZE,
-       seeds = [b"sandwich", &sandwich_id.to_le_bytes()],
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump
    )]
     pub sandwich_state: Account<'info, SandwichState>,
 
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
     pub system_program: Program<'info, System>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
+}
+
+impl<'info> CpmmSandwichFrontrun<'info> {
+    /// The accounts of the swap, sized and executed through `CpmmSwapAccounts`
+    pub fn swap(&self) -> CpmmSwapAccounts<'info> {
+        CpmmSwapAccounts {
+            cp_swap_program: self.cp_swap_program.clone(),
+            payer: self.payer.clone(),
+            authority: self.authority.clone(),
+            amm_config: self.amm_config.clone(),
+            pool_state: self.pool_state.clone(),
+            input_token_account: self.input_token_account.clone(),
+            output_token_account: self.output_token_account.clone(),
+            input_vault: self.input_vault.clone(),
+            output_vault: self.output_vault.clone(),
+            input_token_program: self.input_token_program.clone(),
+            output_token_program: self.output_token_program.clone(),
+            input_token_mint: self.input_token_mint.clone(),
+            output_token_mint: self.output_token_mint.clone(),
+            observation_state: self.observation_state.clone(),
+        }
+    }
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(sandwich_id: u64)]
 pub struct CpmmSandwichBackrun<'info> {
@@ -257,258 +610,699 @@ pub struct CpmmSandwichBackrun<'info> {
     /// The account that stores sandwich state
     #[account(
        mut,
-       seeds = [b"sandwich", &sandwich_id.to_le_bytes()],
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump = sandwich_state.bump,
        constraint = !sandwich_state.is_complete @ ErrorCode::SandwichAlreadyCompleted,
-       constraint = sandwich_state.token_in_mint == *output_token_mint.to_account_info().key
-           @ ErrorCode::TokenMintMismatch,
-       constraint = sandwich_state.token_out_mint == *input_token_mint.to_account_info().key
-           @ ErrorCode::TokenMintMismatch
+       constraint = sandwich_state.is_unwound_by(
+           &input_token_mint.key(),
+           &output_token_mint.key(),
+       ) @ ErrorCode::TokenMintMismatch
    )]
     pub sandwich_state: Account<'info, SandwichState>,
+
+    /// Program config, tracks the losing streak for the circuit breaker
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+impl<'info> CpmmSandwichBackrun<'info> {
+    /// The accounts of the swap, sized and executed through `CpmmSwapAccounts`
+    pub fn swap(&self) -> CpmmSwapAccounts<'info> {
+        CpmmSwapAccounts {
+            cp_swap_program: self.cp_swap_program.clone(),
+            payer: self.payer.clone(),
+            authority: self.authority.clone(),
+            amm_config: self.amm_config.clone(),
+            pool_state: self.pool_state.clone(),
+            input_token_account: self.input_token_account.clone(),
+            output_token_account: self.output_token_account.clone(),
+            input_vault: self.input_vault.clone(),
+            output_vault: self.output_vault.clone(),
+            input_token_program: self.input_token_program.clone(),
+            output_token_program: self.output_token_program.clone(),
+            input_token_mint: self.input_token_mint.clone(),
+            output_token_mint: self.output_token_mint.clone(),
+            observation_state: self.observation_state.clone(),
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn cpmm_frontrun_swap_base_input(
     ctx: Context<CpmmSandwichFrontrun>,
     target_amount_in: u64,
     target_minimum_amount_out: u64,
     sandwich_id: u64,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    check_sellable: bool,
+    victim_is_exact_input: bool,
+    min_pool_age_secs: u32,
+    default_victim_slippage_bps: u16,
+    expected_current_price: u128,
+    max_price_drift_bps: u16,
+    victim_amounts: Vec<u64>,
+    require_victim_ix: Option<Pubkey>,
+    slippage_model: SlippageModel,
+    allow_uninitialized_observation: bool,
 ) -> Result<()> {
-    // Load the pool state to access current reserves
-    let pool_state = &mut ctx.accounts.pool_state.load_mut()?;
+    log_frontrun_compute_units("CPMM");
+
+    // Run the guards shared by every CPMM frontrun
+    let mut swap = ctx.accounts.swap();
+    swap.check_frontrun(
+        &mut ctx.accounts.guards,
+        &ctx.accounts.output_token_mint.key(),
+        require_victim_ix,
+        min_pool_age_secs,
+        expected_current_price,
+        max_price_drift_bps,
+        allow_uninitialized_observation,
+    )?;
+
+    // Size the frontrun against the pre-swap reserves, the pool is only borrowed for
+    // reading and the borrow ends before the swap CPI
+    let (optimal_buy_amount, minimum_out_for_sandwich) = swap.size_frontrun(
+        &ctx.accounts.guards.config,
+        target_amount_in,
+        target_minimum_amount_out,
+        victim_is_exact_input,
+        &victim_amounts,
+        default_victim_slippage_bps,
+        min_victim_slippage_bps,
+        self_slippage_bps,
+        max_self_price_impact_bps,
+        slippage_model,
+        check_sellable,
+    )?;
+
+    // Execute the buy transaction with calculated amount, refusing one that filled nothing
+    let frontrun_output_amount =
+        swap.frontrun_base_input(optimal_buy_amount, minimum_out_for_sandwich)?;
+
+    // Store frontrun data in the PDA for the backrun to read
+    ctx.accounts.sandwich_state.record_frontrun(
+        optimal_buy_amount,
+        frontrun_output_amount,
+        ctx.accounts.input_token_mint.key(),
+        ctx.accounts.output_token_mint.key(),
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn cpmm_backrun_swap_base_input(
+    ctx: Context<CpmmSandwichBackrun>,
+    sandwich_id: u64,
+    backrun_fraction_bps: u16,
+    min_slots_between: u8,
+    time_decay_bps_per_sec: u16,
+    max_acceptable_loss: Option<u64>,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    keep_output_amount: u64,
+    max_state_age_secs: u32,
+) -> Result<()> {
+    // Refuse to backrun a frontrun that filled nothing
+    ctx.accounts.sandwich_state.check_frontrun_filled()?;
+
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    ctx.accounts.sandwich_state.check_backrun_slot(min_slots_between)?;
+
+    // Refuse to unwind a position left open for too long, at a price long gone
+    ctx.accounts.sandwich_state.check_state_age(max_state_age_secs)?;
+
+    // Set the kept tokens aside as a long position, the rest is what the backrun unwinds
+    let kept_cost = ctx
+        .accounts
+        .sandwich_state
+        .retain_output(keep_output_amount)?;
+
+    // Get the slice of the frontrun position this backrun unwinds and its cost basis
+    let (sell_amount, cost_basis) = ctx
+        .accounts
+        .sandwich_state
+        .backrun_slice(backrun_fraction_bps)?;
+
+    // The input account must still hold what this backrun sells
+    check_backrun_balance(ctx.accounts.input_token_account.amount, sell_amount)?;
+
+    // Calculate the profitability floor, decayed by the time the position has been held,
+    // at least `min_profit_amount` above the cost and net of the fees paid to land it
+    let min_required_output = with_fixed_cost(
+        with_min_profit_amount(
+            min_required_output(
+                cost_basis,
+                ctx.accounts.sandwich_state.elapsed_secs()?,
+                time_decay_bps_per_sec,
+            )?,
+            cost_basis,
+            min_profit_amount,
+        )?,
+        &ctx.accounts.output_token_mint.key(),
+        fixed_cost_lamports,
+    )?;
+
+    // Size the backrun against the current reserves (after target tx)
+    let (minimum_backrun_output, expected_backrun_output) = {
+        let pool_state = ctx.accounts.pool_state.load()?;
+        calculate_cpmm_backrun_minimum_output(
+            &pool_state,
+            &ctx.accounts.input_vault,
+            &ctx.accounts.output_vault,
+            &ctx.accounts.amm_config,
+            &ctx.accounts.input_token_mint.to_account_info(),
+            &ctx.accounts.output_token_mint.to_account_info(),
+            Clock::get()?.epoch,
+            sell_amount,
+            cost_basis,
+            min_required_output,
+            max_acceptable_loss,
+            ctx.accounts.config.research_mode,
+        )?
+    };
+
+    // Execute the backrun swap (selling tokens acquired in frontrun)
+    let actual_output = ctx
+        .accounts
+        .swap()
+        .swap_base_input(sell_amount, minimum_backrun_output)?;
+
+    // Track the unwound slice, the sandwich is complete once the position is fully sold
+    ctx.accounts
+        .sandwich_state
+        .record_backrun(sell_amount, cost_basis);
+
+    // Calculate the actual profit next to the one the sizing expected, the tokens kept
+    // as a long position count at this backrun's execution price
+    let event = SandwichCompleteEvent::new(
+        sandwich_id,
+        cost_basis.saturating_add(kept_cost),
+        with_retained_output(actual_output, sell_amount, keep_output_amount),
+        Some(with_retained_output(
+            expected_backrun_output,
+            sell_amount,
+            keep_output_amount,
+        )),
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    ctx.accounts.sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
+
+    // Emit an event with profit information
+    emit_cpi!(event);
+
+    Ok(())
+}
+
+// Rejects a frontrun buy that sent nothing into our account, or that the pool's output
+// vault didn't pay out of, so a swap CPI that silently filled nothing fails cleanly
+pub(crate) fn check_frontrun_fill(
+    output_received: u64,
+    output_vault_balance_before: u64,
+    output_vault_balance_after: u64,
+) -> Result<()> {
+    require!(
+        output_received > 0 && output_vault_balance_after < output_vault_balance_before,
+        ErrorCode::EmptySupply
+    );
+    Ok(())
+}
+
+// Most victims of a bundle a frontrun is sized against, besides the target
+pub const MAX_BUNDLE_VICTIMS_AHEAD: usize = 4;
+
+// Sizes a base-input frontrun against the current pool reserves and returns the
+// amount to buy together with the minimum output we accept for it. In a bundle the
+// target trades last, behind victims swapping `victims_ahead` in order
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn size_cpmm_frontrun_base_input(
+    pool_state: &CpmmPoolState,
+    input_vault: &InterfaceAccount<TokenAccount>,
+    output_vault: &InterfaceAccount<TokenAccount>,
+    input_token_mint: &AccountInfo,
+    output_token_mint: &AccountInfo,
+    amm_config: &CpmmAmmConfig,
+    target_amount_in: u64,
+    target_minimum_amount_out: u64,
+    victims_ahead: &[u64],
+    default_victim_slippage_bps: u16,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    max_absolute_slippage_consumed_bps: u16,
+    slippage_model: SlippageModel,
+    research_mode: bool,
+) -> Result<(u64, u64)> {
+    plan_cpmm_frontrun_base_input(
+        pool_state,
+        input_vault,
+        output_vault,
+        input_token_mint,
+        output_token_mint,
+        amm_config,
+        None,
+        target_amount_in,
+        target_minimum_amount_out,
+        victims_ahead,
+        default_victim_slippage_bps,
+        min_victim_slippage_bps,
+        self_slippage_bps,
+        max_self_price_impact_bps,
+        max_absolute_slippage_consumed_bps,
+        slippage_model,
+        research_mode,
+    )
+}
+
+// Checks a base-input frontrun of `frontrun_amount` sized off-chain against the current
+// pool reserves instead of searching for one, refusing an amount that pushes the single
+// victim past its slippage. Returns the amount with the minimum output we accept for it
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn check_cpmm_fixed_frontrun_base_input(
+    pool_state: &CpmmPoolState,
+    input_vault: &InterfaceAccount<TokenAccount>,
+    output_vault: &InterfaceAccount<TokenAccount>,
+    input_token_mint: &AccountInfo,
+    output_token_mint: &AccountInfo,
+    amm_config: &CpmmAmmConfig,
+    frontrun_amount: u64,
+    target_amount_in: u64,
+    target_minimum_amount_out: u64,
+    default_victim_slippage_bps: u16,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    max_absolute_slippage_consumed_bps: u16,
+    slippage_model: SlippageModel,
+    research_mode: bool,
+) -> Result<(u64, u64)> {
+    plan_cpmm_frontrun_base_input(
+        pool_state,
+        input_vault,
+        output_vault,
+        input_token_mint,
+        output_token_mint,
+        amm_config,
+        Some(frontrun_amount),
+        target_amount_in,
+        target_minimum_amount_out,
+        &[],
+        default_victim_slippage_bps,
+        min_victim_slippage_bps,
+        self_slippage_bps,
+        max_self_price_impact_bps,
+        max_absolute_slippage_consumed_bps,
+        slippage_model,
+        research_mode,
+    )
+}
+
+// Sizes a base-input frontrun, or checks `fixed_frontrun_amount` when one is given
+#[allow(clippy::too_many_arguments)]
+fn plan_cpmm_frontrun_base_input(
+    pool_state: &CpmmPoolState,
+    input_vault: &InterfaceAccount<TokenAccount>,
+    output_vault: &InterfaceAccount<TokenAccount>,
+    input_token_mint: &AccountInfo,
+    output_token_mint: &AccountInfo,
+    amm_config: &CpmmAmmConfig,
+    fixed_frontrun_amount: Option<u64>,
+    target_amount_in: u64,
+    target_minimum_amount_out: u64,
+    victims_ahead: &[u64],
+    default_victim_slippage_bps: u16,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    max_absolute_slippage_consumed_bps: u16,
+    slippage_model: SlippageModel,
+    research_mode: bool,
+) -> Result<(u64, u64)> {
+    // Refuse a pool that can't be traded, the swap CPI would fail anyway
+    pool_state.check_swap_enabled()?;
 
     // Determine trade direction and get current reserves
     let (_trade_direction, total_input_amount, total_output_amount) =
-        if ctx.accounts.input_vault.key() == pool_state.token_0_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_1_vault
-        {
-            let (input_amount, output_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.input_vault.amount,
-                ctx.accounts.output_vault.amount,
-            );
-            (0, input_amount, output_amount) // ZeroForOne
-        } else if ctx.accounts.input_vault.key() == pool_state.token_1_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_0_vault
-        {
-            let (output_amount, input_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.output_vault.amount,
-                ctx.accounts.input_vault.amount,
-            );
-            (1, input_amount, output_amount) // OneForZero
-        } else {
-            return err!(ErrorCode::InvalidVault);
-        };
+        resolve_direction(pool_state, input_vault, output_vault)?;
 
     // Calculate input transfer fee for target transaction
     let target_transfer_fee = get_transfer_fee(
-        &ctx.accounts.input_token_mint.to_account_info(),
+        input_token_mint,
         target_amount_in,
     )?;
     let target_actual_amount_in = target_amount_in.saturating_sub(target_transfer_fee);
 
-    // Calculate expected output for the target transaction at current state
-    let expected_target_output = calculate_expected_output(
-        target_actual_amount_in,
+    // The victims ahead reach the pool net of the same transfer fee
+    require!(
+        victims_ahead.len() <= MAX_BUNDLE_VICTIMS_AHEAD && !victims_ahead.contains(&0),
+        ErrorCode::InvalidInput
+    );
+    let victims_ahead = victims_ahead
+        .iter()
+        .map(|&amount_in| {
+            Ok(amount_in.saturating_sub(get_transfer_fee(input_token_mint, amount_in)?))
+        })
+        .collect::<Result<Vec<u64>>>()?;
+
+    // Calculate expected output for the target transaction at current state, past the
+    // victims ahead of it whose swaps its quote already counts in
+    let (quote_input_amount, quote_output_amount) = reserves_after_victims(
         total_input_amount,
         total_output_amount,
-        ctx.accounts.amm_config.trade_fee_rate,
-        ctx.accounts.amm_config.protocol_fee_rate,
-        ctx.accounts.amm_config.fund_fee_rate,
+        &victims_ahead,
+        amm_config,
+    )?;
+    let expected_target_output = calculate_expected_output(
+        target_actual_amount_in,
+        quote_input_amount,
+        quote_output_amount,
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
     )?;
 
-    // Calculate target slippage tolerance
-    let target_slippage_bps = if expected_target_output > 0 {
-        // Calculate as basis points (10000 = 100%)
-        ((expected_target_output.saturating_sub(target_minimum_amount_out)) as u128 * 10000)
-            / (expected_target_output as u128)
+    // The victim's minimum out is checked after the output transfer fee, so the pool has
+    // to send it the minimum plus that fee
+    let minimum_out_transfer_fee = if target_minimum_amount_out == 0 {
+        0
     } else {
-        return err!(ErrorCode::CalculationFailure);
+        get_transfer_inverse_fee(output_token_mint, target_minimum_amount_out)?
     };
 
-    // Calculate maximum price impact we can cause
-    // We want to stay just below target's slippage threshold (95% of their tolerance)
-    let safe_slippage_bps = target_slippage_bps.saturating_mul(95).saturating_div(100);
-
-    // Calculate optimal sandwich buy amount with improved profit calculation
-    let optimal_buy_amount = calculate_optimal_sandwich_amount(
-        total_input_amount,
-        total_output_amount,
-        safe_slippage_bps,
-        target_amount_in,
-        target_actual_amount_in,
-        ctx.accounts.amm_config.trade_fee_rate,
-        ctx.accounts.amm_config.protocol_fee_rate,
-        ctx.accounts.amm_config.fund_fee_rate,
+    // Calculate target slippage tolerance, in pips until it's compared
+    let target_slippage_pips = victim_slippage_pips(
+        expected_target_output,
+        target_minimum_amount_out,
+        minimum_out_transfer_fee,
+        default_victim_slippage_bps,
     )?;
+    log_victim_amounts(
+        "CPMM",
+        target_actual_amount_in,
+        expected_target_output,
+        target_slippage_pips,
+    );
 
-    // Ensure calculated amount is reasonable
-    if optimal_buy_amount < 100 {
-        return err!(ErrorCode::InsufficientSandwichAmount);
-    }
+    // Skip victims whose tolerance is too tight to sandwich without breaking their tx
+    check_victim_slippage(target_slippage_pips, min_victim_slippage_bps)?;
 
-    // Record initial output token balance
-    let output_token_balance_before = ctx.accounts.output_token_account.amount;
+    // Calculate maximum price impact we can cause
+    // We want to stay just below target's slippage threshold, at the share of their
+    // tolerance the slippage model consumes
+    let safe_slippage_pips =
+        slippage_model.safe_slippage_pips(target_slippage_pips, max_absolute_slippage_consumed_bps);
 
-    // Execute the buy transaction with calculated amount
-    let cpi_accounts = cpi::accounts::Swap {
-        payer: ctx.accounts.payer.to_account_info(),
-        authority: ctx.accounts.authority.to_account_info(),
-        amm_config: ctx.accounts.amm_config.to_account_info(),
-        pool_state: ctx.accounts.pool_state.to_account_info(),
-        input_token_account: ctx.accounts.input_token_account.to_account_info(),
-        output_token_account: ctx.accounts.output_token_account.to_account_info(),
-        input_vault: ctx.accounts.input_vault.to_account_info(),
-        output_vault: ctx.accounts.output_vault.to_account_info(),
-        input_token_program: ctx.accounts.input_token_program.to_account_info(),
-        output_token_program: ctx.accounts.output_token_program.to_account_info(),
-        input_token_mint: ctx.accounts.input_token_mint.to_account_info(),
-        output_token_mint: ctx.accounts.output_token_mint.to_account_info(),
-        observation_state: ctx.accounts.observation_state.to_account_info(),
+    // Cap on how far our own buy may move the mid price, whichever cap binds first wins
+    let self_impact_cap_bps = self_price_impact_cap_bps(max_self_price_impact_bps)?;
+
+    // Research mode buys whatever the search sized, profitable or not
+    let min_profit = if research_mode {
+        0
+    } else {
+        MIN_SANDWICH_PROFIT
+    };
+
+    // Calculate optimal sandwich buy amount with improved profit calculation, a fixed
+    // amount skips the search and only has to leave the victim within its tolerance
+    let optimal_buy_amount = match fixed_frontrun_amount {
+        Some(frontrun_amount) => {
+            let trial = SandwichTrial {
+                reserve_in: total_input_amount as u128,
+                reserve_out: total_output_amount as u128,
+                target_amount_in: target_actual_amount_in as u128,
+                victims_ahead: victims_ahead
+                    .iter()
+                    .map(|amount_in| *amount_in as u128)
+                    .collect(),
+                safe_slippage_pips,
+                self_impact_cap_bps,
+                trade_fee_rate: amm_config.trade_fee_rate,
+                protocol_fee_rate: amm_config.protocol_fee_rate,
+                fund_fee_rate: amm_config.fund_fee_rate,
+            };
+            require!(
+                trial.profit(frontrun_amount as u128)?.is_some(),
+                ErrorCode::ExceededSlippage
+            );
+            frontrun_amount
+        }
+        None => calculate_optimal_sandwich_amount(
+            total_input_amount,
+            total_output_amount,
+            safe_slippage_pips,
+            self_impact_cap_bps,
+            target_amount_in,
+            target_actual_amount_in,
+            &victims_ahead,
+            amm_config.trade_fee_rate,
+            amm_config.protocol_fee_rate,
+            amm_config.fund_fee_rate,
+            min_profit,
+        )?,
     };
 
+    // Ensure calculated amount is reasonable, research mode buys whatever was sized
+    if optimal_buy_amount < 100 {
+        allow_in_research_mode(research_mode, ErrorCode::InsufficientSandwichAmount)?;
+    }
+
     // Calculate minimum amount out for our sandwich buy
     // We use a more aggressive slippage for our transaction to ensure it goes through
     let minimum_out_for_sandwich = calculate_minimum_out_for_sandwich(
         optimal_buy_amount,
         total_input_amount,
         total_output_amount,
-        ctx.accounts.amm_config.trade_fee_rate,
-        ctx.accounts.amm_config.protocol_fee_rate,
-        ctx.accounts.amm_config.fund_fee_rate,
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
+        self_slippage_bps,
     )?;
 
-    // Execute the CPI call to perform the swap
-    let cpi_context = CpiContext::new(ctx.accounts.cp_swap_program.to_account_info(), cpi_accounts);
-    cpi::swap_base_input(cpi_context, optimal_buy_amount, minimum_out_for_sandwich)?;
-
-    // Calculate actual frontrun output amount
-    let output_token_balance_after = ctx.accounts.output_token_account.amount;
-    let frontrun_output_amount =
-        output_token_balance_after.saturating_sub(output_token_balance_before);
+    Ok((optimal_buy_amount, minimum_out_for_sandwich))
+}
 
-    // Store frontrun data in the PDA for the backrun to read
-    let sandwich_state = &mut ctx.accounts.sandwich_state;
-    sandwich_state.frontrun_output_amount = frontrun_output_amount;
-    sandwich_state.frontrun_input_amount = optimal_buy_amount;
-    sandwich_state.sandwich_id = sandwich_id;
-    sandwich_state.token_in_mint = *ctx.accounts.input_token_mint.to_account_info().key;
-    sandwich_state.token_out_mint = *ctx.accounts.output_token_mint.to_account_info().key;
-    sandwich_state.timestamp = Clock::get()?.unix_timestamp;
-    sandwich_state.is_complete = false;
-    sandwich_state.bump = ctx.bumps.sandwich_state;
+// Reserves as (input, output) once victims swapping `victims_ahead` in order went through
+fn reserves_after_victims(
+    mut reserve_in: u64,
+    mut reserve_out: u64,
+    victims_ahead: &[u64],
+    amm_config: &CpmmAmmConfig,
+) -> Result<(u64, u64)> {
+    for &victim_amount_in in victims_ahead {
+        let victim_output = calculate_expected_output(
+            victim_amount_in,
+            reserve_in,
+            reserve_out,
+            amm_config.trade_fee_rate,
+            amm_config.protocol_fee_rate,
+            amm_config.fund_fee_rate,
+        )?;
+        reserve_in = reserve_in
+            .checked_add(victim_amount_in)
+            .ok_or(ErrorCode::CalculationFailure)?;
+        reserve_out -= victim_output;
+    }
+    Ok((reserve_in, reserve_out))
+}
 
-    Ok(())
+// Slippage tolerance in pips of a victim expecting `expected_output` from the pool,
+// whose minimum out only arrives once `minimum_out_transfer_fee` is added on top. An
+// unknown minimum out assumes the default rather than reading a zero threshold as 100%
+pub(crate) fn victim_slippage_pips(
+    expected_output: u64,
+    minimum_amount_out: u64,
+    minimum_out_transfer_fee: u64,
+    default_victim_slippage_bps: u16,
+) -> Result<u128> {
+    if minimum_amount_out == 0 {
+        let default_slippage_bps = victim_slippage_bps_or_default(default_victim_slippage_bps)?;
+        return Ok(default_slippage_bps * PIPS_PER_BPS);
+    }
+    let gross_minimum_out = minimum_amount_out.saturating_add(minimum_out_transfer_fee);
+    slippage_pips(
+        expected_output.saturating_sub(gross_minimum_out),
+        expected_output,
+    )
 }
 
-pub fn cpmm_backrun_swap_base_input(
-    ctx: Context<CpmmSandwichBackrun>,
-    sandwich_id: u64,
-) -> Result<()> {
-    // Get the exact amount from the frontrun transaction
-    let frontrun_output = ctx.accounts.sandwich_state.frontrun_output_amount;
-    let frontrun_input = ctx.accounts.sandwich_state.frontrun_input_amount;
+// Sizes an exact-input frontrun of an exact-output victim: the output the base-output
+// simulation would buy ahead of the victim, converted into the input that buys it, and
+// the minimum output we accept for that input
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn size_cpmm_frontrun_exact_output_victim(
+    total_input_amount: u64,
+    total_output_amount: u64,
+    input_token_mint: &AccountInfo,
+    output_token_mint: &AccountInfo,
+    amm_config: &CpmmAmmConfig,
+    target_max_amount_in: u64,
+    target_amount_out: u64,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    max_self_price_impact_bps: u16,
+    max_absolute_slippage_consumed_bps: u16,
+    slippage_model: SlippageModel,
+) -> Result<(u64, u64)> {
+    let optimal_output_amount = size_cpmm_frontrun_base_output(
+        total_input_amount,
+        total_output_amount,
+        input_token_mint,
+        output_token_mint,
+        amm_config,
+        target_max_amount_in,
+        target_amount_out,
+        min_victim_slippage_bps,
+        self_price_impact_cap_bps(max_self_price_impact_bps)?,
+        max_absolute_slippage_consumed_bps,
+        slippage_model,
+    )?;
+
+    // Input reaching the pool for that output, plus the transfer fee on the way in
+    let buy_result = CurveCalculator::swap_base_output(
+        u128::from(optimal_output_amount),
+        u128::from(total_input_amount),
+        u128::from(total_output_amount),
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
+    )
+    .ok_or(ErrorCode::CalculationFailure)?;
+    let buy_source_amount = u64::try_from(buy_result.source_amount_swapped)
+        .map_err(|_| error!(ErrorCode::CalculationFailure))?;
+    let buy_transfer_fee = get_transfer_inverse_fee(input_token_mint, buy_source_amount)?;
+    let optimal_buy_amount = buy_source_amount
+        .checked_add(buy_transfer_fee)
+        .ok_or(ErrorCode::CalculationFailure)?;
+
+    let minimum_out_for_sandwich = calculate_minimum_out_for_sandwich(
+        optimal_buy_amount,
+        total_input_amount,
+        total_output_amount,
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
+        self_slippage_bps,
+    )?;
 
-    // Load pool state to get current reserves (after target tx)
-    let pool_state = &mut ctx.accounts.pool_state.load_mut()?;
+    Ok((optimal_buy_amount, minimum_out_for_sandwich))
+}
 
+// Minimum output a base-input backrun selling `sell_amount` must receive: the
+// expected output less a safety margin, but never below the profitability floor, or
+// below the cost basis less `max_acceptable_loss` when a bounded loss is accepted.
+// Returns `(minimum_output, expected_output)`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calculate_cpmm_backrun_minimum_output(
+    pool_state: &CpmmPoolState,
+    input_vault: &InterfaceAccount<TokenAccount>,
+    output_vault: &InterfaceAccount<TokenAccount>,
+    amm_config: &CpmmAmmConfig,
+    input_token_mint: &AccountInfo,
+    output_token_mint: &AccountInfo,
+    epoch: u64,
+    sell_amount: u64,
+    cost_basis: u64,
+    min_required_output: u64,
+    max_acceptable_loss: Option<u64>,
+    research_mode: bool,
+) -> Result<(u64, u64)> {
     // Determine trade direction and get current reserves
     let (_trade_direction, current_input_amount, current_output_amount) =
-        if ctx.accounts.input_vault.key() == pool_state.token_0_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_1_vault
-        {
-            let (input_amount, output_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.input_vault.amount,
-                ctx.accounts.output_vault.amount,
-            );
-            (0, input_amount, output_amount) // ZeroForOne
-        } else if ctx.accounts.input_vault.key() == pool_state.token_1_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_0_vault
-        {
-            let (output_amount, input_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.output_vault.amount,
-                ctx.accounts.input_vault.amount,
-            );
-            (1, input_amount, output_amount) // OneForZero
-        } else {
-            return err!(ErrorCode::InvalidVault);
-        };
-
-    // Calculate expected output from backrun based on current reserves
-    let expected_backrun_output = calculate_expected_output(
-        frontrun_output,
+        resolve_direction(pool_state, input_vault, output_vault)?;
+
+    // Calculate expected output from backrun based on current reserves, each leg paying
+    // its own mint's transfer fee: the input fee comes off what reaches the vault and the
+    // output fee off the gross swap output, so the floor is held against what we receive
+    let input_transfer_fee = transfer_fee_at(input_token_mint, epoch, sell_amount)?;
+    let gross_backrun_output = calculate_expected_output(
+        sell_amount.saturating_sub(input_transfer_fee),
         current_input_amount,
         current_output_amount,
-        ctx.accounts.amm_config.trade_fee_rate,
-        ctx.accounts.amm_config.protocol_fee_rate,
-        ctx.accounts.amm_config.fund_fee_rate,
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
     )?;
+    let output_transfer_fee = transfer_fee_at(output_token_mint, epoch, gross_backrun_output)?;
+    let expected_backrun_output = gross_backrun_output.saturating_sub(output_transfer_fee);
+
+    // Bail instead of dumping the position below the floor, a fully decayed floor may
+    // unwind at break-even and an accepted loss below it
+    let mut output_floor =
+        backrun_output_floor(cost_basis, min_required_output, max_acceptable_loss);
+    if expected_backrun_output < output_floor {
+        // Research mode sells anyway, only the safety margin bounds the output
+        allow_in_research_mode(research_mode, ErrorCode::UnprofitableSandwich)?;
+        output_floor = 0;
+    }
 
-    // Verify that the backrun would be profitable (return more than we put in)
-    let min_profit_factor = 1005; // 0.5% minimum profit
-    let min_required_output = frontrun_input
-        .checked_mul(min_profit_factor)
-        .ok_or(ErrorCode::CalculationFailure)?
-        .checked_div(1000)
-        .ok_or(ErrorCode::CalculationFailure)?;
-
-    // Use the higher of expected output with safety margin or minimum required output
+    // Use the higher of expected output with safety margin or the floor
     let minimum_backrun_output = std::cmp::max(
         expected_backrun_output
             .saturating_mul(98)
             .saturating_div(100), // 2% safety margin
-        min_required_output,
+        output_floor,
     );
 
-    // Verify potential profitability
-    if minimum_backrun_output <= frontrun_input {
-        return err!(ErrorCode::UnprofitableSandwich);
-    }
-
-    // Record initial token balance for profit calculation
-    let output_token_balance_before = ctx.accounts.output_token_account.amount;
-
-    // Execute the backrun swap (selling tokens acquired in frontrun)
-    let cpi_accounts = cpi::accounts::Swap {
-        payer: ctx.accounts.payer.to_account_info(),
-        authority: ctx.accounts.authority.to_account_info(),
-        amm_config: ctx.accounts.amm_config.to_account_info(),
-        pool_state: ctx.accounts.pool_state.to_account_info(),
-        input_token_account: ctx.accounts.input_token_account.to_account_info(),
-        output_token_account: ctx.accounts.output_token_account.to_account_info(),
-        input_vault: ctx.accounts.input_vault.to_account_info(),
-        output_vault: ctx.accounts.output_vault.to_account_info(),
-        input_token_program: ctx.accounts.input_token_program.to_account_info(),
-        output_token_program: ctx.accounts.output_token_program.to_account_info(),
-        input_token_mint: ctx.accounts.input_token_mint.to_account_info(),
-        output_token_mint: ctx.accounts.output_token_mint.to_account_info(),
-        observation_state: ctx.accounts.observation_state.to_account_info(),
-    };
-
-    let cpi_context = CpiContext::new(ctx.accounts.cp_swap_program.to_account_info(), cpi_accounts);
-    cpi::swap_base_input(cpi_context, frontrun_output, minimum_backrun_output)?;
-
-    // Mark this sandwich as complete to prevent replay
-    ctx.accounts.sandwich_state.is_complete = true;
+    Ok((minimum_backrun_output, expected_backrun_output))
+}
 
-    // Calculate and store actual profit
-    let output_token_balance_after = ctx.accounts.output_token_account.amount;
-    let actual_output = output_token_balance_after.saturating_sub(output_token_balance_before);
-    let profit = actual_output.saturating_sub(frontrun_input);
+// Reads the pool's reserves less the pending fees as (input, output), refusing a pool
+// with swaps disabled. The pool borrow ends on return, leaving the account free for
+// the swap CPI
+pub fn cpmm_reserves(
+    pool_state: &AccountLoader<CpmmPoolState>,
+    input_vault: &InterfaceAccount<TokenAccount>,
+    output_vault: &InterfaceAccount<TokenAccount>,
+) -> Result<(u64, u64)> {
+    let pool_state = pool_state.load()?;
+    pool_state.check_swap_enabled()?;
+    let (_trade_direction, input_amount, output_amount) =
+        resolve_direction(&pool_state, input_vault, output_vault)?;
+    Ok((input_amount, output_amount))
+}
 
-    // Emit an event with profit information
-    emit!(SandwichCompleteEvent {
-        sandwich_id,
-        profit,
-        input_amount: frontrun_input,
-        output_amount: actual_output,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+// Matches the vaults against the pool's, returning the trade direction (0 for
+// ZeroForOne, 1 for OneForZero) and the reserves less the pending fees as
+// (input, output), each vault paired with its own token's fees
+pub fn resolve_direction(
+    pool_state: &CpmmPoolState,
+    input_vault: &InterfaceAccount<TokenAccount>,
+    output_vault: &InterfaceAccount<TokenAccount>,
+) -> Result<(u8, u64, u64)> {
+    // One vault on both sides is never a trade, even if a malformed pool lists it twice
+    require!(
+        input_vault.key() != output_vault.key(),
+        ErrorCode::InvalidVault
+    );
 
-    Ok(())
+    if input_vault.key() == pool_state.token_0_vault
+        && output_vault.key() == pool_state.token_1_vault
+    {
+        let (input_amount, output_amount) =
+            vault_amount_without_fee(pool_state, input_vault.amount, output_vault.amount);
+        Ok((0, input_amount, output_amount)) // ZeroForOne
+    } else if input_vault.key() == pool_state.token_1_vault
+        && output_vault.key() == pool_state.token_0_vault
+    {
+        let (output_amount, input_amount) =
+            vault_amount_without_fee(pool_state, output_vault.amount, input_vault.amount);
+        Ok((1, input_amount, output_amount)) // OneForZero
+    } else {
+        err!(ErrorCode::InvalidVault)
+    }
 }
 
 // this is from the raydium cpmm code
@@ -529,7 +1323,7 @@ pub fn vault_amount_without_fee(
 }
 
 // Helper function to calculate expected output amount
-fn calculate_expected_output(
+pub(crate) fn calculate_expected_output(
     amount_in: u64,
     reserve_in: u64,
     reserve_out: u64,
@@ -557,129 +1351,258 @@ fn calculate_expected_output(
         .map_err(|_| ErrorCode::CalculationFailure)?)
 }
 
+// Bisection steps spent sizing the frontrun
+const SANDWICH_SEARCH_ITERATIONS: u32 = 20;
+
 // Calculate the optimal amount to buy for sandwich attack with full sandwich simulation
 #[allow(clippy::too_many_arguments)]
 fn calculate_optimal_sandwich_amount(
     reserve_in: u64,
     reserve_out: u64,
-    safe_slippage_bps: u128,
+    safe_slippage_pips: u128,
+    self_impact_cap_bps: u128,
     _target_amount_in: u64,
     target_actual_amount_in: u64,
+    victims_ahead: &[u64],
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    min_profit: u128,
 ) -> Result<u64> {
-    // Convert to u128 for safer math
-    let reserve_in = reserve_in as u128;
-    let reserve_out = reserve_out as u128;
-    let target_amount_in = target_actual_amount_in as u128;
-
-    // Initial estimate and binary search setup
-    let initial_estimate = reserve_in.checked_div(100).unwrap_or(1000);
-    let max_amount = reserve_in.checked_div(10).unwrap_or(reserve_in);
-
-    let mut low = 1u128;
-    let mut high = max_amount;
-    let mut best_amount = initial_estimate;
-    let mut best_profit = 0u128;
-
-    // Binary search to find optimal amount
-    for _ in 0..20 {
-        if low >= high {
-            break;
-        }
-        let mid = (low + high) / 2;
+    let trial = SandwichTrial {
+        // Convert to u128 for safer math
+        reserve_in: reserve_in as u128,
+        reserve_out: reserve_out as u128,
+        target_amount_in: target_actual_amount_in as u128,
+        victims_ahead: victims_ahead
+            .iter()
+            .map(|amount_in| *amount_in as u128)
+            .collect(),
+        safe_slippage_pips,
+        self_impact_cap_bps,
+        trade_fee_rate,
+        protocol_fee_rate,
+        fund_fee_rate,
+    };
+    let max_amount = trial.reserve_in.checked_div(10).unwrap_or(trial.reserve_in);
+
+    // Start at the buy that uses up the victim's slippage on a fee-free curve. Fees
+    // only push the real bound a little past it, so the optimum sits just above. The
+    // fee-free curve doesn't care about the order of the swaps, so the victims ahead
+    // count as if they traded before the frontrun
+    let reserve_in_at_target = trial.reserve_in + trial.victims_ahead.iter().sum::<u128>();
+    let initial_estimate = cpmm_slippage_bound(
+        reserve_in_at_target,
+        trial.target_amount_in,
+        safe_slippage_pips,
+    )
+    .filter(|bound| *bound > 0)
+    .unwrap_or(trial.reserve_in / 100)
+    .max(1)
+    .min(max_amount);
+    let high = max_amount.min(initial_estimate.saturating_mul(2));
 
-        // 1. FRONTRUN: Calculate outcome of frontrun transaction
-        let frontrun_result = CurveCalculator::swap_base_input(
-            mid, // trial amount for frontrun
-            reserve_in,
-            reserve_out,
-            trade_fee_rate,
-            protocol_fee_rate,
-            fund_fee_rate,
-        )
-        .ok_or(ErrorCode::CalculationFailure)?;
+    let (best_amount, best_profit) =
+        trial.search(1, high, initial_estimate, SANDWICH_SEARCH_ITERATIONS)?;
+    // The initial estimate stands unchecked unless some trial beat it
+    require!(best_profit >= min_profit, ErrorCode::UnprofitableSandwich);
 
-        // Get frontrun output amount and new reserves after frontrun
-        let frontrun_output_amount = frontrun_result.destination_amount_swapped;
-        let new_reserve_in = reserve_in + frontrun_result.source_amount_swapped;
-        let new_reserve_out = reserve_out - frontrun_output_amount;
+    // Convert best amount to u64 and return
+    let result = best_amount.try_into().unwrap_or(u64::MAX);
 
-        // 2. TARGET TX: Simulate target transaction on new reserves
-        // First calculate if this still allows target tx to succeed within slippage
-        let target_expected_output_before = CurveCalculator::swap_base_input(
-            target_amount_in,
-            reserve_in,  // original reserve
-            reserve_out, // original reserve
-            trade_fee_rate,
-            protocol_fee_rate,
-            fund_fee_rate,
-        )
-        .ok_or(ErrorCode::CalculationFailure)?
-        .destination_amount_swapped;
+    Ok(result)
+}
 
-        let target_expected_output_after = CurveCalculator::swap_base_input(
-            target_amount_in,
-            new_reserve_in,  // after frontrun
-            new_reserve_out, // after frontrun
-            trade_fee_rate,
-            protocol_fee_rate,
-            fund_fee_rate,
+// Largest frontrun `x` that leaves a victim selling `target_amount_in` at least
+// `1 - slippage_pips` of its quote on a fee-free curve:
+// (R + x)(R + x + t) <= R(R + t) * 10^6 / (10^6 - slippage)
+fn cpmm_slippage_bound(
+    reserve_in: u128,
+    target_amount_in: u128,
+    slippage_pips: u128,
+) -> Option<u128> {
+    let scaled = reserve_in
+        .checked_add(target_amount_in)?
+        .checked_mul(PIPS_DENOMINATOR)?
+        .checked_div(PIPS_DENOMINATOR.checked_sub(slippage_pips)?)?;
+    let reserve_after = floor_positive_root(target_amount_in, U256::full_mul(reserve_in, scaled))?;
+    Some(reserve_after.saturating_sub(reserve_in))
+}
+
+// A pool and victim to size a sandwich against, the victim trading last in a bundle
+// behind `victims_ahead`
+pub(crate) struct SandwichTrial {
+    pub(crate) reserve_in: u128,
+    pub(crate) reserve_out: u128,
+    pub(crate) target_amount_in: u128,
+    pub(crate) victims_ahead: Vec<u128>,
+    pub(crate) safe_slippage_pips: u128,
+    pub(crate) self_impact_cap_bps: u128,
+    pub(crate) trade_fee_rate: u64,
+    pub(crate) protocol_fee_rate: u64,
+    pub(crate) fund_fee_rate: u64,
+}
+
+impl SandwichTrial {
+    fn swap(&self, amount_in: u128, reserve_in: u128, reserve_out: u128) -> Result<u128> {
+        Ok(CurveCalculator::swap_base_input(
+            amount_in,
+            reserve_in,
+            reserve_out,
+            self.trade_fee_rate,
+            self.protocol_fee_rate,
+            self.fund_fee_rate,
         )
         .ok_or(ErrorCode::CalculationFailure)?
-        .destination_amount_swapped;
+        .destination_amount_swapped)
+    }
 
-        // Check if target tx will still execute within slippage
-        let price_impact_bps = ((target_expected_output_before - target_expected_output_after)
-            * 10000)
-            / target_expected_output_before;
+    // Reserves once the victims ahead of the target swapped through them in order
+    fn after_victims_ahead(
+        &self,
+        mut reserve_in: u128,
+        mut reserve_out: u128,
+    ) -> Result<(u128, u128)> {
+        for &victim_amount_in in &self.victims_ahead {
+            let victim_output = self.swap(victim_amount_in, reserve_in, reserve_out)?;
+            reserve_in += victim_amount_in;
+            reserve_out -= victim_output;
+        }
+        Ok((reserve_in, reserve_out))
+    }
+
+    // Profit of frontrunning with `amount`, `None` if it breaks the victim's slippage
+    // or moves the price past our own cap
+    fn profit(&self, amount: u128) -> Result<Option<u128>> {
+        Ok(self
+            .backrun_output(amount)?
+            .map(|backrun_output_amount| backrun_output_amount.saturating_sub(amount)))
+    }
+
+    // What selling the frontrun output back after the victim returns, `None` under the
+    // same conditions as `profit`
+    pub(crate) fn backrun_output(&self, amount: u128) -> Result<Option<u128>> {
+        // 1. FRONTRUN: Calculate outcome of frontrun transaction
+        let frontrun_output_amount = self.swap(amount, self.reserve_in, self.reserve_out)?;
+        let new_reserve_in = self.reserve_in + amount;
+        let new_reserve_out = self.reserve_out - frontrun_output_amount;
 
-        let within_slippage = price_impact_bps <= safe_slippage_bps;
+        // 2. TARGET TX: Simulate target transaction on new reserves, past the victims
+        // ahead of it, whose swaps its quote already counts in
+        // First calculate if this still allows target tx to succeed within slippage
+        let (quote_reserve_in, quote_reserve_out) =
+            self.after_victims_ahead(self.reserve_in, self.reserve_out)?;
+        let (target_reserve_in, target_reserve_out) =
+            self.after_victims_ahead(new_reserve_in, new_reserve_out)?;
+        let target_expected_output_before =
+            self.swap(self.target_amount_in, quote_reserve_in, quote_reserve_out)?;
+        let target_expected_output_after =
+            self.swap(self.target_amount_in, target_reserve_in, target_reserve_out)?;
 
-        // If target would fail due to slippage, this attack size doesn't work
-        if !within_slippage {
-            high = mid - 1;
-            continue;
+        // Check if target tx will still execute within slippage
+        let price_impact_pips = ((target_expected_output_before - target_expected_output_after)
+            * PIPS_DENOMINATOR)
+            .checked_div(target_expected_output_before)
+            .ok_or(ErrorCode::CalculationFailure)?;
+
+        let within_slippage = price_impact_pips <= self.safe_slippage_pips;
+
+        // Our own buy must not move the mid price past the self cap either
+        let within_self_cap = cpmm_mid_price_impact_bps(
+            self.reserve_in,
+            self.reserve_out,
+            new_reserve_in,
+            new_reserve_out,
+        )? <= self.self_impact_cap_bps;
+
+        // If target would fail due to slippage, or we would move price too far, this
+        // attack size doesn't work
+        if !within_slippage || !within_self_cap {
+            return Ok(None);
         }
 
         // 3. Calculate state after target tx executes
-        let after_target_reserve_in = new_reserve_in + target_amount_in;
-        let after_target_reserve_out = new_reserve_out - target_expected_output_after;
+        let after_target_reserve_in = target_reserve_in + self.target_amount_in;
+        let after_target_reserve_out = target_reserve_out - target_expected_output_after;
 
         // 4. BACKRUN: Calculate result of selling frontrun_output_amount
-        let backrun_result = CurveCalculator::swap_base_input(
+        let backrun_output_amount = self.swap(
             frontrun_output_amount,   // selling what we got in frontrun
             after_target_reserve_out, // using reserves after target tx
             after_target_reserve_in,
-            trade_fee_rate,
-            protocol_fee_rate,
-            fund_fee_rate,
-        )
-        .ok_or(ErrorCode::CalculationFailure)?;
+        )?;
 
-        // 5. Calculate actual profit (what we get back minus what we put in)
-        let backrun_output_amount = backrun_result.destination_amount_swapped;
-        let profit = backrun_output_amount.saturating_sub(mid);
+        Ok(Some(backrun_output_amount))
+    }
 
-        // 6. Update best if this is more profitable
-        if profit > best_profit {
-            best_profit = profit;
-            best_amount = mid;
+    // Binary search over [low, high] for the most profitable frontrun, returning it
+    // with its profit. `initial_estimate` stands in if no trial turns a profit
+    fn search(
+        &self,
+        mut low: u128,
+        mut high: u128,
+        initial_estimate: u128,
+        iterations: u32,
+    ) -> Result<(u128, u128)> {
+        let mut best_amount = initial_estimate;
+        let mut best_profit = 0u128;
+
+        for _ in 0..iterations {
+            if low >= high {
+                break;
+            }
+            let mid = (low + high) / 2;
+
+            let profit = match self.profit(mid)? {
+                Some(profit) => profit,
+                None => {
+                    high = mid - 1;
+                    continue;
+                }
+            };
+
+            // Update best if this is more profitable
+            if profit > best_profit {
+                best_profit = profit;
+                best_amount = mid;
+            }
+
+            // Adjust search range - try larger amounts if profitable
+            if profit > 0 {
+                low = mid + 1;
+            } else {
+                high = mid - 1;
+            }
         }
 
-        // 7. Adjust search range - try larger amounts if profitable
-        if profit > 0 {
-            low = mid + 1;
-        } else {
-            high = mid - 1;
-        }
+        Ok((best_amount, best_profit))
     }
+}
 
-    // Convert best amount to u64 and return
-    let result = best_amount.try_into().unwrap_or(u64::MAX);
+// How far, in bps, a swap moving the reserves from `reserve_in`/`reserve_out` to
+// `new_reserve_in`/`new_reserve_out` pushes the mid price (output per input) down
+pub(crate) fn cpmm_mid_price_impact_bps(
+    reserve_in: u128,
+    reserve_out: u128,
+    new_reserve_in: u128,
+    new_reserve_out: u128,
+) -> Result<u128> {
+    // Mid price after the swap relative to before, scaled by 1e6
+    let price_ratio = new_reserve_out
+        .checked_mul(1_000_000)
+        .and_then(|scaled| scaled.checked_div(reserve_out))
+        .and_then(|ratio| ratio.checked_mul(reserve_in))
+        .and_then(|ratio| ratio.checked_div(new_reserve_in))
+        .ok_or(ErrorCode::CalculationFailure)?;
+    Ok(1_000_000u128.saturating_sub(price_ratio) / 100)
+}
 
-    Ok(result)
+// Spot price of the pool as output per input, Q64.64, the reserve ratio a frontrun's
+// `expected_current_price` is checked against
+pub fn cpmm_price_x64(reserve_in: u64, reserve_out: u64) -> Result<u128> {
+    Ok(((reserve_out as u128) << 64)
+        .checked_div(reserve_in as u128)
+        .ok_or(ErrorCode::CalculationFailure)?)
 }
 
 // Calculate minimum output amount for our sandwich buy with aggressive slippage
@@ -690,6 +1613,7 @@ fn calculate_minimum_out_for_sandwich(
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    self_slippage_bps: u16,
 ) -> Result<u64> {
     // Calculate expected output
     let expected_out = calculate_expected_output(
@@ -701,8 +1625,9 @@ fn calculate_minimum_out_for_sandwich(
         fund_fee_rate,
     )?;
 
-    // Apply aggressive slippage tolerance (5%)
-    let min_out = expected_out.saturating_mul(95).saturating_div(100);
+    // Apply our own slippage tolerance (5% by default)
+    let slippage_bps = self_slippage_bps_or_default(self_slippage_bps)?;
+    let min_out = ((expected_out as u128) * (10_000 - slippage_bps) / 10_000) as u64;
 
     Ok(min_out)
 }
@@ -712,6 +1637,14 @@ fn calculate_minimum_out_for_sandwich(
 // this is from the raydium cpmm code
 // https://github.com/raydium-io/raydium-cp-swap/blob/183ddbb11550cea212710a98351779a41873258b/programs/cp-swap/src/utils/token.rs#L159
 pub fn get_transfer_fee(mint_info: &AccountInfo, pre_fee_amount: u64) -> Result<u64> {
+    if *mint_info.owner == Token::id() {
+        return Ok(0);
+    }
+    transfer_fee_at(mint_info, Clock::get()?.epoch, pre_fee_amount)
+}
+
+/// get_transfer_fee at an explicit epoch
+pub fn transfer_fee_at(mint_info: &AccountInfo, epoch: u64, pre_fee_amount: u64) -> Result<u64> {
     if *mint_info.owner == Token::id() {
         return Ok(0);
     }
@@ -720,10 +1653,1087 @@ pub fn get_transfer_fee(mint_info: &AccountInfo, pre_fee_amount: u64) -> Result<
 
     let fee = if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
         transfer_fee_config
-            .calculate_epoch_fee(Clock::get()?.epoch, pre_fee_amount)
-            .unwrap()
+            .calculate_epoch_fee(epoch, pre_fee_amount)
+            .ok_or(ErrorCode::CalculationFailure)?
     } else {
         0
     };
     Ok(fee)
 }
+
+// Rejects output mints we could not sell back in the backrun: non-transferable
+// Token-2022 mints and mints whose transfer fee would eat the whole amount
+pub fn ensure_token_sellable(mint_info: &AccountInfo, amount: u64) -> Result<()> {
+    if *mint_info.owner == Token::id() {
+        return Ok(());
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+    if mint.get_extension::<NonTransferable>().is_ok() {
+        return err!(ErrorCode::TokenNotSellable);
+    }
+
+    if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
+        let epoch = Clock::get()?.epoch;
+        let transfer_fee = transfer_fee_config.get_epoch_fee(epoch);
+        require!(
+            u16::from(transfer_fee.transfer_fee_basis_points) < MAX_FEE_BASIS_POINTS,
+            ErrorCode::TokenNotSellable
+        );
+
+        // Simulate moving the frontrun output, something must be left after the fee
+        let fee = transfer_fee_config
+            .calculate_epoch_fee(epoch, amount)
+            .ok_or(ErrorCode::CalculationFailure)?;
+        require!(fee < amount, ErrorCode::TokenNotSellable);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sandwich_state::tests::state_with_mints;
+    use anchor_spl::token::spl_token;
+    use solana_program::program_pack::Pack;
+
+    // Leaks the account's buffers, InterfaceAccount borrows them for its whole lifetime
+    pub(crate) fn vault(amount: u64) -> InterfaceAccount<'static, TokenAccount> {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+
+        let info = AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            true,
+            Box::leak(Box::new(0)),
+            data.leak(),
+            &spl_token::ID,
+            false,
+            0,
+        );
+        InterfaceAccount::try_from(Box::leak(Box::new(info))).unwrap()
+    }
+
+    pub(crate) fn pool_with_vaults(
+        input_vault: &InterfaceAccount<TokenAccount>,
+        output_vault: &InterfaceAccount<TokenAccount>,
+    ) -> CpmmPoolState {
+        CpmmPoolState {
+            token_0_vault: input_vault.key(),
+            token_1_vault: output_vault.key(),
+            protocol_fees_token_0: 10,
+            fund_fees_token_0: 5,
+            protocol_fees_token_1: 20,
+            fund_fees_token_1: 7,
+            ..Default::default()
+        }
+    }
+
+    // A classic SPL mint, which carries no transfer fee
+    fn spl_mint() -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            false,
+            Box::leak(Box::new(0)),
+            Vec::new().leak(),
+            &spl_token::ID,
+            false,
+            0,
+        )
+    }
+
+    // Leaks the account's buffers like `vault`, writable so the test can borrow it mutably
+    pub(crate) fn pool_loader(state: CpmmPoolState) -> AccountLoader<'static, CpmmPoolState> {
+        let mut data = vec![0u8; 8 + std::mem::size_of::<CpmmPoolState>()];
+        data[..8].copy_from_slice(&CpmmPoolState::DISCRIMINATOR);
+
+        let info = AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            true,
+            Box::leak(Box::new(0)),
+            data.leak(),
+            &crate::ID,
+            false,
+            0,
+        );
+        let loader = AccountLoader::try_from(Box::leak(Box::new(info))).unwrap();
+        *loader.load_mut().unwrap() = state;
+        loader
+    }
+
+    // Sells 10_000 into 1_000_000 against 2_000_000 once the pool fees are taken out,
+    // 19_753 expected at the 0.25% trade fee
+    fn backrun_sizing(
+        cost_basis: u64,
+        max_acceptable_loss: Option<u64>,
+        research_mode: bool,
+    ) -> Result<(u64, u64)> {
+        backrun_sizing_into(&spl_mint(), cost_basis, max_acceptable_loss, research_mode)
+    }
+
+    // `backrun_sizing` with the backrun's output paid in `output_token_mint`
+    fn backrun_sizing_into(
+        output_token_mint: &AccountInfo,
+        cost_basis: u64,
+        max_acceptable_loss: Option<u64>,
+        research_mode: bool,
+    ) -> Result<(u64, u64)> {
+        let input_vault = vault(1_000_015);
+        let output_vault = vault(2_000_027);
+        let amm_config = CpmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        };
+        calculate_cpmm_backrun_minimum_output(
+            &pool_with_vaults(&input_vault, &output_vault),
+            &input_vault,
+            &output_vault,
+            &amm_config,
+            &spl_mint(),
+            output_token_mint,
+            0,
+            10_000,
+            cost_basis,
+            min_required_output(cost_basis, 0, 0)?,
+            max_acceptable_loss,
+            research_mode,
+        )
+    }
+
+    fn backrun_minimum_output(cost_basis: u64, max_acceptable_loss: Option<u64>) -> Result<u64> {
+        backrun_sizing(cost_basis, max_acceptable_loss, false)
+            .map(|(minimum_output, _)| minimum_output)
+    }
+
+    #[test]
+    fn backrun_without_a_loss_bound_requires_the_profit_floor() {
+        // 19_000 plus the 0.5% profit floor needs 19_095, the margin sits above it
+        assert_eq!(backrun_minimum_output(19_000, None).unwrap(), 19_357);
+        // 19_700 plus the profit floor needs 19_798, more than expected
+        assert_eq!(
+            backrun_minimum_output(19_700, None).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+    }
+
+    #[test]
+    fn backrun_within_the_loss_bound_proceeds() {
+        // Losing up to 100 on a 19_800 cost basis lets the 19_753 fill through
+        assert_eq!(backrun_minimum_output(19_800, Some(100)).unwrap(), 19_700);
+    }
+
+    #[test]
+    fn backrun_beyond_the_loss_bound_reverts() {
+        // Losing at most 10 still needs 19_790
+        assert_eq!(
+            backrun_minimum_output(19_800, Some(10)).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+    }
+
+    #[test]
+    fn research_mode_sells_an_unprofitable_backrun() {
+        // Off, a 19_800 cost basis can't be sold back for the 19_753 expected
+        assert_eq!(
+            backrun_sizing(19_800, None, false).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+
+        // On, the sell goes through bounded by the 2% margin and reports the loss
+        let (minimum_output, expected_output) = backrun_sizing(19_800, None, true).unwrap();
+        assert_eq!((minimum_output, expected_output), (19_357, 19_753));
+        let event = SandwichCompleteEvent::new(1, 19_800, minimum_output, Some(expected_output), 0);
+        assert_eq!(event.realized_profit, -443);
+        assert_eq!(event.expected_profit, Some(-47));
+    }
+
+    #[test]
+    fn the_backrun_floor_is_held_against_the_output_after_its_transfer_fee() {
+        use crate::instructions::raydium::cpmm::swap_base_output::tests::transfer_fee_mint;
+
+        // 19_753 out of the pool clears the 19_597 floor on a 19_500 cost basis
+        assert_eq!(
+            backrun_sizing(19_500, None, false).unwrap(),
+            (19_597, 19_753)
+        );
+
+        // A 1% fee on the output withholds 198 of it, the 19_555 we'd receive doesn't
+        let fee_mint = transfer_fee_mint(100);
+        assert_eq!(
+            backrun_sizing_into(&fee_mint, 19_500, None, false).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+        assert_eq!(
+            backrun_sizing_into(&fee_mint, 19_500, None, true).unwrap(),
+            (19_163, 19_555)
+        );
+    }
+
+    #[test]
+    fn a_mint_taking_its_whole_transfer_is_not_sellable() {
+        use crate::instructions::raydium::cpmm::swap_base_output::tests::transfer_fee_mint;
+        use crate::test_utils::syscall_stubs;
+
+        let _stubs = syscall_stubs();
+        assert_eq!(
+            ensure_token_sellable(&transfer_fee_mint(10_000), 1_000_000).unwrap_err(),
+            error!(ErrorCode::TokenNotSellable)
+        );
+
+        // A 1% fee leaves the backrun something to sell, as does a classic SPL mint
+        ensure_token_sellable(&transfer_fee_mint(100), 1_000_000).unwrap();
+        ensure_token_sellable(&spl_mint(), 1_000_000).unwrap();
+    }
+
+    #[test]
+    fn a_fee_on_transfer_victim_logs_its_amounts_net_of_the_fee() {
+        use crate::instructions::raydium::cpmm::swap_base_output::tests::transfer_fee_mint;
+
+        // A 1% fee withholds 10_000 of the victim's 1_000_000 before it reaches the pool
+        let fee_mint = transfer_fee_mint(100);
+        let target_amount_in = 1_000_000;
+        let target_actual_amount_in =
+            target_amount_in - transfer_fee_at(&fee_mint, 0, target_amount_in).unwrap();
+        let expected_target_output = calculate_expected_output(
+            target_actual_amount_in,
+            1_000_000_000,
+            1_000_000_000,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap();
+        let target_slippage_pips =
+            victim_slippage_pips(expected_target_output, 970_000, 0, 0).unwrap();
+
+        assert_eq!(
+            victim_amounts_line(
+                "CPMM",
+                target_actual_amount_in,
+                expected_target_output,
+                target_slippage_pips
+            ),
+            "Victim effective amounts: CPMM in=990000 out=986550 slippage_bps=167.75"
+        );
+        // Off-chain the log falls back to the logging stub
+        log_victim_amounts(
+            "CPMM",
+            target_actual_amount_in,
+            expected_target_output,
+            target_slippage_pips,
+        );
+    }
+
+    #[test]
+    fn self_price_impact_cap_shrinks_the_frontrun() {
+        let (reserve_in, reserve_out) = (1_000_000_000u64, 1_000_000_000u64);
+        let target_amount_in = 10_000_000;
+        let size = |self_impact_cap_bps| {
+            calculate_optimal_sandwich_amount(
+                reserve_in,
+                reserve_out,
+                50_000,
+                self_impact_cap_bps,
+                target_amount_in,
+                target_amount_in,
+                &[],
+                2_500,
+                0,
+                0,
+                MIN_SANDWICH_PROFIT,
+            )
+            .unwrap()
+        };
+
+        // The victim's 5% leaves room for a buy moving the mid price well past 10 bps
+        let uncapped = size(self_price_impact_cap_bps(0).unwrap());
+        let capped = size(self_price_impact_cap_bps(10).unwrap());
+        assert!(capped < uncapped, "{} >= {}", capped, uncapped);
+
+        let swapped = CurveCalculator::swap_base_input(
+            capped as u128,
+            reserve_in as u128,
+            reserve_out as u128,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap();
+        let impact_bps = cpmm_mid_price_impact_bps(
+            reserve_in as u128,
+            reserve_out as u128,
+            reserve_in as u128 + swapped.source_amount_swapped,
+            reserve_out as u128 - swapped.destination_amount_swapped,
+        )
+        .unwrap();
+        assert!(impact_bps <= 10, "{}", impact_bps);
+    }
+
+    #[test]
+    fn a_search_without_a_profitable_amount_is_refused() {
+        let size = |min_profit| {
+            calculate_optimal_sandwich_amount(
+                1_000_000_000,
+                1_000_000_000,
+                47_500,
+                u128::MAX,
+                10_000_000,
+                10_000_000,
+                &[],
+                10_000,
+                0,
+                0,
+                min_profit,
+            )
+        };
+
+        // A 1% fee on both legs eats everything the victim pushes the price by
+        assert_eq!(
+            size(MIN_SANDWICH_PROFIT).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+        // Asking for no profit, as research mode does, gets the unchecked seed back
+        assert_eq!(size(0).unwrap(), 24_753_588);
+    }
+
+    #[test]
+    fn a_search_seeded_at_the_slippage_bound_converges_faster() {
+        let trial = SandwichTrial {
+            reserve_in: 1_000_000_000,
+            reserve_out: 1_000_000_000,
+            target_amount_in: 10_000_000,
+            victims_ahead: vec![],
+            safe_slippage_pips: 47_500,
+            self_impact_cap_bps: u128::MAX,
+            trade_fee_rate: 2_500,
+            protocol_fee_rate: 0,
+            fund_fee_rate: 0,
+        };
+        let max_amount = 100_000_000;
+
+        // Brute force over the whole range, 1_000 apart
+        let optimum = (1..max_amount)
+            .step_by(1_000)
+            .filter_map(|amount| trial.profit(amount).unwrap())
+            .max()
+            .unwrap();
+
+        let seed = cpmm_slippage_bound(1_000_000_000, 10_000_000, 47_500).unwrap();
+        let (_, seeded) = trial.search(1, seed * 2, seed, 4).unwrap();
+        let (_, unseeded) = trial.search(1, max_amount, 1, 4).unwrap();
+
+        // From the seed, four steps land within 1% of the optimum, halving the whole
+        // range the same four times is still more than 10% short
+        assert!(seeded * 100 >= optimum * 99, "{} vs {}", seeded, optimum);
+        assert!(unseeded * 10 < optimum * 9, "{} vs {}", unseeded, optimum);
+    }
+
+    #[test]
+    fn reserves_are_read_without_holding_the_pool_borrow() {
+        let input_vault = vault(1_000_015);
+        let output_vault = vault(2_000_027);
+        let pool_state = pool_loader(pool_with_vaults(&input_vault, &output_vault));
+
+        assert_eq!(
+            cpmm_reserves(&pool_state, &input_vault, &output_vault).unwrap(),
+            (1_000_000, 2_000_000)
+        );
+        assert_eq!(
+            cpmm_reserves(&pool_state, &output_vault, &input_vault).unwrap(),
+            (2_000_000, 1_000_000)
+        );
+        assert_eq!(
+            cpmm_reserves(&pool_state, &input_vault, &vault(0)).unwrap_err(),
+            error!(ErrorCode::InvalidVault)
+        );
+
+        // Nothing is left borrowed for the swap CPI to trip over
+        let pool_info = pool_state.to_account_info();
+        assert!(pool_info.try_borrow_mut_data().is_ok());
+
+        // Whereas a pool still loaded would make the CPI fail
+        let _held = pool_state.load_mut().unwrap();
+        assert!(pool_info.try_borrow_mut_data().is_err());
+    }
+
+    // 1_000_000_000 on both sides once the pool fees are taken out, 0.25% trade fee
+    fn victim_pool() -> (
+        InterfaceAccount<'static, TokenAccount>,
+        InterfaceAccount<'static, TokenAccount>,
+        CpmmAmmConfig,
+    ) {
+        let amm_config = CpmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        };
+        (vault(1_000_000_015), vault(1_000_000_027), amm_config)
+    }
+
+    // Reserves after our frontrun buys with `amount_in`
+    fn reserves_after_frontrun(amount_in: u64, amm_config: &CpmmAmmConfig) -> (u128, u128) {
+        let result = CurveCalculator::swap_base_input(
+            amount_in as u128,
+            1_000_000_000,
+            1_000_000_000,
+            amm_config.trade_fee_rate,
+            0,
+            0,
+        )
+        .unwrap();
+        (
+            1_000_000_000 + result.source_amount_swapped,
+            1_000_000_000 - result.destination_amount_swapped,
+        )
+    }
+
+    #[test]
+    fn exact_input_victim_sizing_leaves_the_victim_its_minimum_out() {
+        let (input_vault, output_vault, amm_config) = victim_pool();
+        let target_amount_in = 10_000_000;
+        let expected_out =
+            calculate_expected_output(target_amount_in, 1_000_000_000, 1_000_000_000, 2_500, 0, 0)
+                .unwrap();
+        let target_minimum_amount_out = expected_out / 100 * 95;
+
+        let (buy_amount, minimum_out) = size_cpmm_frontrun_base_input(
+            &pool_with_vaults(&input_vault, &output_vault),
+            &input_vault,
+            &output_vault,
+            &spl_mint(),
+            &spl_mint(),
+            &amm_config,
+            target_amount_in,
+            target_minimum_amount_out,
+            &[],
+            0,
+            0,
+            0,
+            0,
+            0,
+            SlippageModel::Balanced,
+            false,
+        )
+        .unwrap();
+        assert!(buy_amount > 0 && minimum_out > 0);
+
+        let (reserve_in, reserve_out) = reserves_after_frontrun(buy_amount, &amm_config);
+        let victim_out = calculate_expected_output(
+            target_amount_in,
+            reserve_in as u64,
+            reserve_out as u64,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap();
+        assert!(victim_out >= target_minimum_amount_out, "{}", victim_out);
+    }
+
+    #[test]
+    fn an_aggressive_slippage_model_sizes_a_larger_frontrun() {
+        let (input_vault, output_vault, amm_config) = victim_pool();
+        let target_amount_in = 10_000_000;
+        let expected_out =
+            calculate_expected_output(target_amount_in, 1_000_000_000, 1_000_000_000, 2_500, 0, 0)
+                .unwrap();
+        let target_minimum_amount_out = expected_out / 100 * 95;
+
+        let size = |slippage_model: SlippageModel| {
+            size_cpmm_frontrun_base_input(
+                &pool_with_vaults(&input_vault, &output_vault),
+                &input_vault,
+                &output_vault,
+                &spl_mint(),
+                &spl_mint(),
+                &amm_config,
+                target_amount_in,
+                target_minimum_amount_out,
+                &[],
+                0,
+                0,
+                0,
+                0,
+                0,
+                slippage_model,
+                false,
+            )
+            .unwrap()
+            .0
+        };
+
+        // 99% of the victim's 5% tolerance buys more than 80% of it, on the same pool
+        let conservative = size(SlippageModel::Conservative);
+        let aggressive = size(SlippageModel::Aggressive);
+        assert!(aggressive > conservative, "{} {}", aggressive, conservative);
+
+        // Even the aggressive frontrun leaves the victim its minimum out
+        let (reserve_in, reserve_out) = reserves_after_frontrun(aggressive, &amm_config);
+        let victim_out = calculate_expected_output(
+            target_amount_in,
+            reserve_in as u64,
+            reserve_out as u64,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap();
+        assert!(victim_out >= target_minimum_amount_out, "{}", victim_out);
+    }
+
+    #[test]
+    fn a_tighter_self_slippage_raises_the_frontrun_min_out() {
+        // Without fees 1_000_000 in buys 10^15 / 1_001_000_000 = 999_000 out
+        let min_out = |self_slippage_bps| {
+            calculate_minimum_out_for_sandwich(
+                1_000_000,
+                1_000_000_000,
+                1_000_000_000,
+                0,
+                0,
+                0,
+                self_slippage_bps,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(min_out(100), 989_010);
+        assert_eq!(min_out(500), 949_050);
+        // Zero falls back to the 5% default
+        assert_eq!(min_out(0), 949_050);
+    }
+
+    #[test]
+    fn a_frontrun_that_filled_nothing_is_rejected() {
+        // A no-op swap CPI: nothing received and the vault untouched
+        assert_eq!(
+            check_frontrun_fill(0, 1_000_000, 1_000_000).unwrap_err(),
+            error!(ErrorCode::EmptySupply)
+        );
+        // Our balance grew but the pool's vault didn't pay for it
+        assert_eq!(
+            check_frontrun_fill(9_000, 1_000_000, 1_000_000).unwrap_err(),
+            error!(ErrorCode::EmptySupply)
+        );
+        // A real fill, the vault also pays out the output transfer fee
+        check_frontrun_fill(9_000, 1_000_000, 990_900).unwrap();
+    }
+
+    #[test]
+    fn a_cpmm_backrun_sells_through_the_frontrun_pool_backwards() {
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // Both the base-input and the base-output frontrun record the input and output
+        // mints of their own swap, whichever side they fix
+        let state = state_with_mints(input_mint, output_mint);
+
+        // The backrun's input token is the frontrun's output token
+        assert!(state.is_unwound_by(&output_mint, &input_mint));
+        assert!(!state.is_unwound_by(&input_mint, &output_mint));
+    }
+
+    #[test]
+    fn a_fixed_frontrun_breaking_the_victim_is_refused() {
+        let (input_vault, output_vault, amm_config) = victim_pool();
+        let target_amount_in = 10_000_000;
+        let expected_out =
+            calculate_expected_output(target_amount_in, 1_000_000_000, 1_000_000_000, 2_500, 0, 0)
+                .unwrap();
+        // The victim accepts 5% less, 95% of which leaves us 47_506 pips
+        let target_minimum_amount_out = expected_out / 100 * 95;
+
+        let check_capped = |frontrun_amount: u64, max_self_price_impact_bps: u16| {
+            check_cpmm_fixed_frontrun_base_input(
+                &pool_with_vaults(&input_vault, &output_vault),
+                &input_vault,
+                &output_vault,
+                &spl_mint(),
+                &spl_mint(),
+                &amm_config,
+                frontrun_amount,
+                target_amount_in,
+                target_minimum_amount_out,
+                0,
+                0,
+                0,
+                max_self_price_impact_bps,
+                0,
+                SlippageModel::Balanced,
+                false,
+            )
+        };
+        let check = |frontrun_amount: u64| check_capped(frontrun_amount, 0);
+
+        // Takes 38_597 pips off the victim's output, and is bought as given
+        let (buy_amount, minimum_out) = check(20_000_000).unwrap();
+        assert_eq!(buy_amount, 20_000_000);
+        assert!(minimum_out > 0);
+        // 57_064 pips would push the victim past its tolerance
+        assert_eq!(
+            check(30_000_000).unwrap_err(),
+            error!(ErrorCode::ExceededSlippage)
+        );
+
+        // The operator's cap on our own price impact holds a fixed amount too, 20_000_000
+        // moves the mid price about 4%
+        assert_eq!(
+            check_capped(20_000_000, 100).unwrap_err(),
+            error!(ErrorCode::ExceededSlippage)
+        );
+        assert!(check_capped(20_000_000, 500).is_ok());
+    }
+
+    #[test]
+    fn the_absolute_cap_binds_on_a_high_slippage_victim() {
+        let (input_vault, output_vault, amm_config) = victim_pool();
+        let target_amount_in = 10_000_000;
+        let expected_out =
+            calculate_expected_output(target_amount_in, 1_000_000_000, 1_000_000_000, 2_500, 0, 0)
+                .unwrap();
+        // The victim accepts half its quote, 95% of which is a 4750 bps move
+        let target_minimum_amount_out = expected_out / 2;
+
+        let size = |max_absolute_slippage_consumed_bps: u16| {
+            size_cpmm_frontrun_base_input(
+                &pool_with_vaults(&input_vault, &output_vault),
+                &input_vault,
+                &output_vault,
+                &spl_mint(),
+                &spl_mint(),
+                &amm_config,
+                target_amount_in,
+                target_minimum_amount_out,
+                &[],
+                0,
+                0,
+                0,
+                0,
+                max_absolute_slippage_consumed_bps,
+                SlippageModel::Balanced,
+                false,
+            )
+            .unwrap()
+            .0
+        };
+        // Bps the frontrun takes off what the victim receives
+        let victim_impact_bps = |buy_amount: u64| {
+            let (reserve_in, reserve_out) = reserves_after_frontrun(buy_amount, &amm_config);
+            let victim_out = calculate_expected_output(
+                target_amount_in,
+                reserve_in as u64,
+                reserve_out as u64,
+                2_500,
+                0,
+                0,
+            )
+            .unwrap();
+            (expected_out - victim_out) * 10_000 / expected_out
+        };
+
+        let uncapped = size(0);
+        let capped = size(800);
+        assert!(capped < uncapped);
+        assert!(victim_impact_bps(uncapped) > 800);
+        assert!(victim_impact_bps(capped) <= 800);
+    }
+
+    #[test]
+    fn a_bundle_of_two_victims_sizes_a_larger_frontrun() {
+        let (input_vault, output_vault, amm_config) = victim_pool();
+        let target_amount_in = 10_000_000;
+        let size = |victims_ahead: &[u64]| {
+            size_cpmm_frontrun_base_input(
+                &pool_with_vaults(&input_vault, &output_vault),
+                &input_vault,
+                &output_vault,
+                &spl_mint(),
+                &spl_mint(),
+                &amm_config,
+                target_amount_in,
+                0,
+                victims_ahead,
+                500,
+                0,
+                0,
+                0,
+                0,
+                SlippageModel::Balanced,
+                false,
+            )
+            .map(|(buy_amount, _)| buy_amount)
+        };
+
+        // A second victim of the same size trading first moves the price further for the
+        // backrun to take back
+        let single = size(&[]).unwrap();
+        let bundle = size(&[target_amount_in]).unwrap();
+        assert!(bundle > single, "{} <= {}", bundle, single);
+
+        // The last victim still clears its 5% against its quote behind the first one
+        let victim_out = |reserve_in: u128, reserve_out: u128| {
+            let first_out = calculate_expected_output(
+                target_amount_in,
+                reserve_in as u64,
+                reserve_out as u64,
+                2_500,
+                0,
+                0,
+            )
+            .unwrap();
+            calculate_expected_output(
+                target_amount_in,
+                reserve_in as u64 + target_amount_in,
+                reserve_out as u64 - first_out,
+                2_500,
+                0,
+                0,
+            )
+            .unwrap()
+        };
+        let quoted_out = victim_out(1_000_000_000, 1_000_000_000);
+        let (reserve_in, reserve_out) = reserves_after_frontrun(bundle, &amm_config);
+        assert!(victim_out(reserve_in, reserve_out) >= quoted_out / 100 * 95);
+
+        // Bundles are kept small
+        assert_eq!(
+            size(&[1; MAX_BUNDLE_VICTIMS_AHEAD + 1]).unwrap_err(),
+            error!(ErrorCode::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn an_unknown_minimum_out_assumes_the_default_victim_slippage() {
+        let (input_vault, output_vault, amm_config) = victim_pool();
+        let target_amount_in = 10_000_000;
+        let size = |target_minimum_amount_out, default_victim_slippage_bps| {
+            size_cpmm_frontrun_base_input(
+                &pool_with_vaults(&input_vault, &output_vault),
+                &input_vault,
+                &output_vault,
+                &spl_mint(),
+                &spl_mint(),
+                &amm_config,
+                target_amount_in,
+                target_minimum_amount_out,
+                &[],
+                default_victim_slippage_bps,
+                0,
+                0,
+                0,
+                0,
+                SlippageModel::Balanced,
+                false,
+            )
+            .unwrap()
+            .0
+        };
+
+        // Sized against the 1% default, the victim still gets 99% of its quote
+        let buy_amount = size(0, 0);
+        let expected_out =
+            calculate_expected_output(target_amount_in, 1_000_000_000, 1_000_000_000, 2_500, 0, 0)
+                .unwrap();
+        let (reserve_in, reserve_out) = reserves_after_frontrun(buy_amount, &amm_config);
+        let victim_out = calculate_expected_output(
+            target_amount_in,
+            reserve_in as u64,
+            reserve_out as u64,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap();
+        assert!(victim_out >= expected_out / 100 * 99, "{}", victim_out);
+
+        // A near-zero known minimum still reads as a huge tolerance, the default is tighter
+        assert!(buy_amount < size(1, 0));
+        // A wider configured default sizes a larger frontrun
+        assert!(buy_amount < size(0, 300));
+    }
+
+    #[test]
+    fn a_fee_on_transfer_output_narrows_the_victim_slippage() {
+        use crate::instructions::raydium::cpmm::swap_base_output::{
+            tests::transfer_fee_mint, transfer_inverse_fee_at,
+        };
+
+        // The victim wants 950_000 out of an expected 1_000_000, 5% read naively
+        assert_eq!(
+            victim_slippage_pips(1_000_000, 950_000, 0, 0).unwrap(),
+            50_000
+        );
+
+        // With a 1% fee on the output the pool must send 959_596 for 950_000 to arrive
+        let fee = transfer_inverse_fee_at(&transfer_fee_mint(100), 0, 950_000).unwrap();
+        assert_eq!(fee, 9_596);
+        assert_eq!(
+            victim_slippage_pips(1_000_000, 950_000, fee, 0).unwrap(),
+            40_404
+        );
+    }
+
+    #[test]
+    fn a_sub_basis_point_victim_tolerance_is_measured_not_defaulted() {
+        // 0.05% under the quote is 500 pips, 95% of it stays at 475 instead of 4 bps
+        assert_eq!(victim_slippage_pips(1_000_000, 999_500, 0, 0).unwrap(), 500);
+        assert_eq!(SlippageModel::Balanced.safe_slippage_pips(500, 0), 475);
+        // Under a basis point still registers rather than reading as no tolerance
+        assert_eq!(victim_slippage_pips(1_000_000, 999_950, 0, 0).unwrap(), 50);
+        // Only an unknown minimum out falls back to the 1% default
+        assert_eq!(victim_slippage_pips(1_000_000, 0, 0, 0).unwrap(), 10_000);
+    }
+
+    #[test]
+    fn only_the_pool_swap_bit_blocks_the_frontrun() {
+        let (input_vault, output_vault, mut amm_config) = victim_pool();
+        // Disabling pool creation on the config leaves existing pools tradable
+        amm_config.disable_create_pool = true;
+        let size = |status| {
+            let mut pool_state = pool_with_vaults(&input_vault, &output_vault);
+            pool_state.status = status;
+            size_cpmm_frontrun_base_input(
+                &pool_state,
+                &input_vault,
+                &output_vault,
+                &spl_mint(),
+                &spl_mint(),
+                &amm_config,
+                10_000_000,
+                9_000_000,
+                &[],
+                0,
+                0,
+                0,
+                0,
+                0,
+                SlippageModel::Balanced,
+                false,
+            )
+        };
+
+        assert!(size(0).is_ok());
+        // Disabled deposits and withdrawals don't matter to a swap
+        assert!(size(0b011).is_ok());
+        assert_eq!(size(0b100).unwrap_err(), error!(ErrorCode::PoolSwapDisabled));
+    }
+
+    #[test]
+    fn exact_output_victim_sizing_leaves_the_victim_its_max_in() {
+        let (_, _, amm_config) = victim_pool();
+        let target_amount_out = 9_000_000;
+        let required_in = CurveCalculator::swap_base_output(
+            target_amount_out as u128,
+            1_000_000_000,
+            1_000_000_000,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap()
+        .source_amount_swapped as u64;
+        let target_max_amount_in = required_in / 100 * 105;
+
+        let (buy_amount, minimum_out) = size_cpmm_frontrun_exact_output_victim(
+            1_000_000_000,
+            1_000_000_000,
+            &spl_mint(),
+            &spl_mint(),
+            &amm_config,
+            target_max_amount_in,
+            target_amount_out,
+            0,
+            0,
+            0,
+            0,
+            SlippageModel::Balanced,
+        )
+        .unwrap();
+        assert!(buy_amount > 0 && minimum_out > 0);
+
+        let (reserve_in, reserve_out) = reserves_after_frontrun(buy_amount, &amm_config);
+        let victim_in = CurveCalculator::swap_base_output(
+            target_amount_out as u128,
+            reserve_in,
+            reserve_out,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap()
+        .source_amount_swapped;
+        assert!(victim_in <= target_max_amount_in as u128, "{}", victim_in);
+    }
+
+    #[test]
+    fn an_exact_output_victim_without_slack_is_not_sandwiched() {
+        let (_, _, amm_config) = victim_pool();
+        let target_amount_out = 9_000_000;
+        let required_in = CurveCalculator::swap_base_output(
+            target_amount_out as u128,
+            1_000_000_000,
+            1_000_000_000,
+            2_500,
+            0,
+            0,
+        )
+        .unwrap()
+        .source_amount_swapped as u64;
+
+        // Read as an exact-output victim, paying exactly what the pool asks leaves no room
+        assert_eq!(
+            size_cpmm_frontrun_exact_output_victim(
+                1_000_000_000,
+                1_000_000_000,
+                &spl_mint(),
+                &spl_mint(),
+                &amm_config,
+                required_in,
+                target_amount_out,
+                1,
+                0,
+                0,
+                0,
+                SlippageModel::Balanced,
+            )
+            .unwrap_err(),
+            error!(ErrorCode::VictimSlippageTooTight)
+        );
+    }
+
+    #[test]
+    fn both_directions_pair_each_vault_with_its_own_fees() {
+        let token_0_vault = vault(1_000_015);
+        let token_1_vault = vault(2_000_027);
+        let pool_state = pool_with_vaults(&token_0_vault, &token_1_vault);
+
+        assert_eq!(
+            resolve_direction(&pool_state, &token_0_vault, &token_1_vault).unwrap(),
+            (0, 1_000_000, 2_000_000)
+        );
+        // Selling token 1 reports the same reserves, input first
+        assert_eq!(
+            resolve_direction(&pool_state, &token_1_vault, &token_0_vault).unwrap(),
+            (1, 2_000_000, 1_000_000)
+        );
+        assert_eq!(
+            cpmm_reserves(&pool_loader(pool_state), &token_1_vault, &token_0_vault).unwrap(),
+            (2_000_000, 1_000_000)
+        );
+    }
+
+    #[test]
+    fn a_reserve_ratio_past_the_drift_tolerance_is_rejected() {
+        let expected_current_price = cpmm_price_x64(1_000_000, 2_000_000).unwrap();
+        assert_eq!(expected_current_price, 2 << 64);
+
+        // Another swap landed first and moved the price by about 197 bps
+        let live_price = cpmm_price_x64(1_010_000, 1_980_198).unwrap();
+        assert!(check_price_drift(live_price, expected_current_price, 200).is_ok());
+        assert_eq!(
+            check_price_drift(live_price, expected_current_price, 100).unwrap_err(),
+            error!(ErrorCode::PriceDrifted)
+        );
+    }
+
+    #[test]
+    fn a_foreign_vault_has_no_direction() {
+        let token_0_vault = vault(1_000_015);
+        let token_1_vault = vault(2_000_027);
+        let pool_state = pool_with_vaults(&token_0_vault, &token_1_vault);
+
+        assert_eq!(
+            resolve_direction(&pool_state, &token_0_vault, &vault(2_000_027)).unwrap_err(),
+            error!(ErrorCode::InvalidVault)
+        );
+        assert_eq!(
+            resolve_direction(&pool_state, &token_0_vault, &token_0_vault).unwrap_err(),
+            error!(ErrorCode::InvalidVault)
+        );
+    }
+
+    #[test]
+    fn a_pool_listing_one_vault_twice_has_no_direction() {
+        let token_vault = vault(1_000_015);
+        let pool_state = pool_with_vaults(&token_vault, &token_vault);
+
+        assert_eq!(
+            resolve_direction(&pool_state, &token_vault, &token_vault).unwrap_err(),
+            error!(ErrorCode::InvalidVault)
+        );
+    }
+
+    mod optimizer_props {
+        use super::*;
+        use proptest::prelude::{prop_assert, prop_assert_eq, proptest, ProptestConfig, Strategy};
+
+        // Reserves and victims stay under a quarter of u64 so every post-swap reserve
+        // still fits a vault balance. Shifting spreads them over every magnitude, dust
+        // pools and dust victims included
+        fn amount() -> impl Strategy<Value = u64> {
+            (1..=u64::MAX / 4, 0u32..62).prop_map(|(amount, shift)| (amount >> shift).max(1))
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(4096))]
+
+            #[test]
+            fn the_optimizer_stays_within_the_pool_fraction(
+                reserve_in in amount(),
+                reserve_out in amount(),
+                target_amount_in in amount(),
+                safe_slippage_pips in 0u128..=1_000_000,
+                self_impact_cap_bps in 0u128..=10_000,
+                trade_fee_rate in 0u64..100_000,
+                protocol_fee_rate in 0u64..=1_000_000,
+                fund_fee_rate in 0u64..=1_000_000
+            ) {
+                // An error is a refusal to size, only a panic or an oversized buy fails
+                if let Ok(amount) = calculate_optimal_sandwich_amount(
+                    reserve_in,
+                    reserve_out,
+                    safe_slippage_pips,
+                    self_impact_cap_bps,
+                    target_amount_in,
+                    target_amount_in,
+                    &[],
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                    fund_fee_rate,
+                    MIN_SANDWICH_PROFIT,
+                ) {
+                    prop_assert!(amount <= reserve_in / 10);
+                }
+
+                // A reported profit is the one its amount actually makes
+                let trial = SandwichTrial {
+                    reserve_in: reserve_in as u128,
+                    reserve_out: reserve_out as u128,
+                    target_amount_in: target_amount_in as u128,
+                    victims_ahead: vec![],
+                    safe_slippage_pips,
+                    self_impact_cap_bps,
+                    trade_fee_rate,
+                    protocol_fee_rate,
+                    fund_fee_rate,
+                };
+                let max_amount = trial.reserve_in / 10;
+                if let Ok((amount, profit)) = trial.search(1, max_amount, 1, 20) {
+                    prop_assert!(amount <= max_amount.max(1));
+                    if profit > 0 {
+                        prop_assert_eq!(trial.profit(amount).ok(), Some(Some(profit)));
+                    }
+                }
+            }
+        }
+    }
+}
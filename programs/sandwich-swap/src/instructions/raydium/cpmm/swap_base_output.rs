@@ -1,6 +1,7 @@
 use crate::{error::ErrorCode};
 use anchor_lang::prelude::*;
 use anchor_spl::{
+    associated_token::AssociatedToken,
     token::Token,
     token_2022::spl_token_2022::{
         self,
@@ -12,8 +13,18 @@ use anchor_spl::{
     token_interface::{Mint, TokenAccount, TokenInterface},
 };
 use raydium_cpmm_cpi::{cpi, program::RaydiumCpmm};
-use crate::sandwich_state::{SandwichCompleteEvent, SandwichState};
-use super::{vault_amount_without_fee, CurveCalculator};
+use crate::sandwich_state::{
+    backrun_output_floor, check_backrun_balance, check_pool_age, check_victim_slippage,
+    min_required_output, self_slippage_bps_or_default, slippage_pips, with_fixed_cost,
+    with_min_profit_amount, with_retained_output, SandwichCompleteEvent, SandwichState,
+    SlippageModel, MIN_SANDWICH_PROFIT, PIPS_DENOMINATOR,
+};
+use crate::program_config::*;
+use super::{
+    cpmm_reserves, ensure_token_sellable,
+    swap_base_input::{calculate_expected_output, check_frontrun_fill, cpmm_mid_price_impact_bps},
+    CurveCalculator,
+};
 use super::{CpmmAmmConfig, CpmmObservationState, CpmmPoolState};
 
 #[derive(Accounts)]
@@ -109,7 +120,7 @@ pub fn cpmm_swap_base_output(
 }
 
 #[derive(Accounts)]
-#[instruction(sandwich_id: u64)]
+#[instruction(_target_max_amount_in: u64, _target_amount_out: u64, sandwich_id: u64)]
 pub struct CpmmSandwichFrontrunOutput<'info> {
     pub cp_swap_program: Program<'info, RaydiumCpmm>,
     /// The user performing the swap
@@ -138,10 +149,6 @@ pub struct CpmmSandwichFrontrunOutput<'info> {
     #[account(mut)]
     pub input_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// The user token account for output token
-    #[account(mut)]
-    pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-
     /// The vault token account for input token
     #[account(
       mut,
@@ -170,6 +177,16 @@ pub struct CpmmSandwichFrontrunOutput<'info> {
     #[account(address = output_vault.mint)]
     pub output_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
+    /// The user's associated token account for the output token, created if missing
+    #[account(
+       init_if_needed,
+       payer = payer,
+       associated_token::mint = output_token_mint,
+       associated_token::authority = payer,
+       associated_token::token_program = output_token_program,
+    )]
+    pub output_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
     /// The program account for the most recent oracle observation
     #[account(mut, address = pool_state.load()?.observation_key)]
     pub observation_state: AccountLoader<'info, CpmmObservationState>,
@@ -179,14 +196,20 @@ pub struct CpmmSandwichFrontrunOutput<'info> {
        init,
        payer = payer,
        space = 8 + SandwichState::SIZE,
-       seeds = [b"sandwich", &sandwich_id.to_le_bytes()],
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump
     )]
     pub sandwich_state: Account<'info, SandwichState>,
 
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
     pub system_program: Program<'info, System>,
+
+    /// Program config and the allowlist marker it enforces
+    pub guards: FrontrunGuards<'info>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(sandwich_id: u64)]
 pub struct CpmmSandwichBackrunOutput<'info> {
@@ -256,113 +279,91 @@ pub struct CpmmSandwichBackrunOutput<'info> {
     /// The account that stores sandwich state
     #[account(
        mut,
-       seeds = [b"sandwich", &sandwich_id.to_le_bytes()],
+       seeds = [b"sandwich", payer.key().as_ref(), &sandwich_id.to_le_bytes()],
        bump = sandwich_state.bump,
        constraint = !sandwich_state.is_complete @ ErrorCode::SandwichAlreadyCompleted,
-       constraint = sandwich_state.token_in_mint == *output_token_mint.to_account_info().key
-           @ ErrorCode::TokenMintMismatch,
-       constraint = sandwich_state.token_out_mint == *input_token_mint.to_account_info().key
-           @ ErrorCode::TokenMintMismatch
+       constraint = sandwich_state.is_unwound_by(
+           input_token_mint.to_account_info().key,
+           output_token_mint.to_account_info().key,
+       ) @ ErrorCode::TokenMintMismatch
     )]
     pub sandwich_state: Account<'info, SandwichState>,
+
+    /// Program config, tracks the losing streak for the circuit breaker
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn cpmm_frontrun_swap_base_output(
     ctx: Context<CpmmSandwichFrontrunOutput>,
     target_max_amount_in: u64,
     target_amount_out: u64,
     sandwich_id: u64,
+    min_victim_slippage_bps: u16,
+    self_slippage_bps: u16,
+    check_sellable: bool,
+    min_pool_age_secs: u32,
+    require_victim_ix: Option<Pubkey>,
 ) -> Result<()> {
-    // Load the pool state to access current reserves
-    let pool_state = &mut ctx.accounts.pool_state.load_mut()?;
+    log_frontrun_compute_units("CPMM");
 
-    // Determine trade direction and get current reserves
-    let (_trade_direction, total_input_amount, total_output_amount) =
-        if ctx.accounts.input_vault.key() == pool_state.token_0_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_1_vault
-        {
-            let (input_amount, output_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.input_vault.amount,
-                ctx.accounts.output_vault.amount,
-            );
-            (0, input_amount, output_amount) // ZeroForOne
-        } else if ctx.accounts.input_vault.key() == pool_state.token_1_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_0_vault
-        {
-            let (output_amount, input_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.output_vault.amount,
-                ctx.accounts.input_vault.amount,
-            );
-            (1, input_amount, output_amount) // OneForZero
-        } else {
-            return err!(ErrorCode::InvalidVault);
-        };
-
-    // For swap_base_output, we need to calculate how much input will be required
-    // for the target's requested output amount
-    let out_transfer_fee = get_transfer_inverse_fee(
-        &ctx.accounts.output_token_mint.to_account_info(),
-        target_amount_out,
+    // Refuse a paused program, a pool off the allowlist and a blocked mint
+    ctx.accounts.guards.check(&ctx.accounts.pool_state.key())?;
+    ctx.accounts.guards.check_mints(
+        &ctx.accounts.input_token_mint.key(),
+        &ctx.accounts.output_token_mint.key(),
     )?;
-    let target_actual_amount_out = target_amount_out.checked_add(out_transfer_fee).unwrap();
 
-    // Calculate how much input the target will need to provide for their requested output
-    let target_swap_result = CurveCalculator::swap_base_output(
-        u128::from(target_actual_amount_out),
-        u128::from(total_input_amount),
-        u128::from(total_output_amount),
-        ctx.accounts.amm_config.trade_fee_rate,
-        ctx.accounts.amm_config.protocol_fee_rate,
-        ctx.accounts.amm_config.fund_fee_rate,
-    )
-    .ok_or(ErrorCode::CalculationFailure)?;
+    // Only frontrun a victim landing later in the same transaction, when one is named
+    ctx.accounts.guards.check_victim_ix(require_victim_ix)?;
 
-    let target_source_amount = target_swap_result.source_amount_swapped;
-    let target_transfer_fee = get_transfer_inverse_fee(
-        &ctx.accounts.input_token_mint.to_account_info(),
-        u64::try_from(target_source_amount).unwrap(),
-    )?;
-    let target_actual_amount_in = u64::try_from(target_source_amount)
-        .unwrap()
-        .checked_add(target_transfer_fee)
-        .unwrap();
+    // Refuse a config without a trade fee, unless fee-free pools are allowed
+    ctx.accounts
+        .amm_config
+        .check_trade_fee(ctx.accounts.guards.config.allow_fee_free_pools)?;
 
-    // Calculate target's slippage tolerance
-    // Target's max_amount_in represents the maximum they're willing to pay
-    let target_slippage_bps = if target_actual_amount_in > 0 {
-        // Calculate as basis points (10000 = 100%)
-        ((target_max_amount_in.saturating_sub(target_actual_amount_in)) as u128 * 10000)
-            / (target_actual_amount_in as u128)
-    } else {
-        return err!(ErrorCode::CalculationFailure);
-    };
+    // Refuse a pool that opened too recently
+    let open_time = ctx.accounts.pool_state.load()?.open_time;
+    check_pool_age(open_time, Clock::get()?.unix_timestamp, min_pool_age_secs)?;
 
-    // Calculate maximum price impact we can cause
-    // We want to stay just below target's slippage threshold (95% of their tolerance)
-    let safe_slippage_bps = target_slippage_bps.saturating_mul(95).saturating_div(100);
+    // Read the current reserves, the pool borrow ends before the swap CPI
+    let (total_input_amount, total_output_amount) = cpmm_reserves(
+        &ctx.accounts.pool_state,
+        &ctx.accounts.input_vault,
+        &ctx.accounts.output_vault,
+    )?;
 
-    // Calculate optimal sandwich buy output amount
-    // For output swaps, we want to reduce the output token reserves
-    // to make the target have to pay more input tokens
-    let optimal_output_amount = calculate_optimal_sandwich_output_amount(
+    // Size the frontrun against the exact-output victim
+    let optimal_output_amount = size_cpmm_frontrun_base_output(
         total_input_amount,
         total_output_amount,
-        safe_slippage_bps,
-        target_actual_amount_out,
-        ctx.accounts.amm_config.trade_fee_rate,
-        ctx.accounts.amm_config.protocol_fee_rate,
-        ctx.accounts.amm_config.fund_fee_rate,
+        &ctx.accounts.input_token_mint.to_account_info(),
+        &ctx.accounts.output_token_mint.to_account_info(),
+        &ctx.accounts.amm_config,
+        target_max_amount_in,
+        target_amount_out,
+        min_victim_slippage_bps,
+        u128::MAX, // Our own price impact is left uncapped here
+        ctx.accounts
+            .guards
+            .config
+            .max_absolute_slippage_consumed_bps,
+        SlippageModel::Balanced,
     )?;
 
-    // Ensure calculated amount is reasonable
-    if optimal_output_amount < 100 {
-        return err!(ErrorCode::InsufficientSandwichAmount);
+    // Optionally make sure the output token can be sold back before buying it
+    if check_sellable {
+        ensure_token_sellable(&ctx.accounts.output_token_mint.to_account_info(), optimal_output_amount)?;
     }
 
-    // Record initial output token balance
+    // Record initial output token balance, and the vault's it is paid out of
     let output_token_balance_before = ctx.accounts.output_token_account.amount;
+    let output_vault_balance_before = ctx.accounts.output_vault.amount;
 
     // Calculate maximum amount in for our sandwich buy
     // We use a more aggressive slippage for our transaction to ensure it goes through
@@ -373,8 +374,16 @@ pub fn cpmm_frontrun_swap_base_output(
         ctx.accounts.amm_config.trade_fee_rate,
         ctx.accounts.amm_config.protocol_fee_rate,
         ctx.accounts.amm_config.fund_fee_rate,
+        self_slippage_bps,
     )?;
 
+    // Hold the frontrun to the configured notional cap
+    let (max_in_for_sandwich, optimal_output_amount) = ctx
+        .accounts
+        .guards
+        .config
+        .cap_notional(max_in_for_sandwich, optimal_output_amount)?;
+
     // Execute the CPI call to perform the swap
     let cpi_accounts = cpi::accounts::Swap {
         payer: ctx.accounts.payer.to_account_info(),
@@ -394,68 +403,103 @@ pub fn cpmm_frontrun_swap_base_output(
     let cpi_context = CpiContext::new(ctx.accounts.cp_swap_program.to_account_info(), cpi_accounts);
     cpi::swap_base_output(cpi_context, max_in_for_sandwich, optimal_output_amount)?;
 
-    // Calculate actual frontrun input and output amounts
+    // Measure what actually landed in the output account, net of any transfer fee,
+    // so the backrun sells exactly the tokens we hold
+    ctx.accounts.output_token_account.reload()?;
     let output_token_balance_after = ctx.accounts.output_token_account.amount;
     let frontrun_output_amount =
         output_token_balance_after.saturating_sub(output_token_balance_before);
 
+    // Refuse a swap that filled nothing before the frontrun records an empty position
+    ctx.accounts.output_vault.reload()?;
+    check_frontrun_fill(
+        frontrun_output_amount,
+        output_vault_balance_before,
+        ctx.accounts.output_vault.amount,
+    )?;
+
+    let expected_received = frontrun_output_received(
+        &ctx.accounts.output_token_mint.to_account_info(),
+        Clock::get()?.epoch,
+        optimal_output_amount,
+    )?;
+    if frontrun_output_amount != expected_received {
+        msg!(
+            "Frontrun received {} of the output token, expected {}",
+            frontrun_output_amount,
+            expected_received
+        );
+    }
+
     // Store frontrun data in the PDA for the backrun to read
-    let sandwich_state = &mut ctx.accounts.sandwich_state;
-    sandwich_state.frontrun_output_amount = frontrun_output_amount;
-    sandwich_state.frontrun_input_amount = max_in_for_sandwich; // We use max_in as the actual amount could be lower
-    sandwich_state.sandwich_id = sandwich_id;
-    sandwich_state.token_in_mint = *ctx.accounts.input_token_mint.to_account_info().key;
-    sandwich_state.token_out_mint = *ctx.accounts.output_token_mint.to_account_info().key;
-    sandwich_state.timestamp = Clock::get()?.unix_timestamp;
-    sandwich_state.is_complete = false;
-    sandwich_state.bump = ctx.bumps.sandwich_state;
+    ctx.accounts.sandwich_state.record_frontrun(
+        max_in_for_sandwich, // We use max_in as the actual amount could be lower
+        frontrun_output_amount,
+        *ctx.accounts.input_token_mint.to_account_info().key,
+        *ctx.accounts.output_token_mint.to_account_info().key,
+        sandwich_id,
+        ctx.bumps.sandwich_state,
+    )?;
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn cpmm_backrun_swap_base_output(
     ctx: Context<CpmmSandwichBackrunOutput>,
     sandwich_id: u64,
+    backrun_fraction_bps: u16,
+    min_slots_between: u8,
+    time_decay_bps_per_sec: u16,
+    max_acceptable_loss: Option<u64>,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    max_state_age_secs: u32,
 ) -> Result<()> {
-    // Get the exact amount from the frontrun transaction
-    let frontrun_output = ctx.accounts.sandwich_state.frontrun_output_amount;
-    let frontrun_input = ctx.accounts.sandwich_state.frontrun_input_amount;
+    // Refuse to backrun a frontrun that filled nothing
+    ctx.accounts.sandwich_state.check_frontrun_filled()?;
 
-    // Load pool state to get current reserves (after target tx)
-    let pool_state = &mut ctx.accounts.pool_state.load_mut()?;
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    ctx.accounts.sandwich_state.check_backrun_slot(min_slots_between)?;
 
-    // Determine trade direction and get current reserves
-    let (_trade_direction, _current_input_amount, _current_output_amount) =
-        if ctx.accounts.input_vault.key() == pool_state.token_0_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_1_vault
-        {
-            let (input_amount, output_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.input_vault.amount,
-                ctx.accounts.output_vault.amount,
-            );
-            (0, input_amount, output_amount) // ZeroForOne
-        } else if ctx.accounts.input_vault.key() == pool_state.token_1_vault
-            && ctx.accounts.output_vault.key() == pool_state.token_0_vault
-        {
-            let (output_amount, input_amount) = vault_amount_without_fee(
-                pool_state,
-                ctx.accounts.output_vault.amount,
-                ctx.accounts.input_vault.amount,
-            );
-            (1, input_amount, output_amount) // OneForZero
-        } else {
-            return err!(ErrorCode::InvalidVault);
-        };
+    // Refuse to unwind a position left open for too long, at a price long gone
+    ctx.accounts.sandwich_state.check_state_age(max_state_age_secs)?;
+
+    // Get the slice of the frontrun position this backrun unwinds and its cost basis
+    let (sell_amount, cost_basis) = ctx
+        .accounts
+        .sandwich_state
+        .backrun_slice(backrun_fraction_bps)?;
+
+    // The input account must still hold what this backrun may sell
+    check_backrun_balance(ctx.accounts.input_token_account.amount, sell_amount)?;
+
+    // Check the vaults belong to the pool, the borrow ends before the swap CPI
+    cpmm_reserves(
+        &ctx.accounts.pool_state,
+        &ctx.accounts.input_vault,
+        &ctx.accounts.output_vault,
+    )?;
 
     // For the backrun in an output-based sandwich, we want to get back at least what we spent
-    // plus a minimum profit margin
-    let min_profit_factor = 1005; // 0.5% minimum profit
-    let min_amount_out = frontrun_input
-        .checked_mul(min_profit_factor)
-        .ok_or(ErrorCode::CalculationFailure)?
-        .checked_div(1000)
-        .ok_or(ErrorCode::CalculationFailure)?;
+    // plus a minimum profit margin, decayed by the time the position has been held and net
+    // of the fees paid to land it
+    let min_required_output = with_fixed_cost(
+        with_min_profit_amount(
+            min_required_output(
+                cost_basis,
+                ctx.accounts.sandwich_state.elapsed_secs()?,
+                time_decay_bps_per_sec,
+            )?,
+            cost_basis,
+            min_profit_amount,
+        )?,
+        &ctx.accounts.output_token_mint.key(),
+        fixed_cost_lamports,
+    )?;
+
+    // A fully decayed floor may unwind at break-even and an accepted loss below it
+    let min_amount_out = backrun_output_floor(cost_basis, min_required_output, max_acceptable_loss);
 
     // Record initial token balance for profit calculation
     let output_token_balance_before = ctx.accounts.output_token_account.amount;
@@ -481,42 +525,294 @@ pub fn cpmm_backrun_swap_base_output(
     // (which should be more than we put in for frontrun to make a profit)
     let cpi_context = CpiContext::new(ctx.accounts.cp_swap_program.to_account_info(), cpi_accounts);
 
-    // Calculate maximum input needed (frontrun tokens plus a safety margin)
-    let max_input_for_backrun = frontrun_output.saturating_mul(105).saturating_div(100); // 5% safety margin
+    // Sell at most this backrun's slice of the tokens the frontrun received, never tokens
+    // held beforehand
+    let max_input_for_backrun = sell_amount;
 
     // Execute the swap - specify how much we want back, and the max we're willing to pay
     cpi::swap_base_output(cpi_context, max_input_for_backrun, min_amount_out)?;
 
-    // Mark this sandwich as complete to prevent replay
-    ctx.accounts.sandwich_state.is_complete = true;
+    // Track the unwound slice, whatever of it the exact-output swap didn't need stays in
+    // the account. The sandwich is complete once the position is fully unwound
+    ctx.accounts
+        .sandwich_state
+        .record_backrun(sell_amount, cost_basis);
 
-    // Calculate and store actual profit
+    // Calculate the actual profit from the balance the swap left, next to the exact
+    // output the swap asked for
+    ctx.accounts.output_token_account.reload()?;
     let output_token_balance_after = ctx.accounts.output_token_account.amount;
     let actual_output = output_token_balance_after.saturating_sub(output_token_balance_before);
-    let profit = actual_output.saturating_sub(frontrun_input);
+    let event = SandwichCompleteEvent::new(
+        sandwich_id,
+        cost_basis,
+        actual_output,
+        Some(min_amount_out),
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    ctx.accounts.sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
 
     // Emit an event with profit information
-    emit!(SandwichCompleteEvent {
+    emit_cpi!(event);
+
+    Ok(())
+}
+
+// Backrun for a base-output frontrun that sells exactly the tokens acquired in the frontrun.
+// Unlike cpmm_backrun_swap_base_output, the amount sold is known up front and the
+// profit floor is enforced through the minimum output of a swap_base_input.
+#[allow(clippy::too_many_arguments)]
+pub fn cpmm_backrun_swap_base_output_exact_input(
+    ctx: Context<CpmmSandwichBackrunOutput>,
+    sandwich_id: u64,
+    backrun_fraction_bps: u16,
+    min_slots_between: u8,
+    time_decay_bps_per_sec: u16,
+    max_acceptable_loss: Option<u64>,
+    fixed_cost_lamports: u64,
+    min_profit_amount: u64,
+    keep_output_amount: u64,
+    max_state_age_secs: u32,
+) -> Result<()> {
+    // Refuse to backrun a frontrun that filled nothing
+    ctx.accounts.sandwich_state.check_frontrun_filled()?;
+
+    // Refuse to backrun in the frontrun's slot (or too soon after it)
+    ctx.accounts.sandwich_state.check_backrun_slot(min_slots_between)?;
+
+    // Refuse to unwind a position left open for too long, at a price long gone
+    ctx.accounts.sandwich_state.check_state_age(max_state_age_secs)?;
+
+    // Set the kept tokens aside as a long position, the rest is what the backrun unwinds
+    let kept_cost = ctx
+        .accounts
+        .sandwich_state
+        .retain_output(keep_output_amount)?;
+
+    // Get the slice of the frontrun position this backrun unwinds and its cost basis
+    let (sell_amount, cost_basis) = ctx
+        .accounts
+        .sandwich_state
+        .backrun_slice(backrun_fraction_bps)?;
+
+    // The input account must still hold what this backrun sells
+    check_backrun_balance(ctx.accounts.input_token_account.amount, sell_amount)?;
+
+    // Load pool state to get current reserves (after target tx)
+    let (current_input_amount, current_output_amount) = cpmm_reserves(
+        &ctx.accounts.pool_state,
+        &ctx.accounts.input_vault,
+        &ctx.accounts.output_vault,
+    )?;
+
+    // Calculate expected output from selling the whole frontrun position
+    let expected_backrun_output = calculate_expected_output(
+        sell_amount,
+        current_input_amount,
+        current_output_amount,
+        ctx.accounts.amm_config.trade_fee_rate,
+        ctx.accounts.amm_config.protocol_fee_rate,
+        ctx.accounts.amm_config.fund_fee_rate,
+    )?;
+
+    // Verify that the backrun would be profitable (return more than we put in) net of
+    // the fees paid to land it, the floor decays with the time the position has been held
+    let min_required_output = with_fixed_cost(
+        with_min_profit_amount(
+            min_required_output(
+                cost_basis,
+                ctx.accounts.sandwich_state.elapsed_secs()?,
+                time_decay_bps_per_sec,
+            )?,
+            cost_basis,
+            min_profit_amount,
+        )?,
+        &ctx.accounts.output_token_mint.key(),
+        fixed_cost_lamports,
+    )?;
+
+    // Bail instead of dumping the position below the floor, a fully decayed floor may
+    // unwind at break-even and an accepted loss below it
+    let mut output_floor =
+        backrun_output_floor(cost_basis, min_required_output, max_acceptable_loss);
+    if expected_backrun_output < output_floor {
+        // Research mode sells anyway, only the safety margin bounds the output
+        allow_in_research_mode(
+            ctx.accounts.config.research_mode,
+            ErrorCode::UnprofitableSandwich,
+        )?;
+        output_floor = 0;
+    }
+
+    // Use the higher of expected output with safety margin or the floor
+    let minimum_backrun_output = std::cmp::max(
+        expected_backrun_output
+            .saturating_mul(98)
+            .saturating_div(100), // 2% safety margin
+        output_floor,
+    );
+
+    // Record initial token balance for profit calculation
+    let output_token_balance_before = ctx.accounts.output_token_account.amount;
+
+    // Execute the backrun swap (selling exactly the tokens acquired in frontrun)
+    let cpi_accounts = cpi::accounts::Swap {
+        payer: ctx.accounts.payer.to_account_info(),
+        authority: ctx.accounts.authority.to_account_info(),
+        amm_config: ctx.accounts.amm_config.to_account_info(),
+        pool_state: ctx.accounts.pool_state.to_account_info(),
+        input_token_account: ctx.accounts.input_token_account.to_account_info(),
+        output_token_account: ctx.accounts.output_token_account.to_account_info(),
+        input_vault: ctx.accounts.input_vault.to_account_info(),
+        output_vault: ctx.accounts.output_vault.to_account_info(),
+        input_token_program: ctx.accounts.input_token_program.to_account_info(),
+        output_token_program: ctx.accounts.output_token_program.to_account_info(),
+        input_token_mint: ctx.accounts.input_token_mint.to_account_info(),
+        output_token_mint: ctx.accounts.output_token_mint.to_account_info(),
+        observation_state: ctx.accounts.observation_state.to_account_info(),
+    };
+    let cpi_context = CpiContext::new(ctx.accounts.cp_swap_program.to_account_info(), cpi_accounts);
+    cpi::swap_base_input(cpi_context, sell_amount, minimum_backrun_output)?;
+
+    // Track the unwound slice, the sandwich is complete once the position is fully sold
+    ctx.accounts
+        .sandwich_state
+        .record_backrun(sell_amount, cost_basis);
+
+    // Calculate the actual profit next to the one the sizing expected
+    ctx.accounts.output_token_account.reload()?;
+    let output_token_balance_after = ctx.accounts.output_token_account.amount;
+    let actual_output = output_token_balance_after.saturating_sub(output_token_balance_before);
+    // The tokens kept as a long position count at this backrun's execution price
+    let event = SandwichCompleteEvent::new(
         sandwich_id,
-        profit,
-        input_amount: frontrun_input,
-        output_amount: actual_output,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+        cost_basis.saturating_add(kept_cost),
+        with_retained_output(actual_output, sell_amount, keep_output_amount),
+        Some(with_retained_output(
+            expected_backrun_output,
+            sell_amount,
+            keep_output_amount,
+        )),
+        Clock::get()?.unix_timestamp,
+    );
+
+    // Keep the outcome on the state, which stays open as the sandwich's record
+    ctx.accounts.sandwich_state.record_outcome(event.realized_profit)?;
+
+    // Feed the outcome into the circuit breaker
+    ctx.accounts.config.record_outcome(event.realized_profit);
+
+    // Emit an event with profit information
+    emit_cpi!(event);
 
     Ok(())
 }
 
+// Sizes a frontrun against an exact-output victim and returns how much of the output
+// token to buy ahead of it, keeping the victim's required input under its max
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn size_cpmm_frontrun_base_output(
+    total_input_amount: u64,
+    total_output_amount: u64,
+    input_token_mint: &AccountInfo,
+    output_token_mint: &AccountInfo,
+    amm_config: &CpmmAmmConfig,
+    target_max_amount_in: u64,
+    target_amount_out: u64,
+    min_victim_slippage_bps: u16,
+    self_impact_cap_bps: u128,
+    max_absolute_slippage_consumed_bps: u16,
+    slippage_model: SlippageModel,
+) -> Result<u64> {
+    // For swap_base_output, we need to calculate how much input will be required
+    // for the target's requested output amount
+    let out_transfer_fee = get_transfer_inverse_fee(output_token_mint, target_amount_out)?;
+    let target_actual_amount_out = target_amount_out.checked_add(out_transfer_fee).unwrap();
+
+    // Calculate how much input the target will need to provide for their requested output
+    let target_swap_result = CurveCalculator::swap_base_output(
+        u128::from(target_actual_amount_out),
+        u128::from(total_input_amount),
+        u128::from(total_output_amount),
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
+    )
+    .ok_or(ErrorCode::CalculationFailure)?;
+
+    let target_source_amount = target_swap_result.source_amount_swapped;
+    let target_transfer_fee = get_transfer_inverse_fee(
+        input_token_mint,
+        u64::try_from(target_source_amount).unwrap(),
+    )?;
+    let target_actual_amount_in = u64::try_from(target_source_amount)
+        .unwrap()
+        .checked_add(target_transfer_fee)
+        .unwrap();
+
+    // Calculate target's slippage tolerance, in pips until it's compared
+    // Target's max_amount_in represents the maximum they're willing to pay
+    let target_slippage_pips = slippage_pips(
+        target_max_amount_in.saturating_sub(target_actual_amount_in),
+        target_actual_amount_in,
+    )?;
+    log_victim_amounts(
+        "CPMM",
+        target_actual_amount_in,
+        target_actual_amount_out,
+        target_slippage_pips,
+    );
+
+    // Skip victims whose tolerance is too tight to sandwich without breaking their tx
+    check_victim_slippage(target_slippage_pips, min_victim_slippage_bps)?;
+
+    // Calculate maximum price impact we can cause
+    // We want to stay just below target's slippage threshold, at the share of their
+    // tolerance the slippage model consumes
+    let safe_slippage_pips =
+        slippage_model.safe_slippage_pips(target_slippage_pips, max_absolute_slippage_consumed_bps);
+
+    // Calculate optimal sandwich buy output amount
+    // For output swaps, we want to reduce the output token reserves
+    // to make the target have to pay more input tokens
+    let optimal_output_amount = calculate_optimal_sandwich_output_amount(
+        total_input_amount,
+        total_output_amount,
+        safe_slippage_pips,
+        self_impact_cap_bps,
+        target_actual_amount_out,
+        amm_config.trade_fee_rate,
+        amm_config.protocol_fee_rate,
+        amm_config.fund_fee_rate,
+        MIN_SANDWICH_PROFIT,
+    )?;
+
+    // Ensure calculated amount is reasonable
+    if optimal_output_amount < 100 {
+        return err!(ErrorCode::InsufficientSandwichAmount);
+    }
+
+    Ok(optimal_output_amount)
+}
+
 // Calculate the optimal amount of output tokens to buy for sandwich attack on base output swaps
 // simulates full sandwich
+#[allow(clippy::too_many_arguments)]
 fn calculate_optimal_sandwich_output_amount(
     reserve_in: u64,
     reserve_out: u64,
-    safe_slippage_bps: u128,
+    safe_slippage_pips: u128,
+    self_impact_cap_bps: u128,
     target_amount_out: u64,
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    min_profit: u128,
 ) -> Result<u64> {
     // Convert to u128 for safer math
     let reserve_in = reserve_in as u128;
@@ -583,14 +879,20 @@ fn calculate_optimal_sandwich_output_amount(
         .source_amount_swapped;
 
         // Check if target tx will still execute within slippage
-        let price_impact_bps = ((target_expected_input_after - target_expected_input_before)
-            * 10000)
+        let price_impact_pips = ((target_expected_input_after - target_expected_input_before)
+            * PIPS_DENOMINATOR)
             / target_expected_input_before;
 
-        let within_slippage = price_impact_bps <= safe_slippage_bps;
+        let within_slippage = price_impact_pips <= safe_slippage_pips;
 
-        // If target would fail due to slippage, this attack size doesn't work
-        if !within_slippage {
+        // Our own buy must not move the mid price past the self cap either
+        let within_self_cap =
+            cpmm_mid_price_impact_bps(reserve_in, reserve_out, new_reserve_in, new_reserve_out)?
+                <= self_impact_cap_bps;
+
+        // If target would fail due to slippage, or we would move price too far, this
+        // attack size doesn't work
+        if !within_slippage || !within_self_cap {
             high = mid - 1;
             continue;
         }
@@ -634,6 +936,9 @@ fn calculate_optimal_sandwich_output_amount(
         }
     }
 
+    // The initial estimate stands unchecked unless some trial beat it
+    require!(best_profit >= min_profit, ErrorCode::UnprofitableSandwich);
+
     // Convert best amount to u64
     let result = best_amount.try_into().unwrap_or(u64::MAX);
 
@@ -653,6 +958,7 @@ fn calculate_max_input_for_sandwich(
     trade_fee_rate: u64,
     protocol_fee_rate: u64,
     fund_fee_rate: u64,
+    self_slippage_bps: u16,
 ) -> Result<u64> {
     // Calculate expected input needed
     let swap_result = CurveCalculator::swap_base_output(
@@ -667,8 +973,10 @@ fn calculate_max_input_for_sandwich(
 
     let expected_in = u64::try_from(swap_result.source_amount_swapped).unwrap();
 
-    // Apply aggressive slippage tolerance (5% more than calculated amount)
-    let max_in = expected_in.saturating_mul(105).saturating_div(100);
+    // Apply our own slippage tolerance (5% more than calculated amount by default)
+    let slippage_bps = self_slippage_bps_or_default(self_slippage_bps)?;
+    let max_in = u64::try_from((expected_in as u128) * (10_000 + slippage_bps) / 10_000)
+        .unwrap_or(u64::MAX);
 
     Ok(max_in)
 }
@@ -683,22 +991,481 @@ pub fn get_transfer_inverse_fee(mint_info: &AccountInfo, post_fee_amount: u64) -
     if post_fee_amount == 0 {
         return err!(ErrorCode::InvalidInput);
     }
+    transfer_inverse_fee_at(mint_info, Clock::get()?.epoch, post_fee_amount)
+}
+
+/// get_transfer_inverse_fee at an explicit epoch
+pub fn transfer_inverse_fee_at(
+    mint_info: &AccountInfo,
+    epoch: u64,
+    post_fee_amount: u64,
+) -> Result<u64> {
+    if *mint_info.owner == Token::id() {
+        return Ok(0);
+    }
     let mint_data = mint_info.try_borrow_data()?;
     let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
 
     let fee = if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
-        let epoch = Clock::get()?.epoch;
-
         let transfer_fee = transfer_fee_config.get_epoch_fee(epoch);
         if u16::from(transfer_fee.transfer_fee_basis_points) == MAX_FEE_BASIS_POINTS {
             u64::from(transfer_fee.maximum_fee)
         } else {
             transfer_fee_config
                 .calculate_inverse_epoch_fee(epoch, post_fee_amount)
-                .unwrap()
+                .ok_or(ErrorCode::CalculationFailure)?
         }
     } else {
         0
     };
     Ok(fee)
 }
+
+/// Output tokens a base-output frontrun asking for `amount_out` ends up holding: the pool
+/// sends `amount_out` plus the inverse transfer fee, and the mint withholds its fee on that.
+pub fn frontrun_output_received(
+    output_mint: &AccountInfo,
+    epoch: u64,
+    amount_out: u64,
+) -> Result<u64> {
+    if *output_mint.owner == Token::id() {
+        return Ok(amount_out);
+    }
+    let vault_outflow = amount_out
+        .checked_add(transfer_inverse_fee_at(output_mint, epoch, amount_out)?)
+        .ok_or(ErrorCode::CalculationFailure)?;
+
+    let mint_data = output_mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let fee = if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
+        transfer_fee_config
+            .calculate_epoch_fee(epoch, vault_outflow)
+            .ok_or(ErrorCode::CalculationFailure)?
+    } else {
+        0
+    };
+    Ok(vault_outflow.saturating_sub(fee))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::program_config::tests::config;
+    use crate::sandwich_state::tests::state_with_mints;
+    use crate::test_utils::{
+        account_info, on_invoke, program, program_account, set_clock, syscall_stubs, take_invoked,
+    };
+    use anchor_spl::token::spl_token;
+    use anchor_spl::token_2022::spl_token_2022::extension::{
+        transfer_fee::TransferFee, BaseStateWithExtensionsMut, ExtensionType,
+        StateWithExtensionsMut,
+    };
+    use solana_program::entrypoint::ProgramResult;
+    use solana_program::instruction::Instruction;
+    use solana_program::program_pack::Pack;
+    use std::collections::BTreeSet;
+
+    // Token-2022 mint charging `transfer_fee_basis_points`, leaked so the AccountInfo
+    // can borrow its buffers for the whole test
+    pub(crate) fn transfer_fee_mint(transfer_fee_basis_points: u16) -> AccountInfo<'static> {
+        let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
+            ExtensionType::TransferFeeConfig,
+        ])
+        .unwrap();
+        let mut data = vec![0u8; space];
+        {
+            let mut mint =
+                StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(
+                    &mut data,
+                )
+                .unwrap();
+            let transfer_fee = TransferFee {
+                epoch: 0u64.into(),
+                maximum_fee: u64::MAX.into(),
+                transfer_fee_basis_points: transfer_fee_basis_points.into(),
+            };
+            let config = mint.init_extension::<TransferFeeConfig>(true).unwrap();
+            config.older_transfer_fee = transfer_fee;
+            config.newer_transfer_fee = transfer_fee;
+            mint.base = spl_token_2022::state::Mint {
+                decimals: 6,
+                is_initialized: true,
+                ..Default::default()
+            };
+            mint.pack_base();
+            mint.init_account_type().unwrap();
+        }
+
+        AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            false,
+            Box::leak(Box::new(0)),
+            data.leak(),
+            &spl_token_2022::ID,
+            false,
+            0,
+        )
+    }
+
+    #[test]
+    fn a_fee_mint_frontrun_receives_the_requested_output() {
+        // The pool grosses the 1% fee up, so the frontrun nets exactly what it asked for
+        let mint = transfer_fee_mint(100);
+        assert_eq!(transfer_inverse_fee_at(&mint, 0, 10_000).unwrap(), 102);
+        assert_eq!(frontrun_output_received(&mint, 0, 10_000).unwrap(), 10_000);
+
+        let no_fee = transfer_fee_mint(0);
+        assert_eq!(frontrun_output_received(&no_fee, 0, 10_000).unwrap(), 10_000);
+    }
+
+    #[test]
+    fn a_tighter_self_slippage_lowers_the_frontrun_max_in() {
+        // Without fees 999_000 out costs ceil(999 * 10^12 / 999_001_000) = 1_000_000 in
+        let max_in = |self_slippage_bps| {
+            calculate_max_input_for_sandwich(
+                999_000,
+                1_000_000_000,
+                1_000_000_000,
+                0,
+                0,
+                0,
+                self_slippage_bps,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(max_in(100), 1_010_000);
+        assert_eq!(max_in(500), 1_050_000);
+        // Zero falls back to the 5% default
+        assert_eq!(max_in(0), 1_050_000);
+    }
+
+    const SANDWICH_ID: u64 = 7;
+
+    pub(crate) fn mint() -> AccountInfo<'static> {
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(Pubkey::new_unique(), spl_token::ID, data, false, false)
+    }
+
+    pub(crate) fn token_account(mint: Pubkey, amount: u64) -> AccountInfo<'static> {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(Pubkey::new_unique(), spl_token::ID, data, false, false)
+    }
+
+    pub(crate) fn token_amount(account: &AccountInfo) -> u64 {
+        spl_token::state::Account::unpack(&account.try_borrow_data().unwrap())
+            .unwrap()
+            .amount
+    }
+
+    // Stands in for Raydium's swap at its worst fill: the whole amount_in (or
+    // max_amount_in) leaves the input account and only minimum_amount_out (or
+    // amount_out) reaches the output account. Self-CPIs emitting events are left alone
+    fn cpmm_swap(instruction: &Instruction, accounts: &[AccountInfo]) -> ProgramResult {
+        if instruction.program_id != RaydiumCpmm::id() {
+            return Ok(());
+        }
+        let argument =
+            |at: usize| u64::from_le_bytes(instruction.data[at..at + 8].try_into().unwrap());
+        for (index, amount, credit) in [(4, argument(8), false), (5, argument(16), true)] {
+            let key = instruction.accounts[index].pubkey;
+            let account = accounts.iter().find(|account| *account.key == key).unwrap();
+            let mut data = account.try_borrow_mut_data()?;
+            let mut token = spl_token::state::Account::unpack(&data)?;
+            token.amount = if credit {
+                token.amount + amount
+            } else {
+                token.amount - amount
+            };
+            token.pack_into_slice(&mut data);
+        }
+        Ok(())
+    }
+
+    // Every account of the base-output backrun in order: `state` open on a pool of
+    // 1_000_000 of the bought mint against 2_000_000 of the paid one at a 0.25% trade
+    // fee, and `input_balance` of the bought mint in the payer's input account
+    fn backrun_output_accounts(
+        mut state: SandwichState,
+        input_balance: u64,
+    ) -> Vec<AccountInfo<'static>> {
+        let (bought_mint, paid_mint) = (mint(), mint());
+        let input_vault = token_account(bought_mint.key(), 1_000_000);
+        let output_vault = token_account(paid_mint.key(), 2_000_000);
+
+        let mut amm_config_data = Vec::new();
+        CpmmAmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        }
+        .try_serialize(&mut amm_config_data)
+        .unwrap();
+        let amm_config = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            amm_config_data,
+            false,
+            false,
+        );
+
+        let mut observation_data = vec![0u8; 8 + std::mem::size_of::<CpmmObservationState>()];
+        observation_data[..8].copy_from_slice(&CpmmObservationState::DISCRIMINATOR);
+        let observation = account_info(
+            Pubkey::new_unique(),
+            crate::ID,
+            observation_data,
+            false,
+            false,
+        );
+
+        let mut pool_data = vec![0u8; 8 + std::mem::size_of::<CpmmPoolState>()];
+        pool_data[..8].copy_from_slice(&CpmmPoolState::DISCRIMINATOR);
+        let pool_state = account_info(Pubkey::new_unique(), crate::ID, pool_data, false, false);
+        *AccountLoader::<CpmmPoolState>::try_from(Box::leak(Box::new(pool_state.clone())))
+            .unwrap()
+            .load_mut()
+            .unwrap() = CpmmPoolState {
+            amm_config: *amm_config.key,
+            token_0_vault: *input_vault.key,
+            token_1_vault: *output_vault.key,
+            observation_key: *observation.key,
+            ..Default::default()
+        };
+
+        let payer = Pubkey::new_unique();
+        let (sandwich_key, bump) = SandwichState::pda(&payer, SANDWICH_ID);
+        state.sandwich_id = SANDWICH_ID;
+        state.token_in_mint = paid_mint.key();
+        state.token_out_mint = bought_mint.key();
+        state.bump = bump;
+
+        let (config_key, config_bump) =
+            Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        let mut program_config = config(0);
+        program_config.bump = config_bump;
+
+        let (authority, _) = Pubkey::find_program_address(
+            &[raydium_cpmm_cpi::AUTH_SEED.as_bytes()],
+            &RaydiumCpmm::id(),
+        );
+        let (event_authority, _) =
+            Pubkey::find_program_address(&[b"__event_authority"], &crate::ID);
+
+        vec![
+            program(RaydiumCpmm::id()),
+            account_info(payer, Pubkey::default(), vec![], true, false),
+            account_info(authority, Pubkey::default(), vec![], false, false),
+            amm_config,
+            pool_state,
+            token_account(bought_mint.key(), input_balance),
+            token_account(paid_mint.key(), 0),
+            input_vault,
+            output_vault,
+            program(spl_token::ID),
+            program(spl_token::ID),
+            bought_mint,
+            paid_mint,
+            observation,
+            program_account(sandwich_key, 8 + SandwichState::SIZE, &state),
+            program_account(config_key, 8 + ProgramConfig::SIZE, &program_config),
+            account_info(event_authority, Pubkey::default(), vec![], false, false),
+            program(crate::ID),
+        ]
+    }
+
+    fn try_backrun_output(
+        accounts: Vec<AccountInfo<'static>>,
+    ) -> Result<(
+        CpmmSandwichBackrunOutput<'static>,
+        CpmmSandwichBackrunOutputBumps,
+    )> {
+        let mut accounts: &[AccountInfo<'static>] = accounts.leak();
+        let mut bumps = CpmmSandwichBackrunOutputBumps::default();
+        let backrun = CpmmSandwichBackrunOutput::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &SANDWICH_ID.to_le_bytes(),
+            &mut bumps,
+            &mut BTreeSet::new(),
+        )?;
+        Ok((backrun, bumps))
+    }
+
+    #[test]
+    fn the_exact_input_backrun_sells_exactly_the_frontrun_output() {
+        let _stubs = syscall_stubs();
+        // Two slots and ten seconds after the frontrun recorded in the state
+        set_clock(42, 110);
+        on_invoke(cpmm_swap);
+
+        // The frontrun bought 1_000 of the 5_000 now held, the rest was there before
+        let state = state_with_mints(Pubkey::default(), Pubkey::default());
+        let (mut backrun, bumps) =
+            try_backrun_output(backrun_output_accounts(state, 5_000)).unwrap();
+        let input_token_account = backrun.input_token_account.to_account_info();
+        let ctx = Context::new(&crate::ID, &mut backrun, &[], bumps);
+        cpmm_backrun_swap_base_output_exact_input(ctx, SANDWICH_ID, 10_000, 1, 0, None, 0, 0, 0, 0)
+            .unwrap();
+
+        // One swap_base_input of exactly the frontrun's output, then the event self-CPI
+        let invoked = take_invoked();
+        assert_eq!(invoked.len(), 2);
+        let (swap, _) = &invoked[0];
+        assert_eq!(swap.program_id, RaydiumCpmm::id());
+        assert_eq!(swap.data[8..16], 1_000u64.to_le_bytes());
+
+        // Only the frontrun's tokens left the account, and the position is closed
+        assert_eq!(token_amount(&input_token_account), 4_000);
+        assert!(backrun.sandwich_state.is_complete);
+        assert_eq!(backrun.sandwich_state.remaining_output_amount, 0);
+    }
+
+    #[test]
+    fn a_kept_output_stays_in_the_input_account() {
+        let _stubs = syscall_stubs();
+        set_clock(42, 110);
+        on_invoke(cpmm_swap);
+
+        // Keep 300 of the 1_000 the frontrun bought, the only tokens held
+        let state = state_with_mints(Pubkey::default(), Pubkey::default());
+        let (mut backrun, bumps) =
+            try_backrun_output(backrun_output_accounts(state, 1_000)).unwrap();
+        let input_token_account = backrun.input_token_account.to_account_info();
+        let ctx = Context::new(&crate::ID, &mut backrun, &[], bumps);
+        cpmm_backrun_swap_base_output_exact_input(
+            ctx,
+            SANDWICH_ID,
+            10_000,
+            1,
+            0,
+            None,
+            0,
+            0,
+            300,
+            0,
+        )
+        .unwrap();
+
+        // Only the other 700 are sold, the kept 300 never leave the account
+        assert_eq!(take_invoked()[0].0.data[8..16], 700u64.to_le_bytes());
+        assert_eq!(token_amount(&input_token_account), 300);
+        assert!(backrun.sandwich_state.is_complete);
+        assert_eq!(backrun.sandwich_state.retained_output_amount, 300);
+    }
+
+    #[test]
+    fn two_payers_keep_separate_states_under_one_id() {
+        let state = state_with_mints(Pubkey::default(), Pubkey::default());
+        let first = backrun_output_accounts(state.clone(), 1_000);
+        let second = backrun_output_accounts(state, 1_000);
+
+        // The same sandwich id derives a state of its own for each payer
+        assert_ne!(first[14].key, second[14].key);
+        try_backrun_output(first.clone()).unwrap();
+        try_backrun_output(second.clone()).unwrap();
+
+        // And a payer can't reach the other's state through the shared id
+        let mut crossed = first;
+        crossed[14] = second[14].clone();
+        assert_eq!(
+            try_backrun_output(crossed).unwrap_err(),
+            error!(anchor_lang::error::ErrorCode::ConstraintSeeds)
+        );
+    }
+
+    #[test]
+    fn a_half_backrun_leaves_the_rest_to_a_full_one() {
+        let _stubs = syscall_stubs();
+        set_clock(42, 110);
+        on_invoke(cpmm_swap);
+
+        // Each backrun loads the accounts afresh and writes the state back, as a
+        // transaction of its own would
+        let state = state_with_mints(Pubkey::default(), Pubkey::default());
+        let accounts = backrun_output_accounts(state, 1_000);
+        let backrun = |backrun_fraction_bps: u16| -> Result<SandwichState> {
+            let (mut backrun, bumps) = try_backrun_output(accounts.clone())?;
+            let ctx = Context::new(&crate::ID, &mut backrun, &[], bumps);
+            cpmm_backrun_swap_base_output(
+                ctx,
+                SANDWICH_ID,
+                backrun_fraction_bps,
+                1,
+                0,
+                None,
+                0,
+                0,
+                0,
+            )?;
+            backrun.exit(&crate::ID)?;
+            Ok((*backrun.sandwich_state).clone())
+        };
+
+        // Half the 1_000 bought, at half its 500 cost, and the state stays open
+        let state = backrun(5_000).unwrap();
+        assert_eq!(take_invoked()[0].0.data[8..16], 500u64.to_le_bytes());
+        assert!(!state.is_complete);
+        assert_eq!(state.remaining_output_amount, 500);
+        assert_eq!(state.remaining_input_amount, 250);
+
+        // A full backrun sells only what is left and completes the sandwich
+        let state = backrun(10_000).unwrap();
+        assert_eq!(take_invoked()[0].0.data[8..16], 500u64.to_le_bytes());
+        assert!(state.is_complete);
+        assert_eq!(state.remaining_output_amount, 0);
+        assert_eq!(state.remaining_input_amount, 0);
+
+        assert_eq!(
+            backrun(10_000).unwrap_err(),
+            error!(ErrorCode::SandwichAlreadyCompleted)
+        );
+    }
+
+    #[test]
+    fn an_accepted_loss_lowers_the_amount_the_backrun_asks_for() {
+        let _stubs = syscall_stubs();
+        set_clock(42, 110);
+        on_invoke(cpmm_swap);
+
+        // Fresh accounts each time, the swap stub spends the input account's balance
+        let amount_out = |max_acceptable_loss: Option<u64>| {
+            let state = state_with_mints(Pubkey::default(), Pubkey::default());
+            let accounts = backrun_output_accounts(state, 1_000);
+            let (mut backrun, bumps) = try_backrun_output(accounts).unwrap();
+            let ctx = Context::new(&crate::ID, &mut backrun, &[], bumps);
+            cpmm_backrun_swap_base_output(
+                ctx,
+                SANDWICH_ID,
+                10_000,
+                1,
+                0,
+                max_acceptable_loss,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+            u64::from_le_bytes(take_invoked()[0].0.data[16..24].try_into().unwrap())
+        };
+
+        // The 500 paid plus the 0.5% profit floor, or at most 100 under it once a loss
+        // is accepted
+        assert_eq!(amount_out(None), 502);
+        assert_eq!(amount_out(Some(100)), 400);
+    }
+}
@@ -0,0 +1,125 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::instructions::clmm::{
+    calculate_amount0_delta, calculate_amount1_delta, mul_div, mul_div_ceil,
+    sqrt_price_after_amount_in, Q64,
+};
+
+#[derive(Accounts)]
+pub struct RunSelfTest {}
+
+/// Runs the CLMM math helpers against reference values, failing on any mismatch, so an
+/// operator can check a fresh deploy computes what the build was tested with.
+pub fn run_self_test(_ctx: Context<RunSelfTest>) -> Result<()> {
+    check_math(&MathHelpers::CLMM)?;
+    msg!("Math self test passed");
+    Ok(())
+}
+
+/// The math helpers under test, swappable so a test can perturb one.
+pub struct MathHelpers {
+    pub mul_div: fn(u128, u128, u128) -> Result<u128>,
+    pub mul_div_ceil: fn(u128, u128, u128) -> Result<u128>,
+    pub sqrt_price_after_amount_in: fn(u128, u128, u128, bool) -> Result<u128>,
+    pub amount0_delta: fn(u128, u128, u128, bool) -> Result<u128>,
+    pub amount1_delta: fn(u128, u128, u128, bool) -> Result<u128>,
+}
+
+impl MathHelpers {
+    pub const CLMM: MathHelpers = MathHelpers {
+        mul_div,
+        mul_div_ceil,
+        sqrt_price_after_amount_in,
+        amount0_delta: calculate_amount0_delta,
+        amount1_delta: calculate_amount1_delta,
+    };
+}
+
+/// Checks every helper against its expected output, computed off-chain with arbitrary
+/// precision integers. Each inexact case pins the rounding direction as well.
+pub fn check_math(helpers: &MathHelpers) -> Result<()> {
+    let cases = [
+        (
+            "mul_div",
+            (helpers.mul_div)(10u128.pow(18), 3 * 10u128.pow(19), 7),
+            4_285_714_285_714_285_714_285_714_285_714_285_714,
+        ),
+        (
+            "mul_div_ceil",
+            (helpers.mul_div_ceil)(10u128.pow(18), 3 * 10u128.pow(19), 7),
+            4_285_714_285_714_285_714_285_714_285_714_285_715,
+        ),
+        (
+            "sqrt_price_after_amount_in 0->1",
+            (helpers.sqrt_price_after_amount_in)(Q64, 1_000_000_000_000, 1_000_000_000, true),
+            18_428_315_757_951_600_015,
+        ),
+        (
+            "sqrt_price_after_amount_in 1->0",
+            (helpers.sqrt_price_after_amount_in)(Q64, 1_000_000_000_000, 1_000_000_000, false),
+            18_465_190_817_783_261_167,
+        ),
+        (
+            "amount0_delta down",
+            (helpers.amount0_delta)(1 << 40, 3 << 40, 1_000_000, false),
+            11_184_810_666_666,
+        ),
+        (
+            "amount0_delta up",
+            (helpers.amount0_delta)(1 << 40, 3 << 40, 1_000_000, true),
+            11_184_810_666_667,
+        ),
+        (
+            "amount1_delta down",
+            (helpers.amount1_delta)(Q64, Q64 + 12_345_678_901_234_567, 1_000_000_000_000, false),
+            669_260_594,
+        ),
+        (
+            "amount1_delta up",
+            (helpers.amount1_delta)(Q64, Q64 + 12_345_678_901_234_567, 1_000_000_000_000, true),
+            669_260_595,
+        ),
+    ];
+
+    for (name, actual, expected) in cases {
+        if actual.ok() != Some(expected) {
+            msg!("Self test mismatch in {}", name);
+            return err!(ErrorCode::SelfTestFailed);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_reference_values_pass() {
+        check_math(&MathHelpers::CLMM).unwrap();
+    }
+
+    #[test]
+    fn a_perturbed_helper_fails() {
+        // Rounding down where the helper should round up
+        let helpers = MathHelpers {
+            mul_div_ceil: mul_div,
+            ..MathHelpers::CLMM
+        };
+        assert_eq!(
+            check_math(&helpers).unwrap_err(),
+            error!(ErrorCode::SelfTestFailed)
+        );
+
+        // A helper erroring out fails the same way
+        let helpers = MathHelpers {
+            amount1_delta: |_, _, _, _| err!(ErrorCode::CalculationFailure),
+            ..MathHelpers::CLMM
+        };
+        assert_eq!(
+            check_math(&helpers).unwrap_err(),
+            error!(ErrorCode::SelfTestFailed)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::program_config::SlotCounter;
+
+#[derive(Accounts)]
+#[instruction(slot: u64)]
+pub struct OpenSlotCounter<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + SlotCounter::SIZE,
+        seeds = [SlotCounter::SEED, &slot.to_le_bytes()],
+        bump
+    )]
+    pub slot_counter: Account<'info, SlotCounter>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSlotCounter<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Only a counter of a past slot, no frontrun can count in it anymore
+    #[account(
+        mut,
+        close = payer,
+        has_one = payer,
+        constraint = slot_counter.slot < Clock::get()?.slot @ ErrorCode::InvalidInput
+    )]
+    pub slot_counter: Account<'info, SlotCounter>,
+}
+
+/// Opens the counter of the current `slot` ahead of a frontrun, leaving the count alone
+/// when an earlier transaction of the slot already opened it.
+pub fn open_slot_counter(ctx: Context<OpenSlotCounter>, slot: u64) -> Result<()> {
+    require!(slot == Clock::get()?.slot, ErrorCode::InvalidInput);
+
+    let slot_counter = &mut ctx.accounts.slot_counter;
+    if slot_counter.payer == Pubkey::default() {
+        slot_counter.slot = slot;
+        slot_counter.count = 0;
+        slot_counter.payer = ctx.accounts.payer.key();
+        slot_counter.bump = ctx.bumps.slot_counter;
+    }
+
+    Ok(())
+}
+
+pub fn close_slot_counter(_ctx: Context<CloseSlotCounter>) -> Result<()> {
+    // The counter account is closed into its payer by the context
+    Ok(())
+}
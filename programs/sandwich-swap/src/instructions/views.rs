@@ -0,0 +1,72 @@
+use anchor_lang::prelude::*;
+
+use crate::sandwich_state::SandwichState;
+
+#[derive(Accounts)]
+#[instruction(sandwich_id: u64)]
+pub struct GetSandwichState<'info> {
+    /// CHECK: Owner of the sandwich, only used to derive the state PDA
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"sandwich", owner.key().as_ref(), &sandwich_id.to_le_bytes()],
+        bump = sandwich_state.bump
+    )]
+    pub sandwich_state: Account<'info, SandwichState>,
+}
+
+pub fn get_sandwich_state(ctx: Context<GetSandwichState>, _sandwich_id: u64) -> Result<()> {
+    // Emit the full state so log based indexers don't need to fetch the account
+    emit!(ctx.accounts.sandwich_state.to_event());
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct DeriveSandwichPda<'info> {
+    /// CHECK: Owner the state PDA is namespaced by, only its key is read
+    pub owner: UncheckedAccount<'info>,
+}
+
+/// Address and bump of a sandwich state, returned by `derive_sandwich_pda`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SandwichPda {
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+/// Returns the state PDA of `owner`'s sandwich `sandwich_id` as return data, so another
+/// program can reference the state without re-implementing its seeds. The state doesn't
+/// have to exist yet.
+pub fn derive_sandwich_pda(
+    ctx: Context<DeriveSandwichPda>,
+    sandwich_id: u64,
+) -> Result<SandwichPda> {
+    let (address, bump) = SandwichState::pda(ctx.accounts.owner.key, sandwich_id);
+    Ok(SandwichPda { address, bump })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::account_info;
+
+    #[test]
+    fn the_derived_pda_matches_the_state_seeds() {
+        let owner = Pubkey::new_unique();
+        let owner_info = account_info(owner, Pubkey::default(), vec![], false, false);
+        let mut accounts = DeriveSandwichPda {
+            owner: UncheckedAccount::try_from(Box::leak(Box::new(owner_info))),
+        };
+        let ctx = Context::new(&crate::ID, &mut accounts, &[], DeriveSandwichPdaBumps {});
+
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"sandwich", owner.as_ref(), &7u64.to_le_bytes()],
+            &crate::ID,
+        );
+        assert_eq!(
+            derive_sandwich_pda(ctx, 7).unwrap(),
+            SandwichPda { address, bump }
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::{self, AssociatedToken, Create},
+    token::{self, spl_token, CloseAccount, Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct SweepWsol<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = spl_token::native_mint::ID)]
+    pub native_mint: Account<'info, Mint>,
+
+    /// The user's WSOL ATA, closed into the user
+    #[account(
+        mut,
+        associated_token::mint = native_mint,
+        associated_token::authority = user,
+    )]
+    pub wsol_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Closes the user's WSOL ATA, returning the wrapped SOL and its rent to the user as
+/// native SOL. With `recreate` the ATA is opened again empty, paying the rent back.
+pub fn sweep_wsol(ctx: Context<SweepWsol>, recreate: bool) -> Result<()> {
+    let account_lamports = ctx.accounts.wsol_account.to_account_info().lamports();
+
+    // A native account closes with any balance, all of its lamports go to the user
+    token::close_account(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.wsol_account.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        },
+    ))?;
+
+    if recreate {
+        associated_token::create(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            Create {
+                payer: ctx.accounts.user.to_account_info(),
+                associated_token: ctx.accounts.wsol_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+                mint: ctx.accounts.native_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+    }
+
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(TokenAccount::LEN);
+    msg!(
+        "Swept {} lamports of WSOL",
+        swept_lamports(account_lamports, rent_exempt_minimum, recreate)
+    );
+
+    Ok(())
+}
+
+/// Native SOL the user gains from sweeping a WSOL account holding `account_lamports`:
+/// everything, or only what sits above the rent when the account is recreated.
+pub fn swept_lamports(account_lamports: u64, rent_exempt_minimum: u64, recreate: bool) -> u64 {
+    if recreate {
+        account_lamports.saturating_sub(rent_exempt_minimum)
+    } else {
+        account_lamports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweeping_recovers_the_wrapped_sol_and_the_rent() {
+        let rent_exempt_minimum = Rent::default().minimum_balance(TokenAccount::LEN);
+        let account_lamports = rent_exempt_minimum + 1_234;
+
+        assert_eq!(
+            swept_lamports(account_lamports, rent_exempt_minimum, false),
+            account_lamports
+        );
+        // Recreating pays the rent back, leaving just the dust
+        assert_eq!(
+            swept_lamports(account_lamports, rent_exempt_minimum, true),
+            1_234
+        );
+    }
+}
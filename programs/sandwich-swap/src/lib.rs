@@ -8,9 +8,14 @@ declare_id!("XArSfgXtRWmxtyUW6dS6tTky1uYwpvaKEEq5eg93w15");
 
 pub mod error;
 pub mod instructions;
-mod sandwich_state;
+mod compact_sandwich_state;
+pub mod program_config;
+pub mod sandwich_state;
+#[cfg(test)]
+pub(crate) mod test_utils;
 
 use instructions::*;
+use sandwich_state::SlippageModel;
 
 #[program]
 pub mod sandwich_swap {
@@ -22,15 +27,39 @@ pub mod sandwich_swap {
         target_amount_in: u64,
         target_minimum_amount_out: u64,
         sandwich_id: u64,
+        require_victim_ix: Option<Pubkey>,
+        max_fee_cache_age_slots: u64,
     ) -> Result<()> {
-        instructions::amm_frontrun_swap_base_in(ctx, target_amount_in, target_minimum_amount_out, sandwich_id)
+        instructions::amm_frontrun_swap_base_in(
+            ctx,
+            target_amount_in,
+            target_minimum_amount_out,
+            sandwich_id,
+            require_victim_ix,
+            max_fee_cache_age_slots,
+        )
     }
 
     pub fn backrun_raydium_amm_swap_base_in(
         ctx: Context<AmmBackrunSwapBaseIn>,
         sandwich_id: u64,
+        min_slots_between: u8,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        max_state_age_secs: u32,
     ) -> Result<()> {
-        instructions::amm_backrun_swap_base_in(ctx, sandwich_id)
+        instructions::amm_backrun_swap_base_in(
+            ctx,
+            sandwich_id,
+            min_slots_between,
+            fixed_cost_lamports,
+            min_profit_amount,
+            max_state_age_secs,
+        )
+    }
+
+    pub fn refresh_amm_fees(ctx: Context<RefreshAmmFees>) -> Result<()> {
+        instructions::refresh_amm_fees(ctx)
     }
 
     // Raydium CLMM
@@ -57,6 +86,16 @@ pub mod sandwich_swap {
         target_sqrt_price_limit_x64: u128,
         target_is_base_input: bool,
         sandwich_id: u64,
+        min_victim_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        check_sellable: bool,
+        min_pool_age_secs: u32,
+        allow_uninitialized_observation: bool,
+        max_ticks_to_cross: u16,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        require_victim_ix: Option<Pubkey>,
+        slippage_model: SlippageModel,
     ) -> Result<()> {
         instructions::clmm_frontrun_swap(
             ctx,
@@ -65,14 +104,83 @@ pub mod sandwich_swap {
             target_sqrt_price_limit_x64,
             target_is_base_input,
             sandwich_id,
+            min_victim_slippage_bps,
+            max_self_price_impact_bps,
+            check_sellable,
+            min_pool_age_secs,
+            allow_uninitialized_observation,
+            max_ticks_to_cross,
+            expected_current_price,
+            max_price_drift_bps,
+            require_victim_ix,
+            slippage_model,
+        )
+    }
+
+    pub fn raydium_clmm_frontrun_swap_fixed<'a, 'b, 'c: 'info, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ClmmSandwichFrontrun<'info>>,
+        target_amount: u64,
+        target_other_amount_threshold: u64,
+        target_sqrt_price_limit_x64: u128,
+        target_is_base_input: bool,
+        sandwich_id: u64,
+        frontrun_amount: u64,
+        min_victim_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        check_sellable: bool,
+        min_pool_age_secs: u32,
+        allow_uninitialized_observation: bool,
+        max_ticks_to_cross: u16,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        require_victim_ix: Option<Pubkey>,
+        slippage_model: SlippageModel,
+    ) -> Result<()> {
+        instructions::clmm_frontrun_swap_fixed(
+            ctx,
+            target_amount,
+            target_other_amount_threshold,
+            target_sqrt_price_limit_x64,
+            target_is_base_input,
+            sandwich_id,
+            frontrun_amount,
+            min_victim_slippage_bps,
+            max_self_price_impact_bps,
+            check_sellable,
+            min_pool_age_secs,
+            allow_uninitialized_observation,
+            max_ticks_to_cross,
+            expected_current_price,
+            max_price_drift_bps,
+            require_victim_ix,
+            slippage_model,
         )
     }
 
     pub fn raydium_clmm_backrun_swap<'a, 'b, 'c: 'info, 'info>(
         ctx: Context<'a, 'b, 'c, 'info, ClmmSandwichBackrun<'info>>,
         sandwich_id: u64,
+        backrun_fraction_bps: u16,
+        min_slots_between: u8,
+        time_decay_bps_per_sec: u16,
+        max_acceptable_loss: Option<u64>,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        keep_output_amount: u64,
+        max_state_age_secs: u32,
     ) -> Result<()> {
-        instructions::clmm_backrun_swap(ctx, sandwich_id)
+        instructions::clmm_backrun_swap(
+            ctx,
+            sandwich_id,
+            backrun_fraction_bps,
+            min_slots_between,
+            time_decay_bps_per_sec,
+            max_acceptable_loss,
+            fixed_cost_lamports,
+            min_profit_amount,
+            keep_output_amount,
+            max_state_age_secs,
+        )
     }
 
 
@@ -98,20 +206,73 @@ pub mod sandwich_swap {
         target_max_amount_in: u64,
         target_amount_out: u64,
         sandwich_id: u64,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        check_sellable: bool,
+        min_pool_age_secs: u32,
+        require_victim_ix: Option<Pubkey>,
     ) -> Result<()> {
         instructions::cpmm_frontrun_swap_base_output(
             ctx,
             target_max_amount_in,
             target_amount_out,
             sandwich_id,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            check_sellable,
+            min_pool_age_secs,
+            require_victim_ix,
         )
     }
 
     pub fn raydium_cpmm_backrun_swap_base_output(
         ctx: Context<CpmmSandwichBackrunOutput>,
         sandwich_id: u64,
+        backrun_fraction_bps: u16,
+        min_slots_between: u8,
+        time_decay_bps_per_sec: u16,
+        max_acceptable_loss: Option<u64>,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        max_state_age_secs: u32,
     ) -> Result<()> {
-        instructions::cpmm_backrun_swap_base_output(ctx, sandwich_id)
+        instructions::cpmm_backrun_swap_base_output(
+            ctx,
+            sandwich_id,
+            backrun_fraction_bps,
+            min_slots_between,
+            time_decay_bps_per_sec,
+            max_acceptable_loss,
+            fixed_cost_lamports,
+            min_profit_amount,
+            max_state_age_secs,
+        )
+    }
+
+    pub fn raydium_cpmm_backrun_swap_base_output_exact_input(
+        ctx: Context<CpmmSandwichBackrunOutput>,
+        sandwich_id: u64,
+        backrun_fraction_bps: u16,
+        min_slots_between: u8,
+        time_decay_bps_per_sec: u16,
+        max_acceptable_loss: Option<u64>,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        keep_output_amount: u64,
+        max_state_age_secs: u32,
+    ) -> Result<()> {
+        instructions::cpmm_backrun_swap_base_output_exact_input(
+            ctx,
+            sandwich_id,
+            backrun_fraction_bps,
+            min_slots_between,
+            time_decay_bps_per_sec,
+            max_acceptable_loss,
+            fixed_cost_lamports,
+            min_profit_amount,
+            keep_output_amount,
+            max_state_age_secs,
+        )
     }
 
     pub fn raydium_cpmm_frontrun_swap_base_input(
@@ -119,20 +280,241 @@ pub mod sandwich_swap {
         target_amount_in: u64,
         target_minimum_amount_out: u64,
         sandwich_id: u64,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        check_sellable: bool,
+        victim_is_exact_input: bool,
+        min_pool_age_secs: u32,
+        default_victim_slippage_bps: u16,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        victim_amounts: Vec<u64>,
+        require_victim_ix: Option<Pubkey>,
+        slippage_model: SlippageModel,
+        allow_uninitialized_observation: bool,
     ) -> Result<()> {
         instructions::cpmm_frontrun_swap_base_input(
             ctx,
             target_amount_in,
             target_minimum_amount_out,
             sandwich_id,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            max_self_price_impact_bps,
+            check_sellable,
+            victim_is_exact_input,
+            min_pool_age_secs,
+            default_victim_slippage_bps,
+            expected_current_price,
+            max_price_drift_bps,
+            victim_amounts,
+            require_victim_ix,
+            slippage_model,
+            allow_uninitialized_observation,
+        )
+    }
+
+    pub fn raydium_cpmm_frontrun_prepared(
+        ctx: Context<CpmmSandwichFrontrunPrepared>,
+        target_amount_in: u64,
+        target_minimum_amount_out: u64,
+        sandwich_id: u64,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        check_sellable: bool,
+        victim_is_exact_input: bool,
+        min_pool_age_secs: u32,
+        default_victim_slippage_bps: u16,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        victim_amounts: Vec<u64>,
+        require_victim_ix: Option<Pubkey>,
+        slippage_model: SlippageModel,
+        allow_uninitialized_observation: bool,
+    ) -> Result<()> {
+        instructions::cpmm_frontrun_prepared(
+            ctx,
+            target_amount_in,
+            target_minimum_amount_out,
+            sandwich_id,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            max_self_price_impact_bps,
+            check_sellable,
+            victim_is_exact_input,
+            min_pool_age_secs,
+            default_victim_slippage_bps,
+            expected_current_price,
+            max_price_drift_bps,
+            victim_amounts,
+            require_victim_ix,
+            slippage_model,
+            allow_uninitialized_observation,
+        )
+    }
+
+    pub fn raydium_cpmm_frontrun_swap_fixed(
+        ctx: Context<CpmmSandwichFrontrun>,
+        target_amount_in: u64,
+        target_minimum_amount_out: u64,
+        sandwich_id: u64,
+        frontrun_amount: u64,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        check_sellable: bool,
+        min_pool_age_secs: u32,
+        default_victim_slippage_bps: u16,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        require_victim_ix: Option<Pubkey>,
+        slippage_model: SlippageModel,
+        allow_uninitialized_observation: bool,
+    ) -> Result<()> {
+        instructions::cpmm_frontrun_swap_fixed(
+            ctx,
+            target_amount_in,
+            target_minimum_amount_out,
+            sandwich_id,
+            frontrun_amount,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            max_self_price_impact_bps,
+            check_sellable,
+            min_pool_age_secs,
+            default_victim_slippage_bps,
+            expected_current_price,
+            max_price_drift_bps,
+            require_victim_ix,
+            slippage_model,
+            allow_uninitialized_observation,
+        )
+    }
+
+    pub fn raydium_route_frontrun(
+        ctx: Context<RaydiumRouteFrontrun>,
+        target_amount_in: u64,
+        target_minimum_amount_out: u64,
+        sandwich_id: u64,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        check_sellable: bool,
+        min_pool_age_secs: u32,
+        default_victim_slippage_bps: u16,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        require_victim_ix: Option<Pubkey>,
+        slippage_model: SlippageModel,
+        allow_uninitialized_observation: bool,
+    ) -> Result<()> {
+        instructions::cpmm_route_frontrun(
+            ctx,
+            target_amount_in,
+            target_minimum_amount_out,
+            sandwich_id,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            max_self_price_impact_bps,
+            check_sellable,
+            min_pool_age_secs,
+            default_victim_slippage_bps,
+            expected_current_price,
+            max_price_drift_bps,
+            require_victim_ix,
+            slippage_model,
+            allow_uninitialized_observation,
         )
     }
 
     pub fn raydium_cpmm_backrun_swap_base_input(
         ctx: Context<CpmmSandwichBackrun>,
         sandwich_id: u64,
+        backrun_fraction_bps: u16,
+        min_slots_between: u8,
+        time_decay_bps_per_sec: u16,
+        max_acceptable_loss: Option<u64>,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        keep_output_amount: u64,
+        max_state_age_secs: u32,
     ) -> Result<()> {
-        instructions::cpmm_backrun_swap_base_input(ctx, sandwich_id)
+        instructions::cpmm_backrun_swap_base_input(
+            ctx,
+            sandwich_id,
+            backrun_fraction_bps,
+            min_slots_between,
+            time_decay_bps_per_sec,
+            max_acceptable_loss,
+            fixed_cost_lamports,
+            min_profit_amount,
+            keep_output_amount,
+            max_state_age_secs,
+        )
+    }
+
+    pub fn raydium_cpmm_compact_frontrun_swap_base_input(
+        ctx: Context<CpmmCompactFrontrun>,
+        target_amount_in: u64,
+        target_minimum_amount_out: u64,
+        sandwich_id: u64,
+        min_victim_slippage_bps: u16,
+        self_slippage_bps: u16,
+        max_self_price_impact_bps: u16,
+        check_sellable: bool,
+        min_pool_age_secs: u32,
+        default_victim_slippage_bps: u16,
+        expected_current_price: u128,
+        max_price_drift_bps: u16,
+        require_victim_ix: Option<Pubkey>,
+        slippage_model: SlippageModel,
+        allow_uninitialized_observation: bool,
+    ) -> Result<()> {
+        instructions::cpmm_compact_frontrun_swap_base_input(
+            ctx,
+            target_amount_in,
+            target_minimum_amount_out,
+            sandwich_id,
+            min_victim_slippage_bps,
+            self_slippage_bps,
+            max_self_price_impact_bps,
+            check_sellable,
+            min_pool_age_secs,
+            default_victim_slippage_bps,
+            expected_current_price,
+            max_price_drift_bps,
+            require_victim_ix,
+            slippage_model,
+            allow_uninitialized_observation,
+        )
+    }
+
+    pub fn raydium_cpmm_compact_backrun_swap_base_input(
+        ctx: Context<CpmmCompactBackrun>,
+        sandwich_id: u64,
+        min_slots_between: u8,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        max_state_age_secs: u32,
+    ) -> Result<()> {
+        instructions::cpmm_compact_backrun_swap_base_input(
+            ctx,
+            sandwich_id,
+            min_slots_between,
+            fixed_cost_lamports,
+            min_profit_amount,
+            max_state_age_secs,
+        )
+    }
+
+    pub fn raydium_cpmm_simulate_grid(
+        ctx: Context<CpmmSimulateGrid>,
+        target_amount_in: u64,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        instructions::cpmm_simulate_grid(ctx, target_amount_in, amounts)
     }
     
     pub fn pump_frontrun_buy(
@@ -140,29 +522,73 @@ pub mod sandwich_swap {
         base_amount_out: u64,
         max_quote_amount_in: u64,
         sandwich_id: u64,
+        self_slippage_bps: u16,
+        require_victim_ix: Option<Pubkey>,
     ) -> Result<()> {
-        instructions::pumpswap_frontrun_buy(ctx, base_amount_out, max_quote_amount_in, sandwich_id)
+        instructions::pumpswap_frontrun_buy(
+            ctx,
+            base_amount_out,
+            max_quote_amount_in,
+            sandwich_id,
+            self_slippage_bps,
+            require_victim_ix,
+        )
     }
     
-    pub fn pump_frontrun_sell(
-        ctx: Context<PumpSwapContext>,
+    pub fn pump_frontrun_sell<'a, 'b, 'c: 'info, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, PumpSwapContext<'info>>,
         base_amount_in: u64,
         min_quote_amount_out: u64,
         sandwich_id: u64,
+        remaining_accounts_writable: Vec<bool>,
+        self_slippage_bps: u16,
+        require_victim_ix: Option<Pubkey>,
     ) -> Result<()> {
-        instructions::pumpswap_frontrun_sell(ctx, base_amount_in, min_quote_amount_out, sandwich_id)
+        instructions::pumpswap_frontrun_sell(
+            ctx,
+            base_amount_in,
+            min_quote_amount_out,
+            sandwich_id,
+            remaining_accounts_writable,
+            self_slippage_bps,
+            require_victim_ix,
+        )
     }
     
     pub fn pump_backrun_buy(
-        ctx: Context<PumpSwapContext>,
+        ctx: Context<PumpSwapBackrunContext>,
+        sandwich_id: u64,
+        min_slots_between: u8,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        max_state_age_secs: u32,
     ) -> Result<()> {
-        instructions::pumpswap_backrun_buy(ctx)
+        instructions::pumpswap_backrun_buy(
+            ctx,
+            sandwich_id,
+            min_slots_between,
+            fixed_cost_lamports,
+            min_profit_amount,
+            max_state_age_secs,
+        )
     }
     
     pub fn pump_backrun_sell(
-        ctx: Context<PumpSwapContext>,
+        ctx: Context<PumpSwapBackrunContext>,
+        sandwich_id: u64,
+        min_slots_between: u8,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
+        max_state_age_secs: u32,
     ) -> Result<()> {
-        instructions::pumpswap_backrun_sell(ctx)
+        instructions::pumpswap_backrun_sell(
+            ctx,
+            sandwich_id,
+            min_slots_between,
+            fixed_cost_lamports,
+            min_profit_amount,
+            max_state_age_secs,
+        )
     }
 
     // PumpFun
@@ -171,15 +597,141 @@ pub mod sandwich_swap {
         target_base_amount_out: u64,
         target_max_quote_amount_in: u64,
         sandwich_id: u64,
+        require_victim_ix: Option<Pubkey>,
     ) -> Result<()> {
-        instructions::pumpfun_frontrun_buy(ctx, target_base_amount_out, target_max_quote_amount_in, sandwich_id)
+        instructions::pumpfun_frontrun_buy(
+            ctx,
+            target_base_amount_out,
+            target_max_quote_amount_in,
+            sandwich_id,
+            require_victim_ix,
+        )
     }
 
     pub fn pumpfun_backrun_buy(
         ctx: Context<PumpFunBackrunBuyContext>,
         sandwich_id: u64,
+        min_slots_between: u8,
+        max_state_age_secs: u32,
+        fixed_cost_lamports: u64,
+        min_profit_amount: u64,
     ) -> Result<()> {
-        instructions::pumpfun_backrun_buy(ctx, sandwich_id)
+        instructions::pumpfun_backrun_buy(
+            ctx,
+            sandwich_id,
+            min_slots_between,
+            max_state_age_secs,
+            fixed_cost_lamports,
+            min_profit_amount,
+        )
+    }
+
+    pub fn pumpfun_simulate_frontrun(
+        ctx: Context<PumpFunSimulateFrontrun>,
+        target_token_amount_out: u64,
+        target_max_sol_amount_in: u64,
+    ) -> Result<()> {
+        instructions::pumpfun_simulate_frontrun(ctx, target_token_amount_out, target_max_sol_amount_in)
+    }
+
+    // Admin
+    pub fn initialize_config(ctx: Context<InitializeConfig>, max_consecutive_losses: u16) -> Result<()> {
+        instructions::initialize_config(ctx, max_consecutive_losses)
+    }
+
+    pub fn reset_circuit_breaker(ctx: Context<UpdateConfig>) -> Result<()> {
+        instructions::reset_circuit_breaker(ctx)
+    }
+
+    pub fn set_enforce_allowlist(ctx: Context<UpdateConfig>, enforce_allowlist: bool) -> Result<()> {
+        instructions::set_enforce_allowlist(ctx, enforce_allowlist)
+    }
+
+    pub fn set_enforce_blocklist(ctx: Context<UpdateConfig>, enforce_blocklist: bool) -> Result<()> {
+        instructions::set_enforce_blocklist(ctx, enforce_blocklist)
+    }
+
+    pub fn set_research_mode(ctx: Context<UpdateConfig>, research_mode: bool) -> Result<()> {
+        instructions::set_research_mode(ctx, research_mode)
+    }
+
+    pub fn set_notional_cap(
+        ctx: Context<UpdateConfig>,
+        max_notional: u64,
+        clamp_notional: bool,
+    ) -> Result<()> {
+        instructions::set_notional_cap(ctx, max_notional, clamp_notional)
+    }
+
+    pub fn set_allow_fee_free_pools(
+        ctx: Context<UpdateConfig>,
+        allow_fee_free_pools: bool,
+    ) -> Result<()> {
+        instructions::set_allow_fee_free_pools(ctx, allow_fee_free_pools)
+    }
+
+    pub fn set_max_absolute_slippage_consumed(
+        ctx: Context<UpdateConfig>,
+        max_absolute_slippage_consumed_bps: u16,
+    ) -> Result<()> {
+        instructions::set_max_absolute_slippage_consumed(ctx, max_absolute_slippage_consumed_bps)
+    }
+
+    pub fn set_max_per_slot(ctx: Context<UpdateConfig>, max_per_slot: u16) -> Result<()> {
+        instructions::set_max_per_slot(ctx, max_per_slot)
+    }
+
+    pub fn allow_pool(ctx: Context<AllowPool>, pool: Pubkey) -> Result<()> {
+        instructions::allow_pool(ctx, pool)
+    }
+
+    pub fn disallow_pool(ctx: Context<DisallowPool>) -> Result<()> {
+        instructions::disallow_pool(ctx)
+    }
+
+    pub fn block_mint(ctx: Context<BlockMint>, mint: Pubkey) -> Result<()> {
+        instructions::block_mint(ctx, mint)
+    }
+
+    pub fn unblock_mint(ctx: Context<UnblockMint>) -> Result<()> {
+        instructions::unblock_mint(ctx)
+    }
+
+    pub fn track_frontrun_slot(ctx: Context<TrackFrontrunSlot>, pool: Pubkey) -> Result<()> {
+        instructions::track_frontrun_slot(ctx, pool)
+    }
+
+    pub fn open_slot_counter(ctx: Context<OpenSlotCounter>, slot: u64) -> Result<()> {
+        instructions::open_slot_counter(ctx, slot)
+    }
+
+    pub fn close_slot_counter(ctx: Context<CloseSlotCounter>) -> Result<()> {
+        instructions::close_slot_counter(ctx)
+    }
+
+    pub fn register_mint(ctx: Context<RegisterMint>, mint: Pubkey) -> Result<()> {
+        instructions::register_mint(ctx, mint)
+    }
+
+    // Maintenance
+    pub fn sweep_wsol(ctx: Context<SweepWsol>, recreate: bool) -> Result<()> {
+        instructions::sweep_wsol(ctx, recreate)
+    }
+
+    pub fn run_self_test(ctx: Context<RunSelfTest>) -> Result<()> {
+        instructions::run_self_test(ctx)
+    }
+
+    // Views
+    pub fn get_sandwich_state(ctx: Context<GetSandwichState>, sandwich_id: u64) -> Result<()> {
+        instructions::get_sandwich_state(ctx, sandwich_id)
+    }
+
+    pub fn derive_sandwich_pda(
+        ctx: Context<DeriveSandwichPda>,
+        sandwich_id: u64,
+    ) -> Result<SandwichPda> {
+        instructions::derive_sandwich_pda(ctx, sandwich_id)
     }
 
 }
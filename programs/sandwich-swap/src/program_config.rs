@@ -0,0 +1,732 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::log::sol_log_compute_units;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self, load_current_index_checked, load_instruction_at_checked,
+};
+
+use crate::error::ErrorCode;
+use crate::sandwich_state::PIPS_PER_BPS;
+
+/// Program wide settings, stored in the `[b"config"]` PDA.
+#[account]
+pub struct ProgramConfig {
+    pub admin: Pubkey,               // Authority allowed to update the config
+    pub paused: bool,                // Frontruns are rejected while paused
+    pub max_consecutive_losses: u16, // Losing backruns in a row before pausing (0 disables)
+    pub consecutive_losses: u16,     // Current streak of losing backruns
+    pub bump: u8,
+    pub enforce_allowlist: bool,     // Frontruns require an AllowedPool marker for the pool
+    pub research_mode: bool,         // Unprofitable sandwiches execute anyway, off by default
+    pub max_notional: u64,           // Largest input a frontrun may spend (0 disables)
+    pub clamp_notional: bool,        // Over the cap, clamp the frontrun instead of rejecting it
+    pub allow_fee_free_pools: bool,  // CPMM frontruns accept a config without a trade fee
+    pub max_absolute_slippage_consumed_bps: u16, // Most victim slippage consumed (0 disables)
+    pub enforce_blocklist: bool,     // Frontruns reject mints carrying a BlockedMint marker
+    pub max_per_slot: u16,           // Frontruns allowed per slot, see SlotCounter (0 disables)
+}
+
+impl ProgramConfig {
+    pub const SIZE: usize = 32 + 1 + 2 + 2 + 1 + 1 + 1 + 8 + 1 + 1 + 2 + 1 + 2; // Size in bytes
+    pub const SEED: &'static [u8] = b"config";
+
+    /// Feeds a backrun's profit into the circuit breaker, pausing the program once
+    /// `max_consecutive_losses` losing backruns happened in a row.
+    pub fn record_outcome(&mut self, profit: i64) {
+        if profit > 0 {
+            self.consecutive_losses = 0;
+            return;
+        }
+
+        self.consecutive_losses = self.consecutive_losses.saturating_add(1);
+        if self.max_consecutive_losses > 0 && self.consecutive_losses >= self.max_consecutive_losses {
+            self.paused = true;
+        }
+    }
+
+    /// Holds a frontrun spending up to `amount_in` for `amount_out` to `max_notional`.
+    /// Over the cap it is rejected, or clamped to the cap with `amount_out` scaled down in
+    /// proportion: the curves are concave, so the smaller trade still meets the scaled output.
+    pub fn cap_notional(&self, amount_in: u64, amount_out: u64) -> Result<(u64, u64)> {
+        if self.max_notional == 0 || amount_in <= self.max_notional {
+            return Ok((amount_in, amount_out));
+        }
+        require!(self.clamp_notional, ErrorCode::ExceedsNotionalCap);
+
+        let scaled_out = (amount_out as u128) * (self.max_notional as u128) / (amount_in as u128);
+        Ok((self.max_notional, scaled_out as u64))
+    }
+}
+
+/// Fails with `error` unless the config is in research mode, where the sandwich carries
+/// on and the error is only logged so the outcome of an unprofitable one can be measured.
+pub fn allow_in_research_mode(research_mode: bool, error: ErrorCode) -> Result<()> {
+    if !research_mode {
+        return Err(error.into());
+    }
+    msg!("Research mode, continuing past: {}", error);
+    Ok(())
+}
+
+/// Line logged ahead of the compute units left at the start of a `venue` frontrun.
+pub fn frontrun_compute_units_line(venue: &str) -> String {
+    format!("{} frontrun, compute units left:", venue)
+}
+
+/// Logs the compute units left at the start of a frontrun, so operators can calibrate
+/// their ComputeBudget instructions per pool type.
+pub fn log_frontrun_compute_units(venue: &str) {
+    msg!("{}", frontrun_compute_units_line(venue));
+    sol_log_compute_units();
+}
+
+/// Prefix of the line logging a victim's effective amounts, stable for log parsers.
+pub const VICTIM_AMOUNTS_LOG_PREFIX: &str = "Victim effective amounts:";
+
+/// Line logging the amounts a `venue` victim actually swaps once transfer fees are taken,
+/// and the slippage tolerance derived from them, in basis points to two decimals.
+pub fn victim_amounts_line(
+    venue: &str,
+    actual_amount_in: u64,
+    actual_amount_out: u64,
+    slippage_pips: u128,
+) -> String {
+    format!(
+        "{} {} in={} out={} slippage_bps={}.{:02}",
+        VICTIM_AMOUNTS_LOG_PREFIX,
+        venue,
+        actual_amount_in,
+        actual_amount_out,
+        slippage_pips / PIPS_PER_BPS,
+        slippage_pips % PIPS_PER_BPS
+    )
+}
+
+/// Logs what a frontrun read off its victim, so operators can check the sizing's inputs
+/// against their own off-chain decoding.
+pub fn log_victim_amounts(
+    venue: &str,
+    actual_amount_in: u64,
+    actual_amount_out: u64,
+    slippage_pips: u128,
+) {
+    msg!(
+        "{}",
+        victim_amounts_line(venue, actual_amount_in, actual_amount_out, slippage_pips)
+    );
+}
+
+/// Allowlist marker for a pool, stored in the `[b"allow", pool]` PDA.
+#[account]
+pub struct AllowedPool {
+    pub pool: Pubkey, // The allowlisted pool
+    pub bump: u8,
+}
+
+impl AllowedPool {
+    pub const SIZE: usize = 32 + 1; // Size in bytes
+    pub const SEED: &'static [u8] = b"allow";
+}
+
+/// Blocklist marker for a mint, stored in the `[b"block", mint]` PDA.
+#[account]
+pub struct BlockedMint {
+    pub mint: Pubkey, // The blocked mint
+    pub bump: u8,
+}
+
+impl BlockedMint {
+    pub const SIZE: usize = 32 + 1; // Size in bytes
+    pub const SEED: &'static [u8] = b"block";
+
+    /// Address of the marker blocking `mint`, whether or not it exists.
+    pub fn address(mint: &Pubkey) -> Pubkey {
+        Self::pda(mint).0
+    }
+
+    /// Address and bump of the marker blocking `mint`.
+    pub fn pda(mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED, mint.as_ref()], &crate::ID)
+    }
+}
+
+/// Slot of the last frontrun on a pool, stored in the `[b"frontrun_slot", pool]` PDA.
+#[account]
+pub struct PoolFrontrunSlot {
+    pub pool: Pubkey,   // The tracked pool
+    pub last_slot: u64, // Slot of the last frontrun on the pool
+    pub bump: u8,
+}
+
+impl PoolFrontrunSlot {
+    pub const SIZE: usize = 32 + 8 + 1; // Size in bytes
+    pub const SEED: &'static [u8] = b"frontrun_slot";
+
+    /// Records a frontrun landing in `slot`, rejecting a second one in the same slot
+    /// (e.g. a retried transaction duplicated by the RPC).
+    pub fn record(&mut self, slot: u64) -> Result<()> {
+        require!(self.last_slot != slot, ErrorCode::DuplicateFrontrun);
+        self.last_slot = slot;
+        Ok(())
+    }
+}
+
+/// Frontruns landed in a slot across all pools, stored in the `[b"slot_counter", slot (le)]`
+/// PDA. Opened by `open_slot_counter` ahead of the frontruns of its slot.
+#[account]
+pub struct SlotCounter {
+    pub slot: u64,     // The counted slot
+    pub count: u16,    // Frontruns landed in the slot so far
+    pub payer: Pubkey, // Paid the rent, refunded once the slot is over
+    pub bump: u8,
+}
+
+impl SlotCounter {
+    pub const SIZE: usize = 8 + 2 + 32 + 1; // Size in bytes
+    pub const SEED: &'static [u8] = b"slot_counter";
+
+    /// Address of the counter of `slot`, whether or not it exists.
+    pub fn address(slot: u64) -> Pubkey {
+        Pubkey::find_program_address(&[Self::SEED, &slot.to_le_bytes()], &crate::ID).0
+    }
+
+    /// Counts a frontrun landing in `slot`, rejecting one past `max_per_slot`. A counter
+    /// left over from an earlier slot can't count for this one, each slot has its own.
+    pub fn record(&mut self, slot: u64, max_per_slot: u16) -> Result<()> {
+        require!(self.slot == slot, ErrorCode::InvalidInput);
+        require!(self.count < max_per_slot, ErrorCode::SlotLimitReached);
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// Accounts guarding every frontrun: the program config and the allowlist and blocklist
+/// markers it enforces. Nested in the frontrun contexts, which can't constrain the markers
+/// against their own pool and mints from here, see `FrontrunGuards::check` and
+/// `FrontrunGuards::check_mints`.
+#[derive(Accounts)]
+pub struct FrontrunGuards<'info> {
+    /// Program config, frontruns are rejected while it is paused. Read-only, so frontruns
+    /// on different pools don't write-lock a shared account
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Allowlist marker for the pool, required while the config enforces the allowlist
+    pub allowed_pool: Option<Account<'info, AllowedPool>>,
+
+    /// Last frontrun slot of the pool, when given a second frontrun in the same slot fails
+    #[account(mut)]
+    pub frontrun_slot: Option<Account<'info, PoolFrontrunSlot>>,
+
+    /// CHECK: Blocklist marker address of the input mint, required while the config
+    /// enforces the blocklist. Left uncreated unless the mint is blocked
+    pub input_mint_block: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Blocklist marker address of the output mint, as for the input mint
+    pub output_mint_block: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Instructions sysvar, required when the frontrun has to find its victim's
+    /// instruction later in the transaction
+    #[account(address = instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    /// Counter of the current slot, required while the config limits frontruns per slot
+    #[account(mut)]
+    pub slot_counter: Option<Account<'info, SlotCounter>>,
+}
+
+impl<'info> FrontrunGuards<'info> {
+    /// Rejects the frontrun while the program is paused, when the config enforces the
+    /// allowlist and `pool` isn't on it, when the pool was already frontrun this slot, or
+    /// when the slot already holds `max_per_slot` frontruns.
+    pub fn check(&mut self, pool: &Pubkey) -> Result<()> {
+        require!(!self.config.paused, ErrorCode::ProgramPaused);
+
+        if self.config.enforce_allowlist {
+            let allowed_pool = self.allowed_pool.as_ref().ok_or(ErrorCode::PoolNotAllowed)?;
+            require_keys_eq!(allowed_pool.pool, *pool, ErrorCode::PoolNotAllowed);
+        }
+
+        if let Some(frontrun_slot) = self.frontrun_slot.as_mut() {
+            require_keys_eq!(frontrun_slot.pool, *pool, ErrorCode::InvalidInput);
+            frontrun_slot.record(Clock::get()?.slot)?;
+        }
+
+        if self.config.max_per_slot > 0 {
+            let slot_counter = self
+                .slot_counter
+                .as_mut()
+                .ok_or(ErrorCode::SlotCounterRequired)?;
+            slot_counter.record(Clock::get()?.slot, self.config.max_per_slot)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rejects the frontrun when the config enforces the blocklist and either mint has a
+    /// `BlockedMint` marker. The markers are passed by address, so an unblocked mint's is
+    /// an empty account rather than a missing one.
+    pub fn check_mints(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> Result<()> {
+        if !self.config.enforce_blocklist {
+            return Ok(());
+        }
+
+        for (mint, marker) in [
+            (input_mint, &self.input_mint_block),
+            (output_mint, &self.output_mint_block),
+        ] {
+            let marker = marker.as_ref().ok_or(ErrorCode::MintBlocked)?;
+            require_keys_eq!(
+                marker.key(),
+                BlockedMint::address(mint),
+                ErrorCode::InvalidInput
+            );
+            // Only `block_mint` creates an account owned by this program at the address
+            require!(*marker.owner != crate::ID, ErrorCode::MintBlocked);
+        }
+
+        Ok(())
+    }
+
+    /// Rejects the frontrun unless an instruction of `victim_program` follows it in the
+    /// transaction, a frontrun landing without its victim being a plain market buy.
+    /// `None` skips the check. Only top-level instructions are seen, so a victim routed
+    /// through an aggregator is found by the aggregator's program id.
+    pub fn check_victim_ix(&self, victim_program: Option<Pubkey>) -> Result<()> {
+        let Some(victim_program) = victim_program else {
+            return Ok(());
+        };
+        let instructions_sysvar = self
+            .instructions_sysvar
+            .as_ref()
+            .ok_or(ErrorCode::VictimNotInTransaction)?;
+        require!(
+            invokes_after_current(instructions_sysvar, &victim_program)?,
+            ErrorCode::VictimNotInTransaction
+        );
+        Ok(())
+    }
+}
+
+/// Whether an instruction after the executing one, in the transaction `instructions_sysvar`
+/// describes, invokes `program_id`.
+pub fn invokes_after_current(
+    instructions_sysvar: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<bool> {
+    let mut index = load_current_index_checked(instructions_sysvar)? as usize + 1;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        if instruction.program_id == *program_id {
+            return Ok(true);
+        }
+        index += 1;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_utils::{account_info, program_account, set_clock, syscall_stubs};
+    use std::collections::BTreeSet;
+
+    pub(crate) fn config(max_consecutive_losses: u16) -> ProgramConfig {
+        ProgramConfig {
+            admin: Pubkey::new_unique(),
+            paused: false,
+            max_consecutive_losses,
+            consecutive_losses: 0,
+            bump: 255,
+            enforce_allowlist: false,
+            research_mode: false,
+            max_notional: 0,
+            clamp_notional: false,
+            allow_fee_free_pools: false,
+            max_absolute_slippage_consumed_bps: 0,
+            enforce_blocklist: false,
+            max_per_slot: 0,
+        }
+    }
+
+    #[test]
+    fn record_outcome_pauses_after_the_loss_streak() {
+        let mut config = config(3);
+
+        config.record_outcome(-1);
+        config.record_outcome(0);
+        assert_eq!(config.consecutive_losses, 2);
+        assert!(!config.paused);
+
+        config.record_outcome(-5);
+        assert_eq!(config.consecutive_losses, 3);
+        assert!(config.paused);
+    }
+
+    #[test]
+    fn record_outcome_resets_the_streak_on_a_win() {
+        let mut config = config(2);
+
+        config.record_outcome(-1);
+        config.record_outcome(1);
+        assert_eq!(config.consecutive_losses, 0);
+
+        config.record_outcome(-1);
+        assert!(!config.paused);
+    }
+
+    #[test]
+    fn the_notional_cap_rejects_past_the_boundary() {
+        let mut config = config(0);
+        config.max_notional = 1_000;
+
+        assert_eq!(config.cap_notional(1_000, 2_000).unwrap(), (1_000, 2_000));
+        assert_eq!(
+            config.cap_notional(1_001, 2_000).unwrap_err(),
+            error!(ErrorCode::ExceedsNotionalCap)
+        );
+
+        // No cap configured
+        config.max_notional = 0;
+        assert_eq!(config.cap_notional(u64::MAX, 2_000).unwrap(), (u64::MAX, 2_000));
+    }
+
+    #[test]
+    fn the_notional_cap_clamps_past_the_boundary() {
+        let mut config = config(0);
+        config.max_notional = 1_000;
+        config.clamp_notional = true;
+
+        assert_eq!(config.cap_notional(1_000, 2_000).unwrap(), (1_000, 2_000));
+        // Twice the cap buys at most half the output
+        assert_eq!(config.cap_notional(2_000, 3_000).unwrap(), (1_000, 1_500));
+        assert_eq!(config.cap_notional(1_001, 2_000).unwrap(), (1_000, 1_998));
+    }
+
+    #[test]
+    fn research_mode_downgrades_the_error_to_a_warning() {
+        assert_eq!(
+            allow_in_research_mode(false, ErrorCode::UnprofitableSandwich).unwrap_err(),
+            error!(ErrorCode::UnprofitableSandwich)
+        );
+        assert!(allow_in_research_mode(true, ErrorCode::UnprofitableSandwich).is_ok());
+    }
+
+    #[test]
+    fn a_clmm_frontrun_logs_its_compute_units() {
+        assert_eq!(
+            frontrun_compute_units_line("CLMM"),
+            "CLMM frontrun, compute units left:"
+        );
+        // Off-chain the compute units syscall falls back to the logging stub
+        log_frontrun_compute_units("CLMM");
+    }
+
+    #[test]
+    fn a_second_frontrun_in_the_same_slot_is_rejected() {
+        let mut frontrun_slot = PoolFrontrunSlot {
+            pool: Pubkey::new_unique(),
+            last_slot: 0,
+            bump: 255,
+        };
+
+        frontrun_slot.record(100).unwrap();
+        assert_eq!(
+            frontrun_slot.record(100).unwrap_err(),
+            error!(ErrorCode::DuplicateFrontrun)
+        );
+
+        // The next slot is free again
+        frontrun_slot.record(101).unwrap();
+        assert_eq!(frontrun_slot.last_slot, 101);
+    }
+
+    #[test]
+    fn a_frontrun_past_the_slot_limit_is_rejected() {
+        let mut slot_counter = SlotCounter {
+            slot: 100,
+            count: 0,
+            payer: Pubkey::new_unique(),
+            bump: 255,
+        };
+
+        // The third frontrun of a slot allowing two
+        slot_counter.record(100, 2).unwrap();
+        slot_counter.record(100, 2).unwrap();
+        assert_eq!(
+            slot_counter.record(100, 2).unwrap_err(),
+            error!(ErrorCode::SlotLimitReached)
+        );
+        assert_eq!(slot_counter.count, 2);
+
+        // The full counter can't be carried into the next slot
+        assert_eq!(
+            slot_counter.record(101, 2).unwrap_err(),
+            error!(ErrorCode::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn the_guards_reject_the_frontrun_past_the_slot_limit() {
+        let _stubs = syscall_stubs();
+        set_clock(100, 0);
+        let pool = Pubkey::new_unique();
+        let mut limited = config(0);
+        limited.max_per_slot = 2;
+
+        // A limited slot can't be counted without its counter
+        let mut guards = frontrun_guards(limited.clone(), none(), none(), none());
+        assert_eq!(
+            guards.check(&pool).unwrap_err(),
+            error!(ErrorCode::SlotCounterRequired)
+        );
+
+        // With it, the third frontrun of a slot allowing two is rejected
+        let slot_counter = SlotCounter {
+            slot: 100,
+            count: 0,
+            payer: Pubkey::new_unique(),
+            bump: 255,
+        };
+        let info = program_account(
+            SlotCounter::address(100),
+            8 + SlotCounter::SIZE,
+            &slot_counter,
+        );
+        guards.slot_counter = Some(Account::try_from(Box::leak(Box::new(info))).unwrap());
+        guards.check(&pool).unwrap();
+        guards.check(&pool).unwrap();
+        assert_eq!(
+            guards.check(&pool).unwrap_err(),
+            error!(ErrorCode::SlotLimitReached)
+        );
+    }
+
+    #[test]
+    fn a_pool_off_the_allowlist_is_rejected_until_allowed() {
+        let pool = Pubkey::new_unique();
+        let mut enforced = config(0);
+        enforced.enforce_allowlist = true;
+
+        let mut guards = frontrun_guards(enforced, none(), none(), none());
+        assert_eq!(
+            guards.check(&pool).unwrap_err(),
+            error!(ErrorCode::PoolNotAllowed)
+        );
+
+        // `allow_pool` creates the pool's marker, another pool's marker doesn't do
+        let allowed_pool = |pool: Pubkey| {
+            let (key, bump) =
+                Pubkey::find_program_address(&[AllowedPool::SEED, pool.as_ref()], &crate::ID);
+            let info = program_account(key, 8 + AllowedPool::SIZE, &AllowedPool { pool, bump });
+            Some(Account::try_from(Box::leak(Box::new(info))).unwrap())
+        };
+        guards.allowed_pool = allowed_pool(Pubkey::new_unique());
+        assert_eq!(
+            guards.check(&pool).unwrap_err(),
+            error!(ErrorCode::PoolNotAllowed)
+        );
+
+        guards.allowed_pool = allowed_pool(pool);
+        guards.check(&pool).unwrap();
+    }
+
+    #[test]
+    fn each_slot_counts_from_zero_in_its_own_account() {
+        assert_ne!(SlotCounter::address(100), SlotCounter::address(101));
+
+        let mut next_slot = SlotCounter {
+            slot: 101,
+            count: 0,
+            payer: Pubkey::new_unique(),
+            bump: 255,
+        };
+        next_slot.record(101, 2).unwrap();
+        next_slot.record(101, 2).unwrap();
+        assert_eq!(next_slot.count, 2);
+    }
+
+    #[test]
+    fn record_outcome_never_pauses_when_disabled() {
+        let mut config = config(0);
+        config.consecutive_losses = u16::MAX;
+
+        config.record_outcome(-1);
+        assert_eq!(config.consecutive_losses, u16::MAX);
+        assert!(!config.paused);
+    }
+
+    // The blocklist marker address of `mint`, created by `block_mint` when `blocked`
+    fn mint_block(mint: &Pubkey, blocked: bool) -> AccountInfo<'static> {
+        let (key, bump) = BlockedMint::pda(mint);
+        if !blocked {
+            return account_info(key, Pubkey::default(), vec![], false, false);
+        }
+
+        let mut data = vec![0u8; 8 + BlockedMint::SIZE];
+        BlockedMint { mint: *mint, bump }
+            .try_serialize(&mut data.as_mut_slice())
+            .unwrap();
+        account_info(key, crate::ID, data, false, false)
+    }
+
+    // Guards over `config` and the given optional accounts
+    fn frontrun_guards(
+        mut config: ProgramConfig,
+        input_mint_block: AccountInfo<'static>,
+        output_mint_block: AccountInfo<'static>,
+        instructions_sysvar: AccountInfo<'static>,
+    ) -> FrontrunGuards<'static> {
+        let (config_key, bump) = Pubkey::find_program_address(&[ProgramConfig::SEED], &crate::ID);
+        config.bump = bump;
+        let mut data = vec![0u8; 8 + ProgramConfig::SIZE];
+        config.try_serialize(&mut data.as_mut_slice()).unwrap();
+
+        let mut accounts: &[AccountInfo<'static>] = vec![
+            account_info(config_key, crate::ID, data, false, false),
+            none(),
+            none(),
+            input_mint_block,
+            output_mint_block,
+            instructions_sysvar,
+            none(),
+        ]
+        .leak();
+        FrontrunGuards::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &[],
+            &mut FrontrunGuardsBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .unwrap()
+    }
+
+    // The program id stands in for an optional account left out
+    fn none() -> AccountInfo<'static> {
+        account_info(crate::ID, Pubkey::default(), vec![], false, false)
+    }
+
+    // Guards enforcing the blocklist, given the markers of both mints
+    fn blocklist_guards(
+        input_mint_block: AccountInfo<'static>,
+        output_mint_block: AccountInfo<'static>,
+    ) -> FrontrunGuards<'static> {
+        let mut config = config(0);
+        config.enforce_blocklist = true;
+        frontrun_guards(config, input_mint_block, output_mint_block, none())
+    }
+
+    #[test]
+    fn a_blocked_mint_is_rejected() {
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // Blocked on either side of the frontrun
+        let guards = blocklist_guards(
+            mint_block(&input_mint, true),
+            mint_block(&output_mint, false),
+        );
+        assert_eq!(
+            guards.check_mints(&input_mint, &output_mint).unwrap_err(),
+            error!(ErrorCode::MintBlocked)
+        );
+        let guards = blocklist_guards(
+            mint_block(&input_mint, false),
+            mint_block(&output_mint, true),
+        );
+        assert_eq!(
+            guards.check_mints(&input_mint, &output_mint).unwrap_err(),
+            error!(ErrorCode::MintBlocked)
+        );
+
+        // A marker for another mint can't stand in for the blocked one's
+        let guards = blocklist_guards(
+            mint_block(&output_mint, false),
+            mint_block(&output_mint, false),
+        );
+        assert_eq!(
+            guards.check_mints(&input_mint, &output_mint).unwrap_err(),
+            error!(ErrorCode::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn an_unblocked_mint_proceeds() {
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let guards = blocklist_guards(
+            mint_block(&input_mint, false),
+            mint_block(&output_mint, false),
+        );
+        guards.check_mints(&input_mint, &output_mint).unwrap();
+
+        // A blocked mint trades while the blocklist isn't enforced
+        let mut guards = blocklist_guards(
+            mint_block(&input_mint, true),
+            mint_block(&output_mint, false),
+        );
+        guards.config.enforce_blocklist = false;
+        guards.check_mints(&input_mint, &output_mint).unwrap();
+    }
+
+    // The instructions sysvar of a transaction invoking `programs` in order, executing
+    // the one at `current`
+    fn instructions_sysvar(programs: &[Pubkey], current: u16) -> AccountInfo<'static> {
+        use anchor_lang::solana_program::sysvar;
+        use instructions::{construct_instructions_data, store_current_index, BorrowedInstruction};
+
+        let instructions: Vec<BorrowedInstruction> = programs
+            .iter()
+            .map(|program_id| BorrowedInstruction {
+                program_id,
+                accounts: vec![],
+                data: &[],
+            })
+            .collect();
+        let mut data = construct_instructions_data(&instructions);
+        store_current_index(&mut data, current);
+        account_info(instructions::ID, sysvar::ID, data, false, false)
+    }
+
+    #[test]
+    fn a_frontrun_without_its_victim_after_it_is_rejected() {
+        let (victim, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // The victim only lands before the frontrun
+        let sysvar = instructions_sysvar(&[victim, crate::ID, other], 1);
+        let guards = frontrun_guards(config(0), none(), none(), sysvar);
+        assert_eq!(
+            guards.check_victim_ix(Some(victim)).unwrap_err(),
+            error!(ErrorCode::VictimNotInTransaction)
+        );
+
+        // Nothing follows the frontrun
+        let sysvar = instructions_sysvar(&[crate::ID], 0);
+        let guards = frontrun_guards(config(0), none(), none(), sysvar);
+        assert_eq!(
+            guards.check_victim_ix(Some(victim)).unwrap_err(),
+            error!(ErrorCode::VictimNotInTransaction)
+        );
+
+        // Without the sysvar the victim can't be found
+        let guards = frontrun_guards(config(0), none(), none(), none());
+        assert_eq!(
+            guards.check_victim_ix(Some(victim)).unwrap_err(),
+            error!(ErrorCode::VictimNotInTransaction)
+        );
+    }
+
+    #[test]
+    fn a_frontrun_followed_by_its_victim_proceeds() {
+        let (victim, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let sysvar = instructions_sysvar(&[other, crate::ID, other, victim, crate::ID], 1);
+        let guards = frontrun_guards(config(0), none(), none(), sysvar);
+        guards.check_victim_ix(Some(victim)).unwrap();
+
+        // Not asking for a victim skips the check
+        let guards = frontrun_guards(config(0), none(), none(), none());
+        guards.check_victim_ix(None).unwrap();
+    }
+}
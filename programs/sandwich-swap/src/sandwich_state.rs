@@ -1,6 +1,10 @@
 use anchor_lang::{account, event};
 use solana_program::pubkey::Pubkey;
 use anchor_lang::prelude::*;
+use anchor_spl::token::spl_token;
+
+use crate::error::ErrorCode;
+use crate::instructions::amm::quadratic::U256;
 
 #[account]
 pub struct SandwichState {
@@ -9,21 +13,893 @@ pub struct SandwichState {
     pub target_tx_signature: [u8; 64], // Target tx signature for tracking
     pub sandwich_id: u64,            // Unique identifier for this sandwich
     pub is_complete: bool,           // Flag to prevent double execution
-    pub token_in_mint: Pubkey,       // Input token mint (for verification)
-    pub token_out_mint: Pubkey,      // Output token mint (for verification)
+    pub token_in_mint: Pubkey,       // Mint the frontrun paid with, the backrun buys it back
+    pub token_out_mint: Pubkey,      // Mint the frontrun bought, the backrun sells it
     pub timestamp: i64,              // Timestamp for tracking
     pub bump: u8,                    // PDA bump
+    pub version: u8,                 // Layout/seed version, see SandwichState::VERSION
+    pub remaining_output_amount: u64, // Frontrun output not yet sold by a backrun
+    pub remaining_input_amount: u64, // Cost basis of the remaining output
+    pub frontrun_slot: u64,          // Slot the frontrun landed in
+    pub realized_profit: i64,        // Profit summed over the backruns so far
+    pub completed_at: i64,           // Timestamp of the backrun that completed the sandwich
+    pub completed_slot: u64,         // Slot of the backrun that completed the sandwich
+    pub retained_output_amount: u64, // Frontrun output kept as a long position, never sold
 }
 
 impl SandwichState {
-    pub const SIZE: usize = 8 + 8 + 64 + 8 + 1 + 32 + 32 + 8 + 1; // Size in bytes
+    pub const SIZE: usize = 8 + 8 + 64 + 8 + 1 + 32 + 32 + 8 + 1 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8; // Size in bytes
+
+    /// Current state version.
+    /// v2: PDA seeds are namespaced by the payer, `[b"sandwich", payer, sandwich_id (le)]`.
+    /// v3: appends the fields from `remaining_output_amount` through
+    /// `retained_output_amount`, `SIZE` bytes in all. A shorter v2 state is never
+    /// reallocated, `init_if_needed` refuses it for its space.
+    pub const VERSION: u8 = 3;
+
+    /// Address and bump of the state of `owner`'s sandwich `sandwich_id`, under the
+    /// current seeds.
+    pub fn pda(owner: &Pubkey, sandwich_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"sandwich", owner.as_ref(), &sandwich_id.to_le_bytes()],
+            &crate::ID,
+        )
+    }
+
+    /// Returns `(sell_amount, cost_basis)` for a backrun unwinding `backrun_fraction_bps`
+    /// of the original frontrun output, capped at what is still held.
+    /// A fraction of 0 unwinds the whole remaining position.
+    pub fn backrun_slice(&self, backrun_fraction_bps: u16) -> Result<(u64, u64)> {
+        require!(backrun_fraction_bps <= 10_000, ErrorCode::InvalidInput);
+
+        let remaining_output = self.remaining_output_amount;
+        let remaining_input = self.remaining_input_amount;
+        if backrun_fraction_bps == 0 {
+            return Ok((remaining_output, remaining_input));
+        }
+
+        let requested = (self.frontrun_output_amount as u128)
+            .checked_mul(backrun_fraction_bps as u128)
+            .ok_or(ErrorCode::CalculationFailure)?
+            / 10_000;
+        let sell_amount = std::cmp::min(requested as u64, remaining_output);
+        if sell_amount == 0 {
+            return err!(ErrorCode::InsufficientSandwichAmount);
+        }
+
+        // Pro-rata cost basis, the last slice takes whatever is left to avoid rounding dust
+        let cost_basis = if sell_amount == remaining_output {
+            remaining_input
+        } else {
+            ((remaining_input as u128) * (sell_amount as u128) / (remaining_output as u128)) as u64
+        };
+
+        Ok((sell_amount, cost_basis))
+    }
+
+    /// Sets `keep_output_amount` of the remaining output aside as a long position the
+    /// backruns never sell and returns its pro-rata cost basis. Something has to be left
+    /// to sell, keeping the whole position is a hold rather than a backrun.
+    pub fn retain_output(&mut self, keep_output_amount: u64) -> Result<u64> {
+        if keep_output_amount == 0 {
+            return Ok(0);
+        }
+
+        let remaining_output = self.remaining_output_amount;
+        require!(
+            keep_output_amount < remaining_output,
+            ErrorCode::InsufficientSandwichAmount
+        );
+        let kept_cost = ((self.remaining_input_amount as u128) * (keep_output_amount as u128)
+            / (remaining_output as u128)) as u64;
+
+        self.remaining_output_amount -= keep_output_amount;
+        self.remaining_input_amount -= kept_cost;
+        self.retained_output_amount = self
+            .retained_output_amount
+            .saturating_add(keep_output_amount);
+        Ok(kept_cost)
+    }
+
+    /// Opens a fresh position from a filled frontrun, resetting whatever a previous
+    /// sandwich left behind in a reused state. `token_in_mint` is the mint the frontrun
+    /// paid with and `token_out_mint` the one it bought, on every venue and whichever of
+    /// its input or output the frontrun fixed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_frontrun(
+        &mut self,
+        input_amount: u64,
+        output_amount: u64,
+        token_in_mint: Pubkey,
+        token_out_mint: Pubkey,
+        sandwich_id: u64,
+        bump: u8,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.frontrun_output_amount = output_amount;
+        self.frontrun_input_amount = input_amount;
+        self.sandwich_id = sandwich_id;
+        self.token_in_mint = token_in_mint;
+        self.token_out_mint = token_out_mint;
+        self.timestamp = clock.unix_timestamp;
+        self.is_complete = false;
+        self.bump = bump;
+        self.version = Self::VERSION;
+        self.remaining_output_amount = output_amount;
+        self.remaining_input_amount = input_amount;
+        self.frontrun_slot = clock.slot;
+        self.realized_profit = 0;
+        self.completed_at = 0;
+        self.completed_slot = 0;
+        self.retained_output_amount = 0;
+        Ok(())
+    }
+
+    /// Whether a backrun swapping `backrun_input_mint` into `backrun_output_mint` unwinds
+    /// the frontrun, selling the mint it bought for the one it paid with.
+    pub fn is_unwound_by(&self, backrun_input_mint: &Pubkey, backrun_output_mint: &Pubkey) -> bool {
+        self.token_out_mint == *backrun_input_mint && self.token_in_mint == *backrun_output_mint
+    }
+
+    /// Rejects a backrun of a frontrun that filled nothing, which has nothing to sell.
+    pub fn check_frontrun_filled(&self) -> Result<()> {
+        require!(self.frontrun_output_amount > 0, ErrorCode::EmptySupply);
+        Ok(())
+    }
+
+    /// Rejects a backrun landing fewer than `min_slots_between` slots after the frontrun,
+    /// e.g. 1 refuses to backrun in the frontrun's own slot.
+    pub fn check_backrun_slot(&self, min_slots_between: u8) -> Result<()> {
+        let current_slot = Clock::get()?.slot;
+        require!(
+            current_slot.saturating_sub(self.frontrun_slot) >= min_slots_between as u64,
+            ErrorCode::BackrunTooEarly
+        );
+        Ok(())
+    }
+
+    /// Rejects a backrun of a frontrun older than `max_state_age_secs`, 0 selects
+    /// `DEFAULT_MAX_STATE_AGE_SECS`. Whatever its deadline, a forgotten position isn't
+    /// unwound by accident days later at a wildly different price.
+    pub fn check_state_age(&self, max_state_age_secs: u32) -> Result<()> {
+        self.check_state_age_at(Clock::get()?.unix_timestamp, max_state_age_secs)
+    }
+
+    /// `check_state_age` against the clock reading `now`.
+    pub fn check_state_age_at(&self, now: i64, max_state_age_secs: u32) -> Result<()> {
+        check_frontrun_age(self.timestamp, now, max_state_age_secs)
+    }
+
+    /// Seconds since the frontrun, 0 if the clock reads earlier than the frontrun.
+    pub fn elapsed_secs(&self) -> Result<u64> {
+        let now = Clock::get()?.unix_timestamp;
+        Ok(now.saturating_sub(self.timestamp).max(0) as u64)
+    }
+
+    /// Records a (possibly partial) backrun; the sandwich is complete once fully unwound.
+    pub fn record_backrun(&mut self, sold_amount: u64, cost_basis: u64) {
+        self.remaining_output_amount = self.remaining_output_amount.saturating_sub(sold_amount);
+        self.remaining_input_amount = self.remaining_input_amount.saturating_sub(cost_basis);
+        self.is_complete = self.remaining_output_amount == 0;
+    }
+
+    /// Writes a backrun's profit into the state so a completed sandwich stays on chain
+    /// as a full record, see `record_outcome_at`.
+    pub fn record_outcome(&mut self, realized_profit: i64) -> Result<()> {
+        let clock = Clock::get()?;
+        self.record_outcome_at(realized_profit, clock.unix_timestamp, clock.slot);
+        Ok(())
+    }
+
+    /// Adds a backrun's profit to the running total; the backrun that completes the
+    /// sandwich also stamps its timestamp and slot.
+    pub fn record_outcome_at(&mut self, realized_profit: i64, timestamp: i64, slot: u64) {
+        self.realized_profit = self.realized_profit.saturating_add(realized_profit);
+        if self.is_complete {
+            self.completed_at = timestamp;
+            self.completed_slot = slot;
+        }
+    }
+
+    /// Snapshot of every field, emitted by `get_sandwich_state`.
+    pub fn to_event(&self) -> SandwichStateEvent {
+        SandwichStateEvent {
+            frontrun_output_amount: self.frontrun_output_amount,
+            frontrun_input_amount: self.frontrun_input_amount,
+            target_tx_signature: self.target_tx_signature,
+            sandwich_id: self.sandwich_id,
+            is_complete: self.is_complete,
+            token_in_mint: self.token_in_mint,
+            token_out_mint: self.token_out_mint,
+            timestamp: self.timestamp,
+            bump: self.bump,
+            version: self.version,
+            remaining_output_amount: self.remaining_output_amount,
+            remaining_input_amount: self.remaining_input_amount,
+            frontrun_slot: self.frontrun_slot,
+            realized_profit: self.realized_profit,
+            completed_at: self.completed_at,
+            completed_slot: self.completed_slot,
+            retained_output_amount: self.retained_output_amount,
+        }
+    }
+}
+
+/// Default age past which a backrun refuses to unwind a SandwichState (1 day).
+pub const DEFAULT_MAX_STATE_AGE_SECS: u32 = 86_400;
+
+/// Rejects unwinding a frontrun stamped `timestamp` once the clock reading `now` is more
+/// than `max_state_age_secs` past it, 0 selects `DEFAULT_MAX_STATE_AGE_SECS`.
+pub fn check_frontrun_age(timestamp: i64, now: i64, max_state_age_secs: u32) -> Result<()> {
+    let max_state_age_secs = if max_state_age_secs == 0 {
+        DEFAULT_MAX_STATE_AGE_SECS
+    } else {
+        max_state_age_secs
+    };
+    require!(
+        now.saturating_sub(timestamp) <= max_state_age_secs as i64,
+        ErrorCode::SandwichExpired
+    );
+    Ok(())
+}
+
+/// Default slippage allowed on our own frontrun swaps (5%).
+pub const DEFAULT_SELF_SLIPPAGE_BPS: u16 = 500;
+
+/// Slippage allowed on our own frontrun swaps, 0 selects `DEFAULT_SELF_SLIPPAGE_BPS`.
+pub fn self_slippage_bps_or_default(self_slippage_bps: u16) -> Result<u128> {
+    require!(self_slippage_bps < 10_000, ErrorCode::InvalidInput);
+    let slippage_bps = if self_slippage_bps == 0 {
+        DEFAULT_SELF_SLIPPAGE_BPS
+    } else {
+        self_slippage_bps
+    };
+    Ok(slippage_bps as u128)
+}
+
+/// Default slippage assumed for a victim whose minimum out is unknown (1%).
+pub const DEFAULT_VICTIM_SLIPPAGE_BPS: u16 = 100;
+
+/// Slippage assumed for a victim passing a minimum out of 0, i.e. unknown, 0 selects
+/// `DEFAULT_VICTIM_SLIPPAGE_BPS`.
+pub fn victim_slippage_bps_or_default(default_victim_slippage_bps: u16) -> Result<u128> {
+    require!(default_victim_slippage_bps < 10_000, ErrorCode::InvalidInput);
+    let slippage_bps = if default_victim_slippage_bps == 0 {
+        DEFAULT_VICTIM_SLIPPAGE_BPS
+    } else {
+        default_victim_slippage_bps
+    };
+    Ok(slippage_bps as u128)
+}
+
+/// Cap on how far our own frontrun may move the pool's mid price, 0 leaves it uncapped.
+pub fn self_price_impact_cap_bps(max_self_price_impact_bps: u16) -> Result<u128> {
+    require!(max_self_price_impact_bps <= 10_000, ErrorCode::InvalidInput);
+    if max_self_price_impact_bps == 0 {
+        return Ok(u128::MAX);
+    }
+    Ok(max_self_price_impact_bps as u128)
+}
+
+/// Slippage is carried in pips, millionths, and only compared against the basis-point
+/// settings, so a tolerance under a basis point isn't truncated away on the way.
+pub const PIPS_PER_BPS: u128 = 100;
+
+/// Pips in 100%.
+pub const PIPS_DENOMINATOR: u128 = 1_000_000;
+
+/// Least profit, in the frontrun's input token, a frontrun search has to find. A search
+/// that never beats it has only its unchecked starting guess to offer.
+pub const MIN_SANDWICH_PROFIT: u128 = 100;
+
+/// `slack` as a share of `reference`, in pips.
+pub fn slippage_pips(slack: u64, reference: u64) -> Result<u128> {
+    require!(reference > 0, ErrorCode::CalculationFailure);
+    Ok(slack as u128 * PIPS_DENOMINATOR / reference as u128)
+}
+
+/// Victim slippage a frontrun may consume, in pips, under `SlippageModel::Balanced`.
+pub fn safe_slippage_pips(
+    target_slippage_pips: u128,
+    max_absolute_slippage_consumed_bps: u16,
+) -> u128 {
+    SlippageModel::Balanced
+        .safe_slippage_pips(target_slippage_pips, max_absolute_slippage_consumed_bps)
+}
+
+/// How far an operator trusts the slippage estimates, one knob scaling both the victim
+/// slippage a frontrun consumes and the slippage allowed on its own swap.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlippageModel {
+    Conservative,
+    #[default]
+    Balanced,
+    Aggressive,
+}
+
+impl SlippageModel {
+    /// Percentage of the victim's tolerance a frontrun may consume, the rest is the margin
+    /// that keeps the victim's swap clearing.
+    pub fn victim_share_pct(self) -> u128 {
+        match self {
+            SlippageModel::Conservative => 80,
+            SlippageModel::Balanced => 95,
+            SlippageModel::Aggressive => 99,
+        }
+    }
+
+    /// Victim slippage a frontrun may consume, in pips: `victim_share_pct` of the victim's
+    /// tolerance, held to `max_absolute_slippage_consumed_bps` however loose that
+    /// tolerance is. 0 leaves it uncapped.
+    pub fn safe_slippage_pips(
+        self,
+        target_slippage_pips: u128,
+        max_absolute_slippage_consumed_bps: u16,
+    ) -> u128 {
+        let safe_slippage_pips = target_slippage_pips
+            .saturating_mul(self.victim_share_pct())
+            .saturating_div(100);
+        if max_absolute_slippage_consumed_bps == 0 {
+            return safe_slippage_pips;
+        }
+        safe_slippage_pips.min(max_absolute_slippage_consumed_bps as u128 * PIPS_PER_BPS)
+    }
+
+    /// Slippage allowed on our own frontrun swap, `self_slippage_bps` (0 for the default)
+    /// halved by `Conservative` and doubled by `Aggressive`. Never 0, which would select
+    /// the default again, and always under 100%.
+    pub fn self_slippage_bps(self, self_slippage_bps: u16) -> Result<u16> {
+        let slippage_bps = self_slippage_bps_or_default(self_slippage_bps)?;
+        let slippage_bps = match self {
+            SlippageModel::Conservative => slippage_bps / 2,
+            SlippageModel::Balanced => slippage_bps,
+            SlippageModel::Aggressive => slippage_bps * 2,
+        };
+        Ok(slippage_bps.clamp(1, 9_999) as u16)
+    }
+}
+
+/// Rejects a backrun selling `sell_amount` out of an input account holding only `balance`,
+/// e.g. when tokens were moved out since the frontrun, before the swap CPI fails on it.
+pub fn check_backrun_balance(balance: u64, sell_amount: u64) -> Result<()> {
+    require!(balance >= sell_amount, ErrorCode::InsufficientUserBalance);
+    Ok(())
+}
+
+/// Rejects a pool that opened less than `min_pool_age_secs` before `now`, so a fresh
+/// launch that may rug right away isn't sandwiched. 0 disables the check.
+pub fn check_pool_age(open_time: u64, now: i64, min_pool_age_secs: u32) -> Result<()> {
+    if min_pool_age_secs == 0 {
+        return Ok(());
+    }
+    let pool_age_secs = now as i128 - open_time as i128;
+    require!(
+        pool_age_secs >= min_pool_age_secs as i128,
+        ErrorCode::PoolTooNew
+    );
+    Ok(())
+}
+
+/// Rejects a frontrun whose pool moved more than `max_price_drift_bps` away from
+/// `expected_price`, the price the bot sized it at off-chain. 0 disables the check.
+pub fn check_price_drift(
+    live_price: u128,
+    expected_price: u128,
+    max_price_drift_bps: u16,
+) -> Result<()> {
+    if max_price_drift_bps == 0 {
+        return Ok(());
+    }
+    require!(expected_price > 0, ErrorCode::InvalidInput);
+    let drift = U256::full_mul(live_price.abs_diff(expected_price), 10_000);
+    let tolerance = U256::full_mul(expected_price, max_price_drift_bps as u128);
+    require!(drift <= tolerance, ErrorCode::PriceDrifted);
+    Ok(())
+}
+
+/// Minimum profit a backrun must lock in on top of its cost basis (0.5%).
+pub const MIN_PROFIT_BPS: u64 = 50;
+
+/// Output a backrun must return for `cost_basis`: the cost plus `MIN_PROFIT_BPS`, with the
+/// profit shrinking by `time_decay_bps_per_sec` per second held until it reaches break-even.
+pub fn min_required_output(
+    cost_basis: u64,
+    elapsed_secs: u64,
+    time_decay_bps_per_sec: u16,
+) -> Result<u64> {
+    let decay_bps = elapsed_secs.saturating_mul(time_decay_bps_per_sec as u64);
+    let profit_bps = MIN_PROFIT_BPS.saturating_sub(decay_bps);
+    let min_required_output = (cost_basis as u128)
+        .checked_mul(10_000 + profit_bps as u128)
+        .ok_or(ErrorCode::CalculationFailure)?
+        / 10_000;
+    u64::try_from(min_required_output).map_err(|_| error!(ErrorCode::CalculationFailure))
+}
+
+/// `min_required_output` raised to at least `cost_basis` plus `min_profit_amount`, in the
+/// profit mint's units. The relative floor lets a small position lock in a profit too small
+/// to be worth landing, this one doesn't decay with the time the position has been held.
+pub fn with_min_profit_amount(
+    min_required_output: u64,
+    cost_basis: u64,
+    min_profit_amount: u64,
+) -> Result<u64> {
+    let absolute_floor = cost_basis
+        .checked_add(min_profit_amount)
+        .ok_or(ErrorCode::CalculationFailure)?;
+    Ok(min_required_output.max(absolute_floor))
+}
+
+/// `min_required_output` raised by the `fixed_cost_lamports` the operator pays in priority
+/// fees and tips, so the profit check is net of them. Only a WSOL `profit_mint` pays in
+/// lamports, the floor of a profit in any other mint is left unchanged.
+pub fn with_fixed_cost(
+    min_required_output: u64,
+    profit_mint: &Pubkey,
+    fixed_cost_lamports: u64,
+) -> Result<u64> {
+    if *profit_mint != spl_token::native_mint::ID {
+        return Ok(min_required_output);
+    }
+    let min_required_output = min_required_output
+        .checked_add(fixed_cost_lamports)
+        .ok_or(ErrorCode::CalculationFailure)?;
+    Ok(min_required_output)
+}
+
+/// Output a backrun must not fall below: `min_required_output`, or with a
+/// `max_acceptable_loss` the cost basis less that loss, so the position can be unwound
+/// at a bounded loss to free capital instead of reverting.
+pub fn backrun_output_floor(
+    cost_basis: u64,
+    min_required_output: u64,
+    max_acceptable_loss: Option<u64>,
+) -> u64 {
+    match max_acceptable_loss {
+        Some(max_acceptable_loss) => cost_basis.saturating_sub(max_acceptable_loss),
+        None => min_required_output,
+    }
+}
+
+/// `output_amount` of a backrun selling `sold_amount` plus the `retained_amount` kept back
+/// from it, valued at the price the sale got, so a kept long position counts toward the
+/// profit.
+pub fn with_retained_output(output_amount: u64, sold_amount: u64, retained_amount: u64) -> u64 {
+    if sold_amount == 0 {
+        return output_amount;
+    }
+    let value = (retained_amount as u128) * (output_amount as u128) / (sold_amount as u128);
+    output_amount.saturating_add(u64::try_from(value).unwrap_or(u64::MAX))
+}
+
+/// Signed profit of a sandwich, negative when the backrun returned less than it cost.
+pub fn signed_profit(output_amount: u64, input_amount: u64) -> i64 {
+    let profit = output_amount as i128 - input_amount as i128;
+    profit.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Rejects a victim whose slippage tolerance, in pips, is under `min_victim_slippage_bps`,
+/// too tight to sandwich without breaking their swap.
+pub fn check_victim_slippage(
+    target_slippage_pips: u128,
+    min_victim_slippage_bps: u16,
+) -> Result<()> {
+    require!(
+        target_slippage_pips >= min_victim_slippage_bps as u128 * PIPS_PER_BPS,
+        ErrorCode::VictimSlippageTooTight
+    );
+    Ok(())
 }
 
 #[event]
 pub struct SandwichCompleteEvent {
     pub sandwich_id: u64,
-    pub profit: u64,
+    pub realized_profit: i64,         // Negative for a losing sandwich
+    pub expected_profit: Option<i64>, // From the backrun's simulated output, if it ran one
     pub input_amount: u64,
     pub output_amount: u64,
     pub timestamp: i64,
 }
+
+impl SandwichCompleteEvent {
+    /// Completion of a backrun that received `output_amount` for `input_amount`, next to the
+    /// profit its sizing predicted from `expected_output`.
+    pub fn new(
+        sandwich_id: u64,
+        input_amount: u64,
+        output_amount: u64,
+        expected_output: Option<u64>,
+        timestamp: i64,
+    ) -> Self {
+        SandwichCompleteEvent {
+            sandwich_id,
+            realized_profit: signed_profit(output_amount, input_amount),
+            expected_profit: expected_output
+                .map(|expected_output| signed_profit(expected_output, input_amount)),
+            input_amount,
+            output_amount,
+            timestamp,
+        }
+    }
+}
+
+#[event]
+pub struct ClmmFrontrunDetailEvent {
+    pub optimal_amount: u64,
+    pub frontrun_sqrt_price_limit_x64: u128,
+    pub current_sqrt_price_x64: u128,
+    pub zero_for_one: bool,
+    pub safe_slippage_bps: u128,
+}
+
+#[event]
+pub struct GridSimEvent {
+    pub amount: u64,
+    pub profit: i64, // Negative for a losing amount
+}
+
+#[event]
+pub struct PumpFunFrontrunSimEvent {
+    pub my_token_amount_out: u64,
+    pub my_max_sol_in: u64,
+    pub profit_pct: f64, // Fraction of the SOL paid, 0.005 for 0.5%
+}
+
+// Mirrors SandwichState, keep both in sync
+#[event]
+pub struct SandwichStateEvent {
+    pub frontrun_output_amount: u64,
+    pub frontrun_input_amount: u64,
+    pub target_tx_signature: [u8; 64],
+    pub sandwich_id: u64,
+    pub is_complete: bool,
+    pub token_in_mint: Pubkey,
+    pub token_out_mint: Pubkey,
+    pub timestamp: i64,
+    pub bump: u8,
+    pub version: u8,
+    pub remaining_output_amount: u64,
+    pub remaining_input_amount: u64,
+    pub frontrun_slot: u64,
+    pub realized_profit: i64,
+    pub completed_at: i64,
+    pub completed_slot: u64,
+    pub retained_output_amount: u64,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // An open position recorded by a frontrun paying `token_in_mint` for `token_out_mint`
+    pub(crate) fn state_with_mints(token_in_mint: Pubkey, token_out_mint: Pubkey) -> SandwichState {
+        SandwichState {
+            frontrun_output_amount: 1_000,
+            frontrun_input_amount: 500,
+            target_tx_signature: [0; 64],
+            sandwich_id: 1,
+            is_complete: false,
+            token_in_mint,
+            token_out_mint,
+            timestamp: 100,
+            bump: 255,
+            version: SandwichState::VERSION,
+            remaining_output_amount: 1_000,
+            remaining_input_amount: 500,
+            frontrun_slot: 40,
+            realized_profit: 0,
+            completed_at: 0,
+            completed_slot: 0,
+            retained_output_amount: 0,
+        }
+    }
+
+    #[test]
+    fn the_state_event_round_trips_every_field() {
+        let state = SandwichState {
+            frontrun_output_amount: 1,
+            frontrun_input_amount: 2,
+            target_tx_signature: [3; 64],
+            sandwich_id: 4,
+            is_complete: true,
+            token_in_mint: Pubkey::new_unique(),
+            token_out_mint: Pubkey::new_unique(),
+            timestamp: 5,
+            bump: 6,
+            version: 7,
+            remaining_output_amount: 8,
+            remaining_input_amount: 9,
+            frontrun_slot: 10,
+            realized_profit: -11,
+            completed_at: 12,
+            completed_slot: 13,
+            retained_output_amount: 14,
+        };
+
+        // Same fields in the same order, so an indexer decodes the event as the account
+        let data = state.to_event().try_to_vec().unwrap();
+        assert_eq!(data, state.try_to_vec().unwrap());
+
+        let event = SandwichStateEvent::try_from_slice(&data).unwrap();
+        assert_eq!(event.token_in_mint, state.token_in_mint);
+        assert_eq!(event.frontrun_slot, state.frontrun_slot);
+    }
+
+    #[test]
+    fn only_the_opposite_swap_unwinds_the_frontrun() {
+        let (paid, bought) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let state = state_with_mints(paid, bought);
+
+        assert!(state.is_unwound_by(&bought, &paid));
+        // Repeating the frontrun's direction, or a mint it never touched
+        assert!(!state.is_unwound_by(&paid, &bought));
+        assert!(!state.is_unwound_by(&bought, &Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn a_frontrun_that_filled_nothing_has_no_backrun() {
+        let mut state = state_with_mints(Pubkey::new_unique(), Pubkey::new_unique());
+        state.frontrun_output_amount = 0;
+        state.remaining_output_amount = 0;
+        // The CLMM, CPMM, AMM and PumpFun backruns all check this before anything else
+        assert_eq!(
+            state.check_frontrun_filled().unwrap_err(),
+            error!(ErrorCode::EmptySupply)
+        );
+
+        state.frontrun_output_amount = 1_000;
+        assert!(state.check_frontrun_filled().is_ok());
+    }
+
+    #[test]
+    fn a_state_past_its_max_age_is_rejected() {
+        // Recorded by a frontrun at timestamp 100
+        let state = state_with_mints(Pubkey::new_unique(), Pubkey::new_unique());
+
+        // A minute later the backrun proceeds, an hour later it's over a 10 minute cap
+        assert!(state.check_state_age_at(160, 600).is_ok());
+        assert_eq!(
+            state.check_state_age_at(3_700, 600).unwrap_err(),
+            error!(ErrorCode::SandwichExpired)
+        );
+
+        // Left at 0 the cap is a day, a days-old position is still refused
+        assert!(state.check_state_age_at(3_700, 0).is_ok());
+        assert_eq!(
+            state.check_state_age_at(100 + 3 * 86_400, 0).unwrap_err(),
+            error!(ErrorCode::SandwichExpired)
+        );
+    }
+
+    #[test]
+    fn a_completed_state_keeps_the_final_snapshot() {
+        let mut state = state_with_mints(Pubkey::new_unique(), Pubkey::new_unique());
+
+        // A partial backrun adds its profit but leaves the sandwich open
+        state.record_backrun(400, 200);
+        state.record_outcome_at(30, 110, 42);
+        assert_eq!(state.realized_profit, 30);
+        assert_eq!((state.completed_at, state.completed_slot), (0, 0));
+
+        state.record_backrun(600, 300);
+        state.record_outcome_at(-10, 120, 45);
+
+        // Read back the account as an indexer would
+        let mut data = Vec::new();
+        state.try_serialize(&mut data).unwrap();
+        let stored = SandwichState::try_deserialize(&mut data.as_slice()).unwrap();
+        assert!(stored.is_complete);
+        assert_eq!(stored.realized_profit, 20);
+        assert_eq!(stored.completed_at, 120);
+        assert_eq!(stored.completed_slot, 45);
+    }
+
+    #[test]
+    fn a_kept_output_is_set_aside_from_the_backrun() {
+        let mut state = state_with_mints(Pubkey::new_unique(), Pubkey::new_unique());
+
+        // Keep 300 of the 1_000 bought for 500, the backrun unwinds the other 700
+        assert_eq!(state.retain_output(300).unwrap(), 150);
+        let (sell_amount, cost_basis) = state.backrun_slice(0).unwrap();
+        assert_eq!((sell_amount, cost_basis), (700, 350));
+
+        state.record_backrun(sell_amount, cost_basis);
+        assert!(state.is_complete);
+        assert_eq!(state.remaining_output_amount, 0);
+        assert_eq!(state.retained_output_amount, 300);
+
+        // Selling 700 for 770 values the kept 300 at 330
+        assert_eq!(with_retained_output(770, 700, 300), 1_100);
+        assert_eq!(with_retained_output(770, 700, 0), 770);
+    }
+
+    #[test]
+    fn keeping_the_whole_position_is_refused() {
+        let mut state = state_with_mints(Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(
+            state.retain_output(1_000).unwrap_err(),
+            error!(ErrorCode::InsufficientSandwichAmount)
+        );
+        // Nothing was set aside by the refused call
+        assert_eq!(state.retain_output(0).unwrap(), 0);
+        assert_eq!(state.remaining_output_amount, 1_000);
+        assert_eq!(state.retained_output_amount, 0);
+    }
+
+    #[test]
+    fn min_required_output_adds_the_min_profit() {
+        assert_eq!(min_required_output(10_000, 0, 0).unwrap(), 10_050);
+        assert_eq!(min_required_output(0, 0, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn min_required_output_decays_to_break_even() {
+        assert_eq!(min_required_output(10_000, 2, 10).unwrap(), 10_030);
+        assert_eq!(min_required_output(10_000, 10, 10).unwrap(), 10_000);
+        assert_eq!(
+            min_required_output(10_000, u64::MAX, u16::MAX).unwrap(),
+            10_000
+        );
+    }
+
+    #[test]
+    fn a_small_absolute_profit_is_raised_to_the_min_profit_amount() {
+        // 0.5% of 10_000 is a 50 profit, the absolute floor asks for 1_000
+        assert_eq!(
+            with_min_profit_amount(10_050, 10_000, 1_000).unwrap(),
+            11_000
+        );
+        // Below the relative profit it changes nothing, nor does a zero amount
+        assert_eq!(with_min_profit_amount(10_050, 10_000, 20).unwrap(), 10_050);
+        assert_eq!(with_min_profit_amount(10_050, 10_000, 0).unwrap(), 10_050);
+        assert_eq!(
+            with_min_profit_amount(u64::MAX, u64::MAX, 1).unwrap_err(),
+            error!(ErrorCode::CalculationFailure)
+        );
+    }
+
+    #[test]
+    fn the_fixed_cost_only_raises_a_sol_profit_floor() {
+        let wsol = spl_token::native_mint::ID;
+        assert_eq!(with_fixed_cost(10_050, &wsol, 5_000).unwrap(), 15_050);
+        assert_eq!(with_fixed_cost(10_050, &wsol, 0).unwrap(), 10_050);
+        assert_eq!(
+            with_fixed_cost(10_050, &Pubkey::new_unique(), 5_000).unwrap(),
+            10_050
+        );
+        assert_eq!(
+            with_fixed_cost(u64::MAX, &wsol, 1).unwrap_err(),
+            error!(ErrorCode::CalculationFailure)
+        );
+    }
+
+    #[test]
+    fn the_complete_event_reports_realized_and_expected_profit() {
+        // Simulated 10_100 back for a 10_000 cost, the balance only grew by 10_050
+        let (balance_before, balance_after) = (5_000u64, 15_050u64);
+        let event = SandwichCompleteEvent::new(
+            1,
+            10_000,
+            balance_after - balance_before,
+            Some(10_100),
+            2,
+        );
+        assert_eq!(event.realized_profit, 50);
+        assert_eq!(event.expected_profit, Some(100));
+        assert_eq!(event.output_amount, 10_050);
+
+        let event = SandwichCompleteEvent::new(1, 10_000, 9_900, None, 2);
+        assert_eq!(event.realized_profit, -100);
+        assert_eq!(event.expected_profit, None);
+    }
+
+    #[test]
+    fn the_absolute_cap_holds_a_loose_victim_tolerance() {
+        // 95% of the victim's tolerance while it stays under the cap
+        assert_eq!(safe_slippage_pips(50_000, 800), 47_500);
+        assert_eq!(safe_slippage_pips(500_000, 800), 80_000);
+        assert_eq!(safe_slippage_pips(500_000, 0), 475_000);
+    }
+
+    #[test]
+    fn a_sub_basis_point_slippage_keeps_its_precision() {
+        // 0.05% is 500 pips, 95% of it doesn't truncate to 4 bps
+        assert_eq!(slippage_pips(500, 1_000_000).unwrap(), 500);
+        assert_eq!(safe_slippage_pips(500, 0), 475);
+        // A fraction of a basis point still registers
+        assert_eq!(slippage_pips(7, 100_000).unwrap(), 70);
+        assert_eq!(
+            slippage_pips(1, 0).unwrap_err(),
+            error!(ErrorCode::CalculationFailure)
+        );
+    }
+
+    #[test]
+    fn the_slippage_model_scales_both_tolerances() {
+        use SlippageModel::*;
+
+        // 80%, 95% and 99% of a 5% victim tolerance
+        assert_eq!(Conservative.safe_slippage_pips(50_000, 0), 40_000);
+        assert_eq!(Balanced.safe_slippage_pips(50_000, 0), 47_500);
+        assert_eq!(Aggressive.safe_slippage_pips(50_000, 0), 49_500);
+        // The absolute cap still binds whatever the model
+        assert_eq!(Aggressive.safe_slippage_pips(50_000, 400), 40_000);
+
+        // Half and double the default 5% on our own swap, an explicit 1 bps never rounds
+        // down to 0 (which would select the default again)
+        assert_eq!(Conservative.self_slippage_bps(0).unwrap(), 250);
+        assert_eq!(Balanced.self_slippage_bps(0).unwrap(), 500);
+        assert_eq!(Aggressive.self_slippage_bps(0).unwrap(), 1_000);
+        assert_eq!(Conservative.self_slippage_bps(1).unwrap(), 1);
+        assert_eq!(Aggressive.self_slippage_bps(6_000).unwrap(), 9_999);
+    }
+
+    #[test]
+    fn a_price_past_the_drift_tolerance_is_rejected() {
+        // 1% either way is within a 100 bps tolerance, a hair more is not
+        assert!(check_price_drift(10_100, 10_000, 100).is_ok());
+        assert!(check_price_drift(9_900, 10_000, 100).is_ok());
+        assert_eq!(
+            check_price_drift(10_101, 10_000, 100).unwrap_err(),
+            error!(ErrorCode::PriceDrifted)
+        );
+        assert_eq!(
+            check_price_drift(9_899, 10_000, 100).unwrap_err(),
+            error!(ErrorCode::PriceDrifted)
+        );
+        assert!(check_price_drift(u128::MAX, 10_000, 0).is_ok());
+    }
+
+    #[test]
+    fn a_drained_input_account_fails_before_the_swap() {
+        // The frontrun bought 10_000, all but 2_500 were moved out since
+        assert_eq!(
+            check_backrun_balance(2_500, 10_000).unwrap_err(),
+            error!(ErrorCode::InsufficientUserBalance)
+        );
+        assert!(check_backrun_balance(10_000, 10_000).is_ok());
+        // Tokens held besides the position don't get in the way
+        assert!(check_backrun_balance(12_000, 10_000).is_ok());
+    }
+
+    #[test]
+    fn a_pool_younger_than_the_floor_is_rejected() {
+        let now = 1_700_000_000;
+        assert_eq!(
+            check_pool_age(now as u64 - 1, now, 60).unwrap_err(),
+            error!(ErrorCode::PoolTooNew)
+        );
+        assert!(check_pool_age(now as u64 - 3_600, now, 60).is_ok());
+        // Not open yet is younger than any floor, a zero floor skips the check
+        assert!(check_pool_age(now as u64 + 10, now, 1).is_err());
+        assert!(check_pool_age(now as u64 + 10, now, 0).is_ok());
+    }
+
+    #[test]
+    fn signed_profit_is_signed_and_clamped() {
+        assert_eq!(signed_profit(150, 100), 50);
+        assert_eq!(signed_profit(100, 150), -50);
+        assert_eq!(signed_profit(100, 100), 0);
+        assert_eq!(signed_profit(u64::MAX, 0), i64::MAX);
+        assert_eq!(signed_profit(0, u64::MAX), i64::MIN);
+    }
+
+    #[test]
+    fn a_victim_below_the_slippage_floor_is_skipped() {
+        // A zero-slippage victim's swap breaks on any frontrun
+        assert_eq!(
+            check_victim_slippage(0, 10).unwrap_err(),
+            error!(ErrorCode::VictimSlippageTooTight)
+        );
+        // 9.99 bps is short of a 10 bps floor, the pips don't round up to it
+        assert_eq!(
+            check_victim_slippage(999, 10).unwrap_err(),
+            error!(ErrorCode::VictimSlippageTooTight)
+        );
+
+        // A loose 1% victim clears a 10 bps floor, and without a floor anything goes
+        assert!(check_victim_slippage(10_000, 10).is_ok());
+        assert!(check_victim_slippage(1_000, 10).is_ok());
+        assert!(check_victim_slippage(0, 0).is_ok());
+    }
+}
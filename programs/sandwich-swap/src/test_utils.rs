@@ -0,0 +1,128 @@
+use anchor_lang::prelude::*;
+use solana_program::entrypoint::{ProgramResult, SUCCESS};
+use solana_program::instruction::Instruction;
+use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use std::sync::{Mutex, MutexGuard};
+
+// Leaks the account's buffers, the accounts context borrows them for its whole lifetime
+pub(crate) fn account_info(
+    key: Pubkey,
+    owner: Pubkey,
+    data: Vec<u8>,
+    is_signer: bool,
+    executable: bool,
+) -> AccountInfo<'static> {
+    AccountInfo::new(
+        Box::leak(Box::new(key)),
+        is_signer,
+        true,
+        Box::leak(Box::new(0)),
+        data.leak(),
+        Box::leak(Box::new(owner)),
+        executable,
+        0,
+    )
+}
+
+// An executable account at `id`, what a `Program` account checks for
+pub(crate) fn program(id: Pubkey) -> AccountInfo<'static> {
+    account_info(id, Pubkey::default(), vec![], false, true)
+}
+
+// An account of this program at `key` holding `state` behind its discriminator
+pub(crate) fn program_account<T: AccountSerialize>(
+    key: Pubkey,
+    space: usize,
+    state: &T,
+) -> AccountInfo<'static> {
+    let mut data = vec![0u8; space];
+    state.try_serialize(&mut data.as_mut_slice()).unwrap();
+    account_info(key, crate::ID, data, false, false)
+}
+
+// Applies an invoked instruction to its accounts, standing in for the callee
+pub(crate) type InvokeHandler = fn(&Instruction, &[AccountInfo]) -> ProgramResult;
+
+// The syscall stubs are one process-wide global, every test using them holds this lock
+static SYSCALLS: Mutex<()> = Mutex::new(());
+
+// The instructions invoked and their signer seeds, the CPI never leaves the test
+static INVOKED: Mutex<Vec<(Instruction, Vec<Vec<u8>>)>> = Mutex::new(Vec::new());
+static ON_INVOKE: Mutex<Option<InvokeHandler>> = Mutex::new(None);
+static RETURN_DATA: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static CLOCK: Mutex<Option<Clock>> = Mutex::new(None);
+
+struct TestStubs;
+
+impl SyscallStubs for TestStubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let seeds = signers_seeds
+            .concat()
+            .iter()
+            .map(|seed| seed.to_vec())
+            .collect();
+        INVOKED.lock().unwrap().push((instruction.clone(), seeds));
+        let handler = *ON_INVOKE.lock().unwrap();
+        match handler {
+            Some(handler) => handler(instruction, account_infos),
+            None => Ok(()),
+        }
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = CLOCK.lock().unwrap().clone().unwrap_or_default();
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        *RETURN_DATA.lock().unwrap() = data.to_vec();
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        Some((crate::ID, RETURN_DATA.lock().unwrap().clone()))
+    }
+}
+
+// Installs the stubs with nothing invoked yet, a default clock and no return data, held
+// for the test until the guard drops
+pub(crate) fn syscall_stubs() -> MutexGuard<'static, ()> {
+    let guard = SYSCALLS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    INVOKED.lock().unwrap().clear();
+    *ON_INVOKE.lock().unwrap() = None;
+    RETURN_DATA.lock().unwrap().clear();
+    *CLOCK.lock().unwrap() = None;
+    set_syscall_stubs(Box::new(TestStubs));
+    guard
+}
+
+// The clock `Clock::get` reads until the stubs are installed again
+pub(crate) fn set_clock(slot: u64, unix_timestamp: i64) {
+    *CLOCK.lock().unwrap() = Some(Clock {
+        slot,
+        unix_timestamp,
+        ..Default::default()
+    });
+}
+
+// Runs `handler` on every instruction invoked until the stubs are installed again
+pub(crate) fn on_invoke(handler: InvokeHandler) {
+    *ON_INVOKE.lock().unwrap() = Some(handler);
+}
+
+// Takes the instructions invoked so far, oldest first
+pub(crate) fn take_invoked() -> Vec<(Instruction, Vec<Vec<u8>>)> {
+    std::mem::take(&mut *INVOKED.lock().unwrap())
+}
@@ -0,0 +1,332 @@
+//! LiteSVM harness for the end-to-end sandwich tests. sandwich-swap runs against the
+//! mock DEX in `fixtures/mock-dex`, deployed at both the Raydium CPMM and PumpFun ids.
+//!
+//! Build both programs before running the tests, from the workspace root:
+//!
+//! ```text
+//! anchor build
+//! cargo build-sbf --manifest-path programs/sandwich-swap/tests/fixtures/mock-dex/Cargo.toml --sbf-out-dir target/deploy
+//! ```
+
+// Each test binary uses its own subset of the helpers
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use anchor_lang::{
+    AccountDeserialize, AccountSerialize, AnchorDeserialize, Discriminator, Id, InstructionData,
+    ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use litesvm::{types::TransactionMetadata, LiteSVM};
+use raydium_cpmm_cpi::program::RaydiumCpmm;
+use sandwich_swap::instructions::PumpFun;
+use sandwich_swap::program_config::{__client_accounts_frontrun_guards, ProgramConfig};
+use sandwich_swap::sandwich_state::{SandwichCompleteEvent, SandwichState};
+use solana_sdk::{
+    account::Account,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Clock,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+const OPERATOR_LAMPORTS: u64 = 1_000 * 1_000_000_000;
+
+pub struct Harness {
+    pub svm: LiteSVM,
+    /// Signs the sandwiches, holds the upgrade authority and owns the program config
+    pub operator: Keypair,
+}
+
+impl Harness {
+    /// Deploys sandwich-swap upgradeable by the operator next to the mock DEX, funds the
+    /// operator and initializes the program config
+    pub fn new() -> Self {
+        let mut svm = LiteSVM::new();
+        let mock_dex = read_program("mock_dex");
+        svm.add_program(RaydiumCpmm::id(), &mock_dex);
+        svm.add_program(PumpFun::id(), &mock_dex);
+
+        let operator = Keypair::new();
+        svm.airdrop(&operator.pubkey(), OPERATOR_LAMPORTS).unwrap();
+
+        let mut harness = Self { svm, operator };
+        harness.deploy_sandwich_swap();
+        harness.initialize_config();
+        harness
+    }
+
+    // The config may only be initialized by the upgrade authority, which `add_program`
+    // leaves out, so the program and its program data are laid out as the upgradeable
+    // loader would. The program data goes first, the program is loaded from it
+    fn deploy_sandwich_swap(&mut self) {
+        let program_data = program_data_address();
+        let mut data = bincode_state(&UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: Some(self.operator.pubkey()),
+        });
+        data.extend_from_slice(&read_program("sandwich_swap"));
+        self.set_data_account(program_data, bpf_loader_upgradeable::ID, data);
+
+        let data = bincode_state(&UpgradeableLoaderState::Program {
+            programdata_address: program_data,
+        });
+        self.set_account(
+            sandwich_swap::ID,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: bpf_loader_upgradeable::ID,
+                executable: true,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    fn initialize_config(&mut self) {
+        let ix = Instruction {
+            program_id: sandwich_swap::ID,
+            accounts: sandwich_swap::accounts::InitializeConfig {
+                admin: self.operator.pubkey(),
+                config: config_pda(),
+                program: sandwich_swap::ID,
+                program_data: program_data_address(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: sandwich_swap::instruction::InitializeConfig {
+                max_consecutive_losses: 0,
+            }
+            .data(),
+        };
+        self.send(&[ix], &[]);
+    }
+
+    /// Sends `ixs` paid for by the operator with the compute limit raised to the maximum,
+    /// panicking with the program logs if the transaction fails
+    pub fn send(&mut self, ixs: &[Instruction], extra_signers: &[&Keypair]) -> TransactionMetadata {
+        let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_400_000)];
+        instructions.extend_from_slice(ixs);
+
+        let mut signers = vec![&self.operator];
+        signers.extend_from_slice(extra_signers);
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.operator.pubkey()),
+            &signers,
+            self.svm.latest_blockhash(),
+        );
+
+        match self.svm.send_transaction(tx) {
+            Ok(meta) => meta,
+            Err(failed) => panic!(
+                "transaction failed: {:?}\n{}",
+                failed.err,
+                failed.meta.logs.join("\n")
+            ),
+        }
+    }
+
+    /// Moves the clock forward `slots` slots
+    pub fn advance_slots(&mut self, slots: u64) {
+        let slot = self.svm.get_sysvar::<Clock>().slot;
+        self.svm.warp_to_slot(slot + slots);
+    }
+
+    pub fn set_account(&mut self, address: Pubkey, account: Account) {
+        self.svm.set_account(address, account).unwrap();
+    }
+
+    /// Account holding `data`, rent exempt
+    pub fn set_data_account(&mut self, address: Pubkey, owner: Pubkey, data: Vec<u8>) {
+        self.set_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    /// Anchor `#[account]` fixture, serialized with its discriminator
+    pub fn set_anchor_account<T: AccountSerialize>(
+        &mut self,
+        address: Pubkey,
+        owner: Pubkey,
+        value: &T,
+    ) {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        self.set_data_account(address, owner, data);
+    }
+
+    /// Anchor zero-copy fixture, its discriminator followed by the raw struct
+    pub fn set_zero_copy_account<T: Discriminator + bytemuck::Pod>(
+        &mut self,
+        address: Pubkey,
+        owner: Pubkey,
+        value: &T,
+    ) {
+        let mut data = T::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(value));
+        self.set_data_account(address, owner, data);
+    }
+
+    /// New SPL Token mint with no mint authority
+    pub fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint = Pubkey::new_unique();
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals,
+            is_initialized: true,
+            supply: u64::MAX / 2,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        self.set_data_account(mint, spl_token::ID, data);
+        mint
+    }
+
+    /// SPL Token account at `address` holding `amount` of `mint`
+    pub fn set_token_account(&mut self, address: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        self.set_data_account(address, spl_token::ID, data);
+    }
+
+    /// New SPL Token account holding `amount` of `mint`
+    pub fn create_token_account(&mut self, mint: Pubkey, owner: Pubkey, amount: u64) -> Pubkey {
+        let address = Pubkey::new_unique();
+        self.set_token_account(address, mint, owner, amount);
+        address
+    }
+
+    pub fn token_balance(&self, address: &Pubkey) -> u64 {
+        let account = self
+            .svm
+            .get_account(address)
+            .expect("token account missing");
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    }
+
+    pub fn lamports(&self, address: &Pubkey) -> u64 {
+        self.svm
+            .get_account(address)
+            .map_or(0, |account| account.lamports)
+    }
+
+    pub fn sandwich_state(&self, sandwich_id: u64) -> SandwichState {
+        let account = self
+            .svm
+            .get_account(&sandwich_pda(&self.operator.pubkey(), sandwich_id))
+            .expect("sandwich state missing");
+        SandwichState::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    /// Frontrun guards for a config that enforces neither the allowlist nor the blocklist,
+    /// nor any limit per slot
+    pub fn guards(&self) -> __client_accounts_frontrun_guards::FrontrunGuards {
+        __client_accounts_frontrun_guards::FrontrunGuards {
+            config: config_pda(),
+            allowed_pool: None,
+            frontrun_slot: None,
+            input_mint_block: None,
+            output_mint_block: None,
+            instructions_sysvar: None,
+            slot_counter: None,
+        }
+    }
+}
+
+fn read_program(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../../target/deploy")
+        .join(format!("{name}.so"));
+    std::fs::read(&path).unwrap_or_else(|err| {
+        panic!(
+            "can't read {}: {err}. Build the programs first, see tests/common/mod.rs",
+            path.display()
+        )
+    })
+}
+
+// The upgradeable loader's bincode layout: a u32 variant, then the variant's fields with
+// an option as a one byte tag
+fn bincode_state(state: &UpgradeableLoaderState) -> Vec<u8> {
+    let mut data = Vec::new();
+    match state {
+        UpgradeableLoaderState::Program {
+            programdata_address,
+        } => {
+            data.extend_from_slice(&2u32.to_le_bytes());
+            data.extend_from_slice(programdata_address.as_ref());
+        }
+        UpgradeableLoaderState::ProgramData {
+            slot,
+            upgrade_authority_address,
+        } => {
+            data.extend_from_slice(&3u32.to_le_bytes());
+            data.extend_from_slice(&slot.to_le_bytes());
+            match upgrade_authority_address {
+                Some(upgrade_authority) => {
+                    data.push(1);
+                    data.extend_from_slice(upgrade_authority.as_ref());
+                }
+                None => data.push(0),
+            }
+        }
+        _ => unimplemented!("only the program and its program data are laid out"),
+    }
+    data
+}
+
+pub fn program_data_address() -> Pubkey {
+    Pubkey::find_program_address(&[sandwich_swap::ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+pub fn config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[ProgramConfig::SEED], &sandwich_swap::ID).0
+}
+
+pub fn sandwich_pda(owner: &Pubkey, sandwich_id: u64) -> Pubkey {
+    SandwichState::pda(owner, sandwich_id).0
+}
+
+/// sandwich-swap's `#[event_cpi]` authority
+pub fn event_authority() -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], &sandwich_swap::ID).0
+}
+
+/// `SandwichCompleteEvent` a backrun emitted with `emit_cpi!`, from the self-CPI's data
+pub fn sandwich_complete_event(meta: &TransactionMetadata) -> SandwichCompleteEvent {
+    meta.inner_instructions
+        .iter()
+        .flatten()
+        .map(|inner| inner.instruction.data.as_slice())
+        .find(|data| {
+            data.len() >= 16
+                && data[..8] == anchor_lang::event::EVENT_IX_TAG_LE
+                && data[8..16] == SandwichCompleteEvent::DISCRIMINATOR
+        })
+        .map(|data| SandwichCompleteEvent::try_from_slice(&data[16..]).unwrap())
+        .expect("no SandwichCompleteEvent self-CPI")
+}
@@ -0,0 +1,290 @@
+//! Frontrun, victim and backrun of a Raydium CPMM base-input sandwich against the mock CPMM.
+
+mod common;
+
+use anchor_lang::{Id, InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token::spl_token;
+use common::*;
+use raydium_cpmm_cpi::program::RaydiumCpmm;
+use sandwich_swap::instructions::{
+    CpmmAmmConfig, CpmmObservationState, CpmmPoolState, CurveCalculator,
+};
+use sandwich_swap::sandwich_state::SlippageModel;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program,
+};
+
+const RESERVE: u64 = 1_000_000_000_000;
+/// 0.25%, in hundredths of a bip
+const TRADE_FEE_RATE: u64 = 2_500;
+const VICTIM_AMOUNT_IN: u64 = 20_000_000_000;
+/// The victim accepts 3% less than the quote
+const VICTIM_SLIPPAGE_PCT: u64 = 3;
+const SANDWICH_ID: u64 = 1;
+
+struct Pool {
+    amm_config: Pubkey,
+    pool_state: Pubkey,
+    observation_state: Pubkey,
+    authority: Pubkey,
+    mint_0: Pubkey,
+    mint_1: Pubkey,
+    vault_0: Pubkey,
+    vault_1: Pubkey,
+}
+
+/// Pool of `RESERVE` of each token. The config, pool and observation are sandwich-swap
+/// account types, so their fixtures are owned by sandwich-swap
+fn create_pool(harness: &mut Harness) -> Pool {
+    let authority = Pubkey::find_program_address(
+        &[raydium_cpmm_cpi::AUTH_SEED.as_bytes()],
+        &RaydiumCpmm::id(),
+    )
+    .0;
+    let mint_0 = harness.create_mint(9);
+    let mint_1 = harness.create_mint(9);
+    let vault_0 = harness.create_token_account(mint_0, authority, RESERVE);
+    let vault_1 = harness.create_token_account(mint_1, authority, RESERVE);
+
+    let amm_config = Pubkey::new_unique();
+    harness.set_anchor_account(
+        amm_config,
+        sandwich_swap::ID,
+        &CpmmAmmConfig {
+            trade_fee_rate: TRADE_FEE_RATE,
+            ..Default::default()
+        },
+    );
+
+    let observation_state = Pubkey::new_unique();
+    harness.set_data_account(
+        observation_state,
+        sandwich_swap::ID,
+        [
+            <CpmmObservationState as anchor_lang::Discriminator>::DISCRIMINATOR.as_slice(),
+            &[0; std::mem::size_of::<CpmmObservationState>()],
+        ]
+        .concat(),
+    );
+
+    let pool_state = Pubkey::new_unique();
+    harness.set_zero_copy_account(
+        pool_state,
+        sandwich_swap::ID,
+        &CpmmPoolState {
+            amm_config,
+            token_0_vault: vault_0,
+            token_1_vault: vault_1,
+            token_0_mint: mint_0,
+            token_1_mint: mint_1,
+            token_0_program: spl_token::ID,
+            token_1_program: spl_token::ID,
+            observation_key: observation_state,
+            mint_0_decimals: 9,
+            mint_1_decimals: 9,
+            ..Default::default()
+        },
+    );
+
+    Pool {
+        amm_config,
+        pool_state,
+        observation_state,
+        authority,
+        mint_0,
+        mint_1,
+        vault_0,
+        vault_1,
+    }
+}
+
+/// The victim's raw swap of `VICTIM_AMOUNT_IN` token 0 for token 1 on the pool
+fn victim_swap(
+    pool: &Pool,
+    victim: &Keypair,
+    input_token_account: Pubkey,
+    output_token_account: Pubkey,
+    minimum_amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: RaydiumCpmm::id(),
+        accounts: raydium_cpmm_cpi::accounts::Swap {
+            payer: victim.pubkey(),
+            authority: pool.authority,
+            amm_config: pool.amm_config,
+            pool_state: pool.pool_state,
+            input_token_account,
+            output_token_account,
+            input_vault: pool.vault_0,
+            output_vault: pool.vault_1,
+            input_token_program: spl_token::ID,
+            output_token_program: spl_token::ID,
+            input_token_mint: pool.mint_0,
+            output_token_mint: pool.mint_1,
+            observation_state: pool.observation_state,
+        }
+        .to_account_metas(None),
+        data: raydium_cpmm_cpi::instruction::SwapBaseInput {
+            amount_in: VICTIM_AMOUNT_IN,
+            minimum_amount_out,
+        }
+        .data(),
+    }
+}
+
+#[test]
+fn cpmm_base_input_sandwich_is_profitable() {
+    let mut harness = Harness::new();
+    let pool = create_pool(&mut harness);
+    let operator = harness.operator.pubkey();
+
+    let operator_token_0 = harness.create_token_account(pool.mint_0, operator, RESERVE);
+    let operator_token_1 = get_associated_token_address(&operator, &pool.mint_1);
+
+    // The operator pays the victim's transaction fee, the victim only signs
+    let victim = Keypair::new();
+    let victim_token_0 =
+        harness.create_token_account(pool.mint_0, victim.pubkey(), VICTIM_AMOUNT_IN);
+    let victim_token_1 = harness.create_token_account(pool.mint_1, victim.pubkey(), 0);
+
+    // The victim quotes its swap against the untouched pool
+    let victim_quote = CurveCalculator::swap_base_input(
+        VICTIM_AMOUNT_IN as u128,
+        RESERVE as u128,
+        RESERVE as u128,
+        TRADE_FEE_RATE,
+        0,
+        0,
+    )
+    .unwrap()
+    .destination_amount_swapped as u64;
+    let victim_minimum_out = victim_quote * (100 - VICTIM_SLIPPAGE_PCT) / 100;
+
+    let frontrun = Instruction {
+        program_id: sandwich_swap::ID,
+        accounts: sandwich_swap::accounts::CpmmSandwichFrontrun {
+            cp_swap_program: RaydiumCpmm::id(),
+            payer: operator,
+            authority: pool.authority,
+            amm_config: pool.amm_config,
+            pool_state: pool.pool_state,
+            input_token_account: operator_token_0,
+            input_vault: pool.vault_0,
+            output_vault: pool.vault_1,
+            input_token_program: spl_token::ID,
+            output_token_program: spl_token::ID,
+            input_token_mint: pool.mint_0,
+            output_token_mint: pool.mint_1,
+            output_token_account: operator_token_1,
+            observation_state: pool.observation_state,
+            sandwich_state: sandwich_pda(&operator, SANDWICH_ID),
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            guards: harness.guards(),
+        }
+        .to_account_metas(None),
+        data: sandwich_swap::instruction::RaydiumCpmmFrontrunSwapBaseInput {
+            target_amount_in: VICTIM_AMOUNT_IN,
+            target_minimum_amount_out: victim_minimum_out,
+            sandwich_id: SANDWICH_ID,
+            min_victim_slippage_bps: 0,
+            self_slippage_bps: 0,
+            max_self_price_impact_bps: 0,
+            check_sellable: false,
+            victim_is_exact_input: true,
+            min_pool_age_secs: 0,
+            default_victim_slippage_bps: 0,
+            expected_current_price: 0,
+            max_price_drift_bps: 0,
+            victim_amounts: vec![],
+            require_victim_ix: None,
+            slippage_model: SlippageModel::Balanced,
+            // The fixture pool never traded, so its observation was never written
+            allow_uninitialized_observation: true,
+        }
+        .data(),
+    };
+    harness.send(&[frontrun], &[]);
+
+    let frontrun_state = harness.sandwich_state(SANDWICH_ID);
+    assert!(frontrun_state.frontrun_output_amount > 0);
+    assert_eq!(
+        harness.token_balance(&operator_token_1),
+        frontrun_state.frontrun_output_amount
+    );
+
+    // The victim still clears its minimum after the frontrun
+    harness.send(
+        &[victim_swap(
+            &pool,
+            &victim,
+            victim_token_0,
+            victim_token_1,
+            victim_minimum_out,
+        )],
+        &[&victim],
+    );
+    assert!(harness.token_balance(&victim_token_1) >= victim_minimum_out);
+
+    harness.advance_slots(1);
+    let backrun = Instruction {
+        program_id: sandwich_swap::ID,
+        accounts: sandwich_swap::accounts::CpmmSandwichBackrun {
+            cp_swap_program: RaydiumCpmm::id(),
+            payer: operator,
+            authority: pool.authority,
+            amm_config: pool.amm_config,
+            pool_state: pool.pool_state,
+            input_token_account: operator_token_1,
+            output_token_account: operator_token_0,
+            input_vault: pool.vault_1,
+            output_vault: pool.vault_0,
+            input_token_program: spl_token::ID,
+            output_token_program: spl_token::ID,
+            input_token_mint: pool.mint_1,
+            output_token_mint: pool.mint_0,
+            observation_state: pool.observation_state,
+            sandwich_state: sandwich_pda(&operator, SANDWICH_ID),
+            config: config_pda(),
+            event_authority: event_authority(),
+            program: sandwich_swap::ID,
+        }
+        .to_account_metas(None),
+        data: sandwich_swap::instruction::RaydiumCpmmBackrunSwapBaseInput {
+            sandwich_id: SANDWICH_ID,
+            backrun_fraction_bps: 0,
+            min_slots_between: 1,
+            time_decay_bps_per_sec: 0,
+            max_acceptable_loss: None,
+            fixed_cost_lamports: 0,
+            min_profit_amount: 0,
+            keep_output_amount: 0,
+            max_state_age_secs: 0,
+        }
+        .data(),
+    };
+    let meta = harness.send(&[backrun], &[]);
+
+    let event = sandwich_complete_event(&meta);
+    assert_eq!(event.sandwich_id, SANDWICH_ID);
+    assert!(
+        event.realized_profit > 0,
+        "sandwich lost {}",
+        event.realized_profit
+    );
+    assert_eq!(event.input_amount, frontrun_state.frontrun_input_amount);
+
+    // The whole position was sold back into token 0, at a profit
+    let state = harness.sandwich_state(SANDWICH_ID);
+    assert!(state.is_complete);
+    assert_eq!(state.realized_profit, event.realized_profit);
+    assert_eq!(harness.token_balance(&operator_token_1), 0);
+    assert_eq!(
+        harness.token_balance(&operator_token_0),
+        RESERVE + event.realized_profit as u64
+    );
+}
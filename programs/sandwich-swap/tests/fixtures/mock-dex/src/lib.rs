@@ -0,0 +1,285 @@
+//! Stand-in for the Raydium CPMM and PumpFun programs in the sandwich-swap LiteSVM tests.
+//! The same binary is deployed at both program ids and dispatches on the instruction
+//! discriminators sandwich-swap sends: CPMM `swap_base_input` and PumpFun `buy` and `sell`,
+//! with the account lists sandwich-swap passes them.
+//!
+//! sandwich-swap loads the pool, config and bonding curve as its own account types, so the
+//! test fixtures for them are owned by sandwich-swap and only read here. The mock prices
+//! every trade off the live vault balances instead, and keeps the bonding curve's SOL in
+//! the account passed as the creator fee vault, the only writable one it can own.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+
+/// sha256("global:swap_base_input")[..8]
+pub const CPMM_SWAP_BASE_INPUT: [u8; 8] = [143, 190, 90, 218, 196, 30, 51, 222];
+/// sha256("global:buy")[..8]
+pub const PUMPFUN_BUY: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+/// sha256("global:sell")[..8]
+pub const PUMPFUN_SELL: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+
+/// Seed of the CPMM vault authority PDA, Raydium's `AUTH_SEED`
+pub const CPMM_AUTH_SEED: &[u8] = b"vault_and_lp_mint_auth_seed";
+/// Seed of a PumpFun bonding curve PDA, followed by the mint
+pub const BONDING_CURVE_SEED: &[u8] = b"bonding-curve";
+
+const CPMM_FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+/// PumpFun's 1% fee, charged on top of a buy and taken out of a sell
+const PUMPFUN_FEE_BPS: u128 = 100;
+
+/// Error code of a trade outside the caller's slippage limit
+pub const EXCEEDED_SLIPPAGE: u32 = 6000;
+
+// Byte offsets past the 8-byte discriminators: `trade_fee_rate` in the CPMM amm config,
+// the reserves in the bonding curve and `amount` in an SPL token account
+const AMM_CONFIG_TRADE_FEE_RATE_OFFSET: usize = 8 + 1 + 1 + 2;
+const CURVE_VIRTUAL_TOKEN_RESERVES_OFFSET: usize = 8;
+const CURVE_VIRTUAL_SOL_RESERVES_OFFSET: usize = 16;
+const CURVE_REAL_TOKEN_RESERVES_OFFSET: usize = 24;
+const CURVE_REAL_SOL_RESERVES_OFFSET: usize = 32;
+const TOKEN_ACCOUNT_AMOUNT_OFFSET: usize = 64;
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    if instruction_data.len() < 24 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let amount = read_u64(instruction_data, 8)?;
+    let limit = read_u64(instruction_data, 16)?;
+
+    let discriminator: [u8; 8] = instruction_data[..8].try_into().unwrap();
+    match discriminator {
+        CPMM_SWAP_BASE_INPUT => cpmm_swap_base_input(program_id, accounts, amount, limit),
+        PUMPFUN_BUY => pumpfun_buy(program_id, accounts, amount, limit),
+        PUMPFUN_SELL => pumpfun_sell(accounts, amount, limit),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+// Constant product swap of `amount_in`, less the config's trade fee, from the input vault
+// into the output vault. Protocol and fund fees are not tracked, the tests set them to 0
+fn cpmm_swap_base_input(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> ProgramResult {
+    let [payer, authority, amm_config, _pool_state, input_token_account, output_token_account, input_vault, output_vault, input_token_program, output_token_program, _input_token_mint, _output_token_mint, _observation_state] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let trade_fee_rate = read_u64(
+        &amm_config.try_borrow_data()?,
+        AMM_CONFIG_TRADE_FEE_RATE_OFFSET,
+    )?;
+    let reserve_in = token_balance(input_vault)? as u128;
+    let reserve_out = token_balance(output_vault)? as u128;
+
+    // Raydium rounds the trade fee up
+    let amount_in = amount_in as u128;
+    let trade_fee = (amount_in * trade_fee_rate as u128 + CPMM_FEE_RATE_DENOMINATOR - 1)
+        / CPMM_FEE_RATE_DENOMINATOR;
+    let amount_in_less_fee = amount_in - trade_fee;
+    let amount_out = amount_in_less_fee * reserve_out / (reserve_in + amount_in_less_fee);
+    if amount_out < minimum_amount_out as u128 {
+        return Err(ProgramError::Custom(EXCEEDED_SLIPPAGE));
+    }
+
+    let (expected_authority, bump) = Pubkey::find_program_address(&[CPMM_AUTH_SEED], program_id);
+    if *authority.key != expected_authority {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    token_transfer(
+        input_token_program,
+        input_token_account,
+        input_vault,
+        payer,
+        amount_in as u64,
+        &[],
+    )?;
+    token_transfer(
+        output_token_program,
+        output_vault,
+        output_token_account,
+        authority,
+        amount_out as u64,
+        &[&[CPMM_AUTH_SEED, &[bump]]],
+    )
+}
+
+// Buys exactly `token_amount` off the curve, paying its SOL cost into the SOL vault and the
+// fee to the fee recipient
+fn pumpfun_buy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_amount: u64,
+    max_sol_cost: u64,
+) -> ProgramResult {
+    let [_global, fee_recipient, mint, bonding_curve, bonding_curve_ata, user_ata, user, system_program, token_program, sol_vault, _event_authority, _program] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let (virtual_token_reserves, virtual_sol_reserves) =
+        pumpfun_live_reserves(sol_vault, bonding_curve, bonding_curve_ata)?;
+
+    // PumpFun rounds the cost up by a lamport and charges its fee on top
+    let token_amount = token_amount as u128;
+    if token_amount >= virtual_token_reserves {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    let sol_cost =
+        token_amount * virtual_sol_reserves / (virtual_token_reserves - token_amount) + 1;
+    let fee = sol_cost * PUMPFUN_FEE_BPS / 10_000;
+    if sol_cost + fee > max_sol_cost as u128 {
+        return Err(ProgramError::Custom(EXCEEDED_SLIPPAGE));
+    }
+
+    let (expected_curve, bump) =
+        Pubkey::find_program_address(&[BONDING_CURVE_SEED, mint.key.as_ref()], program_id);
+    if *bonding_curve.key != expected_curve {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    invoke(
+        &system_instruction::transfer(user.key, sol_vault.key, sol_cost as u64),
+        &[user.clone(), sol_vault.clone(), system_program.clone()],
+    )?;
+    invoke(
+        &system_instruction::transfer(user.key, fee_recipient.key, fee as u64),
+        &[user.clone(), fee_recipient.clone(), system_program.clone()],
+    )?;
+    token_transfer(
+        token_program,
+        bonding_curve_ata,
+        user_ata,
+        bonding_curve,
+        token_amount as u64,
+        &[&[BONDING_CURVE_SEED, mint.key.as_ref(), &[bump]]],
+    )
+}
+
+// Sells `token_amount` back to the curve, paying the SOL out of the SOL vault less the fee
+fn pumpfun_sell(accounts: &[AccountInfo], token_amount: u64, min_sol_output: u64) -> ProgramResult {
+    let [_global, fee_recipient, _mint, bonding_curve, bonding_curve_ata, user_ata, user, _system_program, sol_vault, token_program, _event_authority, _program] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let (virtual_token_reserves, virtual_sol_reserves) =
+        pumpfun_live_reserves(sol_vault, bonding_curve, bonding_curve_ata)?;
+
+    let token_amount = token_amount as u128;
+    let sol_output = token_amount * virtual_sol_reserves / (virtual_token_reserves + token_amount);
+    let fee = sol_output * PUMPFUN_FEE_BPS / 10_000;
+    if sol_output - fee < min_sol_output as u128 {
+        return Err(ProgramError::Custom(EXCEEDED_SLIPPAGE));
+    }
+
+    token_transfer(
+        token_program,
+        user_ata,
+        bonding_curve_ata,
+        user,
+        token_amount as u64,
+        &[],
+    )?;
+
+    // The SOL vault is the mock's own account, its lamports move without a CPI
+    **sol_vault.try_borrow_mut_lamports()? -= sol_output as u64;
+    **user.try_borrow_mut_lamports()? += (sol_output - fee) as u64;
+    **fee_recipient.try_borrow_mut_lamports()? += fee as u64;
+    Ok(())
+}
+
+// PumpFun's virtual reserves sit a constant offset above its real ones. The offsets come
+// from the curve fixture, the real reserves from the curve's token account and the SOL
+// the vault holds above its rent
+fn pumpfun_live_reserves(
+    sol_vault: &AccountInfo,
+    bonding_curve: &AccountInfo,
+    bonding_curve_ata: &AccountInfo,
+) -> Result<(u128, u128), ProgramError> {
+    let curve = bonding_curve.try_borrow_data()?;
+    let token_offset = read_u64(&curve, CURVE_VIRTUAL_TOKEN_RESERVES_OFFSET)?
+        .checked_sub(read_u64(&curve, CURVE_REAL_TOKEN_RESERVES_OFFSET)?)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let sol_offset = read_u64(&curve, CURVE_VIRTUAL_SOL_RESERVES_OFFSET)?
+        .checked_sub(read_u64(&curve, CURVE_REAL_SOL_RESERVES_OFFSET)?)
+        .ok_or(ProgramError::InvalidAccountData)?;
+
+    let real_token_reserves = token_balance(bonding_curve_ata)?;
+    let real_sol_reserves = sol_vault
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(sol_vault.data_len()));
+
+    Ok((
+        (real_token_reserves + token_offset) as u128,
+        (real_sol_reserves + sol_offset) as u128,
+    ))
+}
+
+// SPL Token `Transfer`, tag 3 followed by the amount
+fn token_transfer<'a>(
+    token_program: &AccountInfo<'a>,
+    from: &AccountInfo<'a>,
+    to: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let mut data = vec![3u8];
+    data.extend_from_slice(&amount.to_le_bytes());
+    let instruction = Instruction {
+        program_id: *token_program.key,
+        accounts: vec![
+            AccountMeta::new(*from.key, false),
+            AccountMeta::new(*to.key, false),
+            AccountMeta::new_readonly(*authority.key, true),
+        ],
+        data,
+    };
+    invoke_signed(
+        &instruction,
+        &[
+            from.clone(),
+            to.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+fn token_balance(token_account: &AccountInfo) -> Result<u64, ProgramError> {
+    read_u64(
+        &token_account.try_borrow_data()?,
+        TOKEN_ACCOUNT_AMOUNT_OFFSET,
+    )
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ProgramError> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ProgramError::InvalidAccountData)
+}
@@ -0,0 +1,252 @@
+//! Frontrun, victim and backrun of a PumpFun buy sandwich against the mock PumpFun.
+
+mod common;
+
+use anchor_lang::{AnchorSerialize, Id, InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token::spl_token;
+use common::*;
+use sandwich_swap::instructions::bonding_curve::{
+    BondingCurveState, PumpFunGlobal, PUMPFUN_GLOBAL_DISCRIMINATOR,
+};
+use sandwich_swap::instructions::{PumpFun, PumpFunBuy};
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+};
+
+// A freshly launched PumpFun curve
+const VIRTUAL_TOKEN_RESERVES: u64 = 1_073_000_000_000_000;
+const VIRTUAL_SOL_RESERVES: u64 = 30_000_000_000;
+const REAL_TOKEN_RESERVES: u64 = 793_100_000_000_000;
+const TOKEN_TOTAL_SUPPLY: u64 = 1_000_000_000_000_000;
+
+const VICTIM_TOKEN_AMOUNT: u64 = 100_000_000_000_000;
+/// The victim accepts paying 20% more than the quote
+const VICTIM_SLIPPAGE_PCT: u64 = 20;
+const SANDWICH_ID: u64 = 1;
+
+struct Curve {
+    global: Pubkey,
+    fee_recipient: Pubkey,
+    mint: Pubkey,
+    bonding_curve: Pubkey,
+    bonding_curve_ata: Pubkey,
+    sol_vault: Pubkey,
+    event_authority: Pubkey,
+}
+
+impl Curve {
+    /// Accounts of a PumpFun buy in the order sandwich-swap's frontrun passes them
+    fn buy_accounts(&self, user: Pubkey, user_ata: Pubkey) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new_readonly(self.global, false),
+            AccountMeta::new(self.fee_recipient, false),
+            AccountMeta::new_readonly(self.mint, false),
+            AccountMeta::new(self.bonding_curve, false),
+            AccountMeta::new(self.bonding_curve_ata, false),
+            AccountMeta::new(user_ata, false),
+            AccountMeta::new(user, true),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new(self.sol_vault, false),
+            AccountMeta::new_readonly(self.event_authority, false),
+            AccountMeta::new_readonly(PumpFun::id(), false),
+        ]
+    }
+}
+
+/// Curve with no SOL raised yet. The bonding curve is a sandwich-swap account type, so
+/// its fixture is owned by sandwich-swap, the mock keeps the curve's SOL in the vault
+fn create_curve(harness: &mut Harness) -> Curve {
+    let fee_recipient = Pubkey::new_unique();
+    harness.svm.airdrop(&fee_recipient, 1_000_000_000).unwrap();
+
+    // PumpFun's global, owned by PumpFun, naming the fee recipient
+    let global = Pubkey::new_unique();
+    let mut global_data = PUMPFUN_GLOBAL_DISCRIMINATOR.to_vec();
+    PumpFunGlobal {
+        initialized: true,
+        fee_recipient,
+        fee_basis_points: 100,
+        ..Default::default()
+    }
+    .serialize(&mut global_data)
+    .unwrap();
+    harness.set_data_account(global, PumpFun::id(), global_data);
+
+    let mint = harness.create_mint(6);
+    let bonding_curve =
+        Pubkey::find_program_address(&[b"bonding-curve", mint.as_ref()], &PumpFun::id()).0;
+    harness.set_zero_copy_account(
+        bonding_curve,
+        sandwich_swap::ID,
+        &BondingCurveState {
+            virtual_token_reserves: VIRTUAL_TOKEN_RESERVES,
+            virtual_sol_reserves: VIRTUAL_SOL_RESERVES,
+            real_token_reserves: REAL_TOKEN_RESERVES,
+            real_sol_reserves: 0,
+            token_total_supply: TOKEN_TOTAL_SUPPLY,
+            complete: false,
+            creator: Pubkey::new_unique(),
+        },
+    );
+    let bonding_curve_ata = get_associated_token_address(&bonding_curve, &mint);
+    harness.set_token_account(bonding_curve_ata, mint, bonding_curve, REAL_TOKEN_RESERVES);
+
+    let sol_vault = Pubkey::new_unique();
+    harness.set_account(
+        sol_vault,
+        Account {
+            lamports: Rent::default().minimum_balance(0),
+            data: vec![],
+            owner: PumpFun::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    Curve {
+        global,
+        fee_recipient,
+        mint,
+        bonding_curve,
+        bonding_curve_ata,
+        sol_vault,
+        event_authority: Pubkey::find_program_address(&[b"__event_authority"], &PumpFun::id()).0,
+    }
+}
+
+/// SOL a buy of `token_amount` costs on the untouched curve, PumpFun's 1% fee included
+fn quote_buy(token_amount: u64) -> u64 {
+    let token_amount = token_amount as u128;
+    let cost = token_amount * VIRTUAL_SOL_RESERVES as u128
+        / (VIRTUAL_TOKEN_RESERVES as u128 - token_amount)
+        + 1;
+    (cost + cost / 100) as u64
+}
+
+#[test]
+fn pumpfun_buy_sandwich_is_profitable() {
+    let mut harness = Harness::new();
+    let curve = create_curve(&mut harness);
+    let operator = harness.operator.pubkey();
+    let operator_ata = get_associated_token_address(&operator, &curve.mint);
+
+    let victim = Keypair::new();
+    harness
+        .svm
+        .airdrop(&victim.pubkey(), 100_000_000_000)
+        .unwrap();
+    let victim_ata = harness.create_token_account(curve.mint, victim.pubkey(), 0);
+    let victim_max_sol_cost = quote_buy(VICTIM_TOKEN_AMOUNT) * (100 + VICTIM_SLIPPAGE_PCT) / 100;
+
+    let frontrun = Instruction {
+        program_id: sandwich_swap::ID,
+        accounts: sandwich_swap::accounts::PumpFunFrontrunBuyContext {
+            global: curve.global,
+            protocol_fee_recipient: curve.fee_recipient,
+            mint: curve.mint,
+            bonding_curve: curve.bonding_curve,
+            bonding_curve_ata: curve.bonding_curve_ata,
+            user_ata: operator_ata,
+            user: operator,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            creator_fee_vault: curve.sol_vault,
+            event_authority: curve.event_authority,
+            pump_program: PumpFun::id(),
+            sandwich_state: sandwich_pda(&operator, SANDWICH_ID),
+            associated_token_program: associated_token::ID,
+            guards: harness.guards(),
+        }
+        .to_account_metas(None),
+        data: sandwich_swap::instruction::PumpfunFrontrunBuy {
+            target_base_amount_out: VICTIM_TOKEN_AMOUNT,
+            target_max_quote_amount_in: victim_max_sol_cost,
+            sandwich_id: SANDWICH_ID,
+            require_victim_ix: None,
+        }
+        .data(),
+    };
+    harness.send(&[frontrun], &[]);
+
+    let frontrun_state = harness.sandwich_state(SANDWICH_ID);
+    assert!(frontrun_state.frontrun_input_amount > 0);
+    assert_eq!(
+        harness.token_balance(&operator_ata),
+        frontrun_state.frontrun_output_amount
+    );
+
+    // The victim's buy still fits its max SOL cost after the frontrun
+    let victim_buy = Instruction {
+        program_id: PumpFun::id(),
+        accounts: curve.buy_accounts(victim.pubkey(), victim_ata),
+        data: PumpFunBuy {
+            token_amount: VICTIM_TOKEN_AMOUNT,
+            max_sol_cost: victim_max_sol_cost,
+        }
+        .data(),
+    };
+    harness.send(&[victim_buy], &[&victim]);
+    assert_eq!(harness.token_balance(&victim_ata), VICTIM_TOKEN_AMOUNT);
+
+    harness.advance_slots(1);
+    let backrun = Instruction {
+        program_id: sandwich_swap::ID,
+        accounts: sandwich_swap::accounts::PumpFunBackrunBuyContext {
+            global: curve.global,
+            protocol_fee_recipient: curve.fee_recipient,
+            mint: curve.mint,
+            bonding_curve: curve.bonding_curve,
+            bonding_curve_ata: curve.bonding_curve_ata,
+            user_ata: operator_ata,
+            user: operator,
+            system_program: system_program::ID,
+            creator_fee_vault: curve.sol_vault,
+            token_program: spl_token::ID,
+            pumpfun_event_authority: curve.event_authority,
+            pump_program: PumpFun::id(),
+            sandwich_state: sandwich_pda(&operator, SANDWICH_ID),
+            config: config_pda(),
+            event_authority: event_authority(),
+            program: sandwich_swap::ID,
+        }
+        .to_account_metas(None),
+        data: sandwich_swap::instruction::PumpfunBackrunBuy {
+            sandwich_id: SANDWICH_ID,
+            min_slots_between: 1,
+            max_state_age_secs: 0,
+            fixed_cost_lamports: 0,
+            min_profit_amount: 0,
+        }
+        .data(),
+    };
+    let meta = harness.send(&[backrun], &[]);
+
+    let event = sandwich_complete_event(&meta);
+    assert_eq!(event.sandwich_id, SANDWICH_ID);
+    assert!(
+        event.realized_profit > 0,
+        "sandwich lost {}",
+        event.realized_profit
+    );
+    assert_eq!(event.input_amount, frontrun_state.frontrun_input_amount);
+
+    // The profit is also the instruction's return data, in lamports
+    assert_eq!(meta.return_data.program_id, sandwich_swap::ID);
+    assert_eq!(
+        meta.return_data.data,
+        (event.realized_profit as u64).to_le_bytes().to_vec()
+    );
+
+    let state = harness.sandwich_state(SANDWICH_ID);
+    assert!(state.is_complete);
+    assert_eq!(state.realized_profit, event.realized_profit);
+    // The emptied token account was closed
+    assert_eq!(harness.lamports(&operator_ata), 0);
+}